@@ -0,0 +1,327 @@
+// Config-defined alert rules evaluated after each fetch and handed to every configured notifier
+use crate::coins::format_coins;
+use crate::config::{AlertMetric, AlertOp, AlertRule, AlertsConfig, TelegramConfig};
+use crate::error::BazaarError;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::names;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+pub struct Alert {
+    // The rule that fired, like "ENCHANTED_GOLD buyPrice > 9000"
+    pub rule: String,
+    pub product: String,
+    pub metric: AlertMetric,
+    pub value: f64,
+    // The metric in the previous snapshot, when there was one
+    pub previous: Option<f64>,
+    // lastUpdated of the snapshot the rule fired on
+    pub last_updated: u64,
+}
+
+impl Alert {
+    pub fn message(&self) -> String {
+        format!("{} {} is {} ({})", names::normalize(&self.product), self.metric.name(), self.metric.format(self.value), self.rule)
+    }
+
+    // The document generic webhooks receive
+    pub fn to_json(&self) -> Value {
+        json!({
+            "rule": self.rule,
+            "product": self.product,
+            "metric": self.metric.name(),
+            "previous": self.previous,
+            "value": self.value,
+            "last_updated": self.last_updated,
+            "timestamp": DateTime::from_timestamp_millis(self.last_updated as i64).map(|time| time.to_rfc3339()),
+            "message": self.message(),
+        })
+    }
+}
+
+impl AlertMetric {
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertMetric::BuyPrice => "buyPrice",
+            AlertMetric::SellPrice => "sellPrice",
+            AlertMetric::BuyVolume => "buyVolume",
+            AlertMetric::SellVolume => "sellVolume",
+            AlertMetric::BuyMovingWeek => "buyMovingWeek",
+            AlertMetric::SellMovingWeek => "sellMovingWeek",
+            AlertMetric::BuyOrders => "buyOrders",
+            AlertMetric::SellOrders => "sellOrders",
+            AlertMetric::Spread => "spread",
+            AlertMetric::Margin => "margin",
+        }
+    }
+
+    // Coins and unit counts abbreviated like 12.3m, the margin as the percentage it is
+    pub fn format(self, value: f64) -> String {
+        match self {
+            AlertMetric::Margin => format!("{:.1}%", value),
+            _ => format_coins(value),
+        }
+    }
+
+    // None for a margin without a sell price
+    pub fn value(self, quick_status: &QuickStatus) -> Option<f64> {
+        let value: f64 = match self {
+            AlertMetric::BuyPrice => quick_status.buyPrice,
+            AlertMetric::SellPrice => quick_status.sellPrice,
+            AlertMetric::BuyVolume => quick_status.buyVolume as f64,
+            AlertMetric::SellVolume => quick_status.sellVolume as f64,
+            AlertMetric::BuyMovingWeek => quick_status.buyMovingWeek as f64,
+            AlertMetric::SellMovingWeek => quick_status.sellMovingWeek as f64,
+            AlertMetric::BuyOrders => quick_status.buyOrders as f64,
+            AlertMetric::SellOrders => quick_status.sellOrders as f64,
+            AlertMetric::Spread => quick_status.buyPrice - quick_status.sellPrice,
+            AlertMetric::Margin if quick_status.sellPrice > 0.0 => (quick_status.buyPrice - quick_status.sellPrice) / quick_status.sellPrice * 100.0,
+            AlertMetric::Margin => return None,
+        };
+        Some(value)
+    }
+}
+
+impl AlertOp {
+    pub fn holds(self, value: f64, limit: f64) -> bool {
+        match self {
+            AlertOp::Above => value > limit,
+            AlertOp::AtLeast => value >= limit,
+            AlertOp::Below => value < limit,
+            AlertOp::AtMost => value <= limit,
+        }
+    }
+}
+
+impl fmt::Display for AlertOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlertOp::Above => ">",
+            AlertOp::AtLeast => ">=",
+            AlertOp::Below => "<",
+            AlertOp::AtMost => "<=",
+        })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.product, self.metric.name(), self.op, self.value)
+    }
+}
+
+// lastUpdated each cooldown rule last fired at, keyed by the rule's description
+#[derive(Deserialize, Serialize, Default)]
+pub struct AlertState {
+    fired: HashMap<String, u64>,
+}
+
+impl AlertState {
+    // A missing or unreadable file starts with every cooldown expired
+    pub fn load(path: &Path) -> Self {
+        fs::read(path).ok().and_then(|data| serde_json::from_slice(&data).ok()).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BazaarError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Records the firing when the rule's cooldown has passed
+    fn try_fire(&mut self, rule: &str, cooldown_millis: i64, now: u64) -> bool {
+        let ready: bool = self.fired.get(rule).is_none_or(|fired| now as i64 - *fired as i64 >= cooldown_millis);
+        if ready {
+            self.fired.insert(rule.to_string(), now);
+        }
+        ready
+    }
+}
+
+// Rules without a cooldown only fire on the crossing: a condition that already held in the
+// previous snapshot stays quiet, and without a previous snapshot every holding rule fires.
+pub fn evaluate(rules: &[AlertRule], previous: Option<&HashMap<String, QuickStatus>>, current: &BazaarResponse, state: &mut AlertState) -> Vec<Alert> {
+    let mut alerts: Vec<Alert> = Vec::new();
+    for rule in rules {
+        let Some(value) = current.products.get(&rule.product).and_then(|product| rule.metric.value(&product.quick_status)) else {
+            continue;
+        };
+        if !rule.op.holds(value, rule.value) {
+            continue;
+        }
+        let before: Option<f64> = previous
+            .and_then(|previous| previous.get(&rule.product))
+            .and_then(|quick_status| rule.metric.value(quick_status));
+        let description: String = rule.to_string();
+        let fires: bool = match &rule.cooldown {
+            Some(cooldown) => state.try_fire(&description, cooldown.millis, current.lastUpdated),
+            None => !before.is_some_and(|before| rule.op.holds(before, rule.value)),
+        };
+        if fires {
+            alerts.push(Alert {
+                rule: description,
+                product: rule.product.clone(),
+                metric: rule.metric,
+                value,
+                previous: before,
+                last_updated: current.lastUpdated,
+            });
+        }
+    }
+    alerts
+}
+
+// Where alerts go besides the log
+pub enum Notifier {
+    Discord { webhook_url: String },
+    Webhook { url: String },
+    Telegram(TelegramConfig),
+    #[cfg(feature = "desktop")]
+    Desktop,
+}
+
+impl Notifier {
+    pub fn from_config(config: &AlertsConfig) -> Vec<Notifier> {
+        let mut notifiers: Vec<Notifier> = Vec::new();
+        if let Some(webhook_url) = &config.discord_webhook {
+            notifiers.push(Notifier::Discord { webhook_url: webhook_url.clone() });
+        }
+        notifiers.extend(config.webhooks.iter().map(|url| Notifier::Webhook { url: url.clone() }));
+        if let Some(telegram) = &config.telegram {
+            notifiers.push(Notifier::Telegram(telegram.clone()));
+        }
+        #[cfg(feature = "desktop")]
+        if config.desktop {
+            notifiers.push(Notifier::Desktop);
+        }
+        #[cfg(not(feature = "desktop"))]
+        if config.desktop {
+            warn!("alerts.desktop is set, but this build has no desktop feature");
+        }
+        notifiers
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Notifier::Discord { .. } => "Discord",
+            Notifier::Webhook { url } => url,
+            Notifier::Telegram(_) => "Telegram",
+            #[cfg(feature = "desktop")]
+            Notifier::Desktop => "the desktop",
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, alerts: &[Alert]) -> Result<(), BazaarError> {
+        match self {
+            Notifier::Discord { webhook_url } => send_discord(client, webhook_url, alerts).await,
+            Notifier::Webhook { url } => send_webhook(client, url, alerts).await,
+            Notifier::Telegram(telegram) => send_telegram(client, telegram, alerts).await,
+            #[cfg(feature = "desktop")]
+            Notifier::Desktop => send_desktop(alerts).await,
+        }
+    }
+}
+
+pub async fn send_discord(client: &reqwest::Client, webhook_url: &str, alerts: &[Alert]) -> Result<(), BazaarError> {
+    let content: String = alerts.iter().map(Alert::message).collect::<Vec<String>>().join("\n");
+    client.post(webhook_url)
+        .json(&json!({ "content": content }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// One POST per alert, so receivers like Home Assistant or n8n can treat each as an event
+pub async fn send_webhook(client: &reqwest::Client, url: &str, alerts: &[Alert]) -> Result<(), BazaarError> {
+    for alert in alerts {
+        client.post(url)
+            .json(&alert.to_json())
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    Ok(())
+}
+
+// When this process last sent Telegram messages, for alerts.telegram.max_messages_per_hour
+static TELEGRAM_SENT: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+// Claims a slot in the hourly budget, false once it's used up
+fn telegram_slot(max_per_hour: usize) -> bool {
+    let mut sent = TELEGRAM_SENT.lock().unwrap();
+    let hour_ago: Option<Instant> = Instant::now().checked_sub(Duration::from_secs(3600));
+    while sent.front().is_some_and(|time| hour_ago.is_some_and(|hour_ago| *time < hour_ago)) {
+        sent.pop_front();
+    }
+    if sent.len() >= max_per_hour {
+        return false;
+    }
+    sent.push_back(Instant::now());
+    true
+}
+
+// One message per fetch, long batches are cut at max_alerts_per_message
+pub async fn send_telegram(client: &reqwest::Client, telegram: &TelegramConfig, alerts: &[Alert]) -> Result<(), BazaarError> {
+    if !telegram_slot(telegram.max_messages_per_hour) {
+        warn!("Telegram sent {} messages in the last hour, dropping {} alerts", telegram.max_messages_per_hour, alerts.len());
+        return Ok(());
+    }
+    let mut lines: Vec<String> = alerts.iter().take(telegram.max_alerts_per_message).map(Alert::message).collect();
+    if alerts.len() > telegram.max_alerts_per_message {
+        lines.push(format!("... and {} more", alerts.len() - telegram.max_alerts_per_message));
+    }
+    let token: &str = telegram.bot_token.as_deref().unwrap_or_default();
+    client.post(format!("https://api.telegram.org/bot{}/sendMessage", token))
+        .json(&json!({ "chat_id": telegram.chat_id, "text": lines.join("\n"), "disable_web_page_preview": true }))
+        .send()
+        .await?
+        .error_for_status()
+        // The URL carries the bot token, keep it out of the logs
+        .map_err(|e| e.without_url())?;
+    Ok(())
+}
+
+// One notification for the whole batch. Showing it blocks on D-Bus (or the platform's
+// equivalent), so it runs off the async workers.
+#[cfg(feature = "desktop")]
+pub async fn send_desktop(alerts: &[Alert]) -> Result<(), BazaarError> {
+    let summary: String = match alerts.len() {
+        1 => "Bazaar alert".to_string(),
+        count => format!("{} bazaar alerts", count),
+    };
+    let body: String = alerts.iter().map(Alert::message).collect::<Vec<String>>().join("\n");
+    tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .appname("bazaar_update")
+            .summary(&summary)
+            .body(&body)
+            .show()
+            .map(|_| ())
+            .map_err(|e| BazaarError::Desktop(e.to_string()))
+    })
+    .await
+    .map_err(|e| BazaarError::Desktop(e.to_string()))?
+}
+
+// Alerts are always logged, notifiers are best effort and never fail the fetch
+pub async fn dispatch(client: &reqwest::Client, config: &AlertsConfig, alerts: &[Alert]) {
+    if alerts.is_empty() {
+        return;
+    }
+    for alert in alerts {
+        warn!("Alert: {}", alert.message());
+    }
+    for notifier in Notifier::from_config(config) {
+        if let Err(e) = notifier.send(client, alerts).await {
+            error!("Failed to send alerts to {}: {}", notifier.name(), e);
+        }
+    }
+}
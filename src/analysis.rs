@@ -0,0 +1,156 @@
+// Per-product metrics computed from a single snapshot
+use crate::model::{ArithmeticError, BazaarResponse, FixedPoint, QuickStatus};
+use crate::items::ItemCatalog;
+use crate::watchlist::Watchlist;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Bazaar tax taken from sell offers (1.125% with the Bazaar Flipper account upgrade), the
+// default for tax.rate
+pub const BAZAAR_TAX_RATE: f64 = 0.01125;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SpreadSort {
+    Spread,
+    Margin,
+    Net,
+}
+
+// Flipping buys with a buy order at the insta-sell price and sells with an
+// offer at the insta-buy price, so the spread is buyPrice - sellPrice
+pub struct Spread {
+    pub product_id: String,
+    pub buy_price: FixedPoint,
+    pub sell_price: FixedPoint,
+    pub spread: FixedPoint,
+    // Spread relative to the price paid, in percent
+    pub margin: FixedPoint,
+    // Spread after the tax on the sell offer
+    pub net_spread: FixedPoint,
+    pub net_margin: FixedPoint,
+}
+
+impl Spread {
+    // Ok(None) for products without both sides of the book
+    pub fn from_quick_status(product_id: &str, quick_status: &QuickStatus, tax_rate: f64) -> Result<Option<Self>, ArithmeticError> {
+        let buy_price: FixedPoint = FixedPoint::from_float(quick_status.buyPrice);
+        let sell_price: FixedPoint = FixedPoint::from_float(quick_status.sellPrice);
+        if buy_price.raw() <= 0 || sell_price.raw() <= 0 {
+            return Ok(None);
+        }
+        let spread: FixedPoint = buy_price.checked_sub(sell_price).ok_or(ArithmeticError("spread"))?;
+        let tax: FixedPoint = buy_price.tax(tax_rate);
+        let net_spread: FixedPoint = spread.checked_sub(tax).ok_or(ArithmeticError("net spread"))?;
+        Ok(Some(Self {
+            product_id: product_id.to_string(),
+            buy_price,
+            sell_price,
+            spread,
+            margin: percent_of(spread, sell_price).ok_or(ArithmeticError("margin"))?,
+            net_spread,
+            net_margin: percent_of(net_spread, sell_price).ok_or(ArithmeticError("net margin"))?,
+        }))
+    }
+
+    fn sort_key(&self, sort: SpreadSort) -> FixedPoint {
+        match sort {
+            SpreadSort::Spread => self.spread,
+            SpreadSort::Margin => self.margin,
+            SpreadSort::Net => self.net_spread,
+        }
+    }
+}
+
+// `part` as a percentage of `whole`
+pub fn percent_of(part: FixedPoint, whole: FixedPoint) -> Option<FixedPoint> {
+    part.checked_mul(FixedPoint::from_float(100.0))?.checked_div(whole)
+}
+
+// Best first according to `sort`
+pub fn spreads(response: &BazaarResponse, watchlist: &Watchlist, tax_rate: f64, sort: SpreadSort) -> Result<Vec<Spread>, ArithmeticError> {
+    let mut rows: Vec<Spread> = Vec::new();
+    for (id, product) in response.products.iter().filter(|(id, _)| watchlist.contains(id)) {
+        rows.extend(Spread::from_quick_status(id, &product.quick_status, tax_rate)?);
+    }
+    rows.sort_by(|a, b| b.sort_key(sort).cmp(&a.sort_key(sort)).then_with(|| a.product_id.cmp(&b.product_id)));
+    Ok(rows)
+}
+
+pub fn print_spreads(rows: &[Spread], items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>14} {:>14} {:>12} {:>9} {:>12} {:>9}",
+        "product_id", "name", "buy_price", "sell_price", "spread", "margin%", "net_spread", "net%"
+    );
+    for row in rows {
+        println!(
+            "{:<40} {:<32} {:>14} {:>14} {:>12} {:>9} {:>12} {:>9}",
+            row.product_id,
+            items.name(&row.product_id),
+            row.buy_price.to_string(),
+            row.sell_price.to_string(),
+            row.spread.to_string(),
+            row.margin.to_string(),
+            row.net_spread.to_string(),
+            row.net_margin.to_string()
+        );
+    }
+}
+
+pub fn print_quick_status(response: &BazaarResponse, watchlist: &Watchlist, items: &ItemCatalog) {
+    let mut ids: Vec<&String> = response.products.keys().filter(|id| watchlist.contains(id)).collect();
+    ids.sort();
+    println!("{:<40} {:<32} {:>14} {:>14} {:>12} {:>12}", "product_id", "name", "buy_price", "sell_price", "buy_volume", "sell_volume");
+    for id in ids {
+        let quick_status: &QuickStatus = &response.products[id].quick_status;
+        println!(
+            "{:<40} {:<32} {:>14.1} {:>14.1} {:>12} {:>12}",
+            id, items.name(id), quick_status.buyPrice, quick_status.sellPrice, quick_status.buyVolume, quick_status.sellVolume
+        );
+    }
+}
+
+// Trimmed view of one product for scripts, prices rounded to whole hundredths
+#[derive(Serialize)]
+pub struct SummaryEntry {
+    pub buy: f64,
+    pub sell: f64,
+    pub spread: f64,
+    // Weekly volume traded on both sides (buyMovingWeek + sellMovingWeek)
+    pub volume: u64,
+}
+
+// Keyed by product ID in sorted order, so the same snapshot always gives the same document
+#[derive(Serialize)]
+pub struct Summary {
+    pub last_updated: u64,
+    pub products: BTreeMap<String, SummaryEntry>,
+}
+
+pub fn summary(response: &BazaarResponse, watchlist: &Watchlist) -> Summary {
+    let products: BTreeMap<String, SummaryEntry> = response.products.iter()
+        .filter(|(id, _)| watchlist.contains(id))
+        .map(|(id, product)| {
+            let quick_status: &QuickStatus = &product.quick_status;
+            let buy: FixedPoint = FixedPoint::from_float(quick_status.buyPrice);
+            let sell: FixedPoint = FixedPoint::from_float(quick_status.sellPrice);
+            (id.clone(), SummaryEntry {
+                buy: buy.to_float(),
+                sell: sell.to_float(),
+                spread: buy.saturating_sub(sell).to_float(),
+                volume: quick_status.buyMovingWeek + quick_status.sellMovingWeek,
+            })
+        })
+        .collect();
+    Summary { last_updated: response.lastUpdated, products }
+}
+
+pub fn print_summary(summary: &Summary, items: &ItemCatalog) {
+    println!("{:<40} {:<32} {:>14} {:>14} {:>12} {:>14}", "product_id", "name", "buy", "sell", "spread", "week_volume");
+    for (id, entry) in &summary.products {
+        println!(
+            "{:<40} {:<32} {:>14.2} {:>14.2} {:>12.2} {:>14}",
+            id, items.name(id), entry.buy, entry.sell, entry.spread, entry.volume
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// Spike detection: prices that jumped further from their rolling mean within one snapshot than
+// the window's standard deviation makes plausible, the signature of a pump or a dump
+use crate::alerts::Alert;
+use crate::config::{AlertMetric, AnomaliesConfig};
+use crate::history::{History, HistoryPoint};
+use crate::indicators::{Series, price_series};
+use crate::items::ItemCatalog;
+use crate::timezone::format_timestamp;
+use crate::watchlist::Watchlist;
+use std::collections::BTreeSet;
+
+// A perfectly flat window would make any change infinitely unlikely, so the standard deviation
+// is taken as at least this fraction of the mean
+const MIN_RELATIVE_STDDEV: f64 = 0.001;
+
+pub struct Anomaly {
+    pub product_id: String,
+    // buyPrice or sellPrice
+    pub metric: AlertMetric,
+    // lastUpdated of the snapshot the price jumped in
+    pub timestamp: i64,
+    // The price in the snapshot before
+    pub previous: f64,
+    pub value: f64,
+    // Of the window before the jump, the jump itself left out
+    pub mean: f64,
+    pub stddev: f64,
+    // Standard deviations from the mean, negative for a drop
+    pub deviations: f64,
+}
+
+impl Anomaly {
+    pub fn to_alert(&self, config: &AnomaliesConfig) -> Alert {
+        Alert {
+            rule: format!(
+                "{} {} {:+.1} standard deviations from its {} mean of {:.1}",
+                self.product_id, self.metric.name(), self.deviations, config.window, self.mean
+            ),
+            product: self.product_id.clone(),
+            metric: self.metric,
+            value: self.value,
+            previous: Some(self.previous),
+            last_updated: self.timestamp as u64,
+        }
+    }
+}
+
+// The sample at `index` against the ones within the window before it. Empty sides of the book
+// (a price of 0) are neither judged nor averaged.
+fn check(series: &[(i64, f64)], index: usize, config: &AnomaliesConfig) -> Option<(f64, f64, f64)> {
+    let (timestamp, value) = series[index];
+    if value <= 0.0 {
+        return None;
+    }
+    let baseline: Vec<f64> = series[..index].iter()
+        .rev()
+        .take_while(|(sample, _)| timestamp - sample <= config.window.millis)
+        .map(|(_, value)| *value)
+        .filter(|value| *value > 0.0)
+        .collect();
+    if baseline.len() < config.min_samples {
+        return None;
+    }
+    let mean: f64 = baseline.iter().sum::<f64>() / baseline.len() as f64;
+    let variance: f64 = baseline.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / baseline.len() as f64;
+    let stddev: f64 = variance.sqrt().max(mean * MIN_RELATIVE_STDDEV);
+    let deviations: f64 = (value - mean) / stddev;
+    (deviations.abs() > config.threshold).then_some((mean, stddev, deviations))
+}
+
+fn anomaly(product_id: &str, metric: AlertMetric, series: &Series, index: usize, config: &AnomaliesConfig) -> Option<Anomaly> {
+    let (mean, stddev, deviations) = check(series, index, config)?;
+    Some(Anomaly {
+        product_id: product_id.to_string(),
+        metric,
+        timestamp: series[index].0,
+        previous: series[index - 1].1,
+        value: series[index].1,
+        mean,
+        stddev,
+        deviations,
+    })
+}
+
+// Every spike in the history, newest first
+pub fn scan(history: &History, watchlist: &Watchlist, config: &AnomaliesConfig) -> Vec<Anomaly> {
+    let ids: BTreeSet<&String> = history.points.iter()
+        .flat_map(|point| point.products.keys())
+        .filter(|id| watchlist.contains(id))
+        .collect();
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    for id in ids {
+        let (buy, sell): (Series, Series) = price_series(history, id);
+        for (metric, series) in [(AlertMetric::BuyPrice, &buy), (AlertMetric::SellPrice, &sell)] {
+            anomalies.extend((1..series.len()).filter_map(|index| anomaly(id, metric, series, index, config)));
+        }
+    }
+    anomalies.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.deviations.abs().total_cmp(&a.deviations.abs())));
+    anomalies
+}
+
+// Only the spikes in the newest snapshot, what a fetch alerts on
+pub fn latest(history: &History, watchlist: &Watchlist, config: &AnomaliesConfig) -> Vec<Anomaly> {
+    let Some(newest) = history.points.last() else {
+        return Vec::new();
+    };
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    for id in newest.products.keys().filter(|id| watchlist.contains(id)) {
+        let (buy, sell): (Series, Series) = price_series(history, id);
+        for (metric, series) in [(AlertMetric::BuyPrice, &buy), (AlertMetric::SellPrice, &sell)] {
+            if series.last().is_some_and(|(timestamp, _)| *timestamp == newest.timestamp) && series.len() > 1 {
+                anomalies.extend(anomaly(id, metric, series, series.len() - 1, config));
+            }
+        }
+    }
+    anomalies.sort_by(|a, b| b.deviations.abs().total_cmp(&a.deviations.abs()).then_with(|| a.product_id.cmp(&b.product_id)));
+    anomalies
+}
+
+// The window a fetch needs to judge `newest`, read before it gets stored
+pub fn with_newest(mut history: History, newest: HistoryPoint) -> History {
+    history.points.retain(|point| point.timestamp < newest.timestamp);
+    history.points.push(newest);
+    history
+}
+
+pub fn print_anomalies(anomalies: &[Anomaly], items: &ItemCatalog) {
+    println!(
+        "{:<25} {:<40} {:<32} {:<10} {:>14} {:>14} {:>14} {:>12} {:>8}",
+        "time", "product_id", "name", "metric", "previous", "price", "mean", "stddev", "sigma"
+    );
+    for anomaly in anomalies {
+        println!(
+            "{:<25} {:<40} {:<32} {:<10} {:>14.2} {:>14.2} {:>14.2} {:>12.2} {:>+8.1}",
+            format_timestamp(anomaly.timestamp),
+            anomaly.product_id,
+            items.name(&anomaly.product_id),
+            anomaly.metric.name(),
+            anomaly.previous,
+            anomaly.value,
+            anomaly.mean,
+            anomaly.stddev,
+            anomaly.deviations,
+        );
+    }
+}
@@ -0,0 +1,96 @@
+// SkyBlock auction house: paginated /v2/skyblock/auctions fetched into one snapshot per run
+use crate::config::ApiConfig;
+use crate::error::BazaarError;
+use crate::fetch::fetch_json;
+use crate::storage::{new_snapshot_path, newest_file, read_last_updated, write_snapshot};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+// Auction snapshots live next to the bazaar ones, in their own subdirectory
+pub const AUCTIONS_DIR: &str = "auctions";
+
+#[derive(Deserialize, Serialize)]
+pub struct Bid {
+    pub bidder: String,
+    pub amount: u64,
+    pub timestamp: u64,
+}
+
+// item_bytes and item_lore are left out, they're most of the payload and nothing reads them
+#[derive(Deserialize, Serialize)]
+pub struct Auction {
+    pub uuid: String,
+    pub auctioneer: String,
+    pub start: u64,
+    pub end: u64,
+    pub item_name: String,
+    pub category: String,
+    pub tier: String,
+    pub starting_bid: u64,
+    pub highest_bid_amount: u64,
+    #[serde(default)]
+    pub bin: bool,
+    pub bids: Vec<Bid>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct AuctionsPage {
+    success: bool,
+    totalPages: u32,
+    lastUpdated: u64,
+    auctions: Vec<Auction>,
+}
+
+// Every page of one fetch merged together
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize)]
+pub struct AuctionsSnapshot {
+    pub lastUpdated: u64,
+    pub auctions: Vec<Auction>,
+}
+
+async fn fetch_page(client: &reqwest::Client, api: &ApiConfig, page: u32) -> Result<AuctionsPage, BazaarError> {
+    let url: String = format!("{}?page={}", api.auctions_url, page);
+    let page: AuctionsPage = fetch_json(client, api, &url).await?;
+    if !page.success {
+        return Err(BazaarError::Api(format!("Auctions page request failed: {}", url)));
+    }
+    Ok(page)
+}
+
+// Pages are fetched one after another, if the API refreshes halfway through the later pages
+// belong to a newer lastUpdated and the snapshot can contain a few duplicates or gaps
+pub async fn fetch_auctions(client: &reqwest::Client, api: &ApiConfig) -> Result<AuctionsSnapshot, BazaarError> {
+    let first: AuctionsPage = fetch_page(client, api, 0).await?;
+    let mut snapshot: AuctionsSnapshot = AuctionsSnapshot { lastUpdated: first.lastUpdated, auctions: first.auctions };
+    for page in 1..first.totalPages {
+        let next: AuctionsPage = fetch_page(client, api, page).await?;
+        if next.lastUpdated != snapshot.lastUpdated {
+            warn!("Auctions refreshed while fetching, page {} is from {}", page, next.lastUpdated);
+        }
+        snapshot.auctions.extend(next.auctions);
+    }
+    Ok(snapshot)
+}
+
+// Returns None when the newest stored auctions snapshot has the same lastUpdated
+pub async fn get_and_dump_auctions(client: &reqwest::Client, api: &ApiConfig, raw_dir: &Path, compress: bool, force: bool) -> Result<Option<PathBuf>, BazaarError> {
+    let dir: PathBuf = raw_dir.join(AUCTIONS_DIR);
+    let snapshot: AuctionsSnapshot = fetch_auctions(client, api).await?;
+    let previous: Option<u64> = newest_file(&dir).and_then(|path| read_last_updated(&path).ok());
+    if previous == Some(snapshot.lastUpdated) && !force {
+        info!(last_updated = snapshot.lastUpdated, "Unchanged, the auctions snapshot is already stored");
+        return Ok(None);
+    }
+
+    info!(last_updated = snapshot.lastUpdated, auctions = snapshot.auctions.len(), "Fetched the auction house");
+
+    let filename: PathBuf = new_snapshot_path(&dir, snapshot.lastUpdated, compress);
+    write_snapshot(&filename, &snapshot, compress).await?;
+
+    info!(path = %filename.display(), "Auctions saved");
+
+    Ok(Some(filename))
+}
@@ -0,0 +1,458 @@
+// Backtests of trading strategies against the stored history. Orders sit in the book and fill at
+// the product's hourly fill rate while no one outbids them, like `flips` estimates, so a backtest
+// shows how a strategy would have done as an order flipper rather than with instant trades. The
+// simulator steps one snapshot at a time, paper trading feeds it live ones along with their order
+// books.
+use crate::error::BazaarError;
+use crate::flips;
+use crate::history::History;
+use crate::model::{BazaarResponse, Order, Product, QuickStatus};
+use crate::storage::{snapshot_files, snapshot_timestamp};
+use crate::strategy::{Instruction, Market, Side, Strategy};
+use crate::timezone::format_timestamp;
+use crate::watchlist::Watchlist;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+const HOUR_MILLIS: f64 = 60.0 * 60.0 * 1000.0;
+
+pub struct BacktestOptions {
+    // Coins the strategy starts with
+    pub capital: f64,
+    pub tax_rate: f64,
+    // Largest fraction of a side's hourly volume one order fills, like `flips --max-share`
+    pub max_share: f64,
+}
+
+// One order from placing it until it filled or was taken down, only the filled part
+pub struct Trade {
+    pub product_id: String,
+    pub placed: i64,
+    pub closed: i64,
+    pub side: Side,
+    pub units: u64,
+    pub price: f64,
+    // Coins paid for a buy, received after tax for a sell
+    pub coins: f64,
+    pub tax: f64,
+    // Sells only: what the units brought in over what they cost
+    pub profit: Option<f64>,
+    pub cash: f64,
+}
+
+// The account after a snapshot, held units valued at what an instant sell would bring after tax
+pub struct EquityPoint {
+    pub timestamp: i64,
+    pub cash: f64,
+    pub holdings: f64,
+    pub equity: f64,
+}
+
+pub struct BacktestReport {
+    pub capital: f64,
+    pub trades: Vec<Trade>,
+    pub equity: Vec<EquityPoint>,
+    // Largest drop from a peak of the equity, in coins and as a fraction of that peak
+    pub max_drawdown: f64,
+    pub max_drawdown_fraction: f64,
+    // Units still held at the end, by product
+    pub holdings: BTreeMap<String, u64>,
+}
+
+impl BacktestReport {
+    pub fn final_equity(&self) -> f64 {
+        self.equity.last().map_or(self.capital, |point| point.equity)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OpenOrder {
+    pub side: Side,
+    pub price: f64,
+    pub placed: i64,
+    pub remaining: u64,
+    pub filled: u64,
+    coins: f64,
+    tax: f64,
+    profit: f64,
+    // Expected fills not yet a whole unit
+    progress: f64,
+    // Units other players had listed at the same price when this went in, filled before it
+    queue: f64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Position {
+    units: u64,
+    // What the units cost, sells take their share of it off
+    cost: f64,
+}
+
+// What a strategy holds: coins, units of each product and the open orders
+#[derive(Serialize, Deserialize)]
+pub struct Account {
+    cash: f64,
+    positions: HashMap<String, Position>,
+    orders: HashMap<String, OpenOrder>,
+}
+
+impl Account {
+    pub fn cash(&self) -> f64 {
+        self.cash
+    }
+
+    pub fn units(&self, product_id: &str) -> u64 {
+        self.positions.get(product_id).map_or(0, |position| position.units)
+    }
+
+    pub fn open_order(&self, product_id: &str) -> Option<&OpenOrder> {
+        self.orders.get(product_id)
+    }
+}
+
+// Everything a simulation carries from one snapshot to the next, what paper trading saves
+#[derive(Serialize, Deserialize)]
+struct Ledger {
+    capital: f64,
+    account: Account,
+    // Newest instant sell price of every product, what held units are valued at
+    prices: HashMap<String, f64>,
+    previous: Option<i64>,
+    peak: f64,
+    max_drawdown: f64,
+    max_drawdown_fraction: f64,
+}
+
+pub struct Simulator {
+    options: BacktestOptions,
+    ledger: Ledger,
+    trades: Vec<Trade>,
+    equity: Vec<EquityPoint>,
+}
+
+impl Simulator {
+    pub fn new(options: BacktestOptions) -> Self {
+        let ledger: Ledger = Ledger {
+            capital: options.capital,
+            account: Account { cash: options.capital, positions: HashMap::new(), orders: HashMap::new() },
+            prices: HashMap::new(),
+            previous: None,
+            peak: options.capital,
+            max_drawdown: 0.0,
+            max_drawdown_fraction: 0.0,
+        };
+        Self { options, ledger, trades: Vec::new(), equity: Vec::new() }
+    }
+
+    // Picks up where a saved simulation stopped, its capital stays what it started with.
+    // A missing file starts a new one, one that doesn't parse is an error so it isn't overwritten.
+    pub fn resume(options: BacktestOptions, path: &Path) -> Result<Self, BazaarError> {
+        let data: Vec<u8> = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(options)),
+            Err(e) => return Err(e.into()),
+        };
+        let ledger: Ledger = serde_json::from_slice(&data)?;
+        Ok(Self { options, ledger, trades: Vec::new(), equity: Vec::new() })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BazaarError> {
+        fs::write(path, serde_json::to_vec_pretty(&self.ledger)?)?;
+        Ok(())
+    }
+
+    pub fn account(&self) -> &Account {
+        &self.ledger.account
+    }
+
+    pub fn capital(&self) -> f64 {
+        self.ledger.capital
+    }
+
+    // lastUpdated of the newest snapshot stepped through
+    pub fn previous(&self) -> Option<i64> {
+        self.ledger.previous
+    }
+
+    // The trades and equity points logged since the last call, so a long simulation doesn't keep them all
+    pub fn take_log(&mut self) -> (Vec<Trade>, Vec<EquityPoint>) {
+        (std::mem::take(&mut self.trades), std::mem::take(&mut self.equity))
+    }
+
+    // Fills `hours` worth of the product's open order. Without the order book it only fills while
+    // it's on top of the book. With it, the side's volume first goes to the units listed at better
+    // prices and to the queue at its own price, the order gets what's left.
+    fn fill(&mut self, product_id: &str, quick_status: &QuickStatus, book: Option<&Product>, hours: f64) {
+        let Some(order) = self.ledger.account.orders.get_mut(product_id) else {
+            return;
+        };
+        let (on_top, moving_week, orders, levels): (bool, u64, u32, Option<&[Order]>) = match order.side {
+            Side::Buy => (order.price >= quick_status.sellPrice, quick_status.sellMovingWeek, quick_status.sellOrders, book.map(|book| &book.sell_summary[..])),
+            Side::Sell => (order.price <= quick_status.buyPrice, quick_status.buyMovingWeek, quick_status.buyOrders, book.map(|book| &book.buy_summary[..])),
+        };
+        // An empty side tells nothing about the queue, snapshots stored without books have them so
+        let levels: Option<&[Order]> = levels.filter(|levels| !levels.is_empty());
+        let expected: f64 = match levels {
+            Some(levels) => {
+                let better = |level: &&Order| match order.side {
+                    Side::Buy => level.pricePerUnit.to_float() > order.price,
+                    Side::Sell => level.pricePerUnit.to_float() < order.price,
+                };
+                let ahead: f64 = levels.iter().filter(better).map(|level| level.amount as f64).sum();
+                let level: f64 = levels.iter()
+                    .filter(|level| level.pricePerUnit.to_float() == order.price)
+                    .map(|level| level.amount as f64)
+                    .sum();
+                let traded: f64 = moving_week as f64 / flips::HOURS_PER_WEEK * hours;
+                // Units pulled from the queue leave it too
+                let queue: f64 = order.queue.min(level);
+                order.queue = (queue - (traded - ahead).max(0.0)).max(0.0);
+                (traded - ahead - queue).clamp(0.0, traded * self.options.max_share)
+            }
+            None if on_top => flips::fill_rate(moving_week, orders, self.options.max_share) * hours,
+            None => return,
+        };
+        order.progress += expected;
+        let units: u64 = (order.progress.floor() as u64).min(order.remaining);
+        order.progress -= units as f64;
+        order.remaining -= units;
+        order.filled += units;
+        let value: f64 = units as f64 * order.price;
+        let position: &mut Position = self.ledger.account.positions.entry(product_id.to_string()).or_default();
+        match order.side {
+            Side::Buy => {
+                self.ledger.account.cash -= value;
+                position.units += units;
+                position.cost += value;
+                order.coins += value;
+            }
+            Side::Sell => {
+                let tax: f64 = value * self.options.tax_rate;
+                let cost: f64 = if position.units > 0 { position.cost * units as f64 / position.units as f64 } else { 0.0 };
+                self.ledger.account.cash += value - tax;
+                position.units -= units;
+                position.cost -= cost;
+                order.coins += value - tax;
+                order.tax += tax;
+                order.profit += value - tax - cost;
+            }
+        }
+    }
+
+    // Takes the product's order down, logging what of it filled
+    fn close(&mut self, product_id: &str, timestamp: i64) {
+        let Some(order) = self.ledger.account.orders.remove(product_id) else {
+            return;
+        };
+        if order.filled == 0 {
+            return;
+        }
+        self.trades.push(Trade {
+            product_id: product_id.to_string(),
+            placed: order.placed,
+            closed: timestamp,
+            side: order.side,
+            units: order.filled,
+            price: (order.coins + order.tax) / order.filled as f64,
+            coins: order.coins,
+            tax: order.tax,
+            profit: (order.side == Side::Sell).then_some(order.profit),
+            cash: self.ledger.account.cash,
+        });
+    }
+
+    fn apply(&mut self, instruction: Instruction, timestamp: i64, books: Option<&HashMap<String, Product>>) {
+        let (product_id, side, price, units): (String, Side, f64, u64) = match instruction {
+            Instruction::Place { product_id, side, price, units } => (product_id, side, price, units),
+            Instruction::Cancel { product_id } => return self.close(&product_id, timestamp),
+        };
+        self.close(&product_id, timestamp);
+        let account: &Account = &self.ledger.account;
+        let units: u64 = match side {
+            Side::Buy if price > 0.0 && price.is_finite() => units.min((account.cash / price).floor() as u64),
+            Side::Buy => 0,
+            Side::Sell => units.min(account.units(&product_id)),
+        };
+        if units == 0 {
+            return;
+        }
+        let queue: f64 = books
+            .and_then(|books| books.get(&product_id))
+            .map(|book| match side {
+                Side::Buy => &book.sell_summary,
+                Side::Sell => &book.buy_summary,
+            })
+            .map_or(0.0, |levels| {
+                levels.iter().filter(|level| level.pricePerUnit.to_float() == price).map(|level| level.amount as f64).sum()
+            });
+        let order: OpenOrder = OpenOrder { side, price, placed: timestamp, remaining: units, filled: 0, coins: 0.0, tax: 0.0, profit: 0.0, progress: 0.0, queue };
+        self.ledger.account.orders.insert(product_id, order);
+    }
+
+    fn advance(&mut self, strategy: &mut dyn Strategy, timestamp: i64, products: &HashMap<String, QuickStatus>, books: Option<&HashMap<String, Product>>) -> &[Trade] {
+        let logged: usize = self.trades.len();
+        let hours: f64 = self.ledger.previous.map_or(0.0, |previous| (timestamp - previous).max(0) as f64 / HOUR_MILLIS);
+        self.ledger.previous = Some(timestamp);
+        let open: Vec<String> = self.ledger.account.orders.keys().cloned().collect();
+        for product_id in open {
+            if let Some(quick_status) = products.get(&product_id) {
+                self.fill(&product_id, quick_status, books.and_then(|books| books.get(&product_id)), hours);
+            }
+            if self.ledger.account.orders.get(&product_id).is_some_and(|order| order.remaining == 0) {
+                self.close(&product_id, timestamp);
+            }
+        }
+        for (id, quick_status) in products {
+            self.ledger.prices.insert(id.clone(), quick_status.sellPrice);
+        }
+        let instructions: Vec<Instruction> = strategy.on_snapshot(&Market { timestamp, products }, &self.ledger.account);
+        for instruction in instructions {
+            self.apply(instruction, timestamp, books);
+        }
+        let ledger: &mut Ledger = &mut self.ledger;
+        let holdings: f64 = ledger.account.positions.iter()
+            .map(|(id, position)| position.units as f64 * ledger.prices.get(id).copied().unwrap_or(0.0) * (1.0 - self.options.tax_rate))
+            .fold(0.0, |total, value| total + value);
+        let equity: f64 = ledger.account.cash + holdings;
+        ledger.peak = ledger.peak.max(equity);
+        if ledger.peak - equity > ledger.max_drawdown {
+            ledger.max_drawdown = ledger.peak - equity;
+            ledger.max_drawdown_fraction = if ledger.peak > 0.0 { ledger.max_drawdown / ledger.peak } else { 0.0 };
+        }
+        self.equity.push(EquityPoint { timestamp, cash: ledger.account.cash, holdings, equity });
+        &self.trades[logged..]
+    }
+
+    // One snapshot: the open orders fill for the time since the previous one, then the strategy
+    // gets its say. Returns the orders that filled or were taken down on the way.
+    pub fn step(&mut self, strategy: &mut dyn Strategy, timestamp: i64, products: &HashMap<String, QuickStatus>) -> &[Trade] {
+        self.advance(strategy, timestamp, products, None)
+    }
+
+    // A step on a full response, its order books tell where the open orders queue
+    pub fn step_live(&mut self, strategy: &mut dyn Strategy, response: &BazaarResponse) -> &[Trade] {
+        let products: HashMap<String, QuickStatus> = strategy.products().into_iter()
+            .filter_map(|id| response.products.get(&id).map(|product| (id, product.quick_status.clone())))
+            .collect();
+        self.advance(strategy, response.lastUpdated as i64, &products, Some(&response.products))
+    }
+
+    // Takes every open order down, logging what filled of them
+    pub fn finish(mut self) -> BacktestReport {
+        let open: Vec<String> = self.ledger.account.orders.keys().cloned().collect();
+        for product_id in open {
+            self.close(&product_id, self.ledger.previous.unwrap_or(0));
+        }
+        BacktestReport {
+            capital: self.ledger.capital,
+            trades: self.trades,
+            equity: self.equity,
+            max_drawdown: self.ledger.max_drawdown,
+            max_drawdown_fraction: self.ledger.max_drawdown_fraction,
+            holdings: self.ledger.account.positions.into_iter()
+                .filter(|(_, position)| position.units > 0)
+                .map(|(id, position)| (id, position.units))
+                .collect(),
+        }
+    }
+}
+
+// The snapshots from `since` to `until` with only `products` read, file names give a cheap
+// first cut and the parsed lastUpdated decides
+pub fn load_history(raw_dir: &Path, products: &[String], since: i64, until: i64) -> Result<History, BazaarError> {
+    let files: Vec<PathBuf> = snapshot_files(raw_dir)?
+        .into_iter()
+        .filter(|file| snapshot_timestamp(file).is_none_or(|timestamp| (since..=until).contains(&timestamp)))
+        .collect();
+    let mut history: History = History::load(&files, &Watchlist::new(products.iter().cloned()))?;
+    history.points.retain(|point| (since..=until).contains(&point.timestamp) && !point.products.is_empty());
+    Ok(history)
+}
+
+pub fn run(strategy: &mut dyn Strategy, history: &History, options: BacktestOptions) -> BacktestReport {
+    let mut simulator: Simulator = Simulator::new(options);
+    for point in &history.points {
+        simulator.step(strategy, point.timestamp, &point.products);
+    }
+    simulator.finish()
+}
+
+pub fn print_report(strategy: &dyn Strategy, report: &BacktestReport) {
+    println!("Strategy: {}", strategy.describe());
+    let (Some(first), Some(last)) = (report.equity.first(), report.equity.last()) else {
+        println!("No snapshots in the range");
+        return;
+    };
+    println!("Period: {} -> {} ({} snapshots)", format_timestamp(first.timestamp), format_timestamp(last.timestamp), report.equity.len());
+    let profit: f64 = report.final_equity() - report.capital;
+    println!(
+        "Capital: {:.1}, final equity: {:.1}, P&L: {:+.1} ({:+.2}%)",
+        report.capital, report.final_equity(), profit, profit / report.capital * 100.0
+    );
+    let sells: Vec<f64> = report.trades.iter().filter_map(|trade| trade.profit).collect();
+    let wins: usize = sells.iter().filter(|profit| **profit > 0.0).count();
+    let win_rate: f64 = if sells.is_empty() { 0.0 } else { wins as f64 / sells.len() as f64 * 100.0 };
+    println!(
+        "Orders filled: {} ({} buys, {} sells, {:.0}% of sells profitable), tax paid: {:.1}",
+        report.trades.len(), report.trades.len() - sells.len(), sells.len(), win_rate,
+        report.trades.iter().map(|trade| trade.tax).sum::<f64>()
+    );
+    println!("Max drawdown: {:.1} ({:.2}%)", report.max_drawdown, report.max_drawdown_fraction * 100.0);
+    for (id, units) in &report.holdings {
+        println!("Still holding {} units of {} at the end, valued at the instant sell price after tax", units, id);
+    }
+}
+
+fn write_trades(wtr: &mut csv::Writer<File>, trades: &[Trade], header: bool) -> Result<(), BazaarError> {
+    if header {
+        wtr.write_record(["product_id", "placed", "closed", "closed_time", "side", "units", "price", "coins", "tax", "profit", "cash"])?;
+    }
+    for trade in trades {
+        wtr.write_record([
+            trade.product_id.clone(),
+            trade.placed.to_string(),
+            trade.closed.to_string(),
+            format_timestamp(trade.closed),
+            trade.side.name().to_string(),
+            trade.units.to_string(),
+            format!("{:.1}", trade.price),
+            format!("{:.1}", trade.coins),
+            format!("{:.1}", trade.tax),
+            trade.profit.map(|profit| format!("{:.1}", profit)).unwrap_or_default(),
+            format!("{:.1}", trade.cash),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_trades_csv(trades: &[Trade], output: &Path) -> Result<(), BazaarError> {
+    write_trades(&mut csv::Writer::from_path(output)?, trades, true)
+}
+
+// The header only goes into a new file
+pub fn append_trades_csv(trades: &[Trade], output: &Path) -> Result<(), BazaarError> {
+    let header: bool = !output.exists();
+    let file: File = File::options().append(true).create(true).open(output)?;
+    write_trades(&mut csv::Writer::from_writer(file), trades, header)
+}
+
+pub fn write_equity_csv(equity: &[EquityPoint], capital: f64, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<std::fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(["timestamp", "time", "cash", "holdings", "equity", "pnl"])?;
+    for point in equity {
+        wtr.write_record([
+            point.timestamp.to_string(),
+            format_timestamp(point.timestamp),
+            format!("{:.1}", point.cash),
+            format!("{:.1}", point.holdings),
+            format!("{:.1}", point.equity),
+            format!("{:.1}", point.equity - capital),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// OHLC candles of buy and sell prices aggregated from the snapshot history
+use crate::error::BazaarError;
+use crate::gaps::{self, Gap};
+use crate::history::History;
+use crate::model::{FixedPoint, QuickStatus};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+pub enum Resolution {
+    #[value(name = "5m")]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[default]
+    #[value(name = "1h")]
+    #[serde(rename = "1h")]
+    Hour,
+    #[value(name = "1d")]
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl Resolution {
+    pub fn millis(self) -> i64 {
+        match self {
+            Resolution::FiveMinutes => 5 * 60 * 1000,
+            Resolution::Hour => 60 * 60 * 1000,
+            Resolution::Day => 24 * 60 * 60 * 1000,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Resolution::FiveMinutes => "5m",
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Ohlc {
+    pub open: FixedPoint,
+    pub high: FixedPoint,
+    pub low: FixedPoint,
+    pub close: FixedPoint,
+}
+
+impl Ohlc {
+    fn new(price: FixedPoint) -> Self {
+        Self { open: price, high: price, low: price, close: price }
+    }
+
+    // Samples arrive in time order, so the latest one is always the close
+    fn update(&mut self, price: FixedPoint) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+    }
+}
+
+pub struct Candle {
+    pub product_id: String,
+    // Bucket start, epoch millis aligned to the resolution in UTC
+    pub timestamp: i64,
+    pub buy: Ohlc,
+    pub sell: Ohlc,
+    // Snapshots that fell into the bucket
+    pub samples: u32,
+    // buyVolume and sellVolume summed over the samples, divided by them it's the average book
+    pub buy_volume: u64,
+    pub sell_volume: u64,
+    // A recorded gap cuts into the bucket, its prices miss part of the period
+    pub incomplete: bool,
+}
+
+// Candles ordered by product, then time. Buckets `gaps` cut into are flagged incomplete.
+pub fn aggregate(history: &History, resolution: Resolution, gaps: &[Gap]) -> Vec<Candle> {
+    let width: i64 = resolution.millis();
+    let mut candles: BTreeMap<(String, i64), Candle> = BTreeMap::new();
+    for point in &history.points {
+        let bucket: i64 = point.timestamp.div_euclid(width) * width;
+        for (id, quick_status) in &point.products {
+            let QuickStatus { buyPrice, sellPrice, buyVolume, sellVolume, .. } = *quick_status;
+            let buy: FixedPoint = FixedPoint::from_float(buyPrice);
+            let sell: FixedPoint = FixedPoint::from_float(sellPrice);
+            candles.entry((id.clone(), bucket))
+                .and_modify(|candle| {
+                    candle.buy.update(buy);
+                    candle.sell.update(sell);
+                    candle.samples += 1;
+                    candle.buy_volume += buyVolume;
+                    candle.sell_volume += sellVolume;
+                })
+                .or_insert_with(|| Candle {
+                    product_id: id.clone(),
+                    timestamp: bucket,
+                    buy: Ohlc::new(buy),
+                    sell: Ohlc::new(sell),
+                    samples: 1,
+                    buy_volume: buyVolume,
+                    sell_volume: sellVolume,
+                    incomplete: gaps::overlapping(gaps, bucket, bucket + width).is_some(),
+                });
+        }
+    }
+    candles.into_values().collect()
+}
+
+fn write_records<W: Write>(wtr: &mut csv::Writer<W>, candles: &[Candle], header: bool) -> Result<(), BazaarError> {
+    if header {
+        wtr.write_record([
+            "product_id", "timestamp",
+            "buy_open", "buy_high", "buy_low", "buy_close",
+            "sell_open", "sell_high", "sell_low", "sell_close",
+            "samples", "incomplete", "buy_volume", "sell_volume",
+        ])?;
+    }
+    for candle in candles {
+        wtr.write_record([
+            &candle.product_id,
+            &candle.timestamp.to_string(),
+            &candle.buy.open.to_string(),
+            &candle.buy.high.to_string(),
+            &candle.buy.low.to_string(),
+            &candle.buy.close.to_string(),
+            &candle.sell.open.to_string(),
+            &candle.sell.high.to_string(),
+            &candle.sell.low.to_string(),
+            &candle.sell.close.to_string(),
+            &candle.samples.to_string(),
+            &candle.incomplete.to_string(),
+            &candle.buy_volume.to_string(),
+            &candle.sell_volume.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_candles_csv(candles: &[Candle], output: &Path) -> Result<(), BazaarError> {
+    write_records(&mut csv::Writer::from_path(output)?, candles, true)
+}
+
+// Adds to the end of `output`, the header only goes into a new file
+pub fn append_candles_csv(candles: &[Candle], output: &Path) -> Result<(), BazaarError> {
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let header: bool = !output.exists();
+    let file: File = File::options().append(true).create(true).open(output)?;
+    write_records(&mut csv::Writer::from_writer(file), candles, header)
+}
@@ -0,0 +1,129 @@
+// Product categories: a shipped mapping of bazaar products to the skill or family they come from,
+// with per-product overrides from [categories] in the config. Products the mapping doesn't know
+// have no category.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub const FARMING: &str = "farming";
+pub const MINING: &str = "mining";
+pub const COMBAT: &str = "combat";
+pub const FORAGING: &str = "foraging";
+pub const FISHING: &str = "fishing";
+pub const ENCHANTMENTS: &str = "enchantments";
+pub const ESSENCES: &str = "essences";
+pub const ODDITIES: &str = "oddities";
+
+pub const CATEGORIES: [&str; 8] = [FARMING, MINING, COMBAT, FORAGING, FISHING, ENCHANTMENTS, ESSENCES, ODDITIES];
+
+// Base items with their enchanted forms, roughly the bazaar's own menus
+const SHIPPED: &[(&str, &[&str])] = &[
+    (FARMING, &[
+        "WHEAT", "ENCHANTED_HAY_BLOCK", "ENCHANTED_BREAD", "TIGHTLY_TIED_HAY_BALE",
+        "SEEDS", "ENCHANTED_SEEDS", "BOX_OF_SEEDS",
+        "CARROT_ITEM", "ENCHANTED_CARROT", "ENCHANTED_GOLDEN_CARROT",
+        "POTATO_ITEM", "ENCHANTED_POTATO", "ENCHANTED_BAKED_POTATO",
+        "PUMPKIN", "ENCHANTED_PUMPKIN", "POLISHED_PUMPKIN",
+        "MELON", "ENCHANTED_MELON", "ENCHANTED_GLISTERING_MELON", "ENCHANTED_MELON_BLOCK",
+        "RED_MUSHROOM", "ENCHANTED_RED_MUSHROOM", "ENCHANTED_HUGE_MUSHROOM_2",
+        "BROWN_MUSHROOM", "ENCHANTED_BROWN_MUSHROOM", "ENCHANTED_HUGE_MUSHROOM_1",
+        "INK_SACK:3", "ENCHANTED_COCOA", "ENCHANTED_COOKIE",
+        "CACTUS", "ENCHANTED_CACTUS_GREEN", "ENCHANTED_CACTUS",
+        "SUGAR_CANE", "ENCHANTED_SUGAR", "ENCHANTED_PAPER", "ENCHANTED_SUGAR_CANE",
+        "NETHER_STALK", "ENCHANTED_NETHER_STALK", "MUTANT_NETHER_STALK",
+        "RAW_CHICKEN", "ENCHANTED_RAW_CHICKEN", "FEATHER", "ENCHANTED_FEATHER", "ENCHANTED_EGG", "SUPER_EGG",
+        "PORK", "ENCHANTED_PORK", "ENCHANTED_GRILLED_PORK",
+        "RAW_BEEF", "ENCHANTED_RAW_BEEF", "LEATHER", "ENCHANTED_LEATHER",
+        "MUTTON", "ENCHANTED_MUTTON", "ENCHANTED_COOKED_MUTTON",
+        "RABBIT", "ENCHANTED_RABBIT", "RABBIT_FOOT", "ENCHANTED_RABBIT_FOOT", "RABBIT_HIDE", "ENCHANTED_RABBIT_HIDE",
+    ]),
+    (MINING, &[
+        "COBBLESTONE", "ENCHANTED_COBBLESTONE",
+        "COAL", "ENCHANTED_COAL", "ENCHANTED_COAL_BLOCK",
+        "IRON_INGOT", "ENCHANTED_IRON", "ENCHANTED_IRON_BLOCK",
+        "GOLD_INGOT", "ENCHANTED_GOLD", "ENCHANTED_GOLD_BLOCK",
+        "DIAMOND", "ENCHANTED_DIAMOND", "ENCHANTED_DIAMOND_BLOCK",
+        "INK_SACK:4", "ENCHANTED_LAPIS_LAZULI", "ENCHANTED_LAPIS_LAZULI_BLOCK",
+        "EMERALD", "ENCHANTED_EMERALD", "ENCHANTED_EMERALD_BLOCK",
+        "REDSTONE", "ENCHANTED_REDSTONE", "ENCHANTED_REDSTONE_BLOCK",
+        "QUARTZ", "ENCHANTED_QUARTZ", "ENCHANTED_QUARTZ_BLOCK",
+        "OBSIDIAN", "ENCHANTED_OBSIDIAN",
+        "GLOWSTONE_DUST", "ENCHANTED_GLOWSTONE_DUST", "ENCHANTED_GLOWSTONE",
+        "GRAVEL", "FLINT", "ENCHANTED_FLINT",
+        "ICE", "PACKED_ICE", "ENCHANTED_ICE", "ENCHANTED_PACKED_ICE",
+        "NETHERRACK", "ENCHANTED_NETHERRACK",
+        "SAND", "ENCHANTED_SAND", "RED_SAND", "ENCHANTED_RED_SAND",
+        "ENDER_STONE", "ENCHANTED_ENDSTONE",
+        "SNOW_BALL", "SNOW_BLOCK", "ENCHANTED_SNOW_BLOCK",
+        "MITHRIL_ORE", "ENCHANTED_MITHRIL", "TITANIUM_ORE", "ENCHANTED_TITANIUM",
+        "HARD_STONE", "ENCHANTED_HARD_STONE", "STARFALL",
+    ]),
+    (COMBAT, &[
+        "ROTTEN_FLESH", "ENCHANTED_ROTTEN_FLESH",
+        "BONE", "ENCHANTED_BONE", "ENCHANTED_BONE_BLOCK",
+        "STRING", "ENCHANTED_STRING",
+        "SPIDER_EYE", "ENCHANTED_SPIDER_EYE", "ENCHANTED_FERMENTED_SPIDER_EYE",
+        "SULPHUR", "ENCHANTED_GUNPOWDER", "ENCHANTED_FIREWORK_ROCKET",
+        "ENDER_PEARL", "ENCHANTED_ENDER_PEARL", "ENCHANTED_EYE_OF_ENDER",
+        "GHAST_TEAR", "ENCHANTED_GHAST_TEAR",
+        "SLIME_BALL", "ENCHANTED_SLIME_BALL", "ENCHANTED_SLIME_BLOCK",
+        "MAGMA_CREAM", "ENCHANTED_MAGMA_CREAM",
+        "BLAZE_ROD", "ENCHANTED_BLAZE_POWDER", "ENCHANTED_BLAZE_ROD",
+        "REVENANT_FLESH", "REVENANT_VISCERA", "TARANTULA_WEB", "TARANTULA_SILK",
+        "WOLF_TOOTH", "GOLDEN_TOOTH", "NULL_SPHERE", "NULL_OVOID",
+    ]),
+    (FORAGING, &[
+        "LOG", "ENCHANTED_OAK_LOG", "LOG:1", "ENCHANTED_SPRUCE_LOG", "LOG:2", "ENCHANTED_BIRCH_LOG",
+        "LOG:3", "ENCHANTED_JUNGLE_LOG", "LOG_2", "ENCHANTED_ACACIA_LOG", "LOG_2:1", "ENCHANTED_DARK_OAK_LOG",
+    ]),
+    (FISHING, &[
+        "RAW_FISH", "ENCHANTED_RAW_FISH", "ENCHANTED_COOKED_FISH",
+        "RAW_FISH:1", "ENCHANTED_RAW_SALMON", "ENCHANTED_COOKED_SALMON",
+        "RAW_FISH:2", "ENCHANTED_CLOWNFISH", "RAW_FISH:3", "ENCHANTED_PUFFERFISH",
+        "PRISMARINE_SHARD", "ENCHANTED_PRISMARINE_SHARD", "PRISMARINE_CRYSTALS", "ENCHANTED_PRISMARINE_CRYSTALS",
+        "CLAY_BALL", "ENCHANTED_CLAY_BALL", "WATER_LILY", "ENCHANTED_WATER_LILY",
+        "INK_SACK", "ENCHANTED_INK_SACK", "SPONGE", "ENCHANTED_SPONGE", "ENCHANTED_WET_SPONGE",
+        "SHARK_FIN", "ENCHANTED_SHARK_FIN", "NURSE_SHARK_TOOTH", "BLUE_SHARK_TOOTH", "TIGER_SHARK_TOOTH", "GREAT_WHITE_SHARK_TOOTH",
+    ]),
+    (ODDITIES, &[
+        "BOOSTER_COOKIE", "HOT_POTATO_BOOK", "FUMING_POTATO_BOOK", "RECOMBOBULATOR_3000", "STOCK_OF_STONKS",
+        "JERRY_BOX_GREEN", "JERRY_BOX_BLUE", "JERRY_BOX_PURPLE", "JERRY_BOX_GOLDEN",
+        "COMPACTOR", "SUPER_COMPACTOR_3000", "SUMMONING_EYE", "EXPERIENCE_BOTTLE", "GRAND_EXP_BOTTLE", "TITANIC_EXP_BOTTLE",
+    ]),
+];
+
+// Whole families named by a prefix
+const PREFIXES: &[(&str, &str)] = &[
+    ("ENCHANTMENT_", ENCHANTMENTS),
+    ("ESSENCE_", ESSENCES),
+];
+
+// Gemstones come in five qualities, like ROUGH_RUBY_GEM or PERFECT_JADE_GEM
+const GEM_QUALITIES: [&str; 5] = ["ROUGH_", "FLAWED_", "FINE_", "FLAWLESS_", "PERFECT_"];
+
+// Set once at startup from [categories], every later lookup sees the same overrides
+static OVERRIDES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// Has no effect after the first call, or once a category has been looked up
+pub fn set_overrides(overrides: &HashMap<String, String>) {
+    let _ = OVERRIDES.set(overrides.iter().map(|(id, category)| (id.to_uppercase(), category.to_lowercase())).collect());
+}
+
+// One of the shipped categories or one an override introduces
+pub fn is_known(category: &str) -> bool {
+    CATEGORIES.contains(&category) || OVERRIDES.get_or_init(HashMap::new).values().any(|known| known == category)
+}
+
+// The override, else the shipped mapping
+pub fn category(product_id: &str) -> Option<&'static str> {
+    if let Some(category) = OVERRIDES.get_or_init(HashMap::new).get(product_id) {
+        return Some(category.as_str());
+    }
+    if let Some((category, _)) = SHIPPED.iter().find(|(_, products)| products.contains(&product_id)) {
+        return Some(category);
+    }
+    if let Some((_, category)) = PREFIXES.iter().find(|(prefix, _)| product_id.starts_with(prefix)) {
+        return Some(category);
+    }
+    let gem: bool = product_id.ends_with("_GEM") && GEM_QUALITIES.iter().any(|quality| product_id.starts_with(quality));
+    gem.then_some(MINING)
+}
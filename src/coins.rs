@@ -0,0 +1,62 @@
+// Coin amounts the way players write them, 12.3k, 1.5m or 4b, both printed and read back from the
+// command line and the config
+use serde::{Deserialize, Deserializer};
+
+const UNITS: [&str; 5] = ["", "k", "m", "b", "t"];
+
+// One decimal below 100, none above
+fn round_coins(value: f64) -> f64 {
+    if value < 100.0 { (value * 10.0).round() / 10.0 } else { value.round() }
+}
+
+// 95.5, 12.3k, 350k, 1.2m, 4b
+pub fn format_coins(value: f64) -> String {
+    let mut scaled: f64 = value.abs();
+    let mut unit: usize = 0;
+    // Rounding can carry into the next unit, 999_950 is "1m" rather than "1000k"
+    while unit + 1 < UNITS.len() && round_coins(scaled) >= 1000.0 {
+        scaled /= 1000.0;
+        unit += 1;
+    }
+    let number: String = format!("{}", round_coins(scaled));
+    let sign: &str = if value < 0.0 && number != "0" { "-" } else { "" };
+    format!("{}{}{}", sign, number, UNITS[unit])
+}
+
+// "50m", "320k", "1.5B", "12,345,678.9" or a plain number
+pub fn parse_coins(value: &str) -> Result<f64, String> {
+    let invalid = || format!("Invalid coin amount {:?}, expected a number like 1500000, 1.5m or 320k", value);
+    let cleaned: String = value.trim().chars().filter(|c| *c != ',' && *c != '_').collect::<String>().to_lowercase();
+    let (number, unit): (&str, usize) = match UNITS.iter().skip(1).position(|unit| cleaned.ends_with(unit)) {
+        Some(index) => (&cleaned[..cleaned.len() - 1], index + 1),
+        None => (&cleaned, 0),
+    };
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    let coins: f64 = number * 1000f64.powi(unit as i32);
+    if coins.is_finite() { Ok(coins) } else { Err(invalid()) }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Amount {
+    Number(f64),
+    Text(String),
+}
+
+impl Amount {
+    fn coins<E: serde::de::Error>(self) -> Result<f64, E> {
+        match self {
+            Amount::Number(number) => Ok(number),
+            Amount::Text(text) => parse_coins(&text).map_err(E::custom),
+        }
+    }
+}
+
+// For config fields, `deserialize_with = "coins::deserialize"`: a number or a string like "50m"
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Amount::deserialize(deserializer)?.coins()
+}
+
+pub fn deserialize_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    Option::<Amount>::deserialize(deserializer)?.map(Amount::coins).transpose()
+}
@@ -0,0 +1,63 @@
+// Compaction of the old history: snapshots past the full-resolution window are replaced by OHLC
+// candles appended to <raw-dir>/compacted/candles_<resolution>.csv, a few rows per product and
+// hour (or day) instead of a snapshot every minute
+use crate::candles::{self, Candle, Resolution};
+use crate::error::BazaarError;
+use crate::gaps;
+use crate::history::History;
+use crate::storage::{is_delta, rewrite_as_keyframe, snapshot_files, snapshot_timestamp};
+use crate::watchlist::Watchlist;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+
+pub const COMPACTED_DIR: &str = "compacted";
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+pub fn compacted_path(raw_dir: &Path, resolution: Resolution) -> PathBuf {
+    raw_dir.join(COMPACTED_DIR).join(format!("candles_{}.csv", resolution.label()))
+}
+
+pub struct CompactReport {
+    pub path: PathBuf,
+    pub snapshots: usize,
+    pub candles: usize,
+    // The delta right after the compacted snapshots, rewritten as a keyframe
+    pub rekeyed: bool,
+}
+
+// Only buckets lying wholly before `full_days` ago are compacted, a later run never starts a
+// second candle for a bucket an earlier one wrote. Snapshots that don't parse are quarantined
+// by the read and left out.
+#[instrument(name = "compact", skip_all)]
+pub async fn compact(raw_dir: &Path, full_days: u64, resolution: Resolution, now: i64, dry_run: bool) -> Result<CompactReport, BazaarError> {
+    let width: i64 = resolution.millis();
+    let cutoff: i64 = (now - full_days as i64 * DAY_MILLIS).div_euclid(width) * width;
+    let files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    let (old, recent): (Vec<PathBuf>, Vec<PathBuf>) = files.into_iter()
+        .filter(|file| snapshot_timestamp(file).is_some())
+        .partition(|file| snapshot_timestamp(file).is_some_and(|timestamp| timestamp < cutoff));
+    let path: PathBuf = compacted_path(raw_dir, resolution);
+    if old.is_empty() {
+        return Ok(CompactReport { path, snapshots: 0, candles: 0, rekeyed: false });
+    }
+    let history: History = tokio::task::block_in_place(|| History::load(&old, &Watchlist::default()))?;
+    let candles: Vec<Candle> = candles::aggregate(&history, resolution, &gaps::load_markers(raw_dir));
+    let rekeyed: bool = recent.first().is_some_and(|file| is_delta(file));
+    if !dry_run {
+        candles::append_candles_csv(&candles, &path)?;
+        if rekeyed {
+            rewrite_as_keyframe(&recent[0]).await?;
+        }
+        for file in old.iter().filter(|file| file.exists()) {
+            fs::remove_file(file)?;
+            // Date folders of a nested name template go once their last snapshot does
+            for dir in file.ancestors().skip(1).take_while(|dir| *dir != raw_dir) {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(CompactReport { path, snapshots: old.len(), candles: candles.len(), rekeyed })
+}
@@ -0,0 +1,122 @@
+// Which products move together: Pearson correlations between the stored price series, averaged
+// into evenly spaced buckets first. Bucket-to-bucket returns are compared by default, as price
+// levels that both trend drift "correlate" without moving together; a strongly negative pair is
+// what a hedge looks for.
+use crate::items::ItemCatalog;
+use crate::query::QueryRow;
+use std::collections::{BTreeMap, HashMap};
+
+// Pairs compared over fewer buckets than this are left blank
+pub const MIN_OVERLAP: usize = 3;
+
+pub struct Correlations {
+    pub products: Vec<String>,
+    // Row and column per product, None where the pair shares too few buckets or one never moves
+    pub matrix: Vec<Vec<Option<f64>>>,
+    // Buckets each pair was compared over, same layout
+    pub overlap: Vec<Vec<usize>>,
+}
+
+pub struct Pair<'a> {
+    pub first: &'a str,
+    pub second: &'a str,
+    pub correlation: f64,
+    pub overlap: usize,
+}
+
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    let n: f64 = pairs.len() as f64;
+    let mean_x: f64 = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y: f64 = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut sxy, mut sxx, mut syy): (f64, f64, f64) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        sxy += (x - mean_x) * (y - mean_y);
+        sxx += (x - mean_x).powi(2);
+        syy += (y - mean_y).powi(2);
+    }
+    (sxx > 0.0 && syy > 0.0).then(|| (sxy / (sxx * syy).sqrt()).clamp(-1.0, 1.0))
+}
+
+// `rows` are resampled every `step` millis. Returns are the relative change from the bucket right
+// before, a missing bucket leaves no return for the one after it.
+pub fn correlations(rows: &[QueryRow], step: i64, returns: bool) -> Correlations {
+    let mut buckets: BTreeMap<&str, BTreeMap<i64, f64>> = BTreeMap::new();
+    for row in rows {
+        buckets.entry(&row.product_id).or_default().insert(row.timestamp, row.value);
+    }
+    let series: Vec<(String, HashMap<i64, f64>)> = buckets.into_iter()
+        .map(|(id, values)| {
+            let values: HashMap<i64, f64> = if returns {
+                values.iter()
+                    .filter_map(|(timestamp, value)| {
+                        let previous: f64 = *values.get(&(timestamp - step))?;
+                        (previous != 0.0).then(|| (*timestamp, value / previous - 1.0))
+                    })
+                    .collect()
+            } else {
+                values.into_iter().collect()
+            };
+            (id.to_string(), values)
+        })
+        .collect();
+    let size: usize = series.len();
+    let mut matrix: Vec<Vec<Option<f64>>> = vec![vec![None; size]; size];
+    let mut overlap: Vec<Vec<usize>> = vec![vec![0; size]; size];
+    for i in 0..size {
+        for j in i..size {
+            let pairs: Vec<(f64, f64)> = series[i].1.iter()
+                .filter_map(|(timestamp, x)| series[j].1.get(timestamp).map(|y| (*x, *y)))
+                .collect();
+            let correlation: Option<f64> = if pairs.len() < MIN_OVERLAP { None } else { pearson(&pairs) };
+            matrix[i][j] = correlation;
+            matrix[j][i] = correlation;
+            overlap[i][j] = pairs.len();
+            overlap[j][i] = pairs.len();
+        }
+    }
+    Correlations { products: series.into_iter().map(|(id, _)| id).collect(), matrix, overlap }
+}
+
+impl Correlations {
+    // Every distinct pair with a correlation, the strongest either way first
+    pub fn pairs(&self) -> Vec<Pair<'_>> {
+        let mut pairs: Vec<Pair> = Vec::new();
+        for i in 0..self.products.len() {
+            for j in i + 1..self.products.len() {
+                if let Some(correlation) = self.matrix[i][j] {
+                    pairs.push(Pair { first: &self.products[i], second: &self.products[j], correlation, overlap: self.overlap[i][j] });
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.correlation.abs().total_cmp(&a.correlation.abs()));
+        pairs
+    }
+}
+
+fn format_correlation(correlation: Option<f64>) -> String {
+    correlation.map(|correlation| format!("{:+.2}", correlation)).unwrap_or_else(|| "-".to_string())
+}
+
+// The matrix with numbered columns to keep it narrow, then the strongest `top` pairs
+pub fn print_correlations(correlations: &Correlations, top: usize, items: &ItemCatalog) {
+    let columns: String = (1..=correlations.products.len()).map(|index| format!(" {:>6}", format!("#{}", index))).collect();
+    println!("{:>4} {:<40}{}", "#", "product_id", columns);
+    for (index, (id, row)) in correlations.products.iter().zip(&correlations.matrix).enumerate() {
+        let cells: String = row.iter().map(|correlation| format!(" {:>6}", format_correlation(*correlation))).collect();
+        println!("{:>4} {:<40}{}", index + 1, id, cells);
+    }
+    let pairs: Vec<Pair> = correlations.pairs();
+    if pairs.is_empty() {
+        println!();
+        println!("No pair shares {} buckets with movement in both, try a longer history or a shorter --step", MIN_OVERLAP);
+        return;
+    }
+    println!();
+    println!("{:<32} {:<32} {:>11} {:>8}", "first", "second", "correlation", "buckets");
+    for pair in pairs.iter().take(top) {
+        println!(
+            "{:<32} {:<32} {:>11} {:>8}",
+            items.name(pair.first), items.name(pair.second), format_correlation(Some(pair.correlation)), pair.overlap
+        );
+    }
+}
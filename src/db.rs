@@ -0,0 +1,188 @@
+// SQLite store of the quick_status history, one row per product per snapshot, and the backfill
+// loop it shares with the Postgres store in pg.rs. Snapshots are keyed by lastUpdated, so
+// importing the same data twice is a no-op.
+use crate::error::BazaarError;
+use crate::history::SummaryProduct;
+use crate::model::QuickStatus;
+#[cfg(feature = "sqlite")]
+use crate::query::SqlTable;
+use crate::storage::read_parallel;
+use crate::watchlist::Watchlist;
+#[cfg(feature = "sqlite")]
+use rusqlite::types::ValueRef;
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, params};
+use std::collections::HashMap;
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    last_updated INTEGER PRIMARY KEY,
+    source TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS quick_status (
+    last_updated INTEGER NOT NULL REFERENCES snapshots (last_updated),
+    product_id TEXT NOT NULL,
+    sell_price REAL NOT NULL,
+    sell_volume INTEGER NOT NULL,
+    sell_moving_week INTEGER NOT NULL,
+    sell_orders INTEGER NOT NULL,
+    buy_price REAL NOT NULL,
+    buy_volume INTEGER NOT NULL,
+    buy_moving_week INTEGER NOT NULL,
+    buy_orders INTEGER NOT NULL,
+    PRIMARY KEY (last_updated, product_id)
+);
+CREATE INDEX IF NOT EXISTS quick_status_product ON quick_status (product_id, last_updated);
+";
+
+// What `query` reads back, in the order of the QuickStatus fields after the timestamp
+pub const SERIES_COLUMNS: &str =
+    "last_updated, product_id, sell_price, sell_volume, sell_moving_week, sell_orders, buy_price, buy_volume, buy_moving_week, buy_orders";
+
+#[cfg(feature = "sqlite")]
+pub struct Database {
+    connection: Connection,
+}
+
+#[derive(Default)]
+pub struct BackfillReport {
+    pub imported: usize,
+    pub rows: usize,
+    // Snapshots whose lastUpdated was already in the database
+    pub skipped: usize,
+    // Files that couldn't be read, every error names its file
+    pub failed: Vec<BazaarError>,
+}
+
+#[cfg(feature = "sqlite")]
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, BazaarError> {
+        let connection: Connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    // Ok(false) when a snapshot with this lastUpdated is already stored
+    pub fn insert_snapshot(&mut self, last_updated: u64, source: &str, products: &HashMap<String, QuickStatus>) -> Result<bool, BazaarError> {
+        let transaction: rusqlite::Transaction = self.connection.transaction()?;
+        let inserted: usize = transaction.execute(
+            "INSERT OR IGNORE INTO snapshots (last_updated, source) VALUES (?1, ?2)",
+            params![last_updated as i64, source],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        {
+            let mut statement: rusqlite::Statement = transaction.prepare(
+                "INSERT INTO quick_status VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+            for (id, quick_status) in products {
+                statement.execute(params![
+                    last_updated as i64,
+                    id,
+                    quick_status.sellPrice,
+                    quick_status.sellVolume as i64,
+                    quick_status.sellMovingWeek as i64,
+                    quick_status.sellOrders,
+                    quick_status.buyPrice,
+                    quick_status.buyVolume as i64,
+                    quick_status.buyMovingWeek as i64,
+                    quick_status.buyOrders,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    // One product's rows from `since` to `until` (epoch millis, both included), oldest first
+    pub fn product_series(&self, product_id: &str, since: i64, until: i64) -> Result<Vec<(i64, QuickStatus)>, BazaarError> {
+        let mut statement: rusqlite::Statement = self.connection.prepare(&format!(
+            "SELECT {} FROM quick_status WHERE product_id = ?1 AND last_updated BETWEEN ?2 AND ?3 ORDER BY last_updated",
+            SERIES_COLUMNS
+        ))?;
+        let rows = statement.query_map(params![product_id, since, until], |row| {
+            Ok((row.get::<_, i64>(0)?, QuickStatus {
+                productId: row.get(1)?,
+                sellPrice: row.get(2)?,
+                sellVolume: row.get::<_, i64>(3)? as u64,
+                sellMovingWeek: row.get::<_, i64>(4)? as u64,
+                sellOrders: row.get(5)?,
+                buyPrice: row.get(6)?,
+                buyVolume: row.get::<_, i64>(7)? as u64,
+                buyMovingWeek: row.get::<_, i64>(8)? as u64,
+                buyOrders: row.get(9)?,
+            }))
+        })?;
+        Ok(rows.collect::<Result<Vec<(i64, QuickStatus)>, rusqlite::Error>>()?)
+    }
+
+    // Any statement `query --sql` is given, every value rendered as text. Statements without a
+    // result (an UPDATE, a CREATE INDEX) come back without columns.
+    pub fn sql(&self, sql: &str) -> Result<SqlTable, BazaarError> {
+        let mut statement: rusqlite::Statement = self.connection.prepare(sql)?;
+        let columns: Vec<String> = statement.column_names().into_iter().map(str::to_string).collect();
+        let mut rows: rusqlite::Rows = statement.query([])?;
+        let mut table: SqlTable = SqlTable { columns, rows: Vec::new() };
+        while let Some(row) = rows.next()? {
+            let values: Vec<String> = (0..table.columns.len())
+                .map(|index| Ok(match row.get_ref(index)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(value) => value.to_string(),
+                    ValueRef::Real(value) => value.to_string(),
+                    ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                    ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+                }))
+                .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+            table.rows.push(values);
+        }
+        Ok(table)
+    }
+
+    pub fn backfill(&mut self, files: &[PathBuf]) -> Result<BackfillReport, BazaarError> {
+        backfill(files, |last_updated, source, products| self.insert_snapshot(last_updated, source, products))
+    }
+}
+
+// Feeds every snapshot to `insert`, which returns Ok(false) for one already stored. Unreadable
+// files are reported and skipped. A broken file also breaks the delta chain behind it, so deltas
+// up to the next keyframe fail as well. Files are parsed in parallel, the inserts stay sequential.
+pub fn backfill<F>(files: &[PathBuf], mut insert: F) -> Result<BackfillReport, BazaarError>
+where
+    F: FnMut(u64, &str, &HashMap<String, QuickStatus>) -> Result<bool, BazaarError>,
+{
+    let mut report: BackfillReport = BackfillReport::default();
+    read_parallel(
+        files,
+        &Watchlist::default(),
+        |_, last_updated, products: &HashMap<String, SummaryProduct>| {
+            let products: HashMap<String, QuickStatus> = products.iter()
+                .map(|(id, product)| (id.clone(), product.quick_status.clone()))
+                .collect();
+            Ok((last_updated, products))
+        },
+        |file, snapshot| {
+            let (last_updated, products) = match snapshot {
+                Ok(snapshot) => snapshot,
+                Err(e) if e.is_file_local() => {
+                    report.failed.push(e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            let source: String = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            if insert(last_updated, &source, &products)? {
+                report.imported += 1;
+                report.rows += products.len();
+            } else {
+                report.skipped += 1;
+            }
+            Ok(())
+        },
+    )?;
+    Ok(report)
+}
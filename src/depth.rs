@@ -0,0 +1,72 @@
+// Order book depth: what instantly buying or selling a quantity costs once it eats through price levels
+use crate::items::ItemCatalog;
+use crate::model::{ArithmeticError, FixedPoint4, Order, Product};
+use tracing::warn;
+
+// Only the top 30 levels of each side are in the API response, so large fills can come up short
+pub struct Fill {
+    pub filled: u64,
+    pub total: FixedPoint4,
+    // Volume-weighted average price, None when nothing could be filled
+    pub average: Option<FixedPoint4>,
+    // Price levels touched, the last one possibly only partly
+    pub levels: usize,
+    pub worst_price: Option<FixedPoint4>,
+}
+
+// Walks `levels` best price first until `quantity` units are filled or the book runs out
+pub fn walk(levels: &[Order], quantity: u64) -> Result<Fill, ArithmeticError> {
+    let mut fill: Fill = Fill { filled: 0, total: FixedPoint4::from_int(0), average: None, levels: 0, worst_price: None };
+    for level in levels {
+        if fill.filled >= quantity {
+            break;
+        }
+        let units: u64 = level.amount.min(quantity - fill.filled);
+        let cost: FixedPoint4 = level.pricePerUnit
+            .checked_mul(FixedPoint4::from_float(units as f64))
+            .ok_or(ArithmeticError("order book level cost"))?;
+        fill.total = fill.total.checked_add(cost).ok_or(ArithmeticError("order book total"))?;
+        fill.filled += units;
+        fill.levels += 1;
+        fill.worst_price = Some(level.pricePerUnit);
+    }
+    if fill.filled > 0 {
+        let average: FixedPoint4 = fill.total
+            .checked_div(FixedPoint4::from_float(fill.filled as f64))
+            .ok_or(ArithmeticError("volume-weighted price"))?;
+        fill.average = Some(average);
+    }
+    Ok(fill)
+}
+
+fn print_fill(label: &str, fill: &Fill, quantity: u64, best: Option<FixedPoint4>) {
+    let format = |value: Option<FixedPoint4>| value.map(|value| value.to_string()).unwrap_or_else(|| "-".to_string());
+    // How much worse the average is than the top of the book, in percent
+    let slippage: String = match (fill.average, best) {
+        (Some(average), Some(best)) if best.raw() > 0 => format!("{:.2}", (average.to_float() - best.to_float()).abs() / best.to_float() * 100.0),
+        _ => "-".to_string(),
+    };
+    println!(
+        "{:<12} {:>12} {:>18} {:>14} {:>14} {:>14} {:>7} {:>10}",
+        label, fill.filled, fill.total.to_string(), format(fill.average), format(best), format(fill.worst_price), fill.levels, slippage
+    );
+    if fill.filled < quantity {
+        warn!("The listed {} levels only hold {} of {} units", label, fill.filled, quantity);
+    }
+}
+
+// buy_summary holds the sell offers an insta-buy fills, sell_summary the buy orders an insta-sell fills
+pub fn print_depth(product: &Product, quantity: u64, tax_rate: f64, items: &ItemCatalog) -> Result<(), ArithmeticError> {
+    let buy: Fill = walk(&product.buy_summary, quantity)?;
+    let sell: Fill = walk(&product.sell_summary, quantity)?;
+    println!("{} ({}), {} units", product.product_id, items.name(&product.product_id), quantity);
+    println!(
+        "{:<12} {:>12} {:>18} {:>14} {:>14} {:>14} {:>7} {:>10}",
+        "side", "filled", "total", "avg_price", "best_price", "worst_price", "levels", "slippage%"
+    );
+    print_fill("insta-buy", &buy, quantity, product.buy_summary.first().map(|order| order.pricePerUnit));
+    print_fill("insta-sell", &sell, quantity, product.sell_summary.first().map(|order| order.pricePerUnit));
+    let tax: FixedPoint4 = sell.total.tax(tax_rate);
+    println!("Insta-sell proceeds after {:.3}% tax: {}", tax_rate * 100.0, sell.total.saturating_sub(tax));
+    Ok(())
+}
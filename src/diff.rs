@@ -0,0 +1,126 @@
+// Per-product changes between two snapshots, with thresholds so small moves stay out of the way
+use crate::error::BazaarError;
+use crate::history::read_quick_status;
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+use std::path::Path;
+
+// A change is shown once any one of these is reached
+pub struct DiffThresholds {
+    // Percent change of buyPrice or sellPrice
+    pub price_percent: f64,
+    // Percent change of buyVolume or sellVolume
+    pub volume_percent: f64,
+    // Absolute change of buyOrders or sellOrders
+    pub orders: i64,
+}
+
+pub struct ProductDiff {
+    pub product_id: String,
+    pub before: QuickStatus,
+    pub after: QuickStatus,
+}
+
+fn percent_change(before: f64, after: f64) -> Option<f64> {
+    (before != 0.0).then(|| (after - before) / before * 100.0)
+}
+
+impl ProductDiff {
+    pub fn buy_change(&self) -> Option<f64> {
+        percent_change(self.before.buyPrice, self.after.buyPrice)
+    }
+
+    pub fn sell_change(&self) -> Option<f64> {
+        percent_change(self.before.sellPrice, self.after.sellPrice)
+    }
+
+    // Largest absolute price change, what the report is sorted by
+    fn price_move(&self) -> f64 {
+        [self.buy_change(), self.sell_change()].into_iter().flatten().map(f64::abs).fold(0.0, f64::max)
+    }
+
+    // A side appearing or emptying (0 on one side) always counts as reaching the threshold
+    fn exceeds(&self, thresholds: &DiffThresholds) -> bool {
+        let percent = |before: f64, after: f64, limit: f64| match percent_change(before, after) {
+            Some(change) => change.abs() >= limit,
+            None => after != 0.0,
+        };
+        percent(self.before.buyPrice, self.after.buyPrice, thresholds.price_percent)
+            || percent(self.before.sellPrice, self.after.sellPrice, thresholds.price_percent)
+            || percent(self.before.buyVolume as f64, self.after.buyVolume as f64, thresholds.volume_percent)
+            || percent(self.before.sellVolume as f64, self.after.sellVolume as f64, thresholds.volume_percent)
+            || (self.after.buyOrders as i64 - self.before.buyOrders as i64).abs() >= thresholds.orders
+            || (self.after.sellOrders as i64 - self.before.sellOrders as i64).abs() >= thresholds.orders
+    }
+}
+
+pub struct SnapshotDiff {
+    // Sorted by the largest price move first
+    pub changed: Vec<ProductDiff>,
+    // Products below every threshold
+    pub unchanged: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+pub fn diff(a: &Path, b: &Path, watchlist: &Watchlist, thresholds: &DiffThresholds) -> Result<SnapshotDiff, BazaarError> {
+    let before: HashMap<String, QuickStatus> = read_quick_status(a)?;
+    let mut after: HashMap<String, QuickStatus> = read_quick_status(b)?;
+    let mut result: SnapshotDiff = SnapshotDiff { changed: Vec::new(), unchanged: 0, added: Vec::new(), removed: Vec::new() };
+    for (id, before) in before.into_iter().filter(|(id, _)| watchlist.contains(id)) {
+        let Some(after) = after.remove(&id) else {
+            result.removed.push(id);
+            continue;
+        };
+        let product: ProductDiff = ProductDiff { product_id: id, before, after };
+        if product.exceeds(thresholds) {
+            result.changed.push(product);
+        } else {
+            result.unchanged += 1;
+        }
+    }
+    result.added = after.into_keys().filter(|id| watchlist.contains(id)).collect();
+    result.added.sort();
+    result.removed.sort();
+    result.changed.sort_by(|a, b| b.price_move().total_cmp(&a.price_move()).then_with(|| a.product_id.cmp(&b.product_id)));
+    Ok(result)
+}
+
+fn format_change(value: Option<f64>) -> String {
+    value.map(|value| format!("{:+.2}", value)).unwrap_or_else(|| "-".to_string())
+}
+
+pub fn print_diff(diff: &SnapshotDiff, items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>14} {:>9} {:>14} {:>9} {:>10} {:>10} {:>8} {:>8}",
+        "product_id", "name", "buy_price", "buy%", "sell_price", "sell%", "buy_vol%", "sell_vol%", "buy_ord", "sell_ord"
+    );
+    for product in &diff.changed {
+        let (before, after) = (&product.before, &product.after);
+        println!(
+            "{:<40} {:<32} {:>14.1} {:>9} {:>14.1} {:>9} {:>10} {:>10} {:>+8} {:>+8}",
+            product.product_id,
+            items.name(&product.product_id),
+            after.buyPrice,
+            format_change(product.buy_change()),
+            after.sellPrice,
+            format_change(product.sell_change()),
+            format_change(percent_change(before.buyVolume as f64, after.buyVolume as f64)),
+            format_change(percent_change(before.sellVolume as f64, after.sellVolume as f64)),
+            after.buyOrders as i64 - before.buyOrders as i64,
+            after.sellOrders as i64 - before.sellOrders as i64
+        );
+    }
+    for id in &diff.added {
+        println!("Added: {} ({})", id, items.name(id));
+    }
+    for id in &diff.removed {
+        println!("Removed: {} ({})", id, items.name(id));
+    }
+    println!(
+        "{} changed, {} below the thresholds, {} added, {} removed",
+        diff.changed.len(), diff.unchanged, diff.added.len(), diff.removed.len()
+    );
+}
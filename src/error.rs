@@ -0,0 +1,109 @@
+// The crate's error type. Variants are split by what a caller can do about them: network errors
+// are worth retrying, a broken snapshot can be skipped, the rest needs someone to look at it.
+use crate::model::ArithmeticError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BazaarError {
+    // Connection failures, timeouts and bodies cut off mid-transfer
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+    #[error("HTTP {status} from {url}")]
+    Http { status: reqwest::StatusCode, url: String },
+    // A gzipped body that doesn't decompress, most likely cut off on the way
+    #[error("Broken gzip body from {url}: {source}")]
+    Decompress { url: String, source: std::io::Error },
+    #[error("{source} (gave up after {attempts} attempts)")]
+    RetriesExhausted { attempts: u32, source: Box<BazaarError> },
+    // The API answered, but with success: false
+    #[error("{0}")]
+    Api(String),
+    #[error("Malformed response from {url}: {source}")]
+    MalformedResponse { url: String, source: serde_json::Error },
+    // JSON that doesn't have the expected shape
+    #[error(transparent)]
+    Deserialize(#[from] serde_json::Error),
+    #[error(transparent)]
+    Filesystem(#[from] std::io::Error),
+    // One snapshot couldn't be read, the others in the raw directory may still be fine
+    #[error("Failed to read {path}: {source}")]
+    Snapshot { path: PathBuf, source: Box<BazaarError> },
+    // The raw directory as a whole: no snapshots, a delta without its keyframe
+    #[error("{0}")]
+    Storage(String),
+    #[error("{0}")]
+    Config(String),
+    // Another process holds the raw directory's lock
+    #[error("{0}")]
+    Locked(String),
+    // Arguments that don't fit the data, like a product no snapshot contains
+    #[error("{0}")]
+    Input(String),
+    #[error(transparent)]
+    Arithmetic(#[from] ArithmeticError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Database(#[from] rusqlite::Error),
+    #[cfg(feature = "postgres")]
+    #[error("{}", postgres_message(.0))]
+    Postgres(#[from] postgres::Error),
+    #[cfg(feature = "xlsx")]
+    #[error(transparent)]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[cfg(feature = "plot")]
+    #[error("Failed to draw the chart: {0}")]
+    Plot(String),
+    #[error("ClickHouse rejected the request: {0}")]
+    ClickHouse(String),
+    #[cfg(feature = "desktop")]
+    #[error("Desktop notification failed: {0}")]
+    Desktop(String),
+}
+
+impl BazaarError {
+    // The same request might succeed later: the connection failed, or the API is busy or rate limiting
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BazaarError::Network(_) | BazaarError::Decompress { .. } => true,
+            BazaarError::Http { status, .. } => *status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    // Only one file is affected, reading the rest of the history can carry on without it
+    pub fn is_file_local(&self) -> bool {
+        matches!(self, BazaarError::Snapshot { .. })
+    }
+
+    // The file itself is broken: it isn't valid JSON or zstd, or is cut off. Not a missing
+    // keyframe, a file that couldn't be opened or any other I/O failure.
+    pub fn is_corrupt(&self) -> bool {
+        match self {
+            BazaarError::Snapshot { source, .. } => match source.as_ref() {
+                BazaarError::Deserialize(e) => !e.is_io(),
+                BazaarError::Filesystem(e) => matches!(e.kind(), std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// postgres::Error only says "db error" or "error connecting to server", the details are in its source
+#[cfg(feature = "postgres")]
+fn postgres_message(error: &postgres::Error) -> String {
+    match std::error::Error::source(error) {
+        Some(source) if error.as_db_error().is_some() => source.to_string(),
+        Some(source) => format!("{}: {}", error, source),
+        None => error.to_string(),
+    }
+}
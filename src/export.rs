@@ -0,0 +1,471 @@
+// CSV summaries of the raw snapshots, other formats live in submodules
+pub mod clickhouse;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod influx;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+use crate::categories;
+use crate::config::AlertMetric;
+use crate::error::BazaarError;
+use crate::gaps::{self, Gap, format_duration};
+use crate::history::{History, Window};
+use crate::indicators::{self, Indicators};
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, FixedPoint, QuickStatus};
+use crate::query::{self, Aggregation, ProductSeries};
+use crate::storage::{read_newest, snapshot_files};
+use crate::timezone::format_timestamp;
+use crate::watchlist::Watchlist;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use csv::{QuoteStyle, WriterBuilder};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{info, instrument, warn};
+
+// Columns `csv` can write, in their default order
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CsvColumn {
+    LastUpdated,
+    ProductId,
+    SellPrice,
+    SellVolume,
+    SellMovingWeek,
+    SellOrders,
+    BuyPrice,
+    BuyVolume,
+    BuyMovingWeek,
+    BuyOrders,
+    Name,
+    Tier,
+    NpcSellPrice,
+    Category,
+}
+
+impl CsvColumn {
+    pub const ALL: [CsvColumn; 14] = [
+        CsvColumn::LastUpdated,
+        CsvColumn::ProductId,
+        CsvColumn::SellPrice,
+        CsvColumn::SellVolume,
+        CsvColumn::SellMovingWeek,
+        CsvColumn::SellOrders,
+        CsvColumn::BuyPrice,
+        CsvColumn::BuyVolume,
+        CsvColumn::BuyMovingWeek,
+        CsvColumn::BuyOrders,
+        CsvColumn::Name,
+        CsvColumn::Tier,
+        CsvColumn::NpcSellPrice,
+        CsvColumn::Category,
+    ];
+
+    fn header(self) -> &'static str {
+        match self {
+            CsvColumn::LastUpdated => "last_updated",
+            CsvColumn::ProductId => "product_id",
+            CsvColumn::SellPrice => "sell_price",
+            CsvColumn::SellVolume => "sell_volume",
+            CsvColumn::SellMovingWeek => "sell_moving_week",
+            CsvColumn::SellOrders => "sell_orders",
+            CsvColumn::BuyPrice => "buy_price",
+            CsvColumn::BuyVolume => "buy_volume",
+            CsvColumn::BuyMovingWeek => "buy_moving_week",
+            CsvColumn::BuyOrders => "buy_orders",
+            CsvColumn::Name => "name",
+            CsvColumn::Tier => "tier",
+            CsvColumn::NpcSellPrice => "npc_sell_price",
+            CsvColumn::Category => "category",
+        }
+    }
+
+    // The quick status numbers, what the long format writes one row per
+    fn is_metric(self) -> bool {
+        matches!(
+            self,
+            CsvColumn::SellPrice | CsvColumn::SellVolume | CsvColumn::SellMovingWeek | CsvColumn::SellOrders
+                | CsvColumn::BuyPrice | CsvColumn::BuyVolume | CsvColumn::BuyMovingWeek | CsvColumn::BuyOrders
+        )
+    }
+
+    fn value(self, last_updated: i64, product_id: &str, quick_status: &QuickStatus, items: &ItemCatalog) -> String {
+        match self {
+            CsvColumn::LastUpdated => last_updated.to_string(),
+            CsvColumn::ProductId => product_id.to_string(),
+            CsvColumn::SellPrice => quick_status.sellPrice.to_string(),
+            CsvColumn::SellVolume => quick_status.sellVolume.to_string(),
+            CsvColumn::SellMovingWeek => quick_status.sellMovingWeek.to_string(),
+            CsvColumn::SellOrders => quick_status.sellOrders.to_string(),
+            CsvColumn::BuyPrice => quick_status.buyPrice.to_string(),
+            CsvColumn::BuyVolume => quick_status.buyVolume.to_string(),
+            CsvColumn::BuyMovingWeek => quick_status.buyMovingWeek.to_string(),
+            CsvColumn::BuyOrders => quick_status.buyOrders.to_string(),
+            CsvColumn::Name => items.name(product_id).to_string(),
+            CsvColumn::Tier => items.tier(product_id).unwrap_or("").to_string(),
+            CsvColumn::NpcSellPrice => items.npc_sell_price(product_id).map(|price| price.to_string()).unwrap_or_default(),
+            CsvColumn::Category => categories::category(product_id).unwrap_or("").to_string(),
+        }
+    }
+}
+
+// A single ASCII field separator, "\t" or "tab" for a tab
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Delimiter(pub u8);
+
+impl Default for Delimiter {
+    fn default() -> Self {
+        Self(b',')
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value {
+            "\\t" | "\t" | "tab" => Ok(Self(b'\t')),
+            _ => match value.as_bytes() {
+                [byte] if byte.is_ascii() && *byte != b'"' && *byte != b'\n' && *byte != b'\r' => Ok(Self(*byte)),
+                _ => Err(format!("Invalid delimiter {:?}, expected a single ASCII character other than a quote or newline", value)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Delimiter {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+// When fields are wrapped in double quotes, embedded quotes are always doubled
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CsvQuote {
+    // Only fields containing the delimiter, a quote or a newline
+    #[default]
+    Necessary,
+    Always,
+    // Every field that isn't a number
+    NonNumeric,
+    // Fields are written as they are, even if that breaks the row
+    Never,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct CsvDialect {
+    pub delimiter: Delimiter,
+    pub quote: CsvQuote,
+}
+
+impl CsvDialect {
+    fn writer(self, output: &Path) -> Result<csv::Writer<fs::File>, BazaarError> {
+        Ok(self.builder().from_path(output)?)
+    }
+
+    fn builder(self) -> WriterBuilder {
+        let quote: QuoteStyle = match self.quote {
+            CsvQuote::Necessary => QuoteStyle::Necessary,
+            CsvQuote::Always => QuoteStyle::Always,
+            CsvQuote::NonNumeric => QuoteStyle::NonNumeric,
+            CsvQuote::Never => QuoteStyle::Never,
+        };
+        let mut builder: WriterBuilder = WriterBuilder::new();
+        builder.delimiter(self.delimiter.0).quote_style(quote);
+        builder
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum CsvSort {
+    #[default]
+    ProductId,
+    Name,
+    SellPrice,
+    BuyPrice,
+    SellVolume,
+    BuyVolume,
+    SellMovingWeek,
+    BuyMovingWeek,
+    // buyPrice - sellPrice
+    Spread,
+    // Spread relative to sellPrice in percent, products without a sell price count as the lowest
+    Margin,
+}
+
+// Rows are sorted within each snapshot, ties fall back to product_id so the output is deterministic
+#[derive(Clone, Copy, Default)]
+pub struct CsvOrder {
+    pub by: CsvSort,
+    pub descending: bool,
+}
+
+impl CsvOrder {
+    fn number(self, quick_status: &QuickStatus) -> f64 {
+        match self.by {
+            CsvSort::SellPrice => quick_status.sellPrice,
+            CsvSort::BuyPrice => quick_status.buyPrice,
+            CsvSort::SellVolume => quick_status.sellVolume as f64,
+            CsvSort::BuyVolume => quick_status.buyVolume as f64,
+            CsvSort::SellMovingWeek => quick_status.sellMovingWeek as f64,
+            CsvSort::BuyMovingWeek => quick_status.buyMovingWeek as f64,
+            CsvSort::Spread => quick_status.buyPrice - quick_status.sellPrice,
+            CsvSort::Margin if quick_status.sellPrice > 0.0 => (quick_status.buyPrice - quick_status.sellPrice) / quick_status.sellPrice * 100.0,
+            CsvSort::Margin | CsvSort::ProductId | CsvSort::Name => f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn sort(self, rows: &mut [(&str, &QuickStatus)], items: &ItemCatalog) {
+        rows.sort_by(|(a_id, a), (b_id, b)| {
+            let ordering: Ordering = match self.by {
+                CsvSort::ProductId => a_id.cmp(b_id),
+                CsvSort::Name => items.name(a_id).cmp(&items.name(b_id)),
+                _ => self.number(a).total_cmp(&self.number(b)),
+            };
+            let ordering: Ordering = if self.descending { ordering.reverse() } else { ordering };
+            ordering.then_with(|| a_id.cmp(b_id))
+        });
+    }
+}
+
+// How `csv` lays out its rows
+pub struct CsvOptions {
+    pub columns: Vec<CsvColumn>,
+    pub order: CsvOrder,
+    pub dialect: CsvDialect,
+}
+
+fn format_average(value: Option<f64>) -> String {
+    value.map(|value| FixedPoint::from_float(value).to_string()).unwrap_or_default()
+}
+
+fn record(columns: &[CsvColumn], last_updated: i64, product_id: &str, quick_status: &QuickStatus, items: &ItemCatalog) -> Vec<String> {
+    columns.iter().map(|column| column.value(last_updated, product_id, quick_status, items)).collect()
+}
+
+// Every window adds SMA and EMA columns for both prices, computed over the whole history
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn generate_csv(raw_dir: &Path, output: &Path, watchlist: &Watchlist, items: &ItemCatalog, options: &CsvOptions, windows: &[Window]) -> Result<(), BazaarError> {
+    let response: BazaarResponse = read_newest(raw_dir, watchlist)?;
+    watchlist.warn_missing(&response);
+    let history: Option<History> = if windows.is_empty() {
+        None
+    } else {
+        Some(History::load(&snapshot_files(raw_dir)?, watchlist)?)
+    };
+
+    let markers: Vec<Gap> = gaps::load_markers(raw_dir);
+    for window in windows {
+        let end: i64 = response.lastUpdated as i64;
+        if let Some(gap) = gaps::overlapping(&markers, end - window.millis, end) {
+            warn!("The {} window includes a gap of {} from {}, its averages miss that time", window, format_duration(gap.millis()), format_timestamp(gap.start));
+        }
+    }
+
+    let mut header: Vec<String> = options.columns.iter().map(|column| column.header().to_string()).collect();
+    for window in windows {
+        for column in ["buy_sma", "buy_ema", "sell_sma", "sell_ema"] {
+            header.push(format!("{}_{}", column, window));
+        }
+    }
+
+    let mut wtr: csv::Writer<fs::File> = options.dialect.writer(output)?;
+    wtr.write_record(&header)?;
+    let mut rows: Vec<(&str, &QuickStatus)> = response.products.iter()
+        .filter(|(id, _)| watchlist.contains(id))
+        .map(|(id, product)| (id.as_str(), &product.quick_status))
+        .collect();
+    options.order.sort(&mut rows, items);
+    for (id, quick_status) in rows {
+        let mut record: Vec<String> = record(&options.columns, response.lastUpdated as i64, id, quick_status, items);
+        if let Some(history) = &history {
+            for indicators in indicators::compute(history, id, windows, &[]) {
+                let Indicators { buy, sell, .. } = indicators;
+                record.extend([format_average(buy.sma), format_average(buy.ema), format_average(sell.sma), format_average(sell.ema)]);
+            }
+        }
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    info!("CSV summary generated");
+
+    Ok(())
+}
+
+// Long format: one row per product per snapshot, oldest snapshot first
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn generate_history_csv(raw_dir: &Path, output: &Path, watchlist: &Watchlist, items: &ItemCatalog, options: &CsvOptions, since: Option<i64>) -> Result<(), BazaarError> {
+    let mut history: History = History::load(&snapshot_files(raw_dir)?, watchlist)?;
+    if let Some(since) = since {
+        history.points.retain(|point| point.timestamp >= since);
+    }
+
+    let mut wtr: csv::Writer<fs::File> = options.dialect.writer(output)?;
+    wtr.write_record(options.columns.iter().map(|column| column.header()))?;
+    let mut rows: usize = 0;
+    for point in &history.points {
+        let mut products: Vec<(&str, &QuickStatus)> = point.products.iter().map(|(id, quick_status)| (id.as_str(), quick_status)).collect();
+        options.order.sort(&mut products, items);
+        for (id, quick_status) in products {
+            wtr.write_record(record(&options.columns, point.timestamp, id, quick_status, items))?;
+            rows += 1;
+        }
+    }
+    wtr.flush()?;
+    info!(snapshots = history.points.len(), rows, "History CSV generated");
+
+    Ok(())
+}
+
+// Time series of a single product across every snapshot, oldest first, ready to graph
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn generate_product_history_csv(raw_dir: &Path, output: &Path, product_id: &str, dialect: CsvDialect, since: Option<i64>) -> Result<(), BazaarError> {
+    let history: History = History::load(&snapshot_files(raw_dir)?, &Watchlist::new([product_id.to_string()]))?;
+    let series: Vec<(i64, &QuickStatus)> = history.product_series(product_id).into_iter()
+        .filter(|(timestamp, _)| since.is_none_or(|since| *timestamp >= since))
+        .collect();
+    if series.is_empty() {
+        return Err(BazaarError::Input(format!("No snapshot contains {}", product_id)));
+    }
+
+    let mut wtr: csv::Writer<fs::File> = dialect.writer(output)?;
+    wtr.write_record(["timestamp", "buy_price", "sell_price", "buy_volume", "sell_volume", "buy_moving_week", "sell_moving_week", "buy_orders", "sell_orders"])?;
+    for (timestamp, quick_status) in &series {
+        wtr.write_record([
+            timestamp.to_string(),
+            quick_status.buyPrice.to_string(),
+            quick_status.sellPrice.to_string(),
+            quick_status.buyVolume.to_string(),
+            quick_status.sellVolume.to_string(),
+            quick_status.buyMovingWeek.to_string(),
+            quick_status.sellMovingWeek.to_string(),
+            quick_status.buyOrders.to_string(),
+            quick_status.sellOrders.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    info!(product_id, snapshots = series.len(), "Product history CSV generated");
+
+    Ok(())
+}
+
+// Wide format for spreadsheets and charting tools: a row per snapshot, or per bucket when resampled,
+// and a column per product holding one metric. Cells stay empty where a snapshot lacks the product.
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn generate_pivot_csv(
+    raw_dir: &Path, output: &Path, watchlist: &Watchlist, metric: AlertMetric, resample: Option<(&Window, Aggregation)>,
+    dialect: CsvDialect, since: Option<i64>,
+) -> Result<(), BazaarError> {
+    let mut history: History = History::load(&snapshot_files(raw_dir)?, watchlist)?;
+    if let Some(since) = since {
+        history.points.retain(|point| point.timestamp >= since);
+    }
+    let mut series: ProductSeries = ProductSeries::new();
+    for point in &history.points {
+        for (id, quick_status) in &point.products {
+            series.entry(id.clone()).or_default().push((point.timestamp, quick_status.clone()));
+        }
+    }
+    if series.is_empty() {
+        return Err(BazaarError::Input("No stored snapshot holds a watchlisted product".to_string()));
+    }
+    let mut table: BTreeMap<i64, HashMap<String, f64>> = BTreeMap::new();
+    let (window, aggregation): (Option<&Window>, Aggregation) = match resample {
+        Some((window, aggregation)) => (Some(window), aggregation),
+        None => (None, Aggregation::Mean),
+    };
+    for row in query::evaluate(&series, metric, window, aggregation) {
+        table.entry(row.timestamp).or_default().insert(row.product_id, row.value);
+    }
+
+    let products: Vec<&String> = series.keys().collect();
+    let mut wtr: csv::Writer<fs::File> = dialect.writer(output)?;
+    wtr.write_record(["timestamp", "time"].into_iter().chain(products.iter().map(|id| id.as_str())))?;
+    for (timestamp, values) in &table {
+        let mut record: Vec<String> = vec![timestamp.to_string(), format_timestamp(*timestamp)];
+        record.extend(products.iter().map(|id| values.get(*id).map(|value| value.to_string()).unwrap_or_default()));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    info!(metric = metric.name(), rows = table.len(), products = products.len(), "Pivot CSV generated");
+
+    Ok(())
+}
+
+// Where `csv --append-long` and the fetches with csv.append_long write, and what
+pub struct LongCsvOptions {
+    pub dir: PathBuf,
+    // Only the quick status numbers among them become rows
+    pub columns: Vec<CsvColumn>,
+    pub dialect: CsvDialect,
+    pub watchlist: Watchlist,
+}
+
+// One file per UTC day of snapshots, like the jsonl storage
+pub fn daily_long_path(dir: &Path, last_updated: i64) -> PathBuf {
+    let time: DateTime<Utc> = DateTime::from_timestamp_millis(last_updated).unwrap_or_else(Utc::now);
+    dir.join(format!("bazaar_long_{}.csv", time.format("%Y%m%d")))
+}
+
+// Timestamp of the last row in a long file, read from its tail so a day's worth of rows isn't parsed
+fn last_long_timestamp(path: &Path, delimiter: u8) -> Option<i64> {
+    let mut file: fs::File = fs::File::open(path).ok()?;
+    let length: u64 = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(4096))).ok()?;
+    let mut tail: String = String::new();
+    file.read_to_string(&mut tail).ok()?;
+    let line: &str = tail.lines().rev().find(|line| !line.trim().is_empty())?;
+    line.split(delimiter as char).next()?.trim_matches('"').parse().ok()
+}
+
+// Tidy data for R and pandas: a timestamp,product_id,metric,value row per value of every watchlisted
+// product, appended to the snapshot's daily file. A snapshot no newer than the file's last row was
+// appended already and is skipped. Returns the file and the rows written.
+#[instrument(name = "export", skip_all, fields(dir = %options.dir.display()))]
+pub fn append_long_csv(response: &BazaarResponse, options: &LongCsvOptions) -> Result<(PathBuf, usize), BazaarError> {
+    let last_updated: i64 = response.lastUpdated as i64;
+    let path: PathBuf = daily_long_path(&options.dir, last_updated);
+    let exists: bool = path.exists();
+    if exists && last_long_timestamp(&path, options.dialect.delimiter.0).is_some_and(|last| last >= last_updated) {
+        return Ok((path, 0));
+    }
+    fs::create_dir_all(&options.dir)?;
+    let file: fs::File = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    let mut wtr: csv::Writer<fs::File> = options.dialect.builder().from_writer(file);
+    if !exists {
+        wtr.write_record(["timestamp", "product_id", "metric", "value"])?;
+    }
+    let metrics: Vec<CsvColumn> = options.columns.iter().copied().filter(|column| column.is_metric()).collect();
+    let mut products: Vec<(&String, &QuickStatus)> = response.products.iter()
+        .filter(|(id, _)| options.watchlist.contains(id))
+        .map(|(id, product)| (id, &product.quick_status))
+        .collect();
+    products.sort_by(|a, b| a.0.cmp(b.0));
+    let timestamp: String = last_updated.to_string();
+    // Metric columns never look up item metadata
+    let items: ItemCatalog = ItemCatalog::default();
+    let mut rows: usize = 0;
+    for (id, quick_status) in products {
+        for metric in &metrics {
+            wtr.write_record([timestamp.as_str(), id.as_str(), metric.header(), &metric.value(last_updated, id, quick_status, &items)])?;
+            rows += 1;
+        }
+    }
+    wtr.flush()?;
+    info!(path = %path.display(), rows, "Long CSV appended");
+    Ok((path, rows))
+}
@@ -0,0 +1,203 @@
+// ClickHouse sink for long, high-resolution histories, written over the HTTP interface as
+// JSONEachRow. Inserts are idempotent: each carries a deduplication token derived from the
+// snapshots in it, and the ReplacingMergeTree keyed on (product_id, last_updated) folds any
+// duplicate that gets past that on its next merge.
+use crate::config::ClickHouseConfig;
+use crate::error::BazaarError;
+use crate::model::{Product, QuickStatus};
+use crate::storage::read_parallel;
+use crate::watchlist::Watchlist;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::runtime::Handle;
+use tracing::{debug, instrument, warn};
+
+// {table} is replaced with database.table. The deduplication window makes plain MergeTree
+// tables remember recent insert tokens the way replicated ones do.
+pub const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS {table} (
+    last_updated UInt64,
+    timestamp DateTime64(3, 'UTC') MATERIALIZED fromUnixTimestamp64Milli(toInt64(last_updated)),
+    product_id LowCardinality(String),
+    sell_price Float64,
+    sell_volume UInt64,
+    sell_moving_week UInt64,
+    sell_orders UInt32,
+    buy_price Float64,
+    buy_volume UInt64,
+    buy_moving_week UInt64,
+    buy_orders UInt32
+)
+ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+ORDER BY (product_id, last_updated)
+SETTINGS non_replicated_deduplication_window = 1000";
+
+// Snapshots per insert during a backfill, ClickHouse prefers few large inserts over many small ones
+const BACKFILL_BATCH: usize = 100;
+
+// Set once this process created the table, so fetches only send the DDL the first time
+static TABLE_READY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+struct Row<'a> {
+    last_updated: u64,
+    product_id: &'a str,
+    sell_price: f64,
+    sell_volume: u64,
+    sell_moving_week: u64,
+    sell_orders: u32,
+    buy_price: f64,
+    buy_volume: u64,
+    buy_moving_week: u64,
+    buy_orders: u32,
+}
+
+impl<'a> Row<'a> {
+    fn new(last_updated: u64, product_id: &'a str, quick_status: &QuickStatus) -> Self {
+        Self {
+            last_updated,
+            product_id,
+            sell_price: quick_status.sellPrice,
+            sell_volume: quick_status.sellVolume,
+            sell_moving_week: quick_status.sellMovingWeek,
+            sell_orders: quick_status.sellOrders,
+            buy_price: quick_status.buyPrice,
+            buy_volume: quick_status.buyVolume,
+            buy_moving_week: quick_status.buyMovingWeek,
+            buy_orders: quick_status.buyOrders,
+        }
+    }
+}
+
+// One JSON object per line, products sorted so the same snapshot always gives the same body
+pub fn snapshot_rows(last_updated: u64, products: &HashMap<String, Product>) -> Result<String, BazaarError> {
+    let mut ids: Vec<&String> = products.keys().collect();
+    ids.sort();
+    let mut rows: String = String::new();
+    for id in ids {
+        rows.push_str(&serde_json::to_string(&Row::new(last_updated, id, &products[id].quick_status))?);
+        rows.push('\n');
+    }
+    Ok(rows)
+}
+
+fn table(config: &ClickHouseConfig) -> String {
+    format!("{}.{}", config.database, config.table)
+}
+
+// Runs one statement, ClickHouse explains failures in the response body
+async fn execute(client: &reqwest::Client, config: &ClickHouseConfig, url: &str, query: &str, token: Option<&str>, body: String) -> Result<(), BazaarError> {
+    let mut params: Vec<(&str, &str)> = vec![("query", query)];
+    if let Some(token) = token {
+        params.push(("insert_deduplicate", "1"));
+        params.push(("insert_deduplication_token", token));
+    }
+    let mut request: reqwest::RequestBuilder = client.post(url).query(&params).body(body);
+    if let Some(user) = &config.user {
+        request = request.header("X-ClickHouse-User", user);
+    }
+    if let Some(password) = &config.password {
+        request = request.header("X-ClickHouse-Key", password);
+    }
+    let response: reqwest::Response = request.send().await?;
+    if !response.status().is_success() {
+        let status: reqwest::StatusCode = response.status();
+        let message: String = response.text().await.unwrap_or_default();
+        return Err(BazaarError::ClickHouse(format!("{}: {}", status, message.trim())));
+    }
+    Ok(())
+}
+
+pub async fn create_table(client: &reqwest::Client, config: &ClickHouseConfig, url: &str) -> Result<(), BazaarError> {
+    execute(client, config, url, &SCHEMA.replace("{table}", &table(config)), None, String::new()).await?;
+    TABLE_READY.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+// `token` names the snapshots in `rows`, an insert with a token ClickHouse has seen is dropped
+pub async fn insert(client: &reqwest::Client, config: &ClickHouseConfig, url: &str, token: &str, rows: String) -> Result<(), BazaarError> {
+    let query: String = format!("INSERT INTO {} FORMAT JSONEachRow", table(config));
+    execute(client, config, url, &query, Some(token), rows).await
+}
+
+// Called after every stored fetch. Failures are logged and never fail the fetch, `export
+// clickhouse` can fill the gap later.
+pub async fn record_fetch(client: &reqwest::Client, config: &ClickHouseConfig, last_updated: u64, products: &HashMap<String, Product>) {
+    let Some(url) = &config.url else {
+        return;
+    };
+    let result: Result<(), BazaarError> = async {
+        if !TABLE_READY.load(Ordering::SeqCst) {
+            create_table(client, config, url).await?;
+        }
+        insert(client, config, url, &last_updated.to_string(), snapshot_rows(last_updated, products)?).await
+    }.await;
+    if let Err(e) = result {
+        warn!("Couldn't write to ClickHouse: {}", e);
+    }
+}
+
+// Snapshots collected for one backfill insert
+#[derive(Default)]
+struct Batch {
+    rows: String,
+    first: Option<u64>,
+    last: u64,
+    snapshots: usize,
+}
+
+impl Batch {
+    fn flush(&mut self, handle: &Handle, client: &reqwest::Client, config: &ClickHouseConfig, url: &str) -> Result<(), BazaarError> {
+        let Some(first) = self.first else {
+            return Ok(());
+        };
+        let token: String = format!("{}-{}", first, self.last);
+        handle.block_on(insert(client, config, url, &token, std::mem::take(&mut self.rows)))?;
+        debug!(first, last = self.last, snapshots = self.snapshots, "Inserted a batch");
+        *self = Batch::default();
+        Ok(())
+    }
+}
+
+// Sends every snapshot in batches of BACKFILL_BATCH, unreadable files are skipped with a warning.
+// Over the same history the batches get the same tokens, so a rerun after a failure only adds
+// what's missing. Returns the snapshots and rows sent.
+#[instrument(name = "export", skip_all, fields(table = %table(config)))]
+pub async fn backfill(client: &reqwest::Client, config: &ClickHouseConfig, url: &str, files: &[PathBuf]) -> Result<(usize, usize), BazaarError> {
+    create_table(client, config, url).await?;
+    let handle: Handle = Handle::current();
+    let (mut snapshots, mut rows): (usize, usize) = (0, 0);
+    let mut batch: Batch = Batch::default();
+    // The reads and the inserts block, so they leave the async workers
+    tokio::task::block_in_place(|| {
+        read_parallel(
+            files,
+            &Watchlist::default(),
+            |_, last_updated, products: &HashMap<String, Product>| Ok((last_updated, products.len(), snapshot_rows(last_updated, products)?)),
+            |file, snapshot| {
+                let (last_updated, count, body) = match snapshot {
+                    Ok(snapshot) => snapshot,
+                    Err(e) if e.is_file_local() => {
+                        warn!("Skipping {}: {}", file.display(), e);
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+                batch.rows.push_str(&body);
+                batch.first.get_or_insert(last_updated);
+                batch.last = last_updated;
+                batch.snapshots += 1;
+                snapshots += 1;
+                rows += count;
+                if batch.snapshots == BACKFILL_BATCH {
+                    batch.flush(&handle, client, config, url)?;
+                }
+                Ok(())
+            },
+        )?;
+        batch.flush(&handle, client, config, url)
+    })?;
+    Ok((snapshots, rows))
+}
@@ -0,0 +1,116 @@
+// Columnar export of the raw snapshot history, one row per product per snapshot
+use crate::error::BazaarError;
+use crate::model::Product;
+use crate::storage::read_parallel;
+use crate::watchlist::Watchlist;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array, UInt64Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "parquet")]
+use parquet::basic::Compression;
+#[cfg(feature = "parquet")]
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{instrument, warn};
+
+fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+        Field::new("product_id", DataType::Utf8, false),
+        Field::new("sell_price", DataType::Float64, false),
+        Field::new("sell_volume", DataType::UInt64, false),
+        Field::new("sell_moving_week", DataType::UInt64, false),
+        Field::new("sell_orders", DataType::UInt32, false),
+        Field::new("buy_price", DataType::Float64, false),
+        Field::new("buy_volume", DataType::UInt64, false),
+        Field::new("buy_moving_week", DataType::UInt64, false),
+        Field::new("buy_orders", DataType::UInt32, false),
+    ]))
+}
+
+// One batch per snapshot, products sorted so row order is stable across runs
+fn snapshot_batch(schema: &SchemaRef, last_updated: u64, products: &HashMap<String, Product>) -> Result<RecordBatch, BazaarError> {
+    let mut products: Vec<&Product> = products.values().collect();
+    products.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+
+    let timestamp: TimestampMillisecondArray = TimestampMillisecondArray::from(vec![last_updated as i64; products.len()]).with_timezone("UTC");
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(timestamp),
+        Arc::new(StringArray::from_iter_values(products.iter().map(|p| p.product_id.as_str()))),
+        Arc::new(Float64Array::from_iter_values(products.iter().map(|p| p.quick_status.sellPrice))),
+        Arc::new(UInt64Array::from_iter_values(products.iter().map(|p| p.quick_status.sellVolume))),
+        Arc::new(UInt64Array::from_iter_values(products.iter().map(|p| p.quick_status.sellMovingWeek))),
+        Arc::new(UInt32Array::from_iter_values(products.iter().map(|p| p.quick_status.sellOrders))),
+        Arc::new(Float64Array::from_iter_values(products.iter().map(|p| p.quick_status.buyPrice))),
+        Arc::new(UInt64Array::from_iter_values(products.iter().map(|p| p.quick_status.buyVolume))),
+        Arc::new(UInt64Array::from_iter_values(products.iter().map(|p| p.quick_status.buyMovingWeek))),
+        Arc::new(UInt32Array::from_iter_values(products.iter().map(|p| p.quick_status.buyOrders))),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+// Snapshots are decoded in parallel batches and written in order, so memory stays bounded
+// however big the history gets.
+// Returns the number of rows written.
+#[cfg(feature = "parquet")]
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn export_parquet(files: &[PathBuf], output: &Path) -> Result<usize, BazaarError> {
+    let schema: SchemaRef = schema();
+    let properties: WriterProperties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer: ArrowWriter<File> = ArrowWriter::try_new(File::create(output)?, schema.clone(), Some(properties))?;
+    let mut rows: usize = 0;
+    read_parallel(
+        files,
+        &Watchlist::default(),
+        |_, last_updated, products: &HashMap<String, Product>| snapshot_batch(&schema, last_updated, products),
+        |file, batch| {
+            let batch: RecordBatch = match batch {
+                Ok(batch) => batch,
+                Err(e) if e.is_file_local() => {
+                    warn!("Skipping {}: {}", file.display(), e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            rows += batch.num_rows();
+            writer.write(&batch)?;
+            Ok(())
+        },
+    )?;
+    writer.close()?;
+    Ok(rows)
+}
+
+// Arrow IPC file (Feather v2), same schema and streaming as the Parquet export but uncompressed,
+// so Polars and DataFusion can memory-map it without decoding
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn export_arrow(files: &[PathBuf], output: &Path) -> Result<usize, BazaarError> {
+    let schema: SchemaRef = schema();
+    let mut writer: FileWriter<File> = FileWriter::try_new(File::create(output)?, &schema)?;
+    let mut rows: usize = 0;
+    read_parallel(
+        files,
+        &Watchlist::default(),
+        |_, last_updated, products: &HashMap<String, Product>| snapshot_batch(&schema, last_updated, products),
+        |file, batch| {
+            let batch: RecordBatch = match batch {
+                Ok(batch) => batch,
+                Err(e) if e.is_file_local() => {
+                    warn!("Skipping {}: {}", file.display(), e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            rows += batch.num_rows();
+            writer.write(&batch)?;
+            Ok(())
+        },
+    )?;
+    writer.finish()?;
+    Ok(rows)
+}
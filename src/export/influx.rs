@@ -0,0 +1,117 @@
+// InfluxDB line protocol, one point per product per snapshot tagged by product_id. Fetches can
+// append it to a file or write it straight to an InfluxDB v2 endpoint, `export influx` converts
+// the stored history for a backfill.
+use crate::config::InfluxConfig;
+use crate::error::BazaarError;
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::storage::{append_lines, read_parallel};
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{instrument, warn};
+
+// Measurements escape commas and spaces, tag values also equals signs
+fn escape(value: &str, equals: bool) -> String {
+    let mut out: String = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == ',' || c == ' ' || (equals && c == '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Counts are integer fields, timestamps are nanoseconds, the protocol's default precision
+fn push_point(lines: &mut String, measurement: &str, last_updated: u64, product_id: &str, quick_status: &QuickStatus) {
+    lines.push_str(&format!(
+        "{},product_id={} sell_price={},sell_volume={}i,sell_moving_week={}i,sell_orders={}i,buy_price={},buy_volume={}i,buy_moving_week={}i,buy_orders={}i {}\n",
+        escape(measurement, false),
+        escape(product_id, true),
+        quick_status.sellPrice,
+        quick_status.sellVolume,
+        quick_status.sellMovingWeek,
+        quick_status.sellOrders,
+        quick_status.buyPrice,
+        quick_status.buyVolume,
+        quick_status.buyMovingWeek,
+        quick_status.buyOrders,
+        last_updated as u128 * 1_000_000,
+    ));
+}
+
+// Products sorted so the output is stable across runs
+pub fn snapshot_lines(measurement: &str, last_updated: u64, products: &HashMap<String, Product>) -> String {
+    let mut ids: Vec<&String> = products.keys().collect();
+    ids.sort();
+    let mut lines: String = String::new();
+    for id in ids {
+        push_point(&mut lines, measurement, last_updated, id, &products[id].quick_status);
+    }
+    lines
+}
+
+// Returns the number of points written
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn export_influx(files: &[PathBuf], output: &Path, measurement: &str) -> Result<usize, BazaarError> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(output)?);
+    let mut points: usize = 0;
+    read_parallel(
+        files,
+        &Watchlist::default(),
+        |_, last_updated, products: &HashMap<String, Product>| Ok((products.len(), snapshot_lines(measurement, last_updated, products))),
+        |file, lines| {
+            let (count, lines): (usize, String) = match lines {
+                Ok(lines) => lines,
+                Err(e) if e.is_file_local() => {
+                    warn!("Skipping {}: {}", file.display(), e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            points += count;
+            writer.write_all(lines.as_bytes())?;
+            Ok(())
+        },
+    )?;
+    writer.flush()?;
+    Ok(points)
+}
+
+// POSTs to /api/v2/write, a rejected write comes back as BazaarError::Http
+pub async fn write_points(client: &reqwest::Client, config: &InfluxConfig, url: &str, lines: String) -> Result<(), BazaarError> {
+    let endpoint: String = format!("{}/api/v2/write", url.trim_end_matches('/'));
+    let mut request: reqwest::RequestBuilder = client.post(&endpoint)
+        .query(&[("org", config.org.as_str()), ("bucket", config.bucket.as_str()), ("precision", "ns")])
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(lines);
+    if let Some(token) = &config.token {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+    let status: reqwest::StatusCode = request.send().await?.status();
+    if !status.is_success() {
+        return Err(BazaarError::Http { status, url: endpoint });
+    }
+    Ok(())
+}
+
+// Called after every stored fetch. Failures are logged and never fail the fetch, the
+// snapshot on disk can always be exported again.
+pub async fn record_fetch(client: &reqwest::Client, config: &InfluxConfig, response: &BazaarResponse) {
+    if config.output.is_none() && config.url.is_none() {
+        return;
+    }
+    let lines: String = snapshot_lines(&config.measurement, response.lastUpdated, &response.products);
+    if let Some(output) = &config.output
+        && let Err(e) = append_lines(output, &lines).await
+    {
+        warn!("Couldn't append line protocol to {}: {}", output.display(), e);
+    }
+    if let Some(url) = &config.url
+        && let Err(e) = write_points(client, config, url, lines).await
+    {
+        warn!("Couldn't write to InfluxDB: {}", e);
+    }
+}
@@ -0,0 +1,74 @@
+// Excel workbook of the newest snapshot with real numeric cells, optionally split by item category
+use crate::error::BazaarError;
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::watchlist::Watchlist;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::instrument;
+
+const HEADER: [&str; 12] = [
+    "product_id", "name", "tier", "sell_price", "sell_volume", "sell_moving_week", "sell_orders",
+    "buy_price", "buy_volume", "buy_moving_week", "buy_orders", "npc_sell_price",
+];
+
+// Products without an items API category (enchantments, essences, ...) end up here
+const UNCATEGORIZED: &str = "Uncategorized";
+
+// Excel caps sheet names at 31 characters and rejects a few punctuation marks
+fn sheet_name(category: &str) -> String {
+    category.chars().filter(|c| !"[]:*?/\\".contains(*c)).take(31).collect()
+}
+
+fn write_sheet(sheet: &mut Worksheet, ids: &[&String], response: &BazaarResponse, items: &ItemCatalog) -> Result<(), XlsxError> {
+    let bold: Format = Format::new().set_bold();
+    let coins: Format = Format::new().set_num_format("#,##0.0");
+    for (col, title) in HEADER.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *title, &bold)?;
+    }
+    for (index, id) in ids.iter().enumerate() {
+        let row: u32 = index as u32 + 1;
+        let quick_status: &QuickStatus = &response.products[*id].quick_status;
+        sheet.write_string(row, 0, id.as_str())?;
+        sheet.write_string(row, 1, items.name(id))?;
+        sheet.write_string(row, 2, items.tier(id).unwrap_or(""))?;
+        sheet.write_number_with_format(row, 3, quick_status.sellPrice, &coins)?;
+        sheet.write_number(row, 4, quick_status.sellVolume as f64)?;
+        sheet.write_number(row, 5, quick_status.sellMovingWeek as f64)?;
+        sheet.write_number(row, 6, quick_status.sellOrders)?;
+        sheet.write_number_with_format(row, 7, quick_status.buyPrice, &coins)?;
+        sheet.write_number(row, 8, quick_status.buyVolume as f64)?;
+        sheet.write_number(row, 9, quick_status.buyMovingWeek as f64)?;
+        sheet.write_number(row, 10, quick_status.buyOrders)?;
+        if let Some(price) = items.npc_sell_price(id) {
+            sheet.write_number_with_format(row, 11, price, &coins)?;
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+    Ok(())
+}
+
+// Always writes a Summary sheet with every product, `by_category` adds one sheet per category.
+// Returns the number of products written.
+#[instrument(name = "export", skip_all, fields(output = %output.display()))]
+pub fn export_xlsx(response: &BazaarResponse, watchlist: &Watchlist, items: &ItemCatalog, output: &Path, by_category: bool) -> Result<usize, BazaarError> {
+    let mut ids: Vec<&String> = response.products.keys().filter(|id| watchlist.contains(id)).collect();
+    ids.sort();
+
+    let mut workbook: Workbook = Workbook::new();
+    write_sheet(workbook.add_worksheet().set_name("Summary")?, &ids, response, items)?;
+    if by_category {
+        let mut categories: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+        for id in &ids {
+            let category: &str = items.category(id).unwrap_or(UNCATEGORIZED);
+            categories.entry(sheet_name(category)).or_default().push(id);
+        }
+        for (category, ids) in &categories {
+            write_sheet(workbook.add_worksheet().set_name(category)?, ids, response, items)?;
+        }
+    }
+    workbook.save(output)?;
+    Ok(ids.len())
+}
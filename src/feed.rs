@@ -0,0 +1,64 @@
+// Live feed of each fetch's changed products, what `daemon --serve` streams to its subscribers
+use crate::model::{BazaarResponse, QuickStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+// Updates a slow subscriber can fall behind by before it misses some
+const CAPACITY: usize = 16;
+
+#[derive(Serialize)]
+pub struct Update {
+    pub last_updated: u64,
+    // Only the products whose quick_status differs from the previous fetch, all of them on the first
+    pub products: Vec<QuickStatus>,
+}
+
+impl Update {
+    // The update restricted to `products`, None when none of them changed. An empty filter keeps everything.
+    pub fn filtered(&self, products: &[String]) -> Option<Update> {
+        let kept: Vec<QuickStatus> = self.products.iter()
+            .filter(|quick_status| products.is_empty() || products.contains(&quick_status.productId))
+            .cloned()
+            .collect();
+        (!kept.is_empty()).then_some(Update { last_updated: self.last_updated, products: kept })
+    }
+}
+
+pub struct Feed {
+    sender: broadcast::Sender<Arc<Update>>,
+    // What the last published fetch had, to tell what changed
+    previous: Mutex<HashMap<String, QuickStatus>>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            previous: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Feed {
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Update>> {
+        self.sender.subscribe()
+    }
+
+    // Publishing without subscribers is fine, the update just goes nowhere
+    pub fn publish(&self, response: &BazaarResponse) {
+        let mut previous = self.previous.lock().unwrap();
+        let mut products: Vec<QuickStatus> = response.products.iter()
+            .filter(|(id, product)| previous.get(*id) != Some(&product.quick_status))
+            .map(|(_, product)| product.quick_status.clone())
+            .collect();
+        *previous = response.products.iter().map(|(id, product)| (id.clone(), product.quick_status.clone())).collect();
+        drop(previous);
+        if products.is_empty() {
+            return;
+        }
+        products.sort_by(|a, b| a.productId.cmp(&b.productId));
+        let _ = self.sender.send(Arc::new(Update { last_updated: response.lastUpdated, products }));
+    }
+}
@@ -0,0 +1,538 @@
+// Fetching the bazaar and writing the response out as a raw snapshot
+use crate::alerts::{self, Alert, AlertState};
+use crate::anomalies::{self, Anomaly};
+use crate::config::{AlertRule, AlertsConfig, AnomaliesConfig, ApiConfig, ClickHouseConfig, InfluxConfig, S3Config, SnapshotFormat};
+use crate::error::BazaarError;
+use crate::export::{self, LongCsvOptions, clickhouse, influx};
+use crate::feed::Feed;
+use crate::history::{self, History, HistoryPoint};
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::paper::PaperTrader;
+use crate::schedule::Ticker;
+use crate::storage::{
+    Delta, append_jsonl, daily_jsonl_path, delta_from, is_delta, new_delta_path, new_snapshot_path, newest_file,
+    newest_jsonl_last_updated, read_last_updated, snapshot_files, snapshot_timestamp, stored_snapshot, write_raw_snapshot,
+    write_snapshot,
+};
+use crate::validation::{self, ValidationReport};
+use crate::watchlist::Watchlist;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time;
+use tracing::{Instrument, debug, error, info, info_span, instrument, warn};
+
+// The one client every request shares, proxy, timeouts and User-Agent from `api`
+pub fn build_client(api: &ApiConfig) -> Result<reqwest::Client, BazaarError> {
+    let mut builder: reqwest::ClientBuilder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(api.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(api.read_timeout_secs))
+        .timeout(Duration::from_secs(api.timeout_secs))
+        .user_agent(&api.user_agent);
+    if let Some(proxy) = &api.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|_| BazaarError::Config(format!("api.proxy {} isn't a proxy URL", proxy)))?);
+    }
+    Ok(builder.build()?)
+}
+
+// Requests are held back until this instant once the API reports the rate limit as used up.
+// It's process-wide so daemon cycles, watch and the TUI all respect the same limit.
+static RATE_LIMITED_UNTIL: Mutex<Option<time::Instant>> = Mutex::new(None);
+
+fn header_u64(headers: &reqwest::header::HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+// Reads RateLimit-Remaining/RateLimit-Reset, returns how long to hold off when nothing is left
+fn note_rate_limit(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let remaining: u64 = header_u64(headers, "ratelimit-remaining")?;
+    let reset: u64 = header_u64(headers, "ratelimit-reset")?;
+    if remaining > 0 {
+        return None;
+    }
+    let wait: Duration = Duration::from_secs(reset);
+    *RATE_LIMITED_UNTIL.lock().unwrap() = Some(time::Instant::now() + wait);
+    Some(wait)
+}
+
+async fn wait_for_rate_limit() {
+    let until: Option<time::Instant> = *RATE_LIMITED_UNTIL.lock().unwrap();
+    if let Some(until) = until && until > time::Instant::now() {
+        warn!("Rate limit used up, waiting {:.1}s for it to reset", (until - time::Instant::now()).as_secs_f64());
+        time::sleep_until(until).await;
+    }
+}
+
+// Why a single attempt failed, only retryable errors are tried again
+struct FetchFailure {
+    error: BazaarError,
+    // The server's Retry-After, or when the rate limit resets
+    retry_after: Option<Duration>,
+}
+
+impl From<reqwest::Error> for FetchFailure {
+    fn from(error: reqwest::Error) -> Self {
+        Self { error: error.into(), retry_after: None }
+    }
+}
+
+// The previous 200 of a URL with its validators, so a 304 can be answered from its body. Only
+// kept when the server sent Last-Modified or an ETag, otherwise there's nothing to revalidate with.
+struct CachedResponse {
+    last_modified: Option<String>,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+static CACHED_RESPONSES: LazyLock<Mutex<HashMap<String, CachedResponse>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn header_string(headers: &reqwest::header::HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    Some(headers.get(name)?.to_str().ok()?.to_string())
+}
+
+// Gunzipped when the server took up Accept-Encoding, reqwest is built without decompression
+fn decode_body(headers: &reqwest::header::HeaderMap, body: &[u8], url: &str) -> Result<Vec<u8>, FetchFailure> {
+    let gzipped: bool = headers.get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|encoding| encoding.to_str().ok())
+        .is_some_and(|encoding| encoding.trim().eq_ignore_ascii_case("gzip"));
+    if !gzipped {
+        return Ok(body.to_vec());
+    }
+    let mut decoded: Vec<u8> = Vec::with_capacity(body.len() * 8);
+    match flate2::read::GzDecoder::new(body).read_to_end(&mut decoded) {
+        Ok(_) => {
+            debug!(url, compressed = body.len(), decoded = decoded.len(), "Gzipped response");
+            Ok(decoded)
+        }
+        Err(source) => Err(FetchFailure { error: BazaarError::Decompress { url: url.to_string(), source }, retry_after: None }),
+    }
+}
+
+async fn fetch_once(client: &reqwest::Client, api: &ApiConfig, url: &str) -> Result<Vec<u8>, FetchFailure> {
+    wait_for_rate_limit().await;
+    let mut request: reqwest::RequestBuilder = client.get(url);
+    if let Some(key) = &api.key {
+        request = request.header("API-Key", key);
+    }
+    if api.gzip {
+        request = request.header(reqwest::header::ACCEPT_ENCODING, "gzip");
+    }
+    if api.conditional_requests && let Some(cached) = CACHED_RESPONSES.lock().unwrap().get(url) {
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        if let Some(etag) = &cached.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+    }
+    let response: reqwest::Response = request.send().await?;
+    let status: reqwest::StatusCode = response.status();
+    let reset: Option<Duration> = note_rate_limit(response.headers());
+    if status == reqwest::StatusCode::NOT_MODIFIED && let Some(cached) = CACHED_RESPONSES.lock().unwrap().get(url) {
+        debug!(url, "Not modified, reusing the previous body");
+        return Ok(cached.body.clone());
+    }
+    if !status.is_success() {
+        let retry_after: Option<Duration> = header_u64(response.headers(), "retry-after")
+            .map(Duration::from_secs)
+            .or(reset);
+        return Err(FetchFailure { error: BazaarError::Http { status, url: url.to_string() }, retry_after });
+    }
+    let headers: reqwest::header::HeaderMap = response.headers().clone();
+    let body: Vec<u8> = decode_body(&headers, &response.bytes().await?, url)?;
+    let last_modified: Option<String> = header_string(&headers, reqwest::header::LAST_MODIFIED);
+    let etag: Option<String> = header_string(&headers, reqwest::header::ETAG);
+    if api.conditional_requests && (last_modified.is_some() || etag.is_some()) {
+        CACHED_RESPONSES.lock().unwrap().insert(url.to_string(), CachedResponse { last_modified, etag, body: body.clone() });
+    }
+    Ok(body)
+}
+
+// Exponential backoff capped at api.max_retry_delay_ms, the upper half of every delay is random jitter
+fn backoff_delay(api: &ApiConfig, attempt: u32) -> Duration {
+    let delay: u64 = api.retry_delay_ms.saturating_mul(1 << attempt.min(20)).min(api.max_retry_delay_ms);
+    Duration::from_millis(delay / 2 + rand::random_range(0..=delay - delay / 2))
+}
+
+// GETs any Hypixel endpoint with the key, retries and rate limiting from `api`, returns the raw body
+pub async fn fetch_bytes(client: &reqwest::Client, api: &ApiConfig, url: &str) -> Result<Vec<u8>, BazaarError> {
+    let mut attempt: u32 = 0;
+    loop {
+        let delay: Duration = match fetch_once(client, api, url).await {
+            Ok(response) => return Ok(response),
+            // Anything retrying won't fix, like a rejected API key
+            Err(failure) if !failure.error.is_retryable() => return Err(failure.error),
+            Err(failure) if attempt >= api.retries => {
+                return Err(BazaarError::RetriesExhausted { attempts: attempt + 1, source: Box::new(failure.error) });
+            }
+            Err(failure) => {
+                let delay: Duration = failure.retry_after.unwrap_or_else(|| backoff_delay(api, attempt));
+                warn!("Fetch failed: {}, retry {}/{} in {:.1}s", failure.error, attempt + 1, api.retries, delay.as_secs_f64());
+                delay
+            }
+        };
+        attempt += 1;
+        time::sleep(delay).await;
+    }
+}
+
+// A malformed body is never retried, the next attempt would most likely get the same one
+pub async fn fetch_json<T: DeserializeOwned>(client: &reqwest::Client, api: &ApiConfig, url: &str) -> Result<T, BazaarError> {
+    let body: Vec<u8> = fetch_bytes(client, api, url).await?;
+    serde_json::from_slice(&body).map_err(|source| BazaarError::MalformedResponse { url: url.to_string(), source })
+}
+
+// The raw body along with the leniently parsed response and what validation found in it
+pub async fn fetch_bazaar_validated(client: &reqwest::Client, api: &ApiConfig) -> Result<(Vec<u8>, BazaarResponse, ValidationReport), BazaarError> {
+    let body: Vec<u8> = fetch_bytes(client, api, &api.url).await?;
+    let (response, report): (BazaarResponse, ValidationReport) = validation::parse_response(&body)
+        .map_err(|source| BazaarError::MalformedResponse { url: api.url.clone(), source })?;
+    Ok((body, response, report))
+}
+
+// Products that fail validation are left out without a word, for callers that only display the response
+pub async fn fetch_bazaar(client: &reqwest::Client, api: &ApiConfig) -> Result<BazaarResponse, BazaarError> {
+    Ok(fetch_bazaar_validated(client, api).await?.1)
+}
+
+// Everything a fetch cycle needs, cloned into every daemon task
+#[derive(Clone)]
+pub struct FetchContext {
+    pub client: reqwest::Client,
+    pub api: ApiConfig,
+    pub raw_dir: PathBuf,
+    pub compress: bool,
+    pub format: SnapshotFormat,
+    pub keyframe_interval: u32,
+    // Snapshot filtering only happens when this is Some, otherwise the full response is stored
+    pub watchlist: Option<Watchlist>,
+    pub alerts: AlertsConfig,
+    pub anomalies: AnomaliesConfig,
+    pub influx: InfluxConfig,
+    pub clickhouse: ClickHouseConfig,
+    pub s3: S3Config,
+    // Write the snapshot even when lastUpdated matches the previous one
+    pub force: bool,
+    // Stored snapshots are published here when the daemon serves live updates
+    pub feed: Option<Arc<Feed>>,
+    // Every new snapshot is traded on when the daemon paper trades
+    pub paper: Option<Arc<PaperTrader>>,
+    // Every stored snapshot is appended to the daily long CSV when csv.append_long is set
+    pub long_csv: Option<Arc<LongCsvOptions>>,
+    // lastUpdated of the newest stored snapshot, shared by every daemon task, 0 until known
+    pub last_updated: Arc<AtomicU64>,
+    // Held while a delta is diffed and written, so overlapping daemon cycles take turns building
+    // on the newest snapshot
+    pub delta_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl FetchContext {
+    // Seeds the last seen lastUpdated from the newest snapshot on disk the first time it's needed
+    fn seed_last_updated(&self) {
+        if self.last_updated.load(Ordering::SeqCst) != 0 {
+            return;
+        }
+        let stored: u64 = match self.format {
+            SnapshotFormat::Json | SnapshotFormat::Delta => newest_file(&self.raw_dir).and_then(|path| read_last_updated(&path).ok()),
+            SnapshotFormat::Jsonl => newest_jsonl_last_updated(&self.raw_dir),
+        }.unwrap_or(0);
+        let _ = self.last_updated.compare_exchange(0, stored, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+// Rules are checked against the full response, before any watchlist filtering
+async fn check_alerts(context: &FetchContext, response: &BazaarResponse) {
+    let rules: Vec<AlertRule> = context.alerts.all_rules();
+    if rules.is_empty() {
+        return;
+    }
+    let previous: Option<HashMap<String, QuickStatus>> = newest_file(&context.raw_dir)
+        .and_then(|path| history::read_quick_status(&path).ok());
+    let mut state: AlertState = AlertState::load(&context.alerts.state);
+    let alerts: Vec<Alert> = alerts::evaluate(&rules, previous.as_ref(), response, &mut state);
+    if rules.iter().any(|rule| rule.cooldown.is_some())
+        && let Err(e) = state.save(&context.alerts.state)
+    {
+        warn!("Couldn't save the alert state to {}: {}", context.alerts.state.display(), e);
+    }
+    alerts::dispatch(&context.client, &context.alerts, &alerts).await;
+}
+
+// The stored window plus the fresh response, judged before it's written. Like the rules this
+// sees every product, and a history that can't be read only costs the check.
+async fn check_anomalies(context: &FetchContext, response: &BazaarResponse) {
+    if !context.anomalies.alert {
+        return;
+    }
+    let files: Vec<PathBuf> = snapshot_files(&context.raw_dir).unwrap_or_default();
+    let history: History = match tokio::task::block_in_place(|| History::load_window(&files, context.anomalies.window.millis, &Watchlist::default())) {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("Couldn't read the history to check for anomalies: {}", e);
+            return;
+        }
+    };
+    let newest: HistoryPoint = HistoryPoint {
+        timestamp: response.lastUpdated as i64,
+        products: response.products.iter().map(|(id, product)| (id.clone(), product.quick_status.clone())).collect(),
+    };
+    let anomalies: Vec<Anomaly> = anomalies::latest(&anomalies::with_newest(history, newest), &Watchlist::default(), &context.anomalies);
+    let alerts: Vec<Alert> = anomalies.iter().map(|anomaly| anomaly.to_alert(&context.anomalies)).collect();
+    alerts::dispatch(&context.client, &context.alerts, &alerts).await;
+}
+
+// A delta against the newest snapshot, or a full keyframe when keyframe_interval - 1 deltas
+// already follow the last one (or there's nothing readable to build on). A slow cycle can finish
+// after a newer one stored its snapshot, that response is dropped: it would sort before the
+// newer delta, which was diffed without it, so neither a delta nor a keyframe fits in between.
+async fn write_delta(context: &FetchContext, response: &BazaarResponse) -> Result<PathBuf, BazaarError> {
+    let _guard: tokio::sync::MutexGuard<()> = context.delta_lock.lock().await;
+    let files: Vec<PathBuf> = snapshot_files(&context.raw_dir).unwrap_or_default();
+    if let Some(newest) = files.last().and_then(|file| snapshot_timestamp(file))
+        && newest > response.lastUpdated as i64
+    {
+        return Err(BazaarError::Storage(format!(
+            "lastUpdated {} is older than the newest stored snapshot, a delta can't go before it",
+            response.lastUpdated,
+        )));
+    }
+    let since_keyframe: usize = files.iter().rev().take_while(|file| is_delta(file)).count();
+    let previous: Option<HashMap<String, QuickStatus>> = if since_keyframe + 1 < context.keyframe_interval as usize {
+        files.last().and_then(|last| history::read_quick_status(last).ok())
+    } else {
+        None
+    };
+    let filename: PathBuf = match previous {
+        Some(previous) => {
+            let filename: PathBuf = new_delta_path(&context.raw_dir, response.lastUpdated, context.compress);
+            let delta: Delta<&Product> = delta_from(&previous, response);
+            info!(changed = delta.products.len(), removed = delta.removed.len(), "Writing a delta");
+            write_snapshot(&filename, &delta, context.compress).await?;
+            filename
+        }
+        None => {
+            let filename: PathBuf = new_snapshot_path(&context.raw_dir, response.lastUpdated, context.compress);
+            write_snapshot(&filename, response, context.compress).await?;
+            filename
+        }
+    };
+    Ok(filename)
+}
+
+// Hypixel only refreshes every 20-60 seconds, polling faster would store duplicates.
+// The swap claims the new value so overlapping daemon cycles can't both write it, and since
+// files are named after lastUpdated an existing one means it was stored before too. Returns the
+// value claimed over, to hand back if storing fails, or None when the snapshot is unchanged.
+fn claim_last_updated(context: &FetchContext, last_updated: u64) -> Option<u64> {
+    context.seed_last_updated();
+    let previous: u64 = context.last_updated.swap(last_updated, Ordering::SeqCst);
+    if context.force {
+        return Some(previous);
+    }
+    if previous == last_updated {
+        info!(last_updated, "Unchanged, the snapshot is already stored");
+        return None;
+    }
+    if let Some(path) = stored_snapshot(&context.raw_dir, last_updated) {
+        info!(last_updated, path = %path.display(), "Unchanged, the snapshot is already stored");
+        return None;
+    }
+    Some(previous)
+}
+
+// Writes the snapshot in the configured format, returns the file it went to
+async fn store(context: &FetchContext, response: &BazaarResponse, body: &[u8], report: &ValidationReport) -> Result<PathBuf, BazaarError> {
+    let raw_dir: &Path = &context.raw_dir;
+    let compress: bool = context.compress;
+    let filename: PathBuf = match context.format {
+        // Unless products were filtered out or repaired, the body is written as Hypixel sent it
+        // instead of pretty-printing the BazaarResponse again
+        SnapshotFormat::Json if context.watchlist.is_none() && !report.has_changes() => {
+            let filename: PathBuf = new_snapshot_path(raw_dir, response.lastUpdated, compress);
+            write_raw_snapshot(&filename, body, compress).await?;
+            filename
+        }
+        SnapshotFormat::Json => {
+            let filename: PathBuf = new_snapshot_path(raw_dir, response.lastUpdated, compress);
+            write_snapshot(&filename, response, compress).await?;
+            filename
+        }
+        SnapshotFormat::Jsonl => {
+            let filename: PathBuf = daily_jsonl_path(raw_dir, response.lastUpdated);
+            append_jsonl(&filename, response).await?;
+            filename
+        }
+        SnapshotFormat::Delta => write_delta(context, response).await?,
+    };
+    Ok(filename)
+}
+
+// Returns None when the bazaar hasn't refreshed since the last stored snapshot
+#[instrument(name = "fetch", skip_all)]
+pub async fn get_and_dump(context: &FetchContext) -> Result<Option<PathBuf>, BazaarError> {
+    let raw_dir: &Path = &context.raw_dir;
+    let (body, mut response, report): (Vec<u8>, BazaarResponse, ValidationReport) = fetch_bazaar_validated(&context.client, &context.api).await?;
+    let Some(previous) = claim_last_updated(context, response.lastUpdated) else {
+        return Ok(None);
+    };
+    report.print_warnings();
+    check_alerts(context, &response).await;
+    check_anomalies(context, &response).await;
+    // Before watchlist filtering too, the strategy's products needn't be stored
+    if let Some(paper) = &context.paper {
+        tokio::task::block_in_place(|| paper.record(&response));
+    }
+    if let Some(watchlist) = &context.watchlist {
+        watchlist.warn_missing(&response);
+        watchlist.retain(&mut response);
+    }
+    
+    info!(success = response.success, last_updated = response.lastUpdated, products = response.products.len(), "Fetched the bazaar");
+    
+    let filename: PathBuf = match store(context, &response, &body, &report).await {
+        Ok(filename) => filename,
+        Err(e) => {
+            // Not stored after all, the next cycle has to write it rather than skip it as unchanged
+            let _ = context.last_updated.compare_exchange(response.lastUpdated, previous, Ordering::SeqCst, Ordering::SeqCst);
+            return Err(e);
+        }
+    };
+    
+    info!(path = %filename.display(), "Response saved");
+    if let Some(feed) = &context.feed {
+        feed.publish(&response);
+    }
+    if let Some(long_csv) = &context.long_csv
+        && let Err(e) = tokio::task::block_in_place(|| export::append_long_csv(&response, long_csv))
+    {
+        warn!("Couldn't append to the long CSV in {}: {}", long_csv.dir.display(), e);
+    }
+    influx::record_fetch(&context.client, &context.influx, &response).await;
+    clickhouse::record_fetch(&context.client, &context.clickhouse, response.lastUpdated, &response.products).await;
+    #[cfg(feature = "s3")]
+    crate::s3::record_fetch(&context.client, &context.s3, raw_dir, &filename, response.lastUpdated).await;
+    #[cfg(not(feature = "s3"))]
+    if context.s3.endpoint.is_some() {
+        warn!("s3.endpoint is set, but this build has no s3 feature");
+    }
+    if let Err(e) = validation::record(raw_dir, &filename, &report).await {
+        warn!("Couldn't record the validation report: {}", e);
+    }
+    
+    Ok(Some(filename))
+}
+
+// Resolves on SIGINT or SIGTERM, on Ctrl-C where there are no Unix signals
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        tokio::select! {
+            Ok(()) = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        return;
+    }
+    // Without a handler there's nothing to wait for, the process simply gets killed
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+// What a daemon session did, logged when it stops
+#[derive(Default)]
+pub struct SessionSummary {
+    pub cycles: u64,
+    pub stored: u64,
+    pub unchanged: u64,
+    pub failed: u64,
+}
+
+enum CycleOutcome {
+    Stored,
+    Unchanged,
+    Failed,
+}
+
+impl SessionSummary {
+    // A cycle that panicked counts as failed
+    fn record(&mut self, outcome: Result<CycleOutcome, tokio::task::JoinError>) {
+        match outcome {
+            Ok(CycleOutcome::Stored) => self.stored += 1,
+            Ok(CycleOutcome::Unchanged) => self.unchanged += 1,
+            Ok(CycleOutcome::Failed) | Err(_) => self.failed += 1,
+        }
+    }
+}
+
+// Errors are logged and the loop carries on, a failed cycle just waits for the next tick.
+// Every cycle runs in its own task so a slow response never delays the following ticks.
+// On SIGINT/SIGTERM no new cycle starts and running ones finish their writes first, a second
+// signal abandons them.
+pub async fn daemon(context: FetchContext, mut ticker: Ticker) -> SessionSummary {
+    let started: time::Instant = time::Instant::now();
+    let mut summary: SessionSummary = SessionSummary::default();
+    let mut tasks: JoinSet<CycleOutcome> = JoinSet::new();
+    loop {
+        tokio::select! {
+            more = ticker.tick() => {
+                if !more {
+                    warn!("No scheduled fetch is left, stopping");
+                    break;
+                }
+            }
+            _ = shutdown_signal() => {
+                while let Some(outcome) = tasks.try_join_next() {
+                    summary.record(outcome);
+                }
+                info!(running = tasks.len(), "Shutting down, letting running cycles finish");
+                break;
+            }
+        }
+        while let Some(outcome) = tasks.try_join_next() {
+            summary.record(outcome);
+        }
+        summary.cycles += 1;
+        let context: FetchContext = context.clone();
+        let cycle: u64 = summary.cycles;
+        tasks.spawn(async move {
+            match get_and_dump(&context).await {
+                Ok(Some(path)) => {
+                    info!(path = %path.display(), "Cycle ok");
+                    CycleOutcome::Stored
+                }
+                Ok(None) => {
+                    info!("Cycle unchanged");
+                    CycleOutcome::Unchanged
+                }
+                Err(e) => {
+                    error!("Cycle failed: {}", e);
+                    CycleOutcome::Failed
+                }
+            }
+        }.instrument(info_span!("cycle", cycle)));
+    }
+    loop {
+        tokio::select! {
+            outcome = tasks.join_next() => match outcome {
+                Some(outcome) => summary.record(outcome),
+                None => break,
+            },
+            _ = shutdown_signal() => {
+                warn!(abandoned = tasks.len(), "Second signal, not waiting for running cycles");
+                summary.failed += tasks.len() as u64;
+                tasks.abort_all();
+                break;
+            }
+        }
+    }
+    info!(
+        "Stopped after {} cycles in {:.0}s: {} stored, {} unchanged, {} failed",
+        summary.cycles, started.elapsed().as_secs_f64(), summary.stored, summary.unchanged, summary.failed
+    );
+    summary
+}
@@ -0,0 +1,89 @@
+// How long a buy order takes to fill: insta-sells work through the buy orders best price first, so
+// an order waits for the units listed at or above its price, then for its own. New orders joining
+// in the meantime, read from how the order count grew over successive snapshots, take their cut.
+use crate::flips::HOURS_PER_WEEK;
+use crate::gaps::format_duration;
+use crate::model::{Product, QuickStatus};
+
+pub struct FillEstimate {
+    pub price: f64,
+    pub quantity: u64,
+    // Units of other buy orders filled first: priced higher, or as high and listed earlier
+    pub ahead: u64,
+    // Insta-sold units per hour, averaged over the snapshots given
+    pub hourly_volume: f64,
+    // Buy orders added per hour over the snapshots, None with fewer than two
+    pub orders_per_hour: Option<f64>,
+    // Units those new orders are expected to take per hour
+    pub competition: f64,
+    // None when the competition takes all the volume
+    pub hours: Option<f64>,
+    // The price reaches the sell offers, an instabuy gets the units right away
+    pub instant: bool,
+    // The book lists fewer levels than lie above the price, more units are likely ahead
+    pub truncated: bool,
+}
+
+// Orders per hour added over the samples, from the first and last count
+fn orders_trend(samples: &[(i64, &QuickStatus)]) -> Option<f64> {
+    let (Some((first, oldest)), Some((last, newest))) = (samples.first(), samples.last()) else {
+        return None;
+    };
+    (last > first).then(|| (newest.sellOrders as f64 - oldest.sellOrders as f64) / ((last - first) as f64 / 3_600_000.0))
+}
+
+// `samples` are the product's quick statuses from successive snapshots, oldest first, the newest
+// snapshot's book alone works too
+pub fn estimate(product: &Product, samples: &[(i64, &QuickStatus)], price: f64, quantity: u64) -> FillEstimate {
+    let quick_status: &QuickStatus = &product.quick_status;
+    let hourly_volume: f64 = if samples.is_empty() {
+        quick_status.sellMovingWeek as f64 / HOURS_PER_WEEK
+    } else {
+        samples.iter().map(|(_, sample)| sample.sellMovingWeek as f64).sum::<f64>() / samples.len() as f64 / HOURS_PER_WEEK
+    };
+    let ahead: u64 = product.sell_summary.iter()
+        .filter(|level| level.pricePerUnit.to_float() >= price)
+        .map(|level| level.amount)
+        .sum();
+    let truncated: bool = product.sell_summary.len() >= 30 && product.sell_summary.last().is_some_and(|level| level.pricePerUnit.to_float() >= price);
+    // Orders that join outbid this one sooner or later, each as big as the listed ones on average
+    let listed: u32 = product.sell_summary.iter().map(|level| level.orders).sum();
+    let order_size: f64 = if listed > 0 { product.sell_summary.iter().map(|level| level.amount as f64).sum::<f64>() / listed as f64 } else { 0.0 };
+    let orders_per_hour: Option<f64> = orders_trend(samples);
+    let competition: f64 = orders_per_hour.unwrap_or(0.0).max(0.0) * order_size;
+    let instant: bool = quick_status.buyPrice > 0.0 && price >= quick_status.buyPrice;
+    let drain: f64 = hourly_volume - competition;
+    let hours: Option<f64> = if instant {
+        Some(0.0)
+    } else {
+        (drain > 0.0).then(|| (ahead + quantity) as f64 / drain)
+    };
+    FillEstimate { price, quantity, ahead, hourly_volume, orders_per_hour, competition, hours, instant, truncated }
+}
+
+// "2h 15m", "now" or "never"
+pub fn format_hours(hours: Option<f64>) -> String {
+    match hours {
+        Some(hours) if hours <= 0.0 => "now".to_string(),
+        Some(hours) => format_duration((hours * 3_600_000.0) as i64),
+        None => "never".to_string(),
+    }
+}
+
+pub fn print_estimate(product_id: &str, estimate: &FillEstimate, samples: usize) {
+    println!("Buy order for {} {} at {:.1} each", estimate.quantity, product_id, estimate.price);
+    if estimate.instant {
+        println!("The price reaches the lowest sell offer, the units can be instabought right away");
+        return;
+    }
+    println!(
+        "Ahead of it: {} units of buy orders priced as high or higher{}",
+        estimate.ahead, if estimate.truncated { ", at least, the listed levels end above the price" } else { "" }
+    );
+    println!("Insta-sold per hour: {:.1} units, averaged over {} snapshots", estimate.hourly_volume, samples.max(1));
+    match estimate.orders_per_hour {
+        Some(orders) => println!("Buy orders added per hour: {:+.1}, taking about {:.1} units an hour", orders, estimate.competition),
+        None => println!("Buy orders added per hour: unknown with a single snapshot"),
+    }
+    println!("Expected fill time: {}", format_hours(estimate.hours));
+}
@@ -0,0 +1,144 @@
+// Order-flip finder: ranks products by the profit an order flipper can expect per hour
+use crate::analysis::Spread;
+use crate::error::BazaarError;
+use crate::filltime::{self, FillEstimate};
+use crate::items::ItemCatalog;
+use crate::liquidity;
+use crate::watchlist::Watchlist;
+use crate::model::{ArithmeticError, BazaarResponse, FixedPoint, QuickStatus};
+use std::path::Path;
+
+pub const HOURS_PER_WEEK: f64 = 168.0;
+
+// What `flips` and the reports rank with unless told otherwise
+pub const DEFAULT_CAPITAL: f64 = 10_000_000.0;
+pub const DEFAULT_MIN_VOLUME: u64 = 100_000;
+pub const DEFAULT_MIN_LIQUIDITY: f64 = 30.0;
+pub const DEFAULT_MAX_SHARE: f64 = 1.0;
+
+pub struct FlipOptions {
+    // Coins available for buy orders
+    pub capital: FixedPoint,
+    // Smallest weekly volume (on the thinner side) worth considering
+    pub min_volume: u64,
+    // Smallest liquidity score worth considering, thin books get outbid or never fill
+    pub min_liquidity: f64,
+    pub tax_rate: f64,
+    // Largest fraction of the hourly movingWeek volume one flipper can expect to capture,
+    // on top of sharing it with the queued orders
+    pub max_share: f64,
+}
+
+impl FlipOptions {
+    pub fn defaults(tax_rate: f64) -> Self {
+        Self {
+            capital: FixedPoint::from_float(DEFAULT_CAPITAL),
+            min_volume: DEFAULT_MIN_VOLUME,
+            min_liquidity: DEFAULT_MIN_LIQUIDITY,
+            tax_rate,
+            max_share: DEFAULT_MAX_SHARE,
+        }
+    }
+}
+
+pub struct Flip {
+    pub spread: Spread,
+    // Weekly units moving through the thinner side of the book
+    pub weekly_volume: u64,
+    // Units we can expect to get filled per hour once competing orders are accounted for
+    pub units_per_hour: FixedPoint,
+    pub profit_per_hour: FixedPoint,
+    // Expected hours until a buy order for as many units as the capital pays for fills, see `filltime`
+    pub fill_hours: Option<f64>,
+}
+
+// Units an order on one side can expect filled per hour: that side's hourly share of
+// `moving_week`, split with the `orders` already queued there and capped at max_share of it
+pub fn fill_rate(moving_week: u64, orders: u32, max_share: f64) -> f64 {
+    let hourly: f64 = moving_week as f64 / HOURS_PER_WEEK;
+    (hourly / (orders as f64 + 1.0)).min(hourly * max_share)
+}
+
+// Hourly insta-sell volume fills buy orders and insta-buy volume fills sell offers.
+// The slower side limits the flip, and capital caps how many units can be held at once.
+fn units_per_hour(quick_status: &QuickStatus, capital_units: FixedPoint, max_share: f64) -> FixedPoint {
+    let buy_side: f64 = fill_rate(quick_status.sellMovingWeek, quick_status.sellOrders, max_share);
+    let sell_side: f64 = fill_rate(quick_status.buyMovingWeek, quick_status.buyOrders, max_share);
+    FixedPoint::from_float(buy_side.min(sell_side)).min(capital_units)
+}
+
+// Most profitable first, unprofitable products are left out
+pub fn find_flips(response: &BazaarResponse, watchlist: &Watchlist, options: &FlipOptions) -> Result<Vec<Flip>, ArithmeticError> {
+    let mut flips: Vec<Flip> = Vec::new();
+    for (id, product) in response.products.iter().filter(|(id, _)| watchlist.contains(id)) {
+        let quick_status: &QuickStatus = &product.quick_status;
+        let weekly_volume: u64 = quick_status.buyMovingWeek.min(quick_status.sellMovingWeek);
+        if weekly_volume < options.min_volume || liquidity::liquidity(id, product).score < options.min_liquidity {
+            continue;
+        }
+        let Some(spread) = Spread::from_quick_status(id, quick_status, options.tax_rate)? else {
+            continue;
+        };
+        if spread.net_spread.raw() <= 0 {
+            continue;
+        }
+        let capital_units: FixedPoint = options.capital.checked_div(spread.sell_price).ok_or(ArithmeticError("capital units"))?;
+        let units_per_hour: FixedPoint = units_per_hour(quick_status, capital_units, options.max_share);
+        let fill: FillEstimate = filltime::estimate(product, &[], quick_status.sellPrice, capital_units.to_float().floor() as u64);
+        flips.push(Flip {
+            profit_per_hour: spread.net_spread.checked_mul(units_per_hour).ok_or(ArithmeticError("profit per hour"))?,
+            spread,
+            weekly_volume,
+            units_per_hour,
+            fill_hours: fill.hours,
+        });
+    }
+    flips.sort_by(|a, b| b.profit_per_hour.cmp(&a.profit_per_hour).then_with(|| a.spread.product_id.cmp(&b.spread.product_id)));
+    Ok(flips)
+}
+
+pub fn print_flips(flips: &[Flip], items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>14} {:>14} {:>12} {:>12} {:>8} {:>12} {:>10} {:>14} {:>12}",
+        "product_id", "name", "buy_price", "sell_price", "spread", "net_spread", "net%", "week_volume", "units/h", "profit/h", "fill_time"
+    );
+    for flip in flips {
+        println!(
+            "{:<40} {:<32} {:>14} {:>14} {:>12} {:>12} {:>8} {:>12} {:>10} {:>14} {:>12}",
+            flip.spread.product_id,
+            items.name(&flip.spread.product_id),
+            flip.spread.buy_price.to_string(),
+            flip.spread.sell_price.to_string(),
+            flip.spread.spread.to_string(),
+            flip.spread.net_spread.to_string(),
+            flip.spread.net_margin.to_string(),
+            flip.weekly_volume,
+            flip.units_per_hour.to_string(),
+            flip.profit_per_hour.format_coins(),
+            filltime::format_hours(flip.fill_hours)
+        );
+    }
+}
+
+pub fn write_flips_csv(flips: &[Flip], items: &ItemCatalog, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<std::fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(["product_id", "name", "buy_price", "sell_price", "spread", "margin", "net_spread", "net_margin", "weekly_volume", "units_per_hour", "profit_per_hour", "fill_hours"])?;
+    for flip in flips {
+        wtr.write_record([
+            &flip.spread.product_id,
+            &items.name(&flip.spread.product_id).into_owned(),
+            &flip.spread.buy_price.to_string(),
+            &flip.spread.sell_price.to_string(),
+            &flip.spread.spread.to_string(),
+            &flip.spread.margin.to_string(),
+            &flip.spread.net_spread.to_string(),
+            &flip.spread.net_margin.to_string(),
+            &flip.weekly_volume.to_string(),
+            &flip.units_per_hour.to_string(),
+            &flip.profit_per_hour.to_string(),
+            &flip.fill_hours.map(|hours| format!("{:.2}", hours)).unwrap_or_default(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,215 @@
+// Rough price projections from the stored history, averaged into evenly spaced buckets first: a
+// least-squares line, Holt's level and trend smoothing, or Holt-Winters with a daily (or other)
+// season on top. Smoothing weights are picked by a grid search on the one-step errors. The band is
+// 95% under normal errors, which bazaar prices only roughly have.
+use crate::error::BazaarError;
+use crate::timezone::format_timestamp;
+use clap::ValueEnum;
+use std::path::Path;
+
+// Two-sided 95% quantile of the normal distribution
+const Z_95: f64 = 1.96;
+const GRID: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ForecastMethod {
+    // Least-squares line through the buckets
+    Linear,
+    // Exponentially smoothed level and trend
+    Holt,
+    // Holt plus an additive season, needs two seasons of history
+    HoltWinters,
+}
+
+impl ForecastMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            ForecastMethod::Linear => "linear",
+            ForecastMethod::Holt => "holt",
+            ForecastMethod::HoltWinters => "holt-winters",
+        }
+    }
+}
+
+pub struct ForecastPoint {
+    pub timestamp: i64,
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+pub struct Forecast {
+    pub method: ForecastMethod,
+    // Smoothing weights the grid search settled on, none for a line
+    pub weights: Vec<(&'static str, f64)>,
+    // Buckets fitted, gaps filled in
+    pub buckets: usize,
+    // Standard deviation of the in-sample errors
+    pub error: f64,
+    pub points: Vec<ForecastPoint>,
+}
+
+// One value every `step` millis from the first bucket to the last, missing buckets interpolated
+fn regular(buckets: &[(i64, f64)], step: i64) -> Vec<f64> {
+    let mut values: Vec<f64> = Vec::new();
+    for pair in buckets.windows(2) {
+        let ((start, from), (end, to)) = (pair[0], pair[1]);
+        let steps: i64 = ((end - start) / step).max(1);
+        values.extend((0..steps).map(|i| from + (to - from) * i as f64 / steps as f64));
+    }
+    values.extend(buckets.last().map(|(_, value)| *value));
+    values
+}
+
+struct Fit {
+    weights: Vec<(&'static str, f64)>,
+    error: f64,
+    // The central projection and its error's standard deviation, 1..=horizon steps ahead
+    projection: Vec<(f64, f64)>,
+}
+
+fn linear(values: &[f64], horizon: usize) -> Fit {
+    let n: f64 = values.len() as f64;
+    let mean_x: f64 = (n - 1.0) / 2.0;
+    let mean_y: f64 = values.iter().sum::<f64>() / n;
+    let sxx: f64 = (0..values.len()).map(|x| (x as f64 - mean_x).powi(2)).sum();
+    let sxy: f64 = values.iter().enumerate().map(|(x, y)| (x as f64 - mean_x) * (y - mean_y)).sum();
+    let slope: f64 = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    let intercept: f64 = mean_y - slope * mean_x;
+    let sse: f64 = values.iter().enumerate().map(|(x, y)| (y - intercept - slope * x as f64).powi(2)).sum();
+    let error: f64 = (sse / (n - 2.0).max(1.0)).sqrt();
+    let projection: Vec<(f64, f64)> = (1..=horizon)
+        .map(|h| {
+            let x: f64 = n - 1.0 + h as f64;
+            let spread: f64 = error * (1.0 + 1.0 / n + (x - mean_x).powi(2) / sxx.max(f64::MIN_POSITIVE)).sqrt();
+            (intercept + slope * x, spread)
+        })
+        .collect();
+    Fit { weights: Vec::new(), error, projection }
+}
+
+// Level, trend and season after smoothing through `values`, with the sum of squared one-step
+// errors. `season` is 0 for plain Holt.
+fn smooth(values: &[f64], season: usize, alpha: f64, beta: f64, gamma: f64) -> (f64, f64, Vec<f64>, f64) {
+    let (mut level, mut trend, mut seasonal, start): (f64, f64, Vec<f64>, usize) = if season == 0 {
+        (values[0], values[1] - values[0], Vec::new(), 1)
+    } else {
+        let first: f64 = values[..season].iter().sum::<f64>() / season as f64;
+        let second: f64 = values[season..2 * season].iter().sum::<f64>() / season as f64;
+        (first, (second - first) / season as f64, values[..season].iter().map(|value| value - first).collect(), season)
+    };
+    let mut sse: f64 = 0.0;
+    for (t, value) in values.iter().enumerate().skip(start) {
+        let offset: f64 = if season == 0 { 0.0 } else { seasonal[t % season] };
+        sse += (value - (level + trend + offset)).powi(2);
+        let previous: f64 = level;
+        level = alpha * (value - offset) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        if season > 0 {
+            seasonal[t % season] = gamma * (value - level) + (1.0 - gamma) * offset;
+        }
+    }
+    (level, trend, seasonal, sse)
+}
+
+fn holt_winters(values: &[f64], season: usize, horizon: usize) -> Fit {
+    let gammas: &[f64] = if season == 0 { &[0.0] } else { &GRID };
+    let mut best: (f64, f64, f64, f64) = (f64::INFINITY, 0.5, 0.1, 0.1);
+    for alpha in GRID {
+        for beta in GRID {
+            for &gamma in gammas {
+                let (_, _, _, sse) = smooth(values, season, alpha, beta, gamma);
+                if sse < best.0 {
+                    best = (sse, alpha, beta, gamma);
+                }
+            }
+        }
+    }
+    let (sse, alpha, beta, gamma) = best;
+    let (level, trend, seasonal, _) = smooth(values, season, alpha, beta, gamma);
+    let fitted: usize = values.len() - if season == 0 { 1 } else { season };
+    let error: f64 = (sse / fitted.max(1) as f64).sqrt();
+    // The error variance grows with every step the smoothed components carry forward
+    let mut variance: f64 = 0.0;
+    let projection: Vec<(f64, f64)> = (1..=horizon)
+        .map(|h| {
+            variance += if h == 1 {
+                1.0
+            } else {
+                let j: usize = h - 1;
+                let seasonal_term: f64 = if season > 0 && j.is_multiple_of(season) { gamma } else { 0.0 };
+                (alpha * (1.0 + j as f64 * beta) + seasonal_term).powi(2)
+            };
+            let offset: f64 = if season == 0 { 0.0 } else { seasonal[(values.len() + h - 1) % season] };
+            (level + h as f64 * trend + offset, error * variance.sqrt())
+        })
+        .collect();
+    let mut weights: Vec<(&'static str, f64)> = vec![("alpha", alpha), ("beta", beta)];
+    if season > 0 {
+        weights.push(("gamma", gamma));
+    }
+    Fit { weights, error, projection }
+}
+
+// `buckets` are (bucket start, value) every `step` millis or with gaps, oldest first. Projects
+// `horizon` steps past the last one, Holt-Winters repeating every `season` steps.
+pub fn forecast(buckets: &[(i64, f64)], step: i64, horizon: usize, method: ForecastMethod, season: usize) -> Result<Forecast, BazaarError> {
+    let values: Vec<f64> = regular(buckets, step);
+    let needed: usize = match method {
+        ForecastMethod::Linear | ForecastMethod::Holt => 3,
+        ForecastMethod::HoltWinters => 2 * season + 1,
+    };
+    if values.len() < needed {
+        return Err(BazaarError::Input(format!(
+            "A {} forecast needs at least {} buckets of history, there are {}",
+            method.name(), needed, values.len()
+        )));
+    }
+    let fit: Fit = match method {
+        ForecastMethod::Linear => linear(&values, horizon),
+        ForecastMethod::Holt => holt_winters(&values, 0, horizon),
+        ForecastMethod::HoltWinters => holt_winters(&values, season, horizon),
+    };
+    let last: i64 = buckets.last().map_or(0, |(timestamp, _)| *timestamp);
+    let points: Vec<ForecastPoint> = fit.projection.iter()
+        .enumerate()
+        .map(|(i, (value, spread))| ForecastPoint {
+            timestamp: last + (i as i64 + 1) * step,
+            value: *value,
+            low: value - Z_95 * spread,
+            high: value + Z_95 * spread,
+        })
+        .collect();
+    Ok(Forecast { method, weights: fit.weights, buckets: values.len(), error: fit.error, points })
+}
+
+pub fn print_forecast(product_id: &str, metric: &str, forecast: &Forecast, last: (i64, f64)) {
+    let weights: String = forecast.weights.iter().map(|(name, weight)| format!("{} {:.1}", name, weight)).collect::<Vec<String>>().join(", ");
+    println!(
+        "{} {} by {}{} over {} buckets, in-sample error {:.1}",
+        product_id, metric, forecast.method.name(), if weights.is_empty() { String::new() } else { format!(" ({})", weights) },
+        forecast.buckets, forecast.error
+    );
+    println!("Last bucket: {:.1} at {}", last.1, format_timestamp(last.0));
+    println!();
+    println!("{:<25} {:>14} {:>14} {:>14}", "time", "forecast", "low_95", "high_95");
+    for point in &forecast.points {
+        println!("{:<25} {:>14.1} {:>14.1} {:>14.1}", format_timestamp(point.timestamp), point.value, point.low, point.high);
+    }
+}
+
+pub fn write_forecast_csv(forecast: &Forecast, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<std::fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(["timestamp", "time", "forecast", "low_95", "high_95"])?;
+    for point in &forecast.points {
+        wtr.write_record([
+            point.timestamp.to_string(),
+            format_timestamp(point.timestamp),
+            format!("{:.2}", point.value),
+            format!("{:.2}", point.low),
+            format!("{:.2}", point.high),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// Holes in the collected time series, periods where the daemon wasn't running or the machine slept.
+// Known gaps are recorded as markers, so analyses can flag the windows they cut into.
+use crate::error::BazaarError;
+use crate::storage::{append_lines, newest_file, newest_jsonl_last_updated, snapshot_timestamp};
+use crate::timezone::format_timestamp;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// Markers go to <raw-dir>/gaps/markers.jsonl, one gap per line
+pub const GAPS_DIR: &str = "gaps";
+const MARKERS_FILE: &str = "markers.jsonl";
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Gap {
+    // Last snapshot before the gap and first one after it, epoch millis. For a gap recorded
+    // on a restart the end is the restart.
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Gap {
+    pub fn millis(&self) -> i64 {
+        self.end - self.start
+    }
+
+    // Cuts into the period from `start` to `end`
+    pub fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.start < end && self.end > start
+    }
+}
+
+pub fn markers_path(raw_dir: &Path) -> PathBuf {
+    raw_dir.join(GAPS_DIR).join(MARKERS_FILE)
+}
+
+// Recorded gaps oldest first, none when nothing was recorded yet. Lines that don't parse are skipped.
+pub fn load_markers(raw_dir: &Path) -> Vec<Gap> {
+    let path: PathBuf = markers_path(raw_dir);
+    let Ok(data) = fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let mut gaps: Vec<Gap> = data.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).map_err(|e| warn!("Skipping a gap marker in {}: {}", path.display(), e)).ok())
+        .collect();
+    gaps.sort_by_key(|gap| gap.start);
+    gaps.dedup_by_key(|gap| (gap.start, gap.end));
+    gaps
+}
+
+// The first recorded gap cutting into the period from `start` to `end`
+pub fn overlapping(gaps: &[Gap], start: i64, end: i64) -> Option<&Gap> {
+    gaps.iter().find(|gap| gap.overlaps(start, end))
+}
+
+pub async fn record_markers(raw_dir: &Path, gaps: &[Gap]) -> Result<(), BazaarError> {
+    let mut lines: String = String::new();
+    for gap in gaps {
+        lines.push_str(&serde_json::to_string(gap)?);
+        lines.push('\n');
+    }
+    append_lines(&markers_path(raw_dir), &lines).await
+}
+
+// Called when the daemon starts: if the newest snapshot is more than `max_gap` millis older
+// than `now`, the time since is recorded as a gap and returned
+pub async fn record_restart(raw_dir: &Path, max_gap: i64, now: i64) -> Result<Option<Gap>, BazaarError> {
+    let newest_snapshot: Option<i64> = newest_file(raw_dir).and_then(|file| snapshot_timestamp(&file));
+    let Some(newest) = newest_snapshot.max(newest_jsonl_last_updated(raw_dir).map(|last_updated| last_updated as i64)) else {
+        return Ok(None);
+    };
+    if now - newest <= max_gap {
+        return Ok(None);
+    }
+    let gap: Gap = Gap { start: newest, end: now };
+    record_markers(raw_dir, std::slice::from_ref(&gap)).await?;
+    Ok(Some(gap))
+}
+
+// Consecutive snapshots further apart than `min_gap` millis, `timestamps` in any order
+pub fn find_gaps(timestamps: &[i64], min_gap: i64) -> Vec<Gap> {
+    let mut sorted: Vec<i64> = timestamps.to_vec();
+    sorted.sort_unstable();
+    sorted.windows(2)
+        .filter(|pair| pair[1] - pair[0] > min_gap)
+        .map(|pair| Gap { start: pair[0], end: pair[1] })
+        .collect()
+}
+
+// "1d 2h", "3h 5m", "12m 30s"
+pub fn format_duration(millis: i64) -> String {
+    let seconds: i64 = millis / 1000;
+    let (days, hours, minutes) = (seconds / 86_400, seconds % 86_400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
+
+pub fn print_gaps(gaps: &[Gap], timestamps: &[i64]) {
+    let (Some(first), Some(last)) = (timestamps.iter().min(), timestamps.iter().max()) else {
+        println!("No snapshots to check");
+        return;
+    };
+    for gap in gaps {
+        println!("{} -> {}  {:>10}", format_timestamp(gap.start), format_timestamp(gap.end), format_duration(gap.millis()));
+    }
+    let span: i64 = last - first;
+    let missing: i64 = gaps.iter().map(Gap::millis).sum();
+    let coverage: f64 = if span > 0 { 100.0 * (span - missing) as f64 / span as f64 } else { 100.0 };
+    println!(
+        "{} gaps in {} snapshots from {} to {}, {} inside gaps ({:.1}% covered)",
+        gaps.len(), timestamps.len(), format_timestamp(*first), format_timestamp(*last), format_duration(missing), coverage
+    );
+}
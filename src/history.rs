@@ -0,0 +1,147 @@
+// Quick status of every product over time, read from the raw snapshots
+use crate::error::BazaarError;
+use crate::watchlist::Watchlist;
+use crate::model::QuickStatus;
+use crate::storage::{chain_start, read_parallel, read_products, snapshot_timestamp};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::warn;
+
+// Only quick_status is materialized, the order books are skipped while parsing
+#[derive(Deserialize)]
+pub struct SummaryProduct {
+    pub quick_status: QuickStatus,
+}
+
+pub struct HistoryPoint {
+    // lastUpdated of the snapshot, epoch millis
+    pub timestamp: i64,
+    pub products: HashMap<String, QuickStatus>,
+}
+
+// A rolling analysis window like "30m", "24h" or "7d", keeps the spelling it was given
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Window {
+    pub millis: i64,
+    label: String,
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value: &str = value.trim();
+        let split: usize = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (count, unit) = value.split_at(split);
+        let unit_millis: i64 = match unit {
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            "w" => 7 * 24 * 60 * 60 * 1000,
+            _ => return Err(format!("Invalid window {:?}, expected a number followed by s, m, h, d or w", value)),
+        };
+        let count: i64 = count.parse().map_err(|_| format!("Invalid window {:?}, missing the number", value))?;
+        if count == 0 {
+            return Err(format!("Invalid window {:?}, must be longer than zero", value));
+        }
+        let millis: i64 = count.checked_mul(unit_millis).ok_or_else(|| format!("Invalid window {:?}, too long", value))?;
+        Ok(Self { millis, label: value.to_string() })
+    }
+}
+
+impl TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+// Points are ordered oldest first
+pub struct History {
+    pub points: Vec<HistoryPoint>,
+}
+
+// Quick status of every product in one snapshot, without the order books
+pub fn read_quick_status(path: &Path) -> Result<HashMap<String, QuickStatus>, BazaarError> {
+    let (_, products): (u64, HashMap<String, SummaryProduct>) = read_products(path, &Watchlist::default())?;
+    Ok(products.into_iter().map(|(id, product)| (id, product.quick_status)).collect())
+}
+
+impl History {
+    // Files are parsed in parallel, unreadable ones are skipped with a warning
+    pub fn load(files: &[PathBuf], watchlist: &Watchlist) -> Result<Self, BazaarError> {
+        let mut points: Vec<HistoryPoint> = Vec::with_capacity(files.len());
+        read_parallel(
+            files,
+            watchlist,
+            |_, last_updated, products: &HashMap<String, SummaryProduct>| Ok(HistoryPoint {
+                timestamp: last_updated as i64,
+                products: products.iter().map(|(id, product)| (id.clone(), product.quick_status.clone())).collect(),
+            }),
+            |file, point| {
+                match point {
+                    Ok(point) => points.push(point),
+                    Err(e) if e.is_file_local() => warn!("Skipping {}: {}", file.display(), e),
+                    Err(e) => return Err(e),
+                }
+                Ok(())
+            },
+        )?;
+        points.sort_by_key(|point| point.timestamp);
+        Ok(Self { points })
+    }
+
+    // The snapshots from the last `window_millis` before the newest one. Reading starts at the
+    // keyframe the first of them builds on, the points before the window are dropped after.
+    pub fn load_window(files: &[PathBuf], window_millis: i64, watchlist: &Watchlist) -> Result<Self, BazaarError> {
+        let Some(latest) = files.last().and_then(|file| snapshot_timestamp(file)) else {
+            return Ok(Self { points: Vec::new() });
+        };
+        let since: i64 = latest - window_millis;
+        let first: usize = files.iter()
+            .position(|file| snapshot_timestamp(file).is_some_and(|timestamp| timestamp >= since))
+            .unwrap_or(files.len());
+        let mut history: Self = Self::load(&files[chain_start(files, first)..], watchlist)?;
+        history.points.retain(|point| point.timestamp >= since);
+        Ok(history)
+    }
+
+    // (timestamp, quick_status) of every point that has the product, oldest first
+    pub fn product_series(&self, product_id: &str) -> Vec<(i64, &QuickStatus)> {
+        self.points.iter()
+            .filter_map(|point| point.products.get(product_id).map(|quick_status| (point.timestamp, quick_status)))
+            .collect()
+    }
+}
+
+// Accepts epoch millis (like lastUpdated), RFC 3339, or a UTC "YYYY-MM-DD[ HH:MM[:SS]]"
+pub fn parse_timestamp(value: &str) -> Result<i64, String> {
+    if let Ok(millis) = value.parse::<i64>() {
+        return Ok(millis);
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.timestamp_millis());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(datetime.and_utc().timestamp_millis());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc().timestamp_millis());
+    }
+    Err(format!("Unrecognized timestamp: {}", value))
+}
@@ -0,0 +1,141 @@
+// Moving averages of buy and sell prices over rolling windows of the snapshot history
+use crate::error::BazaarError;
+use crate::gaps::{self, Gap, format_duration};
+use crate::history::{History, Window};
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::timezone::format_timestamp;
+use tracing::warn;
+
+// (timestamp, price) samples of one product, oldest first
+pub type Series = Vec<(i64, f64)>;
+
+// Averages of one price over one window, None when there's no sample to average
+#[derive(Clone, Copy)]
+pub struct Averages {
+    pub sma: Option<f64>,
+    pub ema: Option<f64>,
+}
+
+pub struct Indicators {
+    pub window: Window,
+    pub buy: Averages,
+    pub sell: Averages,
+    // A recorded gap inside the window, the averages miss that time
+    pub gap: Option<Gap>,
+}
+
+// Values of the samples taken within `window_millis` of the newest one
+pub fn recent(series: &[(i64, f64)], window_millis: i64) -> Vec<f64> {
+    let Some(&(newest, _)) = series.last() else {
+        return Vec::new();
+    };
+    series.iter()
+        .filter(|(timestamp, _)| newest - timestamp < window_millis)
+        .map(|(_, value)| *value)
+        .collect()
+}
+
+// Plain mean of the samples in the window
+pub fn sma(series: &[(i64, f64)], window_millis: i64) -> Option<f64> {
+    let recent: Vec<f64> = recent(series, window_millis);
+    (!recent.is_empty()).then(|| recent.iter().sum::<f64>() / recent.len() as f64)
+}
+
+// Snapshots aren't evenly spaced, so the smoothing factor comes from the time since the
+// previous sample: a sample `window_millis` old carries 1/e of the weight of a fresh one
+pub fn ema(series: &[(i64, f64)], window_millis: i64) -> Option<f64> {
+    let (&(first_timestamp, first_value), rest) = series.split_first()?;
+    let mut ema: f64 = first_value;
+    let mut previous: i64 = first_timestamp;
+    for &(timestamp, value) in rest {
+        let alpha: f64 = 1.0 - (-((timestamp - previous) as f64) / window_millis as f64).exp();
+        ema += alpha * (value - ema);
+        previous = timestamp;
+    }
+    Some(ema)
+}
+
+fn averages(series: &[(i64, f64)], window: &Window) -> Averages {
+    Averages {
+        sma: sma(series, window.millis),
+        ema: ema(series, window.millis),
+    }
+}
+
+// (buy, sell) price series of one product, oldest first
+pub fn price_series(history: &History, product_id: &str) -> (Series, Series) {
+    let series: Vec<(i64, &QuickStatus)> = history.product_series(product_id);
+    (
+        series.iter().map(|(timestamp, quick_status)| (*timestamp, quick_status.buyPrice)).collect(),
+        series.iter().map(|(timestamp, quick_status)| (*timestamp, quick_status.sellPrice)).collect(),
+    )
+}
+
+// One entry per window, in the order given. Windows end at the product's newest sample.
+pub fn compute(history: &History, product_id: &str, windows: &[Window], gaps: &[Gap]) -> Vec<Indicators> {
+    let (buy, sell): (Series, Series) = price_series(history, product_id);
+    let newest: i64 = buy.last().map_or(0, |(timestamp, _)| *timestamp);
+    windows.iter()
+        .map(|window| Indicators {
+            window: window.clone(),
+            buy: averages(&buy, window),
+            sell: averages(&sell, window),
+            gap: gaps::overlapping(gaps, newest - window.millis, newest).cloned(),
+        })
+        .collect()
+}
+
+// How far `price` sits above (positive) or below (negative) `average`, in percent
+pub fn percent_from(price: f64, average: Option<f64>) -> Option<f64> {
+    average.filter(|average| *average > 0.0).map(|average| (price - average) / average * 100.0)
+}
+
+pub fn format_price(value: Option<f64>) -> String {
+    value.map(|value| format!("{:.1}", value)).unwrap_or_else(|| "-".to_string())
+}
+
+pub fn format_percent(value: Option<f64>) -> String {
+    value.map(|value| format!("{:+.2}", value)).unwrap_or_else(|| "-".to_string())
+}
+
+pub fn print_indicators(history: &History, product_id: &str, windows: &[Window], gaps: &[Gap], items: &ItemCatalog) -> Result<(), BazaarError> {
+    let series: Vec<(i64, &QuickStatus)> = history.product_series(product_id);
+    let Some(&(newest, current)) = series.last() else {
+        return Err(BazaarError::Input(format!("No snapshot contains {}", product_id)));
+    };
+    let covered: i64 = newest - series[0].0;
+    println!("{} ({}), {} snapshots", product_id, items.name(product_id), series.len());
+    println!("Current buy price: {:.1}, sell price: {:.1}", current.buyPrice, current.sellPrice);
+    for window in windows.iter().filter(|window| covered < window.millis) {
+        warn!("History only covers {}, shorter than the {} window", format_duration(covered), window);
+    }
+    println!(
+        "{:<8} {:>14} {:>14} {:>12} {:>14} {:>14} {:>12}",
+        "window", "buy_sma", "buy_ema", "buy_vs_sma%", "sell_sma", "sell_ema", "sell_vs_sma%"
+    );
+    let indicators: Vec<Indicators> = compute(history, product_id, windows, gaps);
+    for indicators in &indicators {
+        // Starred when a gap cuts into the window
+        let label: String = format!("{}{}", indicators.window, if indicators.gap.is_some() { "*" } else { "" });
+        println!(
+            "{:<8} {:>14} {:>14} {:>12} {:>14} {:>14} {:>12}",
+            label,
+            format_price(indicators.buy.sma),
+            format_price(indicators.buy.ema),
+            format_percent(percent_from(current.buyPrice, indicators.buy.sma)),
+            format_price(indicators.sell.sma),
+            format_price(indicators.sell.ema),
+            format_percent(percent_from(current.sellPrice, indicators.sell.sma))
+        );
+    }
+    for indicators in &indicators {
+        if let Some(gap) = &indicators.gap {
+            println!(
+                "* {} includes a gap from {} to {} ({}), its averages miss that time",
+                indicators.window, format_timestamp(gap.start), format_timestamp(gap.end), format_duration(gap.millis())
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// Item metadata from /v2/resources/skyblock/items: display names, tiers and NPC sell prices.
+// It changes only with game updates, so it's cached on disk and refreshed once the cache is stale.
+use crate::config::{ApiConfig, ItemsConfig};
+use crate::error::BazaarError;
+use crate::fetch::fetch_json;
+use crate::names;
+use crate::storage::{read_json, write_snapshot};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Item {
+    pub id: String,
+    pub name: String,
+    pub tier: Option<String>,
+    pub category: Option<String>,
+    pub npc_sell_price: Option<f64>,
+}
+
+// The cache file keeps the API's shape, minus the fields Item doesn't read
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize)]
+struct ItemsResponse {
+    success: bool,
+    lastUpdated: u64,
+    items: Vec<Item>,
+}
+
+#[derive(Default)]
+pub struct ItemCatalog {
+    items: HashMap<String, Item>,
+}
+
+impl ItemCatalog {
+    fn from_response(response: ItemsResponse) -> Self {
+        Self {
+            items: response.items.into_iter().map(|item| (item.id.clone(), item)).collect(),
+        }
+    }
+
+    pub fn get(&self, product_id: &str) -> Option<&Item> {
+        self.items.get(product_id)
+    }
+
+    // Falls back to a name made from the product ID, enchantments and a few other bazaar
+    // products aren't items
+    pub fn name<'a>(&'a self, product_id: &str) -> Cow<'a, str> {
+        self.get(product_id).map_or_else(|| Cow::Owned(names::normalize(product_id)), |item| Cow::Borrowed(item.name.as_str()))
+    }
+
+    pub fn tier(&self, product_id: &str) -> Option<&str> {
+        self.get(product_id)?.tier.as_deref()
+    }
+
+    pub fn category(&self, product_id: &str) -> Option<&str> {
+        self.get(product_id)?.category.as_deref()
+    }
+
+    pub fn npc_sell_price(&self, product_id: &str) -> Option<f64> {
+        self.get(product_id)?.npc_sell_price
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+fn cache_age(path: &Path) -> Option<Duration> {
+    let modified: SystemTime = path.metadata().ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+// Always hits the API and rewrites the cache
+pub async fn refresh(client: &reqwest::Client, api: &ApiConfig, cache: &Path) -> Result<ItemCatalog, BazaarError> {
+    let response: ItemsResponse = fetch_json(client, api, &api.items_url).await?;
+    if !response.success {
+        return Err(BazaarError::Api("Items request failed".to_string()));
+    }
+    write_snapshot(cache, &response, false).await?;
+    Ok(ItemCatalog::from_response(response))
+}
+
+// Uses the cache while it's fresh. A failed refresh falls back to the stale cache, or to no
+// metadata at all, with a warning, so reports never fail just because the API is unreachable.
+// `offline` takes the cache however old it is and never refreshes.
+pub async fn load(client: &reqwest::Client, api: &ApiConfig, config: &ItemsConfig, offline: bool) -> ItemCatalog {
+    let max_age: Duration = Duration::from_secs(config.max_age_hours * 3600);
+    let cached: Option<ItemCatalog> = read_json(&config.cache).ok().map(ItemCatalog::from_response);
+    let fresh: bool = cache_age(&config.cache).is_some_and(|age| age <= max_age);
+    if cached.is_some() && fresh {
+        return cached.unwrap_or_default();
+    }
+    if offline {
+        if cached.is_none() {
+            debug!(path = %config.cache.display(), "No cached item metadata, names and categories are left out offline");
+        }
+        return cached.unwrap_or_default();
+    }
+    match refresh(client, api, &config.cache).await {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            warn!("Couldn't refresh item metadata: {}", e);
+            cached.unwrap_or_default()
+        }
+    }
+}
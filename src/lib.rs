@@ -0,0 +1,63 @@
+// Collects and summarizes Hypixel SkyBlock bazaar snapshots, the CLI in main.rs is a thin layer over this
+pub mod alerts;
+pub mod analysis;
+pub mod anomalies;
+pub mod auctions;
+pub mod backtest;
+pub mod candles;
+pub mod categories;
+pub mod coins;
+pub mod compact;
+pub mod config;
+pub mod correlations;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub mod db;
+pub mod depth;
+pub mod diff;
+pub mod error;
+pub mod export;
+pub mod feed;
+pub mod fetch;
+pub mod filltime;
+pub mod flips;
+pub mod forecast;
+pub mod gaps;
+pub mod history;
+pub mod indicators;
+pub mod items;
+pub mod liquidity;
+pub mod logging;
+pub mod model;
+pub mod movers;
+pub mod names;
+pub mod npc;
+pub mod paper;
+#[cfg(feature = "postgres")]
+pub mod pg;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod portfolio;
+pub mod pressure;
+pub mod prune;
+pub mod query;
+pub mod ratios;
+pub mod recipes;
+pub mod replay;
+pub mod report;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shopping;
+pub mod show;
+pub mod site;
+pub mod stats;
+pub mod storage;
+pub mod strategy;
+pub mod timezone;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod validation;
+pub mod volatility;
+pub mod watchlist;
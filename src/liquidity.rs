@@ -0,0 +1,91 @@
+// Liquidity score: how easily a product trades, from its weekly volume, queued order counts and the
+// units resting near the best price. Each part is measured on the thinner side of the book, a market
+// is only as liquid as the side you have to trade against.
+use crate::items::ItemCatalog;
+use crate::model::{Order, Product};
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+
+// Levels this far from the best price still count towards the depth
+const DEPTH_BAND_PERCENT: f64 = 5.0;
+
+// log10 of the value where each part stops adding to the score: 100M units a week, 1000 orders and
+// 10M units resting in the band are already as liquid as the bazaar gets
+const VOLUME_SATURATION: f64 = 8.0;
+const ORDERS_SATURATION: f64 = 3.0;
+const DEPTH_SATURATION: f64 = 7.0;
+
+// Volume is what actually fills orders, the book only says how crowded it is
+const VOLUME_WEIGHT: f64 = 0.5;
+const ORDERS_WEIGHT: f64 = 0.25;
+const DEPTH_WEIGHT: f64 = 0.25;
+
+pub struct Liquidity {
+    pub product_id: String,
+    // movingWeek of the thinner side
+    pub weekly_volume: u64,
+    // Order count of the side with fewer orders
+    pub orders: u32,
+    // Units within DEPTH_BAND_PERCENT of the best price, on the shallower side
+    pub depth: u64,
+    // 0 to 100
+    pub score: f64,
+}
+
+// Units on the levels within the band around the first (best) one
+fn band_depth(levels: &[Order]) -> u64 {
+    let Some(best) = levels.first().map(|level| level.pricePerUnit.to_float()) else {
+        return 0;
+    };
+    levels.iter()
+        .take_while(|level| (level.pricePerUnit.to_float() - best).abs() <= best * DEPTH_BAND_PERCENT / 100.0)
+        .map(|level| level.amount)
+        .sum()
+}
+
+fn part(value: f64, saturation: f64) -> f64 {
+    ((1.0 + value).log10() / saturation).min(1.0)
+}
+
+pub fn liquidity(id: &str, product: &Product) -> Liquidity {
+    let quick_status = &product.quick_status;
+    let weekly_volume: u64 = quick_status.buyMovingWeek.min(quick_status.sellMovingWeek);
+    let orders: u32 = quick_status.buyOrders.min(quick_status.sellOrders);
+    let depth: u64 = band_depth(&product.buy_summary).min(band_depth(&product.sell_summary));
+    let score: f64 = 100.0 * (
+        VOLUME_WEIGHT * part(weekly_volume as f64, VOLUME_SATURATION)
+            + ORDERS_WEIGHT * part(orders as f64, ORDERS_SATURATION)
+            + DEPTH_WEIGHT * part(depth as f64, DEPTH_SATURATION)
+    );
+    Liquidity { product_id: id.to_string(), weekly_volume, orders, depth, score }
+}
+
+// Most liquid first
+pub fn rank(products: &HashMap<String, Product>, watchlist: &Watchlist) -> Vec<Liquidity> {
+    let mut rows: Vec<Liquidity> = products.iter()
+        .filter(|(id, _)| watchlist.contains(id))
+        .map(|(id, product)| liquidity(id, product))
+        .collect();
+    rows.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.product_id.cmp(&b.product_id)));
+    rows
+}
+
+fn print_table(title: &str, rows: &[&Liquidity], items: &ItemCatalog) {
+    println!("{}", title);
+    println!("{:<40} {:<32} {:>14} {:>8} {:>12} {:>8}", "product_id", "name", "week_volume", "orders", "depth", "score");
+    for row in rows {
+        println!(
+            "{:<40} {:<32} {:>14} {:>8} {:>12} {:>8.1}",
+            row.product_id, items.name(&row.product_id), row.weekly_volume, row.orders, row.depth, row.score
+        );
+    }
+}
+
+// The `top` most and least liquid products, the thinnest market last
+pub fn print_liquidity(rows: &[Liquidity], top: usize, items: &ItemCatalog) {
+    let most: Vec<&Liquidity> = rows.iter().take(top).collect();
+    let least: Vec<&Liquidity> = rows.iter().skip(top).rev().take(top).rev().collect();
+    print_table("Most liquid", &most, items);
+    println!();
+    print_table("Least liquid", &least, items);
+}
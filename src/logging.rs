@@ -0,0 +1,47 @@
+// Progress and warnings go through tracing to stderr, so stdout only carries reports and tables
+use crate::timezone;
+use chrono::Utc;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One readable line per event
+    #[default]
+    Text,
+    // One JSON object per line, for journald, docker and log shippers
+    Json,
+}
+
+// Event times in the display timezone, like every other time the CLI prints
+struct DisplayTime;
+
+impl FormatTime for DisplayTime {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", timezone::format_timestamp(Utc::now().timestamp_millis()))
+    }
+}
+
+// `verbosity` counts -v as +1 and -q as -1 from the default INFO. Dependencies stay at INFO at most,
+// so -v doesn't bury our events under HTTP internals. RUST_LOG, when set, wins.
+pub fn init(verbosity: i8, format: LogFormat) {
+    let level: Level = match verbosity {
+        i8::MIN..=-2 => Level::ERROR,
+        -1 => Level::WARN,
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let filter: EnvFilter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(format!("{},bazaar_update={}", level.min(Level::INFO), level)));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.with_target(false).with_timer(DisplayTime).init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).init(),
+    }
+}
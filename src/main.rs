@@ -1,190 +1,1820 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::ops::{Add, Sub, Mul, Div};
-use std::fmt;
+use bazaar_update::alerts::{self, Alert};
+use bazaar_update::analysis::{self, Spread, SpreadSort, Summary};
+use bazaar_update::anomalies::{self, Anomaly};
+use bazaar_update::auctions;
+use bazaar_update::backtest::{self, BacktestOptions, BacktestReport};
+use bazaar_update::candles::{self, Candle, Resolution};
+use bazaar_update::categories;
+use bazaar_update::coins;
+use bazaar_update::compact::{self, CompactReport};
+use bazaar_update::config::{AlertMetric, AlertsConfig, AnomaliesConfig, ApiConfig, Config, CsvConfig, DatabaseConfig, SnapshotFormat};
+#[cfg(feature = "s3")]
+use bazaar_update::config::S3Bundle;
+use bazaar_update::correlations::{self, Correlations};
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use bazaar_update::db::BackfillReport;
+#[cfg(feature = "sqlite")]
+use bazaar_update::db::Database;
+use bazaar_update::depth;
+use bazaar_update::diff::{self, DiffThresholds, SnapshotDiff};
+use bazaar_update::error::BazaarError;
+use bazaar_update::export::{self, CsvColumn, CsvDialect, CsvOptions, CsvOrder, CsvQuote, CsvSort, Delimiter, LongCsvOptions};
+#[cfg(feature = "server")]
+use bazaar_update::feed::Feed;
+use bazaar_update::fetch::{self, FetchContext};
+use bazaar_update::filltime::{self, FillEstimate};
+use bazaar_update::flips::{self, Flip, FlipOptions};
+use bazaar_update::forecast::{self, Forecast, ForecastMethod};
+use bazaar_update::gaps::{self, Gap};
+use bazaar_update::history::{self, History, Window};
+use bazaar_update::indicators;
+use bazaar_update::items::{self, ItemCatalog};
+use bazaar_update::liquidity::{self, Liquidity};
+use bazaar_update::logging::{self, LogFormat};
+use bazaar_update::model::{BazaarResponse, FixedPoint, Product, QuickStatus};
+use bazaar_update::movers::{self, Comparison, MoverMetric};
+use bazaar_update::npc::{self, NpcFlip};
+use bazaar_update::paper::PaperTrader;
+#[cfg(feature = "postgres")]
+use bazaar_update::pg::PgDatabase;
+use bazaar_update::portfolio::{self, Portfolio};
+use bazaar_update::pressure::{self, Pressure};
+use bazaar_update::prune::{self, RetentionPolicy};
+use bazaar_update::query::{self, Aggregation, ProductSeries, QueryRow, QuerySource, SqlTable};
+use bazaar_update::ratios::{self, Ratio};
+use bazaar_update::recipes::{self, Craft, Recipe};
+use bazaar_update::replay::{self, Pipeline, Speed};
+use bazaar_update::report::{self, ReportOptions, ReportPeriod};
+#[cfg(feature = "s3")]
+use bazaar_update::s3;
+use bazaar_update::schedule::{CronExpression, Ticker};
+use bazaar_update::shopping::{self, ListItem, ShoppingPlan};
+use bazaar_update::show;
+use bazaar_update::site;
+use bazaar_update::stats::{self, DatasetStats};
+use bazaar_update::storage::naming::NameTemplate;
+use bazaar_update::storage::lock::DirectoryLock;
+use bazaar_update::storage::archive::{ArchiveReport, Month};
+use bazaar_update::storage::merge::MergeReport;
+use bazaar_update::storage::quarantine::{self, RepairReport};
+use bazaar_update::storage::{self, read_newest, snapshot_files, snapshot_timestamp};
+use bazaar_update::strategy::{Condition, RuleStrategy, Strategy};
+use bazaar_update::timezone::{self, DisplayTimezone};
+use bazaar_update::volatility::{self, Volatility};
+use bazaar_update::watchlist::Watchlist;
 use std::fs;
-use chrono::{Local, Timelike};
-
-// Simple fixed-point with 2 decimal places (scale factor of 100).
-// f.e. 1.23 is stored as 123.
-// honestly scale could be 1 TODO
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
-
-pub struct FixedPoint(i64);
-impl FixedPoint {
-    const SCALE: i64 = 100; // 10^2 for 2 decimal places
-    
-    // Constructor from a float (e.g., FixedPoint::from_float(1.23)) will round anyway 
-    pub fn from_float(value: f64) -> Self {
-        Self((value * Self::SCALE as f64).round() as i64)
-    }
-    
-    // Constructor from an integer (e.g., FixedPoint::from_int(123) for 1.23)
-    pub fn from_int(value: i64) -> Self {
-        Self(value)
-    }
-    
-    // Convert back to float for display or calculations
-    pub fn to_float(self) -> f64 {
-        self.0 as f64 / Self::SCALE as f64
-    }
-    
-    // Get the raw scaled value
-    pub fn raw(self) -> i64 {
-        self.0
-    }
+use chrono::Utc;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::{error, info, warn};
+
+#[derive(Parser)]
+#[command(version, about = "Collects and summarizes Hypixel SkyBlock bazaar snapshots")]
+struct Cli {
+    /// Config file to load [default: bazaar_update.toml if present]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory containing the raw snapshots [default: storage.raw_dir]
+    #[arg(long, global = true)]
+    raw_dir: Option<PathBuf>,
+    /// Snapshot path below the raw directory, like {date}/{epoch_ms} [default: storage.name_template]
+    #[arg(long, global = true)]
+    name_template: Option<NameTemplate>,
+    /// More log output, repeat for trace level
+    #[arg(short = 'v', long = "verbose", global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Less log output, once for warnings only, twice for errors only
+    #[arg(short = 'q', long = "quiet", global = true, action = clap::ArgAction::Count)]
+    quiet: u8,
+    /// How log lines are written to stderr [default: log.format]
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<LogFormat>,
+    /// Timezone times are printed in: UTC, local or an offset like +02:00 [default: display.timezone]
+    #[arg(long, global = true)]
+    timezone: Option<DisplayTimezone>,
+    /// Only include these product IDs, comma separated or repeated [default: watchlist.products]
+    #[arg(short = 'p', long = "product", global = true, value_delimiter = ',')]
+    products: Vec<String>,
+    /// Only include products in these categories, like farming or mining [default: watchlist.categories]
+    #[arg(long = "category", global = true, value_delimiter = ',')]
+    categories: Vec<String>,
+    /// Only read stored data: item metadata comes from the cache however old, and commands that need the network refuse to run
+    #[arg(long, global = true)]
+    offline: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl Add for FixedPoint {
-    type Output = Self;
-    fn add(self, other: Self) -> Self {
-        Self(self.0 + other.0)
+#[derive(Clone, Copy, ValueEnum)]
+enum CsvFormat {
+    Csv,
+    // Shorthand for a tab delimiter
+    Tsv,
+}
+
+#[derive(Args)]
+struct DialectArgs {
+    /// csv, or tsv for tab separated fields
+    #[arg(long, value_enum, conflicts_with = "delimiter")]
+    format: Option<CsvFormat>,
+    /// Field separator, a single character or "\t" [default: csv.delimiter]
+    #[arg(long)]
+    delimiter: Option<Delimiter>,
+    /// Which fields are wrapped in double quotes [default: csv.quote]
+    #[arg(long, value_enum)]
+    quote: Option<CsvQuote>,
+}
+
+impl DialectArgs {
+    fn resolve(self, config: &CsvConfig) -> CsvDialect {
+        let delimiter: Delimiter = match self.format {
+            Some(CsvFormat::Csv) => Delimiter(b','),
+            Some(CsvFormat::Tsv) => Delimiter(b'\t'),
+            None => self.delimiter.unwrap_or(config.delimiter),
+        };
+        CsvDialect { delimiter, quote: self.quote.unwrap_or(config.quote) }
     }
 }
 
-impl Sub for FixedPoint {
-    type Output = Self;
-    fn sub(self, other: Self) -> Self {
-        Self(self.0 - other.0)
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch the bazaar and save the response as a raw snapshot
+    Fetch {
+        /// Write the snapshot zstd-compressed (.json.zst)
+        #[arg(long, global = true)]
+        compress: bool,
+        /// How snapshots are stored: a file per fetch, a daily JSON Lines log or deltas [default: storage.format]
+        #[arg(long, value_enum)]
+        format: Option<SnapshotFormat>,
+        /// Only store watchlisted products in the snapshot [default: watchlist.filter_snapshots]
+        #[arg(long)]
+        watchlist_only: bool,
+        /// Write the snapshot even if the API hasn't updated since the newest one
+        #[arg(long, global = true)]
+        force: bool,
+        #[command(subcommand)]
+        target: Option<FetchTarget>,
+    },
+    /// Write a CSV summary of the newest raw snapshot
+    Csv {
+        /// Path of the generated CSV [default: csv.output]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Use every snapshot instead of the newest, one row per product per snapshot
+        #[arg(long)]
+        all: bool,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD), implies --all
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Add moving average columns computed from every snapshot [default windows: indicators.windows]
+        #[arg(long)]
+        indicators: bool,
+        /// Columns to write, comma separated, in this order [default: csv.columns]
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Option<Vec<CsvColumn>>,
+        /// Order of the rows within a snapshot, ties fall back to product_id
+        #[arg(long, value_enum, default_value_t = CsvSort::ProductId)]
+        sort_by: CsvSort,
+        /// Sort from the largest value down
+        #[arg(long)]
+        descending: bool,
+        /// Append the newest snapshot to the daily timestamp,product_id,metric,value file in csv.long_dir instead
+        #[arg(long, conflicts_with_all = ["output", "all", "since", "indicators", "sort_by", "descending"])]
+        append_long: bool,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+    /// Analyze the newest raw snapshot [default: status]
+    Analyze {
+        #[command(subcommand)]
+        report: Option<AnalyzeReport>,
+    },
+    /// Aggregate every raw snapshot into OHLC candles of buy and sell prices
+    Candles {
+        /// Candle widths to generate, comma separated or repeated
+        #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = [Resolution::FiveMinutes, Resolution::Hour, Resolution::Day])]
+        resolution: Vec<Resolution>,
+        /// Directory the candles_<resolution>.csv files are written to
+        #[arg(short, long, default_value = ".")]
+        output_dir: PathBuf,
+    },
+    /// Print buy, sell, spread and weekly volume of every product in the newest raw snapshot
+    Summary {
+        /// Print a compact JSON document instead, for jq and other scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the top products of the newest raw snapshot as a readable table
+    Show {
+        /// Metric the products are ranked by, largest first
+        #[arg(long, value_enum, default_value_t = CsvSort::BuyMovingWeek)]
+        sort_by: CsvSort,
+        /// Rank from the smallest value up
+        #[arg(long)]
+        ascending: bool,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Rank order flips in the newest raw snapshot by expected profit per hour
+    Flips {
+        /// Coins available for buy orders, like 10m or 500k
+        #[arg(long, default_value_t = flips::DEFAULT_CAPITAL, value_parser = coins::parse_coins)]
+        capital: f64,
+        /// Minimum weekly volume on the thinner side of the book
+        #[arg(long, default_value_t = flips::DEFAULT_MIN_VOLUME)]
+        min_volume: u64,
+        /// Minimum liquidity score from 0 to 100, see `analyze liquidity`
+        #[arg(long, default_value_t = flips::DEFAULT_MIN_LIQUIDITY)]
+        min_liquidity: f64,
+        /// Largest fraction of a side's hourly volume you expect to fill, 1 leaves only the queued orders as the limit
+        #[arg(long, default_value_t = flips::DEFAULT_MAX_SHARE)]
+        max_share: f64,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+        /// Also write the full ranking to this CSV
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Simulate an order-flip strategy on one product over the stored history, with P&L, drawdown and a trade log
+    Backtest {
+        /// Product the strategy trades
+        product_id: String,
+        /// Condition for placing a buy order, like "sellPrice < sma:1h-2%" or "margin > 5", repeat to require several
+        #[arg(long = "buy", required = true)]
+        buy: Vec<Condition>,
+        /// Condition for offering what was bought, like "buyPrice > sma:1h+2%", repeat to require several
+        #[arg(long = "sell", required = true)]
+        sell: Vec<Condition>,
+        /// Coins the strategy starts with, like 10m or 500k
+        #[arg(long, default_value_t = flips::DEFAULT_CAPITAL, value_parser = coins::parse_coins)]
+        capital: f64,
+        /// Fraction of every sale the bazaar keeps [default: tax.rate times tax.multiplier]
+        #[arg(long)]
+        tax: Option<f64>,
+        /// Largest fraction of a side's hourly volume one order fills, like `flips --max-share`
+        #[arg(long, default_value_t = flips::DEFAULT_MAX_SHARE)]
+        max_share: f64,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Skip snapshots newer than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        until: Option<i64>,
+        /// Trade log CSV, a row per filled order
+        #[arg(short, long, default_value = "backtest_trades.csv")]
+        output: PathBuf,
+        /// Also write the cash, value of the units held and equity after every snapshot to this CSV
+        #[arg(long)]
+        equity: Option<PathBuf>,
+    },
+    /// Record your own holdings and value them against the newest snapshot or the stored history
+    Portfolio {
+        /// Holdings file [default: portfolio.path]
+        #[arg(long)]
+        file: Option<PathBuf>,
+        #[command(subcommand)]
+        action: Option<PortfolioAction>,
+    },
+    /// Plan purchases against the newest snapshot's order book
+    Optimize {
+        #[command(subcommand)]
+        kind: OptimizeKind,
+    },
+    /// Write a Markdown report of the last day or week: movers, flips, volume leaders and price charts
+    Report {
+        /// Period the report covers, ending at the newest snapshot
+        #[arg(value_enum)]
+        period: ReportPeriod,
+        /// Directory the report and its charts are written to
+        #[arg(short, long, default_value = "reports")]
+        output_dir: PathBuf,
+        /// Also write the report as a standalone HTML page
+        #[arg(long)]
+        html: bool,
+        /// Rows per table, and products charted when the watchlist names none
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Print one metric of the --product products over the stored history, optionally resampled
+    Query {
+        /// Skip samples older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Skip samples newer than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        until: Option<i64>,
+        /// Quick status field to read, or spread and margin computed from it
+        #[arg(short, long, value_enum, default_value_t = AlertMetric::BuyPrice)]
+        metric: AlertMetric,
+        /// Bucket width like 5m, 1h or 1d, every sample is printed without one
+        #[arg(long)]
+        resample: Option<Window>,
+        /// How the samples in a bucket are combined
+        #[arg(long, value_enum, default_value_t = Aggregation::Mean)]
+        agg: Aggregation,
+        /// Where the history is read from, the databases are filled by `backfill`
+        #[arg(long, value_enum, default_value_t = QuerySource::Raw)]
+        source: QuerySource,
+        /// Also write the result to this CSV
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Run this SQL against the SQLite database instead, like "SELECT product_id, max(buy_price) FROM quick_status GROUP BY 1"
+        #[arg(long, conflicts_with_all = ["since", "until", "metric", "resample", "agg", "source"])]
+        sql: Option<String>,
+    },
+    /// Render the stored history into a self-contained HTML dashboard with charts and product search
+    Site {
+        /// Directory index.html is written to, ready for GitHub Pages
+        #[arg(short, long, default_value = "site")]
+        output_dir: PathBuf,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Most points charted per product, longer histories are thinned evenly
+        #[arg(long, default_value_t = 500, value_parser = clap::value_parser!(u64).range(2..))]
+        max_points: u64,
+    },
+    /// Import every raw snapshot into the SQLite or PostgreSQL database, already imported ones are skipped
+    #[cfg(any(feature = "sqlite", feature = "postgres"))]
+    Backfill {
+        /// SQLite file to import into [default: database.path]
+        #[arg(long, conflicts_with = "url")]
+        database: Option<PathBuf>,
+        /// PostgreSQL connection string to import into instead [default: database.url]
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Print per-product changes between two snapshots
+    Diff {
+        /// Older snapshot
+        #[arg(required_unless_present = "latest_two", requires = "file_b")]
+        file_a: Option<PathBuf>,
+        /// Newer snapshot
+        file_b: Option<PathBuf>,
+        /// Compare the two newest snapshots in the raw directory instead
+        #[arg(long, conflicts_with = "file_a")]
+        latest_two: bool,
+        /// Hide price changes smaller than this many percent
+        #[arg(long, default_value_t = 1.0)]
+        min_price_change: f64,
+        /// Hide volume changes smaller than this many percent
+        #[arg(long, default_value_t = 5.0)]
+        min_volume_change: f64,
+        /// Hide order count changes smaller than this
+        #[arg(long, default_value_t = 5)]
+        min_order_change: i64,
+    },
+    /// Snapshot counts, covered time range, gaps, disk usage per store and snapshots per day
+    Stats {
+        /// Count gaps longer than this many seconds [default: twice daemon.interval]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        min_gap: Option<u64>,
+        /// Number of most recent days listed with their snapshot count
+        #[arg(long, default_value_t = 14)]
+        days: usize,
+    },
+    /// Sanity checks over the collected snapshots
+    Check {
+        #[command(subcommand)]
+        check: CheckKind,
+    },
+    /// Keep fetching snapshots on a fixed interval or a cron schedule until interrupted
+    Daemon {
+        /// Write snapshots zstd-compressed (.json.zst)
+        #[arg(long)]
+        compress: bool,
+        /// How snapshots are stored: a file per fetch, a daily JSON Lines log or deltas [default: storage.format]
+        #[arg(long, value_enum)]
+        format: Option<SnapshotFormat>,
+        /// Only store watchlisted products in snapshots [default: watchlist.filter_snapshots]
+        #[arg(long)]
+        watchlist_only: bool,
+        /// Write a snapshot every cycle even if the bazaar hasn't updated
+        #[arg(long)]
+        force: bool,
+        /// Seconds between fetches [default: daemon.interval]
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: Option<u64>,
+        /// Fetch whenever this cron expression matches (UTC, seconds first), repeat to combine several [default: daemon.schedule]
+        #[arg(long, conflicts_with = "interval")]
+        schedule: Vec<CronExpression>,
+        /// Paper trade the [paper] strategy on every fetched snapshot with a virtual portfolio [default: paper.enabled]
+        #[arg(long)]
+        paper: bool,
+        /// Also serve the HTTP API on this address, with every fetch's changes streamed on /ws and /events
+        #[cfg(feature = "server")]
+        #[arg(long)]
+        serve: Option<std::net::SocketAddr>,
+    },
+    /// Chart the buy and sell price history of one product
+    #[cfg(feature = "plot")]
+    Plot {
+        /// Product to chart
+        product_id: String,
+        /// Image to write, .svg for SVG and anything else for PNG
+        #[arg(short, long, default_value = "plot.png")]
+        output: PathBuf,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Image width in pixels
+        #[arg(long, default_value_t = 1280)]
+        width: u32,
+        /// Image height in pixels
+        #[arg(long, default_value_t = 720)]
+        height: u32,
+    },
+    /// Delete raw snapshots according to a retention policy [default: prune.*]
+    Prune {
+        /// Delete snapshots older than this many days
+        #[arg(long)]
+        keep_days: Option<u64>,
+        /// Keep only one snapshot per hour once they are older than this many days
+        #[arg(long)]
+        hourly_after: Option<u64>,
+        /// Keep only one snapshot per day once they are older than this many days
+        #[arg(long)]
+        daily_after: Option<u64>,
+        /// List what would be deleted without touching anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Replace snapshots older than a few days with OHLC candles of summed volume [default: compact.*]
+    Compact {
+        /// Days of snapshots kept at full resolution
+        #[arg(long)]
+        full_days: Option<u64>,
+        /// Width of the candles older snapshots are replaced with
+        #[arg(short, long, value_enum)]
+        resolution: Option<Resolution>,
+        /// Report what would be compacted without touching anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bundle one month of snapshots into a verified .tar.zst and delete them from the raw directory
+    Archive {
+        /// UTC month to archive, like 2024-05
+        #[arg(long)]
+        month: Month,
+        /// Directory the tarball is written to, as <month>.tar.zst
+        #[arg(short, long, default_value = "archive")]
+        output_dir: PathBuf,
+        /// Leave the archived snapshots in the raw directory
+        #[arg(long)]
+        keep: bool,
+    },
+    /// Copy the snapshots of other collectors' raw directories into this one, deduplicated by lastUpdated
+    Merge {
+        /// Raw directories to merge, the first one's copy wins when two disagree
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
+        /// Write the merged snapshots zstd-compressed (.json.zst)
+        #[arg(long)]
+        compress: bool,
+        /// Report what would be merged without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Salvage the products still readable in quarantined snapshots and move them back
+    Repair {
+        /// List what would be recovered without touching anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Upload the raw snapshots to the S3 bucket in s3.*, existing objects are overwritten
+    #[cfg(feature = "s3")]
+    Upload {
+        /// Every snapshot file, or a bundle per completed UTC hour [default: s3.bundle]
+        #[arg(long, value_enum)]
+        bundle: Option<S3Bundle>,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+    },
+    /// Export the collected history in other file formats
+    Export {
+        #[command(subcommand)]
+        format: ExportFormat,
+    },
+    /// Serve the stored snapshots over a small JSON HTTP API
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: std::net::SocketAddr,
+    },
+    /// Poll the bazaar and print the quick status of products on every refresh
+    Watch {
+        /// Seconds between fetches [default: daemon.interval]
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: Option<u64>,
+        /// Print plain tables instead of the interactive viewer
+        #[cfg(feature = "tui")]
+        #[arg(long)]
+        plain: bool,
+    },
+    /// Feed stored snapshots through the alert rules, anomaly checks and viewer as if they were arriving live
+    Replay {
+        /// How much faster than they were collected snapshots come, like 10x or 0.5x, or max to not wait at all
+        #[arg(long, default_value = "1x")]
+        speed: Speed,
+        /// Start at the first snapshot from this time (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Stop after the last snapshot up to this time (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        until: Option<i64>,
+        /// Send the alerts to the configured notifiers instead of only logging them
+        #[arg(long)]
+        notify: bool,
+        /// Print the moving averages of watchlisted products after each snapshot [default: indicators.windows]
+        #[arg(long)]
+        indicators: bool,
+        /// Print plain tables instead of the interactive viewer
+        #[cfg(feature = "tui")]
+        #[arg(long)]
+        plain: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FetchTarget {
+    /// Fetch every page of the auction house into <raw-dir>/auctions
+    Auctions,
+    /// Refresh the cached item metadata (names, tiers, NPC prices) [default: items.cache]
+    Items,
+}
+
+#[derive(Subcommand)]
+enum CheckKind {
+    /// List periods without any snapshot, where analysis windows are unreliable
+    Gaps {
+        /// Report gaps longer than this many seconds [default: twice daemon.interval]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        min_gap: Option<u64>,
+        /// Record the gaps found as markers, so analyses flag the windows they cut into
+        #[arg(long)]
+        record: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PortfolioAction {
+    /// Print every holding's cost, value after tax and unrealized profit (the default)
+    Show,
+    /// Record a purchase, buying more of a held product averages its cost
+    Add {
+        product_id: String,
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        quantity: u64,
+        /// Coins paid per unit, like 1.2k [default: the newest snapshot's buyPrice]
+        #[arg(long, value_parser = coins::parse_coins)]
+        cost: Option<f64>,
+    },
+    /// Record a sale, of every unit held unless a quantity is given
+    Remove {
+        product_id: String,
+        quantity: Option<u64>,
+    },
+    /// Value today's holdings in every stored snapshot, as if they'd been held all along
+    History {
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// CSV with the value and unrealized profit at every snapshot
+        #[arg(short, long, default_value = "portfolio_value.csv")]
+        output: PathBuf,
+        /// Also chart the value against the cost basis, .svg for SVG and anything else for PNG
+        #[cfg(feature = "plot")]
+        #[arg(long)]
+        plot: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum OptimizeKind {
+    /// Decide per item whether to instabuy or place a buy order, the shortest wait the budget allows
+    Shopping {
+        /// Items needed as PRODUCT=QUANTITY, like ENCHANTED_DIAMOND=64
+        items: Vec<ListItem>,
+        /// Also buy the ingredients of this recipe's output, from the recipe file
+        #[arg(long)]
+        recipe: Option<String>,
+        /// Crafts of --recipe to buy for
+        #[arg(long, default_value_t = 1, requires = "recipe", value_parser = clap::value_parser!(u64).range(1..))]
+        crafts: u64,
+        /// JSON recipe file, see recipes.example.json [default: crafts.recipes]
+        #[arg(long)]
+        recipes: Option<PathBuf>,
+        /// Coins available for the whole list, like 50m or 320k
+        #[arg(long, value_parser = coins::parse_coins)]
+        budget: f64,
+        /// Largest fraction of a side's hourly volume one order fills, like `flips --max-share`
+        #[arg(long, default_value_t = flips::DEFAULT_MAX_SHARE)]
+        max_share: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum AnalyzeReport {
+    /// Print the quick status of every product
+    Status,
+    /// Rank products by buy/sell spread and margin, before and after tax
+    Spreads {
+        /// Column to sort by, largest first
+        #[arg(long, value_enum, default_value_t = SpreadSort::Net)]
+        sort_by: SpreadSort,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Rank recipes by the profit of crafting them from bazaar ingredients, after tax
+    Crafts {
+        /// JSON recipe file, see recipes.example.json [default: crafts.recipes]
+        #[arg(long)]
+        recipes: Option<PathBuf>,
+        /// Insta-buy ingredients and insta-sell the result instead of using orders
+        #[arg(long)]
+        instant: bool,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Track crafted items' prices against their ingredients' and flag profitable crafts or decrafts
+    Ratios {
+        /// JSON recipe file, see recipes.example.json [default: crafts.recipes]
+        #[arg(long)]
+        recipes: Option<PathBuf>,
+        /// History the current ratio is compared against [default: crafts.ratio_window]
+        #[arg(short, long)]
+        window: Option<Window>,
+        /// Margin in percent after tax a craft or decraft needs to be flagged [default: crafts.min_margin]
+        #[arg(long)]
+        min_margin: Option<f64>,
+        /// Insta-buy and insta-sell instead of using orders
+        #[arg(long)]
+        instant: bool,
+        /// Only print flagged rows
+        #[arg(long)]
+        flagged: bool,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Walk a product's order book to price instantly buying or selling a quantity
+    Depth {
+        /// Product to analyze
+        product_id: String,
+        /// Units to buy or sell
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        quantity: u64,
+    },
+    /// Estimate how long a buy order at a price takes to fill, from the book and recent snapshots
+    Filltime {
+        /// Product to analyze
+        product_id: String,
+        /// Price per unit of the order, like 1.2k [default: the top buy order's, the sellPrice]
+        #[arg(long, value_parser = coins::parse_coins)]
+        price: Option<f64>,
+        /// Units ordered
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        quantity: u64,
+        /// Snapshots this far back from the newest give the volume and how fast orders are added
+        #[arg(short, long, default_value = "1h")]
+        window: Window,
+    },
+    /// Project a product's price some hours ahead with a 95% band, rough but enough to time large orders
+    Forecast {
+        /// Product to forecast
+        product_id: String,
+        /// Quick status field to project, or spread and margin computed from it
+        #[arg(short, long, value_enum, default_value_t = AlertMetric::BuyPrice)]
+        metric: AlertMetric,
+        #[arg(long, value_enum, default_value_t = ForecastMethod::Holt)]
+        method: ForecastMethod,
+        /// How far past the newest bucket to project, like 6h or 2d
+        #[arg(long, default_value = "6h")]
+        horizon: Window,
+        /// Width of the buckets the history is averaged into first
+        #[arg(long, default_value = "1h")]
+        step: Window,
+        /// Length of the cycle holt-winters repeats, in whole steps
+        #[arg(long, default_value = "24h")]
+        season: Window,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Also write the projection to this CSV
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare a product's current prices with their moving averages over the stored history
+    Indicators {
+        /// Product to analyze
+        product_id: String,
+        /// Averaging windows like 30m, 24h or 7d, comma separated or repeated [default: indicators.windows]
+        #[arg(short, long, value_delimiter = ',')]
+        window: Vec<Window>,
+    },
+    /// Rank products by how much their prices moved, from the stored history
+    Volatility {
+        /// Windows to measure, comma separated or repeated, the last one decides the ranking
+        #[arg(short, long, value_delimiter = ',', default_values = ["1h", "24h", "7d"])]
+        window: Vec<Window>,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Correlate the watchlisted products' price series to find items that move together or against
+    Correlations {
+        /// Quick status field to compare, or spread and margin computed from it
+        #[arg(short, long, value_enum, default_value_t = AlertMetric::BuyPrice)]
+        metric: AlertMetric,
+        /// Width of the buckets the history is averaged into first
+        #[arg(long, default_value = "1h")]
+        step: Window,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        /// Correlate the bucketed values themselves instead of their changes
+        #[arg(long)]
+        levels: bool,
+        /// Number of pairs to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// List the biggest gainers and losers since the snapshot a window before the newest one
+    Movers {
+        /// How far back the snapshot to compare against is, like 1h, 24h or 7d
+        #[arg(short, long, default_value = "24h")]
+        window: Window,
+        /// Change to rank by
+        #[arg(long, value_enum, default_value_t = MoverMetric::Buy)]
+        sort_by: MoverMetric,
+        /// Number of gainers and losers to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Compare insta-buy against insta-sell volume flowing in over a window, from the movingWeek steps
+    Pressure {
+        /// How far back from the newest snapshot to sum the flow, like 1h or 24h
+        #[arg(short, long, default_value = "1h")]
+        window: Window,
+        /// Leave out products trading fewer units than this in the window
+        #[arg(long, default_value_t = 0)]
+        min_volume: u64,
+        /// Number of products to print for each side
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Score how easily products trade from order counts, order book depth and weekly volume
+    Liquidity {
+        /// Number of most and least liquid products to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Flag prices that jumped more standard deviations from their rolling mean than the threshold
+    Anomalies {
+        /// Rolling window the mean and standard deviation come from [default: anomalies.window]
+        #[arg(short, long)]
+        window: Option<Window>,
+        /// Standard deviations a jump needs to be flagged [default: anomalies.threshold]
+        #[arg(long)]
+        threshold: Option<f64>,
+        /// Only check the newest snapshot
+        #[arg(long)]
+        latest: bool,
+        /// Send the spikes in the newest snapshot to the alert notifiers
+        #[arg(long)]
+        alert: bool,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+    /// Find products that sell to NPCs for more than they cost on the bazaar
+    NpcFlips {
+        /// Units an NPC buys from you per day, for the per-limit profit columns
+        #[arg(long, default_value_t = 640)]
+        daily_limit: u64,
+        /// Number of rows to print
+        #[arg(short = 'n', long, default_value_t = 25)]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportFormat {
+    /// Write one product's prices, volumes and order counts over every snapshot to a CSV
+    History {
+        product_id: String,
+        /// Path of the generated CSV [default: <PRODUCT_ID>_history.csv]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+    /// Write one metric of every watchlisted product to a CSV with a row per snapshot and a column per product
+    Pivot {
+        /// Quick status field to write, or spread and margin computed from it
+        #[arg(short, long, value_enum, default_value_t = AlertMetric::BuyPrice)]
+        metric: AlertMetric,
+        /// Path of the generated CSV [default: pivot_<metric>.csv]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Bucket width like 5m, 1h or 1d, a row per snapshot without one
+        #[arg(long)]
+        resample: Option<Window>,
+        /// How the samples in a bucket are combined
+        #[arg(long, value_enum, default_value_t = Aggregation::Mean)]
+        agg: Aggregation,
+        /// Skip snapshots older than this (epoch millis, RFC 3339 or UTC YYYY-MM-DD)
+        #[arg(long, value_parser = history::parse_timestamp)]
+        since: Option<i64>,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+    /// Insert every raw snapshot into ClickHouse, creating the table when it's missing
+    Clickhouse {
+        /// HTTP interface to insert into [default: clickhouse.url]
+        #[arg(long)]
+        url: Option<String>,
+        /// Print the CREATE TABLE statement instead, to create the table by hand
+        #[arg(long)]
+        print_schema: bool,
+    },
+    /// Convert every raw snapshot into InfluxDB line protocol, one point per product per snapshot
+    Influx {
+        /// Path of the generated file, load it with `influx write --file`
+        #[arg(short, long, default_value = "bazaar_history.lp")]
+        output: PathBuf,
+        /// Measurement the points are written to [default: influx.measurement]
+        #[arg(long)]
+        measurement: Option<String>,
+    },
+    /// Convert every raw snapshot into a single Parquet file
+    #[cfg(feature = "parquet")]
+    Parquet {
+        /// Path of the generated Parquet file
+        #[arg(short, long, default_value = "bazaar_history.parquet")]
+        output: PathBuf,
+    },
+    /// Convert every raw snapshot into a single Arrow IPC (Feather) file
+    #[cfg(feature = "arrow")]
+    Arrow {
+        /// Path of the generated Arrow file
+        #[arg(short, long, default_value = "bazaar_history.arrow")]
+        output: PathBuf,
+    },
+    /// Write the newest snapshot to an Excel workbook
+    #[cfg(feature = "xlsx")]
+    Xlsx {
+        /// Path of the generated workbook
+        #[arg(short, long, default_value = "bazaar_summary.xlsx")]
+        output: PathBuf,
+        /// Add one worksheet per item category next to the summary
+        #[arg(long)]
+        by_category: bool,
+    },
+}
+
+// A fetch that still fails after the retries is logged and the next tick tries again, like the daemon
+async fn watch(client: &reqwest::Client, api: &ApiConfig, interval: u64, watchlist: &Watchlist, items: &ItemCatalog) -> Result<(), BazaarError> {
+    let mut ticker: time::Interval = time::interval(Duration::from_secs(interval));
+    loop {
+        ticker.tick().await;
+        let response: BazaarResponse = match fetch::fetch_bazaar(client, api).await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Fetch failed: {}", e);
+                continue;
+            }
+        };
+        println!("Last updated: {} ({})", response.lastUpdated, timezone::format_timestamp(response.lastUpdated as i64));
+        analysis::print_quick_status(&response, watchlist, items);
     }
 }
 
-impl Mul for FixedPoint {
-    type Output = Self;
-    fn mul(self, other: Self) -> Self {
-        // Scale down after multiplication to maintain precision
-        Self((self.0 * other.0) / Self::SCALE)
+// Postgres when a URL is passed, or configured and no SQLite file is. Both clients block, and the
+// Postgres one runs its own runtime, so the import leaves the async workers. Returns what was
+// imported into for the log, never the URL with its password.
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn backfill(config: &DatabaseConfig, database: Option<PathBuf>, url: Option<String>, files: &[PathBuf]) -> Result<(String, BackfillReport), BazaarError> {
+    let url: Option<String> = url.or_else(|| database.is_none().then(|| config.url.clone()).flatten());
+    tokio::task::block_in_place(|| match url {
+        #[cfg(feature = "postgres")]
+        Some(url) => Ok(("PostgreSQL".to_string(), PgDatabase::connect(&url)?.backfill(files)?)),
+        #[cfg(not(feature = "postgres"))]
+        Some(_) => Err(BazaarError::Config("Importing into PostgreSQL needs a build with the postgres feature".to_string())),
+        #[cfg(feature = "sqlite")]
+        None => {
+            let database: PathBuf = database.unwrap_or_else(|| config.path.clone());
+            let report: BackfillReport = Database::open(&database)?.backfill(files)?;
+            Ok((database.display().to_string(), report))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        None => {
+            let _ = database;
+            Err(BazaarError::Input("This build imports into PostgreSQL only, pass --url or set database.url".to_string()))
+        }
+    })
+}
+
+// The rows `backfill` stored for `products`, from the same database it would import into
+#[cfg_attr(not(any(feature = "sqlite", feature = "postgres")), allow(unused_variables))]
+fn query_database(config: &DatabaseConfig, source: QuerySource, products: &[String], (since, until): (i64, i64)) -> Result<ProductSeries, BazaarError> {
+    tokio::task::block_in_place(|| match source {
+        QuerySource::Raw => unreachable!("raw snapshots aren't read from a database"),
+        #[cfg(feature = "sqlite")]
+        QuerySource::Sqlite => {
+            if !config.path.exists() {
+                return Err(BazaarError::Input(format!("No database at {}, fill it with `backfill` first", config.path.display())));
+            }
+            let database: Database = Database::open(&config.path)?;
+            products.iter().map(|id| Ok((id.clone(), database.product_series(id, since, until)?))).collect()
+        }
+        #[cfg(feature = "postgres")]
+        QuerySource::Postgres => {
+            let url: &str = config.url.as_deref()
+                .ok_or_else(|| BazaarError::Config(format!("Querying PostgreSQL needs database.url (or {})", bazaar_update::config::DATABASE_URL_ENV)))?;
+            let mut database: PgDatabase = PgDatabase::connect(url)?;
+            products.iter().map(|id| Ok((id.clone(), database.product_series(id, since, until)?))).collect()
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(BazaarError::Config("This build has no support for that database, it needs the sqlite or postgres feature".to_string())),
+    })
+}
+
+// `query --sql`, only SQLite runs arbitrary statements
+#[cfg(feature = "sqlite")]
+fn query_sql(config: &DatabaseConfig, sql: &str) -> Result<SqlTable, BazaarError> {
+    if !config.path.exists() {
+        return Err(BazaarError::Input(format!("No database at {}, fill it with `backfill` first", config.path.display())));
     }
+    tokio::task::block_in_place(|| Database::open(&config.path)?.sql(sql))
 }
 
-impl Div for FixedPoint {
-    type Output = Self;
-    fn div(self, other: Self) -> Self {
-        // Scale up before division
-        Self((self.0 * Self::SCALE) / other.0)
+#[cfg(not(feature = "sqlite"))]
+fn query_sql(_config: &DatabaseConfig, _sql: &str) -> Result<SqlTable, BazaarError> {
+    Err(BazaarError::Config("This build has no SQLite support, query --sql needs the sqlite feature".to_string()))
+}
+
+// What of `command` goes over the network, which --offline refuses. Analysis commands read the raw
+// directory and the item cache, and only go online to refresh a stale or missing cache. A failed
+// refresh falls back to what is cached, and --offline skips the refresh, so none are listed here.
+fn network_use(command: &Option<Command>) -> Option<&'static str> {
+    match command {
+        Some(Command::Fetch { .. }) => Some("fetch"),
+        Some(Command::Daemon { .. }) => Some("daemon"),
+        Some(Command::Watch { .. }) => Some("watch"),
+        Some(Command::Replay { notify: true, .. }) => Some("replay --notify"),
+        #[cfg(feature = "s3")]
+        Some(Command::Upload { .. }) => Some("upload"),
+        Some(Command::Export { format: ExportFormat::Clickhouse { print_schema: false, .. } }) => Some("export clickhouse"),
+        Some(Command::Analyze { report: Some(AnalyzeReport::Anomalies { alert: true, .. }) }) => Some("analyze anomalies --alert"),
+        _ => None,
     }
 }
 
-impl fmt::Display for FixedPoint {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.2}", self.to_float())
+fn long_csv_options(config: &CsvConfig, watchlist: &Watchlist) -> LongCsvOptions {
+    LongCsvOptions {
+        dir: config.long_dir.clone(),
+        columns: config.columns.clone(),
+        dialect: CsvDialect { delimiter: config.delimiter, quote: config.quote },
+        watchlist: watchlist.clone(),
     }
 }
 
-fn deserialize_fixed_point<'de, D>(deserializer: D) -> Result<FixedPoint, D::Error> where D: serde::Deserializer<'de>,
-{
-    let value: f64 = Deserialize::deserialize(deserializer)?;
-    Ok(FixedPoint::from_float(value))
-}
-
-#[allow(non_snake_case)]
-#[derive(Deserialize, Serialize)]
-struct Order {
-    amount: u64, // Highest seen: 1186070
-    #[serde(deserialize_with = "deserialize_fixed_point")]
-    pricePerUnit: FixedPoint,
-    orders: u32,
-}
-
-#[allow(non_snake_case)]
-#[derive(Deserialize, Serialize)]
-struct QuickStatus {
-    productId: String, 
-    sellPrice: f64, // MANDATORY
-    sellVolume: u64, // Highest seen: 1292216
-    sellMovingWeek: u64, // Highest seen: 188604293
-    sellOrders: u32, // Highest seen: 202
-    buyPrice: f64, // float IS MANDATORY
-    buyVolume: u64, // Highest seen: 11766801
-    buyMovingWeek: u64, // Highest seen: 9205352
-    buyOrders: u32, // Highest seen: 270
-}
-
-#[derive(Deserialize, Serialize)]
-struct Product {
-    product_id: String,
-    sell_summary: Vec<Order>,
-    buy_summary: Vec<Order>,
-    quick_status: QuickStatus,
-}
-
-#[allow(non_snake_case)]
-#[derive(Deserialize, Serialize)]
-struct BazaarResponse {
-    success: bool,
-    lastUpdated: u64,
-    products: HashMap<String, Product>,
-}
-
-fn get_and_dump() -> Result<(), Box<dyn std::error::Error>> {
-    let response: BazaarResponse =
-    reqwest::blocking::get("https://api.hypixel.net/v2/skyblock/bazaar")?.json()?;
-    
-    println!("Success: {}", response.success);
-    println!("Last updated: {}", response.lastUpdated);
-    println!("Number of products: {}", response.products.len());
-    
-    // Create raw dir if doesn't exist
-    fs::create_dir_all("raw")?;
-    
-    // Generate filename with YYYYMMDD_<seconds-from-midnight>.json format
-    let now: chrono::DateTime<Local> = Local::now();
-    let date_str: String = now.format("%Y%m%d").to_string();
-    let seconds_from_midnight: u32 = (now.hour() * 3600)
-    + (now.minute() * 60)
-    + now.second();
-    let filename: String = format!("raw/{}{:05}.json", date_str, seconds_from_midnight);
-    
-    // Serialize response to JSON and write to file
-    let json: String = serde_json::to_string_pretty(&response)?;
-    fs::write(&filename, json)?;
-    
-    println!("Response saved to: {}", filename);
-    
-    Ok(())
+// sysexits.h codes, so a cron job or service manager can tell a retry apart from a broken setup
+fn exit_code(error: &BazaarError) -> ExitCode {
+    match error {
+        error if error.is_retryable() => ExitCode::from(75),
+        BazaarError::RetriesExhausted { .. } | BazaarError::Locked(_) => ExitCode::from(75),
+        BazaarError::Input(_) => ExitCode::from(64),
+        BazaarError::Config(_) => ExitCode::from(78),
+        _ => ExitCode::FAILURE,
+    }
 }
 
-fn newest_file() -> Option<std::path::PathBuf> {
-    let paths: fs::ReadDir = fs::read_dir("./raw/").ok()?;
-    let mut newest: Option<std::path::PathBuf> = None;
-    for path in paths {
-        let path: std::path::PathBuf = path.ok()?.path();
-        if newest.is_none() || path.file_name()? > newest.as_ref()?.file_name()? {
-            newest = Some(path);
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit_code(&e)
         }
     }
-    newest
-}
-
-fn generate_csv() -> Result<(), Box<dyn std::error::Error>> {
-    let newest_path: std::path::PathBuf = newest_file().ok_or("No raw files found")?;
-    let data: String = fs::read_to_string(&newest_path)?;
-    let response: BazaarResponse = serde_json::from_str(&data)?;
-
-    let mut wtr: csv::Writer<fs::File> = csv::Writer::from_path("bazaar_summary.csv")?;
-    wtr.write_record(&["last_updated",response.lastUpdated.to_string().as_str(), "", "", "", "", ""])?;
-    wtr.write_record(&["product_id", "sell_price", "sell_volume", "buy_price", "buy_volume", "sell_orders", "buy_orders"])?;
-    for(_, product) in response.products.iter() {
-        let quick_status: &QuickStatus = &product.quick_status;
-        wtr.write_record(&[
-            &product.product_id,
-            &quick_status.sellPrice.to_string(),
-            &quick_status.sellVolume.to_string(),
-            &quick_status.buyPrice.to_string(),
-            &quick_status.buyVolume.to_string(),
-            &quick_status.sellOrders.to_string(),
-            &quick_status.buyOrders.to_string()
-        ])?;
-    }
-    wtr.flush()?;
-    println!("CSV summary generated: bazaar_summary.csv");
-
-    Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    get_and_dump()?;
-    generate_csv()?;
+async fn run(cli: Cli) -> Result<(), BazaarError> {
+    let config: Config = Config::load(cli.config.as_deref())?;
+    let raw_dir: PathBuf = cli.raw_dir.unwrap_or_else(|| config.storage.raw_dir.clone());
+    storage::set_name_template(cli.name_template.unwrap_or_else(|| config.storage.name_template.clone()));
+    timezone::set_display_timezone(cli.timezone.unwrap_or(config.display.timezone));
+    logging::init(cli.verbose.min(8) as i8 - cli.quiet.min(8) as i8, cli.log_format.unwrap_or(config.log.format));
+    categories::set_overrides(&config.categories.products);
+    // Product and category filters given on the command line replace the configured watchlist
+    let watchlist: Watchlist = if cli.products.is_empty() && cli.categories.is_empty() {
+        Watchlist::new(config.watchlist.products.clone()).with_categories(config.watchlist.categories.clone())
+    } else {
+        Watchlist::new(cli.products).with_categories(cli.categories)
+    };
+    let offline: bool = cli.offline;
+    if offline && let Some(command) = network_use(&cli.command) {
+        return Err(BazaarError::Input(format!("{} needs the network, drop --offline to run it", command)));
+    }
+    let client: reqwest::Client = fetch::build_client(&config.api)?;
+    let fetch_context = |compress: bool, format: Option<SnapshotFormat>, watchlist_only: bool, force: bool| -> FetchContext {
+        FetchContext {
+            client: client.clone(),
+            api: config.api.clone(),
+            raw_dir: raw_dir.clone(),
+            compress: compress || config.storage.compress,
+            format: format.unwrap_or(config.storage.format),
+            keyframe_interval: config.storage.keyframe_interval,
+            watchlist: (watchlist_only || config.watchlist.filter_snapshots).then(|| watchlist.clone()),
+            alerts: config.alerts.clone(),
+            anomalies: config.anomalies.clone(),
+            influx: config.influx.clone(),
+            clickhouse: config.clickhouse.clone(),
+            s3: config.s3.clone(),
+            force,
+            feed: None,
+            paper: None,
+            long_csv: config.csv.append_long.then(|| Arc::new(long_csv_options(&config.csv, &watchlist))),
+            last_updated: Arc::new(AtomicU64::new(0)),
+            delta_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    };
+    match cli.command {
+        Some(Command::Fetch { compress, format, watchlist_only, force, target: None }) => {
+            let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+            fetch::get_and_dump(&fetch_context(compress, format, watchlist_only, force)).await?;
+        }
+        Some(Command::Fetch { compress, force, target: Some(FetchTarget::Auctions), .. }) => {
+            let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+            let compress: bool = compress || config.storage.compress;
+            auctions::get_and_dump_auctions(&client, &config.api, &raw_dir, compress, force).await?;
+        }
+        Some(Command::Fetch { target: Some(FetchTarget::Items), .. }) => {
+            let catalog: ItemCatalog = items::refresh(&client, &config.api, &config.items.cache).await?;
+            info!(path = %config.items.cache.display(), items = catalog.len(), "Item metadata saved");
+        }
+        Some(Command::Csv { append_long: true, columns, dialect, .. }) => {
+            let response: BazaarResponse = read_newest(&raw_dir, &watchlist)?;
+            watchlist.warn_missing(&response);
+            let options: LongCsvOptions = LongCsvOptions {
+                columns: columns.unwrap_or_else(|| config.csv.columns.clone()),
+                dialect: dialect.resolve(&config.csv),
+                ..long_csv_options(&config.csv, &watchlist)
+            };
+            let (path, rows): (PathBuf, usize) = export::append_long_csv(&response, &options)?;
+            if rows == 0 {
+                info!(path = %path.display(), "The newest snapshot is already in the long CSV");
+            }
+        }
+        Some(Command::Csv { output, all, since, indicators, columns, sort_by, descending, dialect, .. }) => {
+            let output: PathBuf = output.unwrap_or_else(|| config.csv.output.clone());
+            let options: CsvOptions = CsvOptions {
+                columns: columns.unwrap_or_else(|| config.csv.columns.clone()),
+                order: CsvOrder { by: sort_by, descending },
+                dialect: dialect.resolve(&config.csv),
+            };
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            if all || since.is_some() {
+                export::generate_history_csv(&raw_dir, &output, &watchlist, &items, &options, since)?
+            } else {
+                let windows: &[Window] = if indicators { &config.indicators.windows } else { &[] };
+                export::generate_csv(&raw_dir, &output, &watchlist, &items, &options, windows)?
+            }
+        }
+        Some(Command::Summary { json }) => {
+            let response: BazaarResponse = read_newest(&raw_dir, &watchlist)?;
+            watchlist.warn_missing(&response);
+            let summary: Summary = analysis::summary(&response, &watchlist);
+            if json {
+                println!("{}", serde_json::to_string(&summary)?);
+            } else {
+                let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+                analysis::print_summary(&summary, &items);
+            }
+        }
+        Some(Command::Show { sort_by, ascending, top }) => {
+            let response: BazaarResponse = read_newest(&raw_dir, &watchlist)?;
+            watchlist.warn_missing(&response);
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            show::print_top(&response, &watchlist, &items, CsvOrder { by: sort_by, descending: !ascending }, top);
+        }
+        Some(Command::Analyze { report }) => {
+            let report: AnalyzeReport = report.unwrap_or(AnalyzeReport::Status);
+            // Only watchlisted products are parsed, except for crafts and ratios which need every ingredient's price
+            let filter: Watchlist = match report {
+                AnalyzeReport::Crafts { .. } | AnalyzeReport::Ratios { .. } => Watchlist::default(),
+                _ => watchlist.clone(),
+            };
+            let response: BazaarResponse = read_newest(&raw_dir, &filter)?;
+            watchlist.warn_missing(&response);
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            match report {
+                AnalyzeReport::Status => analysis::print_quick_status(&response, &watchlist, &items),
+                AnalyzeReport::Spreads { sort_by, top } => {
+                    let rows: Vec<Spread> = analysis::spreads(&response, &watchlist, config.tax.effective_rate(), sort_by)?;
+                    analysis::print_spreads(&rows[..rows.len().min(top)], &items);
+                }
+                AnalyzeReport::Crafts { recipes, instant, top } => {
+                    let recipes: Vec<Recipe> = recipes::load_recipes(&recipes.unwrap_or_else(|| config.crafts.recipes.clone()))?;
+                    let crafts: Vec<Craft> = recipes::craft_profits(&response, &recipes, &watchlist, config.tax.effective_rate(), instant)?;
+                    recipes::print_crafts(&crafts[..crafts.len().min(top)], &items);
+                }
+                AnalyzeReport::Ratios { recipes, window, min_margin, instant, flagged, top } => {
+                    if min_margin.is_some_and(|margin| !margin.is_finite()) {
+                        return Err(BazaarError::Input("--min-margin must be a finite percentage".to_string()));
+                    }
+                    let recipes: Vec<Recipe> = recipes::load_recipes(&recipes.unwrap_or_else(|| config.crafts.recipes.clone()))?;
+                    let window: Window = window.unwrap_or_else(|| config.crafts.ratio_window.clone());
+                    let files: Vec<PathBuf> = snapshot_files(&raw_dir)?;
+                    let history: History = tokio::task::block_in_place(|| History::load_window(&files, window.millis, &ratios::products(&recipes)))?;
+                    let rows: Vec<Ratio> = ratios::ratios(
+                        &response, &history, &recipes, &watchlist, config.tax.effective_rate(), instant,
+                        min_margin.unwrap_or(config.crafts.min_margin),
+                    )
+                    .into_iter()
+                    .filter(|row| !flagged || row.signal.is_some())
+                    .collect();
+                    ratios::print_ratios(&rows[..rows.len().min(top)], &items);
+                }
+                AnalyzeReport::Depth { product_id, quantity } => {
+                    let product_id: String = product_id.to_uppercase();
+                    let mut response: BazaarResponse = read_newest(&raw_dir, &Watchlist::new([product_id.clone()]))?;
+                    let product: Product = response.products.remove(&product_id)
+                        .ok_or_else(|| BazaarError::Input(format!("{} is not in the newest snapshot", product_id)))?;
+                    depth::print_depth(&product, quantity, config.tax.effective_rate(), &items)?;
+                }
+                AnalyzeReport::Filltime { product_id, price, quantity, window } => {
+                    let product_id: String = product_id.to_uppercase();
+                    let mut response: BazaarResponse = read_newest(&raw_dir, &Watchlist::new([product_id.clone()]))?;
+                    let product: Product = response.products.remove(&product_id)
+                        .ok_or_else(|| BazaarError::Input(format!("{} is not in the newest snapshot", product_id)))?;
+                    let price: f64 = match price {
+                        Some(price) if price.is_finite() && price > 0.0 => price,
+                        Some(_) => return Err(BazaarError::Input("--price must be above 0".to_string())),
+                        None => product.quick_status.sellPrice,
+                    };
+                    let history: History = History::load_window(&snapshot_files(&raw_dir)?, window.millis, &Watchlist::new([product_id.clone()]))?;
+                    let samples: Vec<(i64, &QuickStatus)> = history.product_series(&product_id);
+                    let estimate: FillEstimate = filltime::estimate(&product, &samples, price, quantity);
+                    filltime::print_estimate(&product_id, &estimate, samples.len());
+                }
+                AnalyzeReport::Forecast { product_id, metric, method, horizon, step, season, since, output } => {
+                    let product_id: String = product_id.to_uppercase();
+                    if horizon.millis < step.millis || season.millis < step.millis {
+                        return Err(BazaarError::Input("--horizon and --season can't be shorter than --step".to_string()));
+                    }
+                    let series: ProductSeries = tokio::task::block_in_place(|| {
+                        query::load_raw(&raw_dir, std::slice::from_ref(&product_id), since.unwrap_or(i64::MIN), i64::MAX)
+                    })?;
+                    let buckets: Vec<(i64, f64)> = query::evaluate(&series, metric, Some(&step), Aggregation::Mean)
+                        .into_iter()
+                        .map(|row| (row.timestamp, row.value))
+                        .collect();
+                    let Some(&last) = buckets.last() else {
+                        return Err(BazaarError::Input(format!("No history found for {}", product_id)));
+                    };
+                    let steps = |window: &Window| (window.millis / step.millis) as usize;
+                    let forecast: Forecast = forecast::forecast(&buckets, step.millis, steps(&horizon), method, steps(&season))?;
+                    forecast::print_forecast(&product_id, metric.name(), &forecast, last);
+                    if let Some(output) = output {
+                        forecast::write_forecast_csv(&forecast, &output)?;
+                        info!(path = %output.display(), points = forecast.points.len(), "Forecast written");
+                    }
+                }
+                AnalyzeReport::Indicators { product_id, window } => {
+                    let product_id: String = product_id.to_uppercase();
+                    let windows: Vec<Window> = if window.is_empty() { config.indicators.windows.clone() } else { window };
+                    let history: History = History::load(&snapshot_files(&raw_dir)?, &Watchlist::new([product_id.clone()]))?;
+                    indicators::print_indicators(&history, &product_id, &windows, &gaps::load_markers(&raw_dir), &items)?;
+                }
+                AnalyzeReport::Volatility { window, top } => {
+                    let history: History = History::load(&snapshot_files(&raw_dir)?, &watchlist)?;
+                    let rows: Vec<Volatility> = volatility::volatility(&history, &window);
+                    volatility::print_volatility(&rows[..rows.len().min(top)], &window, &items);
+                }
+                AnalyzeReport::Correlations { metric, step, since, levels, top } => {
+                    let products: Vec<String> = watchlist.products().into_iter().map(str::to_string).collect();
+                    if products.len() < 2 {
+                        return Err(BazaarError::Input("Correlations need at least two products, pass --product or set watchlist.products".to_string()));
+                    }
+                    let series: ProductSeries = tokio::task::block_in_place(|| query::load_raw(&raw_dir, &products, since.unwrap_or(i64::MIN), i64::MAX))?;
+                    let rows: Vec<QueryRow> = query::evaluate(&series, metric, Some(&step), Aggregation::Mean);
+                    let correlations: Correlations = correlations::correlations(&rows, step.millis, !levels);
+                    correlations::print_correlations(&correlations, top, &items);
+                }
+                AnalyzeReport::Movers { window, sort_by, top } => {
+                    let files: Vec<PathBuf> = snapshot_files(&raw_dir)?;
+                    let baseline: &PathBuf = movers::baseline_file(&files, window.millis)
+                        .ok_or_else(|| BazaarError::Input("Movers need at least two snapshots".to_string()))?;
+                    let comparison: Comparison = movers::compare(baseline, &files[files.len() - 1], &watchlist)?;
+                    movers::print_movers(&comparison, sort_by, top, &items);
+                }
+                AnalyzeReport::Pressure { window, min_volume, top } => {
+                    let history: History = History::load_window(&snapshot_files(&raw_dir)?, window.millis, &watchlist)?;
+                    if history.points.len() < 2 {
+                        return Err(BazaarError::Input(format!("Pressure needs at least two snapshots in the last {}", window)));
+                    }
+                    let rows: Vec<Pressure> = pressure::pressure(&history, &watchlist, min_volume);
+                    pressure::print_pressure(&rows, top, &items);
+                }
+                AnalyzeReport::Liquidity { top } => {
+                    let rows: Vec<Liquidity> = liquidity::rank(&response.products, &watchlist);
+                    liquidity::print_liquidity(&rows, top, &items);
+                }
+                AnalyzeReport::Anomalies { window, threshold, latest, alert, top } => {
+                    if threshold.is_some_and(|threshold| !(threshold.is_finite() && threshold > 0.0)) {
+                        return Err(BazaarError::Input("--threshold must be above 0".to_string()));
+                    }
+                    let options: AnomaliesConfig = AnomaliesConfig {
+                        window: window.unwrap_or_else(|| config.anomalies.window.clone()),
+                        threshold: threshold.unwrap_or(config.anomalies.threshold),
+                        ..config.anomalies.clone()
+                    };
+                    let history: History = History::load(&snapshot_files(&raw_dir)?, &watchlist)?;
+                    let rows: Vec<Anomaly> = if latest {
+                        anomalies::latest(&history, &watchlist, &options)
+                    } else {
+                        anomalies::scan(&history, &watchlist, &options)
+                    };
+                    anomalies::print_anomalies(&rows[..rows.len().min(top)], &items);
+                    if alert {
+                        let newest_timestamp: Option<i64> = history.points.last().map(|point| point.timestamp);
+                        let alerts: Vec<Alert> = rows.iter()
+                            .filter(|anomaly| Some(anomaly.timestamp) == newest_timestamp)
+                            .map(|anomaly| anomaly.to_alert(&options))
+                            .collect();
+                        alerts::dispatch(&client, &config.alerts, &alerts).await;
+                    }
+                }
+                AnalyzeReport::NpcFlips { daily_limit, top } => {
+                    if items.is_empty() {
+                        return Err(BazaarError::Input("No item metadata available, NPC prices come from `fetch items`".to_string()));
+                    }
+                    let flips: Vec<NpcFlip> = npc::find_npc_flips(&response, &watchlist, &items, daily_limit)?;
+                    npc::print_npc_flips(&flips[..flips.len().min(top)], &items);
+                }
+            }
+        }
+        Some(Command::Candles { resolution, output_dir }) => {
+            let history: History = History::load(&snapshot_files(&raw_dir)?, &watchlist)?;
+            let markers: Vec<Gap> = gaps::load_markers(&raw_dir);
+            fs::create_dir_all(&output_dir)?;
+            for resolution in resolution {
+                let candles: Vec<Candle> = candles::aggregate(&history, resolution, &markers);
+                let output: PathBuf = output_dir.join(format!("candles_{}.csv", resolution.label()));
+                candles::write_candles_csv(&candles, &output)?;
+                info!(path = %output.display(), resolution = resolution.label(), rows = candles.len(), "Candles written");
+            }
+        }
+        Some(Command::Flips { capital, min_volume, min_liquidity, max_share, top, output }) => {
+            if !(max_share > 0.0 && max_share <= 1.0) {
+                return Err(BazaarError::Input("--max-share must be above 0 and at most 1".to_string()));
+            }
+            let response: BazaarResponse = read_newest(&raw_dir, &watchlist)?;
+            watchlist.warn_missing(&response);
+            let options: FlipOptions = FlipOptions {
+                capital: FixedPoint::from_float(capital),
+                min_volume,
+                min_liquidity,
+                tax_rate: config.tax.effective_rate(),
+                max_share,
+            };
+            let flips: Vec<Flip> = flips::find_flips(&response, &watchlist, &options)?;
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            flips::print_flips(&flips[..flips.len().min(top)], &items);
+            if let Some(output) = output {
+                flips::write_flips_csv(&flips, &items, &output)?;
+                info!(path = %output.display(), "Flips written");
+            }
+        }
+        Some(Command::Backtest { product_id, buy, sell, capital, tax, max_share, since, until, output, equity }) => {
+            if !(capital.is_finite() && capital > 0.0) {
+                return Err(BazaarError::Input("--capital must be above 0".to_string()));
+            }
+            if !(max_share > 0.0 && max_share <= 1.0) {
+                return Err(BazaarError::Input("--max-share must be above 0 and at most 1".to_string()));
+            }
+            if tax.is_some_and(|tax| !(0.0..1.0).contains(&tax)) {
+                return Err(BazaarError::Input("--tax must be at least 0 and below 1".to_string()));
+            }
+            let product_id: String = product_id.to_uppercase();
+            let mut strategy: RuleStrategy = RuleStrategy::new(product_id, buy, sell);
+            let history: History = tokio::task::block_in_place(|| {
+                backtest::load_history(&raw_dir, &strategy.products(), since.unwrap_or(i64::MIN), until.unwrap_or(i64::MAX))
+            })?;
+            if history.points.is_empty() {
+                return Err(BazaarError::Input(format!("No snapshot in the range contains {}", strategy.products().join(", "))));
+            }
+            let options: BacktestOptions = BacktestOptions { capital, tax_rate: tax.unwrap_or(config.tax.effective_rate()), max_share };
+            let report: BacktestReport = backtest::run(&mut strategy, &history, options);
+            backtest::print_report(&strategy, &report);
+            backtest::write_trades_csv(&report.trades, &output)?;
+            info!(path = %output.display(), trades = report.trades.len(), "Trade log written");
+            if let Some(equity) = equity {
+                backtest::write_equity_csv(&report.equity, report.capital, &equity)?;
+                info!(path = %equity.display(), points = report.equity.len(), "Equity curve written");
+            }
+        }
+        Some(Command::Portfolio { file, action }) => {
+            let path: PathBuf = file.unwrap_or_else(|| config.portfolio.path.clone());
+            let mut portfolio: Portfolio = Portfolio::load(&path)?;
+            let tax_rate: f64 = config.tax.effective_rate();
+            match action.unwrap_or(PortfolioAction::Show) {
+                PortfolioAction::Show if portfolio.holdings.is_empty() => println!("No holdings recorded in {}, add some with `portfolio add`", path.display()),
+                PortfolioAction::Show => {
+                    let response: BazaarResponse = read_newest(&raw_dir, &Watchlist::new(portfolio.products()))?;
+                    let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+                    portfolio::print_portfolio(&portfolio, &response, tax_rate, &items);
+                }
+                PortfolioAction::Add { product_id, quantity, cost } => {
+                    let product_id: String = product_id.to_uppercase();
+                    let cost: f64 = match cost {
+                        Some(cost) if cost.is_finite() && cost >= 0.0 => cost,
+                        Some(_) => return Err(BazaarError::Input("--cost must be at least 0".to_string())),
+                        None => read_newest(&raw_dir, &Watchlist::new([product_id.clone()]))?
+                            .products
+                            .get(&product_id)
+                            .map(|product| product.quick_status.buyPrice)
+                            .ok_or_else(|| BazaarError::Input(format!("{} is not in the newest snapshot, pass --cost", product_id)))?,
+                    };
+                    portfolio.add(product_id.clone(), quantity, cost);
+                    portfolio.save(&path)?;
+                    info!(product = %product_id, quantity, cost, path = %path.display(), "Holding recorded");
+                }
+                PortfolioAction::Remove { product_id, quantity } => {
+                    let product_id: String = product_id.to_uppercase();
+                    let left: u64 = portfolio.remove(&product_id, quantity)
+                        .ok_or_else(|| BazaarError::Input(format!("{} is not in the portfolio", product_id)))?;
+                    portfolio.save(&path)?;
+                    info!(product = %product_id, left, path = %path.display(), "Holding updated");
+                }
+                PortfolioAction::History { since, output, #[cfg(feature = "plot")] plot } => {
+                    if portfolio.holdings.is_empty() {
+                        return Err(BazaarError::Input(format!("No holdings recorded in {}", path.display())));
+                    }
+                    let mut history: History = History::load(&snapshot_files(&raw_dir)?, &Watchlist::new(portfolio.products()))?;
+                    if let Some(since) = since {
+                        history.points.retain(|point| point.timestamp >= since);
+                    }
+                    let values: Vec<(i64, f64)> = portfolio::value_history(&portfolio, &history, tax_rate);
+                    if values.is_empty() {
+                        return Err(BazaarError::Input("No snapshot holds every product of the portfolio".to_string()));
+                    }
+                    portfolio::write_value_csv(&values, portfolio.cost_basis(), &output)?;
+                    info!(path = %output.display(), points = values.len(), "Portfolio value written");
+                    #[cfg(feature = "plot")]
+                    if let Some(plot) = plot {
+                        bazaar_update::plot::plot_portfolio(&values, portfolio.cost_basis(), &plot, (1280, 720))?;
+                        info!(path = %plot.display(), "Plot written");
+                    }
+                }
+            }
+        }
+        Some(Command::Optimize { kind: OptimizeKind::Shopping { items: listed, recipe, crafts, recipes, budget, max_share } }) => {
+            if !(budget.is_finite() && budget >= 0.0) {
+                return Err(BazaarError::Input("--budget must be at least 0".to_string()));
+            }
+            if !(max_share > 0.0 && max_share <= 1.0) {
+                return Err(BazaarError::Input("--max-share must be above 0 and at most 1".to_string()));
+            }
+            let recipe: Option<Recipe> = match recipe {
+                Some(output) => {
+                    let output: String = output.to_uppercase();
+                    let recipes: Vec<Recipe> = recipes::load_recipes(&recipes.unwrap_or_else(|| config.crafts.recipes.clone()))?;
+                    let recipe: Recipe = recipes.into_iter()
+                        .find(|recipe| recipe.output == output)
+                        .ok_or_else(|| BazaarError::Input(format!("No recipe makes {}", output)))?;
+                    Some(recipe)
+                }
+                None => None,
+            };
+            let list: Vec<ListItem> = shopping::shopping_list(&listed, recipe.as_ref().map(|recipe| (recipe, crafts)));
+            if list.is_empty() {
+                return Err(BazaarError::Input("Nothing to buy, list items as PRODUCT=QUANTITY or pass --recipe".to_string()));
+            }
+            let products: Watchlist = Watchlist::new(list.iter().map(|item| item.product_id.clone()));
+            let response: BazaarResponse = read_newest(&raw_dir, &products)?;
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            let plan: ShoppingPlan = shopping::plan(&response, &list, budget, max_share)?;
+            shopping::print_plan(&plan, &items);
+        }
+        Some(Command::Report { period, output_dir, html, top }) => {
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            let options: ReportOptions = ReportOptions { period, top, html, tax_rate: config.tax.effective_rate() };
+            for path in report::generate(&raw_dir, &output_dir, &watchlist, &items, &options)? {
+                info!(path = %path.display(), "Report written");
+            }
+        }
+        Some(Command::Query { sql: Some(sql), output, .. }) => {
+            let table: SqlTable = query_sql(&config.database, &sql)?;
+            query::print_table(&table);
+            if let Some(output) = output {
+                query::write_table_csv(&table, &output)?;
+                info!(path = %output.display(), rows = table.rows.len(), "Query result written");
+            }
+        }
+        Some(Command::Query { since, until, metric, resample, agg, source, output, sql: None }) => {
+            let products: Vec<String> = watchlist.products().into_iter().map(str::to_string).collect();
+            if products.is_empty() {
+                return Err(BazaarError::Input("Name the products to query with --product or watchlist.products".to_string()));
+            }
+            let range: (i64, i64) = (since.unwrap_or(i64::MIN), until.unwrap_or(i64::MAX));
+            let series: ProductSeries = match source {
+                QuerySource::Raw => query::load_raw(&raw_dir, &products, range.0, range.1)?,
+                source => query_database(&config.database, source, &products, range)?,
+            };
+            let rows: Vec<QueryRow> = query::evaluate(&series, metric, resample.as_ref(), agg);
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            query::print_rows(&rows, metric, &items);
+            if let Some(output) = output {
+                query::write_csv(&rows, metric, &output)?;
+                info!(path = %output.display(), rows = rows.len(), "Query result written");
+            }
+        }
+        Some(Command::Site { output_dir, since, max_points }) => {
+            let mut history: History = History::load(&snapshot_files(&raw_dir)?, &watchlist)?;
+            if let Some(since) = since {
+                history.points.retain(|point| point.timestamp >= since);
+            }
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            let (path, products): (PathBuf, usize) = site::generate(&history, &watchlist, &items, &output_dir, max_points as usize, Utc::now().timestamp_millis())?;
+            info!(path = %path.display(), products, "Dashboard written");
+        }
+        #[cfg(any(feature = "sqlite", feature = "postgres"))]
+        Some(Command::Backfill { database, url }) => {
+            let (target, report): (String, BackfillReport) = backfill(&config.database, database, url, &snapshot_files(&raw_dir)?)?;
+            for error in &report.failed {
+                warn!("{}", error);
+            }
+            info!(
+                "Backfilled {}: {} snapshots imported ({} rows), {} already present, {} failed",
+                target, report.imported, report.rows, report.skipped, report.failed.len()
+            );
+        }
+        Some(Command::Diff { file_a, file_b, latest_two, min_price_change, min_volume_change, min_order_change }) => {
+            let (a, b): (PathBuf, PathBuf) = match (file_a, file_b) {
+                (Some(a), Some(b)) if !latest_two => (a, b),
+                _ => {
+                    let mut files: Vec<PathBuf> = snapshot_files(&raw_dir)?;
+                    let (Some(b), Some(a)) = (files.pop(), files.pop()) else {
+                        return Err(BazaarError::Input(format!("Need at least two snapshots in {}", raw_dir.display())));
+                    };
+                    (a, b)
+                }
+            };
+            let thresholds: DiffThresholds = DiffThresholds {
+                price_percent: min_price_change,
+                volume_percent: min_volume_change,
+                orders: min_order_change,
+            };
+            let diff: SnapshotDiff = diff::diff(&a, &b, &watchlist, &thresholds)?;
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            println!("{} -> {}", a.display(), b.display());
+            diff::print_diff(&diff, &items);
+        }
+        Some(Command::Stats { min_gap, days }) => {
+            let min_gap: u64 = min_gap.unwrap_or(2 * config.daemon.interval);
+            let mut stats: DatasetStats = stats::collect(&raw_dir, min_gap as i64 * 1000)?;
+            stats.usage.extend(stats::sqlite_usage(&config.database.path));
+            #[cfg(feature = "postgres")]
+            if let Some(url) = &config.database.url {
+                match tokio::task::block_in_place(|| PgDatabase::connect(url)?.disk_usage()) {
+                    Ok(bytes) => stats.usage.push(stats::Usage { label: "PostgreSQL database.url".to_string(), files: 0, bytes }),
+                    Err(e) => warn!("Couldn't measure the PostgreSQL database: {}", e),
+                }
+            }
+            stats::print_stats(&stats, days);
+        }
+        Some(Command::Check { check: CheckKind::Gaps { min_gap, record } }) => {
+            let min_gap: u64 = min_gap.unwrap_or(2 * config.daemon.interval);
+            let mut timestamps: Vec<i64> = Vec::new();
+            for file in snapshot_files(&raw_dir)? {
+                match snapshot_timestamp(&file) {
+                    Some(timestamp) => timestamps.push(timestamp),
+                    None => warn!("Skipping {}, can't tell its time from the name", file.display()),
+                }
+            }
+            let gaps: Vec<Gap> = gaps::find_gaps(&timestamps, min_gap as i64 * 1000);
+            gaps::print_gaps(&gaps, &timestamps);
+            if record {
+                let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+                let markers: Vec<Gap> = gaps::load_markers(&raw_dir);
+                let new: Vec<Gap> = gaps.into_iter().filter(|gap| gaps::overlapping(&markers, gap.start, gap.end).is_none()).collect();
+                gaps::record_markers(&raw_dir, &new).await?;
+                info!(recorded = new.len(), known = markers.len(), path = %gaps::markers_path(&raw_dir).display(), "Gap markers recorded");
+            }
+        }
+        Some(Command::Daemon {
+            compress, format, watchlist_only, force, interval, schedule, paper,
+            #[cfg(feature = "server")]
+            serve,
+        }) => {
+            let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+            let ticker: Ticker = match interval {
+                Some(interval) => Ticker::interval(interval),
+                None if schedule.is_empty() => Ticker::new(config.daemon.schedule.clone(), config.daemon.interval),
+                None => Ticker::new(schedule, config.daemon.interval),
+            };
+            // Twice the time between fetches, like `check gaps`
+            let max_gap: i64 = 2 * ticker.period().map_or(config.daemon.interval * 1000, |period| period.as_millis() as u64) as i64;
+            match gaps::record_restart(&raw_dir, max_gap, Utc::now().timestamp_millis()).await {
+                Ok(Some(gap)) => warn!("No snapshot for {} before this start, recorded a gap marker", gaps::format_duration(gap.millis())),
+                Ok(None) => {}
+                Err(e) => warn!("Couldn't record the gap before this start: {}", e),
+            }
+            let context: FetchContext = fetch_context(compress, format, watchlist_only, force);
+            let context: FetchContext = if paper || config.paper.enabled {
+                let (Some(product), false, false) = (&config.paper.product, config.paper.buy.is_empty(), config.paper.sell.is_empty()) else {
+                    return Err(BazaarError::Config("Paper trading needs paper.product and at least one paper.buy and paper.sell condition".to_string()));
+                };
+                if !(config.paper.capital.is_finite() && config.paper.capital > 0.0) {
+                    return Err(BazaarError::Config("paper.capital must be above 0".to_string()));
+                }
+                if !(config.paper.max_share > 0.0 && config.paper.max_share <= 1.0) {
+                    return Err(BazaarError::Config("paper.max_share must be above 0 and at most 1".to_string()));
+                }
+                let strategy: RuleStrategy = RuleStrategy::new(product.to_uppercase(), config.paper.buy.clone(), config.paper.sell.clone());
+                let options: BacktestOptions = BacktestOptions {
+                    capital: config.paper.capital,
+                    tax_rate: config.tax.effective_rate(),
+                    max_share: config.paper.max_share,
+                };
+                let trader: PaperTrader = PaperTrader::new(Box::new(strategy), options, config.paper.state.clone(), config.paper.trades.clone())?;
+                FetchContext { paper: Some(Arc::new(trader)), ..context }
+            } else {
+                context
+            };
+            // The server lives as long as the daemon, a failure to bind only costs the API
+            #[cfg(feature = "server")]
+            let context: FetchContext = match serve {
+                Some(bind) => {
+                    let feed: Arc<Feed> = Arc::new(Feed::default());
+                    let raw_dir: PathBuf = raw_dir.clone();
+                    let served: Arc<Feed> = feed.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = bazaar_update::server::serve(raw_dir, bind, Some(served)).await {
+                            error!("The HTTP server stopped: {}", e);
+                        }
+                    });
+                    FetchContext { feed: Some(feed), ..context }
+                }
+                None => context,
+            };
+            fetch::daemon(context, ticker).await;
+        }
+        #[cfg(feature = "plot")]
+        Some(Command::Plot { product_id, output, since, width, height }) => {
+            let product_id: String = product_id.to_uppercase();
+            let mut history: History = History::load(&snapshot_files(&raw_dir)?, &Watchlist::new([product_id.clone()]))?;
+            if let Some(since) = since {
+                history.points.retain(|point| point.timestamp >= since);
+            }
+            let points: usize = bazaar_update::plot::plot_history(&history, &product_id, &output, (width, height))?;
+            info!(path = %output.display(), points, "Plot written");
+        }
+        #[cfg(feature = "s3")]
+        Some(Command::Upload { bundle, since }) => {
+            if config.s3.endpoint.is_none() {
+                return Err(BazaarError::Config("Uploading needs s3.endpoint and a bucket in the config".to_string()));
+            }
+            let uploaded: usize = s3::upload_all(&client, &config.s3, &raw_dir, bundle.unwrap_or(config.s3.bundle), since).await?;
+            info!(uploaded, bucket = %config.s3.bucket, "Upload done");
+        }
+        Some(Command::Prune { keep_days, hourly_after, daily_after, dry_run }) => {
+            let policy: RetentionPolicy = RetentionPolicy {
+                keep_days: keep_days.or(config.prune.keep_days),
+                hourly_after_days: hourly_after.or(config.prune.hourly_after_days),
+                daily_after_days: daily_after.or(config.prune.daily_after_days),
+            };
+            if policy.is_empty() {
+                return Err(BazaarError::Input("No retention policy given, pass --keep-days, --hourly-after or --daily-after".to_string()));
+            }
+            let _lock: Option<DirectoryLock> = if dry_run { None } else { Some(storage::lock::lock(&raw_dir)?) };
+            let mut snapshots: Vec<(PathBuf, i64)> = Vec::new();
+            for file in snapshot_files(&raw_dir)? {
+                match snapshot_timestamp(&file) {
+                    Some(timestamp) => snapshots.push((file, timestamp)),
+                    None => warn!("Skipping {}, can't tell its time from the name", file.display()),
+                }
+            }
+            let doomed: Vec<PathBuf> = prune::plan(&snapshots, &policy, Utc::now().timestamp_millis());
+            for file in &doomed {
+                if dry_run {
+                    println!("Would delete: {}", file.display());
+                } else {
+                    fs::remove_file(file)?;
+                    // Date folders of a nested name template go once their last snapshot does
+                    for dir in file.ancestors().skip(1).take_while(|dir| *dir != raw_dir) {
+                        if fs::remove_dir(dir).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            let verb: &str = if dry_run { "Would delete" } else { "Deleted" };
+            info!("{} {} of {} snapshots", verb, doomed.len(), snapshots.len());
+        }
+        Some(Command::Compact { full_days, resolution, dry_run }) => {
+            let Some(full_days) = full_days.or(config.compact.full_days) else {
+                return Err(BazaarError::Input("Pass --full-days or set compact.full_days".to_string()));
+            };
+            let resolution: Resolution = resolution.unwrap_or(config.compact.resolution);
+            let _lock: Option<DirectoryLock> = if dry_run { None } else { Some(storage::lock::lock(&raw_dir)?) };
+            let report: CompactReport = compact::compact(&raw_dir, full_days, resolution, Utc::now().timestamp_millis(), dry_run).await?;
+            let verb: &str = if dry_run { "Would compact" } else { "Compacted" };
+            info!(
+                "{} {} snapshots older than {} days into {} {} candles in {}{}",
+                verb, report.snapshots, full_days, report.candles, resolution.label(), report.path.display(),
+                if report.rekeyed { ", the delta after them rewritten as a keyframe" } else { "" }
+            );
+        }
+        Some(Command::Archive { month, output_dir, keep }) => {
+            let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+            let report: ArchiveReport = storage::archive::archive(&raw_dir, &output_dir, month, keep).await?;
+            info!(
+                "Archived {} snapshots of {} into {} ({} bytes), {} deltas rewritten as keyframes{}",
+                report.files, month, report.path.display(), report.bytes, report.rekeyed, if keep { ", originals kept" } else { "" }
+            );
+        }
+        Some(Command::Merge { sources, compress, dry_run }) => {
+            let target: Option<PathBuf> = fs::canonicalize(&raw_dir).ok();
+            if let Some(source) = sources.iter().find(|source| target.is_some() && fs::canonicalize(source).ok() == target) {
+                return Err(BazaarError::Input(format!("{} is the raw directory merged into", source.display())));
+            }
+            let _lock: Option<DirectoryLock> = if dry_run { None } else { Some(storage::lock::lock(&raw_dir)?) };
+            let report: MergeReport = storage::merge::merge(&raw_dir, &sources, compress || config.storage.compress, dry_run).await?;
+            for error in &report.failed {
+                warn!("{}", error);
+            }
+            for conflict in &report.conflicts {
+                println!("Conflict at {}: kept {}, skipped {}", conflict.last_updated, conflict.kept.display(), conflict.skipped.display());
+            }
+            let verb: &str = if dry_run { "Would merge" } else { "Merged" };
+            info!(
+                "{} {} snapshots into {}, {} already present, {} conflicts, {} deltas rewritten as keyframes, {} failed",
+                verb, report.merged, raw_dir.display(), report.duplicates, report.conflicts.len(), report.rekeyed, report.failed.len()
+            );
+        }
+        Some(Command::Repair { dry_run }) => {
+            let _lock: Option<DirectoryLock> = if dry_run { None } else { Some(storage::lock::lock(&raw_dir)?) };
+            let report: RepairReport = quarantine::repair(&raw_dir, dry_run).await?;
+            let verb: &str = if dry_run { "Would recover" } else { "Recovered" };
+            info!(
+                "{} {} keyframes with {} products and {} deltas behind them, {} snapshots stay quarantined",
+                verb, report.recovered, report.products, report.restored, report.kept
+            );
+        }
+        Some(Command::Export { format }) => match format {
+            ExportFormat::History { product_id, output, since, dialect } => {
+                let product_id: String = product_id.to_uppercase();
+                let output: PathBuf = output.unwrap_or_else(|| PathBuf::from(format!("{}_history.csv", product_id)));
+                export::generate_product_history_csv(&raw_dir, &output, &product_id, dialect.resolve(&config.csv), since)?;
+            }
+            ExportFormat::Pivot { metric, output, resample, agg, since, dialect } => {
+                let output: PathBuf = output.unwrap_or_else(|| PathBuf::from(format!("pivot_{}.csv", metric.name())));
+                tokio::task::block_in_place(|| {
+                    export::generate_pivot_csv(&raw_dir, &output, &watchlist, metric, resample.as_ref().map(|window| (window, agg)), dialect.resolve(&config.csv), since)
+                })?;
+            }
+            ExportFormat::Clickhouse { print_schema: true, .. } => {
+                let table: String = format!("{}.{}", config.clickhouse.database, config.clickhouse.table);
+                println!("{};", export::clickhouse::SCHEMA.replace("{table}", &table));
+            }
+            ExportFormat::Clickhouse { url, print_schema: false } => {
+                let Some(url) = url.or_else(|| config.clickhouse.url.clone()) else {
+                    return Err(BazaarError::Input("Pass --url or set clickhouse.url".to_string()));
+                };
+                let (snapshots, rows): (usize, usize) =
+                    export::clickhouse::backfill(&client, &config.clickhouse, &url, &snapshot_files(&raw_dir)?).await?;
+                info!(snapshots, rows, "ClickHouse export done");
+            }
+            ExportFormat::Influx { output, measurement } => {
+                let measurement: String = measurement.unwrap_or_else(|| config.influx.measurement.clone());
+                let points: usize = export::influx::export_influx(&snapshot_files(&raw_dir)?, &output, &measurement)?;
+                info!(path = %output.display(), points, "Line protocol export generated");
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet { output } => {
+                let rows: usize = export::columnar::export_parquet(&snapshot_files(&raw_dir)?, &output)?;
+                info!(path = %output.display(), rows, "Parquet export generated");
+            }
+            #[cfg(feature = "arrow")]
+            ExportFormat::Arrow { output } => {
+                let rows: usize = export::columnar::export_arrow(&snapshot_files(&raw_dir)?, &output)?;
+                info!(path = %output.display(), rows, "Arrow export generated");
+            }
+            #[cfg(feature = "xlsx")]
+            ExportFormat::Xlsx { output, by_category } => {
+                let response: BazaarResponse = read_newest(&raw_dir, &watchlist)?;
+                watchlist.warn_missing(&response);
+                let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+                let rows: usize = export::xlsx::export_xlsx(&response, &watchlist, &items, &output, by_category)?;
+                info!(path = %output.display(), products = rows, "XLSX export generated");
+            }
+        },
+        #[cfg(feature = "server")]
+        Some(Command::Serve { bind }) => bazaar_update::server::serve(raw_dir, bind, None).await?,
+        #[cfg(feature = "tui")]
+        Some(Command::Watch { interval, plain: false }) => {
+            let interval: u64 = interval.unwrap_or(config.daemon.interval);
+            bazaar_update::tui::run(client, config.api.clone(), &raw_dir, interval, watchlist).await?
+        }
+        Some(Command::Watch { interval, .. }) => {
+            let interval: u64 = interval.unwrap_or(config.daemon.interval);
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            watch(&client, &config.api, interval, &watchlist, &items).await?
+        }
+        Some(Command::Replay {
+            speed, since, until, notify, indicators,
+            #[cfg(feature = "tui")]
+            plain,
+        }) => {
+            let windows: Vec<Window> = if indicators { config.indicators.windows.clone() } else { Vec::new() };
+            if indicators && watchlist.products().is_empty() {
+                return Err(BazaarError::Input("--indicators needs products to follow, pass --product or set watchlist.products".to_string()));
+            }
+            let files: Vec<PathBuf> = replay::replay_files(&raw_dir, since, until)?;
+            if files.is_empty() {
+                return Err(BazaarError::Input(format!("No snapshots to replay in {}", raw_dir.display())));
+            }
+            // Without --notify the alerts only go to the log
+            let alerts_config: AlertsConfig = if notify {
+                config.alerts.clone()
+            } else {
+                AlertsConfig { discord_webhook: None, desktop: false, webhooks: Vec::new(), telegram: None, ..config.alerts.clone() }
+            };
+            let keep: i64 = windows.iter().map(|window| window.millis).max().unwrap_or(0);
+            let mut pipeline: Pipeline = Pipeline::new(&config.alerts, &config.anomalies, watchlist.clone(), keep);
+            info!(files = files.len(), %speed, "Replaying");
+            let mut snapshots: mpsc::Receiver<BazaarResponse> = replay::play(files, since, speed);
+            #[cfg(feature = "tui")]
+            if !plain {
+                let (sender, updates) = mpsc::unbounded_channel::<bazaar_update::tui::Update>();
+                let client: reqwest::Client = client.clone();
+                let player: tokio::task::JoinHandle<()> = tokio::spawn(async move {
+                    while let Some(response) = snapshots.recv().await {
+                        let alerts: Vec<Alert> = pipeline.process(&response);
+                        // Logging would draw over the viewer, the alerts show in its title instead
+                        if notify {
+                            alerts::dispatch(&client, &alerts_config, &alerts).await;
+                        }
+                        if sender.send(bazaar_update::tui::Update::Bazaar(Ok(response))).is_err() {
+                            return;
+                        }
+                        if !alerts.is_empty() {
+                            let _ = sender.send(bazaar_update::tui::Update::Alerts(alerts.iter().map(Alert::message).collect()));
+                        }
+                    }
+                    let _ = sender.send(bazaar_update::tui::Update::Status("Replay finished".to_string()));
+                });
+                let result: Result<(), BazaarError> = bazaar_update::tui::view(watchlist, None, updates);
+                player.abort();
+                return result;
+            }
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            let markers: Vec<Gap> = gaps::load_markers(&raw_dir);
+            while let Some(response) = snapshots.recv().await {
+                let alerts: Vec<Alert> = pipeline.process(&response);
+                println!("Last updated: {} ({})", response.lastUpdated, timezone::format_timestamp(response.lastUpdated as i64));
+                analysis::print_quick_status(&response, &watchlist, &items);
+                if indicators {
+                    replay::print_indicators(pipeline.history(), &watchlist.products(), &windows, &markers);
+                }
+                alerts::dispatch(&client, &alerts_config, &alerts).await;
+            }
+        }
+        // No subcommand keeps the original fetch-then-summarize behavior, offline only the summary
+        None => {
+            if !offline {
+                let _lock: DirectoryLock = storage::lock::lock(&raw_dir)?;
+                fetch::get_and_dump(&fetch_context(false, None, false, false)).await?;
+            }
+            let items: ItemCatalog = items::load(&client, &config.api, &config.items, offline).await;
+            let options: CsvOptions = CsvOptions {
+                columns: config.csv.columns.clone(),
+                order: CsvOrder::default(),
+                dialect: CsvDialect { delimiter: config.csv.delimiter, quote: config.csv.quote },
+            };
+            export::generate_csv(&raw_dir, &config.csv.output, &watchlist, &items, &options, &[])?;
+        }
+    }
     Ok(())
 }
-
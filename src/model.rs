@@ -0,0 +1,339 @@
+// Bazaar API response types and the fixed-point numbers used for prices
+use crate::coins;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Div};
+use std::str::FromStr;
+
+// How a value that falls between two representable ones is rounded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    // Toward negative infinity
+    Floor,
+    // Toward positive infinity
+    Ceil,
+    // Drops the extra digits, what the operators and checked_div do
+    TowardZero,
+    // Halves away from zero, what from_float and rescale do
+    HalfUp,
+    // Halves to the even neighbour (banker's rounding), so rounding many values doesn't drift one way
+    HalfEven,
+}
+
+impl Rounding {
+    // numerator / divisor rounded this way, divisor isn't 0
+    fn divide(self, numerator: i128, divisor: i128) -> i128 {
+        let quotient: i128 = numerator / divisor;
+        let remainder: i128 = numerator % divisor;
+        if remainder == 0 {
+            return quotient;
+        }
+        let negative: bool = (numerator < 0) != (divisor < 0);
+        let away: i128 = if negative { quotient - 1 } else { quotient + 1 };
+        match self {
+            Rounding::Floor => if negative { away } else { quotient },
+            Rounding::Ceil => if negative { quotient } else { away },
+            Rounding::TowardZero => quotient,
+            Rounding::HalfUp | Rounding::HalfEven => match (remainder.abs() * 2).cmp(&divisor.abs()) {
+                Ordering::Less => quotient,
+                Ordering::Greater => away,
+                Ordering::Equal if self == Rounding::HalfUp || quotient % 2 != 0 => away,
+                Ordering::Equal => quotient,
+            },
+        }
+    }
+
+    fn round(self, value: f64) -> f64 {
+        match self {
+            Rounding::Floor => value.floor(),
+            Rounding::Ceil => value.ceil(),
+            Rounding::TowardZero => value.trunc(),
+            Rounding::HalfUp => value.round(),
+            Rounding::HalfEven => value.round_ties_even(),
+        }
+    }
+}
+
+// Tax rates are applied with this many decimals, enough for fractions of a basis point
+const RATE_DECIMALS: u32 = 8;
+const RATE_SCALE: i128 = 10i128.pow(RATE_DECIMALS);
+
+// Simple fixed-point with DECIMALS decimal places (scale factor of 10^DECIMALS).
+// f.e. with 2 decimals 1.23 is stored as 123.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed<const DECIMALS: u32>(i64);
+
+// Coins with 2 decimals, what most of the analysis works in
+pub type FixedPoint = Fixed<2>;
+// 4 decimals for order book prices, which carry more precision than 2 (e.g. 4.1 vs 4.1999)
+pub type FixedPoint4 = Fixed<4>;
+
+impl<const DECIMALS: u32> Fixed<DECIMALS> {
+    const SCALE: i64 = 10i64.pow(DECIMALS);
+    
+    // Constructor from a float (e.g., FixedPoint::from_float(1.23)) will round anyway 
+    pub fn from_float(value: f64) -> Self {
+        Self::from_float_with(value, Rounding::HalfUp)
+    }
+
+    pub fn from_float_with(value: f64, rounding: Rounding) -> Self {
+        Self(rounding.round(value * Self::SCALE as f64) as i64)
+    }
+    
+    // Constructor from a raw scaled integer (e.g., FixedPoint::from_int(123) for 1.23)
+    pub fn from_int(value: i64) -> Self {
+        Self(value)
+    }
+    
+    // Convert back to float for display or calculations
+    pub fn to_float(self) -> f64 {
+        self.0 as f64 / Self::SCALE as f64
+    }
+    
+    // Get the raw scaled value
+    pub fn raw(self) -> i64 {
+        self.0
+    }
+
+    // Abbreviated for tables and alerts, 12345678.9 as "12.3m"
+    pub fn format_coins(self) -> String {
+        coins::format_coins(self.to_float())
+    }
+
+    // Convert to another precision, rounding half away from zero when decimals are dropped
+    pub fn rescale<const TO: u32>(self) -> Fixed<TO> {
+        self.rescale_with(Rounding::HalfUp)
+    }
+
+    pub fn rescale_with<const TO: u32>(self, rounding: Rounding) -> Fixed<TO> {
+        let value: i128 = self.0 as i128;
+        let rescaled: i128 = if TO >= DECIMALS {
+            value * 10i128.pow(TO - DECIMALS)
+        } else {
+            rounding.divide(value, 10i128.pow(DECIMALS - TO))
+        };
+        Fixed(rescaled.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    // The bazaar's cut of a sale worth this much. Rounded half to even, so the taxes summed over
+    // many trades don't come out systematically high. The rate is taken to RATE_DECIMALS places
+    // and multiplied in as an integer, a float product would land just off an exact half.
+    pub fn tax(self, rate: f64) -> Self {
+        let rate: i128 = (rate * RATE_SCALE as f64).round() as i128;
+        let tax: i128 = Rounding::HalfEven.divide(self.0 as i128 * rate, RATE_SCALE);
+        Self(tax.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    // Checked variants return None on overflow (and division by zero) instead of panicking
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    // Widened to i128 so the intermediate product can't overflow before scaling down
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let product: i128 = self.0 as i128 * other.0 as i128 / Self::SCALE as i128;
+        i64::try_from(product).ok().map(Self)
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        self.div_with(other, Rounding::TowardZero)
+    }
+
+    // Checked multiplication and division with the dropped digits rounded as given
+    pub fn mul_with(self, other: Self, rounding: Rounding) -> Option<Self> {
+        let product: i128 = rounding.divide(self.0 as i128 * other.0 as i128, Self::SCALE as i128);
+        i64::try_from(product).ok().map(Self)
+    }
+
+    pub fn div_with(self, other: Self, rounding: Rounding) -> Option<Self> {
+        if other.0 == 0 {
+            return None;
+        }
+        let quotient: i128 = rounding.divide(self.0 as i128 * Self::SCALE as i128, other.0 as i128);
+        i64::try_from(quotient).ok().map(Self)
+    }
+
+    // Saturating variants clamp to the representable range, division by zero saturates by sign
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    pub fn saturating_mul(self, other: Self) -> Self {
+        let product: i128 = self.0 as i128 * other.0 as i128 / Self::SCALE as i128;
+        Self(product.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    pub fn saturating_div(self, other: Self) -> Self {
+        match self.checked_div(other) {
+            Some(quotient) => quotient,
+            None if (self.0 < 0) != (other.0 < 0) => Self(i64::MIN),
+            None => Self(i64::MAX),
+        }
+    }
+}
+
+// Returned by analysis code when a checked FixedPoint operation fails
+#[derive(Debug)]
+pub struct ArithmeticError(pub &'static str);
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fixed-point overflow or division by zero while computing {}", self.0)
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+// Widening is lossless, narrowing has to go through rescale
+impl From<FixedPoint> for FixedPoint4 {
+    fn from(value: FixedPoint) -> Self {
+        value.rescale()
+    }
+}
+
+impl<const DECIMALS: u32> Add for Fixed<DECIMALS> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl<const DECIMALS: u32> Sub for Fixed<DECIMALS> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl<const DECIMALS: u32> Mul for Fixed<DECIMALS> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        // Scale down after multiplication to maintain precision
+        Self((self.0 * other.0) / Self::SCALE)
+    }
+}
+
+impl<const DECIMALS: u32> Div for Fixed<DECIMALS> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        // Scale up before division
+        Self((self.0 * Self::SCALE) / other.0)
+    }
+}
+
+impl<const DECIMALS: u32> fmt::Display for Fixed<DECIMALS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*}", DECIMALS as usize, self.to_float())
+    }
+}
+
+// Command line amounts like "50m" or "320k" as well as plain numbers
+impl<const DECIMALS: u32> FromStr for Fixed<DECIMALS> {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        coins::parse_coins(value).map(Self::from_float)
+    }
+}
+
+// Serialized as the decimal value so snapshots keep the API's JSON shape
+impl<const DECIMALS: u32> Serialize for Fixed<DECIMALS> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_float())
+    }
+}
+
+struct FixedVisitor<const DECIMALS: u32>;
+
+impl<const DECIMALS: u32> serde::de::Visitor<'_> for FixedVisitor<DECIMALS> {
+    type Value = Fixed<DECIMALS>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a decimal number or a string containing one")
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(Fixed::from_float(value))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(Fixed::from_float(value as f64))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(Fixed::from_float(value as f64))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let parsed: f64 = value.trim().parse().map_err(|_| E::invalid_value(serde::de::Unexpected::Str(value), &self))?;
+        Ok(Fixed::from_float(parsed))
+    }
+}
+
+// Accepts numbers as well as numeric strings like "4.1"
+impl<'de, const DECIMALS: u32> Deserialize<'de> for Fixed<DECIMALS> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FixedVisitor)
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Order {
+    pub amount: u64, // Highest seen: 1186070
+    pub pricePerUnit: FixedPoint4,
+    pub orders: u32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct QuickStatus {
+    pub productId: String, 
+    pub sellPrice: f64, // MANDATORY
+    pub sellVolume: u64, // Highest seen: 1292216
+    pub sellMovingWeek: u64, // Highest seen: 188604293
+    pub sellOrders: u32, // Highest seen: 202
+    pub buyPrice: f64, // float IS MANDATORY
+    pub buyVolume: u64, // Highest seen: 11766801
+    pub buyMovingWeek: u64, // Highest seen: 9205352
+    pub buyOrders: u32, // Highest seen: 270
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Product {
+    pub product_id: String,
+    pub sell_summary: Vec<Order>,
+    pub buy_summary: Vec<Order>,
+    pub quick_status: QuickStatus,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize)]
+pub struct BazaarResponse {
+    pub success: bool,
+    pub lastUpdated: u64,
+    pub products: HashMap<String, Product>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tax_rounds_an_exact_half_to_even() {
+        // 0.40 and 1.20 at 1.25% are 0.005 and 0.015, 3.00 at 3.5% is 0.105, all exact halves of a cent
+        assert_eq!(FixedPoint::from_int(40).tax(0.0125), FixedPoint::from_int(0));
+        assert_eq!(FixedPoint::from_int(120).tax(0.0125), FixedPoint::from_int(2));
+        assert_eq!(FixedPoint::from_int(300).tax(0.035), FixedPoint::from_int(10));
+    }
+}
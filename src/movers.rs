@@ -0,0 +1,135 @@
+// Biggest price and volume changes between the newest snapshot and one a window earlier
+use crate::error::BazaarError;
+use crate::gaps::format_duration;
+use crate::history::read_quick_status;
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::storage::{read_last_updated, snapshot_timestamp};
+use crate::timezone::format_timestamp;
+use crate::watchlist::Watchlist;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MoverMetric {
+    Buy,
+    Sell,
+    Volume,
+}
+
+// Percent changes from the baseline to the newest snapshot, None when the baseline value was 0
+pub struct Mover {
+    pub product_id: String,
+    pub buy_price: f64,
+    pub buy_change: Option<f64>,
+    pub sell_price: f64,
+    pub sell_change: Option<f64>,
+    // Weekly volume traded on both sides (buyMovingWeek + sellMovingWeek)
+    pub volume: u64,
+    pub volume_change: Option<f64>,
+}
+
+impl Mover {
+    pub fn change(&self, metric: MoverMetric) -> Option<f64> {
+        match metric {
+            MoverMetric::Buy => self.buy_change,
+            MoverMetric::Sell => self.sell_change,
+            MoverMetric::Volume => self.volume_change,
+        }
+    }
+}
+
+// Both snapshots the movers are computed from, epoch millis
+pub struct Comparison {
+    pub baseline: i64,
+    pub latest: i64,
+    pub movers: Vec<Mover>,
+}
+
+fn percent_change(before: f64, after: f64) -> Option<f64> {
+    (before > 0.0).then(|| (after - before) / before * 100.0)
+}
+
+// The newest file taken at least `window_millis` before the last one. Falls back to the
+// oldest snapshot with a warning when the history is shorter than the window.
+pub fn baseline_file(files: &[PathBuf], window_millis: i64) -> Option<&PathBuf> {
+    let (last, older) = files.split_last()?;
+    let latest: i64 = snapshot_timestamp(last)?;
+    let baseline: Option<&PathBuf> = older.iter()
+        .rev()
+        .find(|file| snapshot_timestamp(file).is_some_and(|timestamp| timestamp <= latest - window_millis));
+    if baseline.is_none() && !older.is_empty() {
+        warn!("No snapshot is {} older than the newest one, comparing against the oldest", format_duration(window_millis));
+    }
+    baseline.or(older.first())
+}
+
+pub fn compare(baseline: &Path, latest: &Path, watchlist: &Watchlist) -> Result<Comparison, BazaarError> {
+    let before: HashMap<String, QuickStatus> = read_quick_status(baseline)?;
+    let after: HashMap<String, QuickStatus> = read_quick_status(latest)?;
+    let movers: Vec<Mover> = after.iter()
+        .filter(|(id, _)| watchlist.contains(id))
+        .filter_map(|(id, now)| {
+            let then: &QuickStatus = before.get(id)?;
+            let volume: u64 = now.buyMovingWeek + now.sellMovingWeek;
+            Some(Mover {
+                product_id: id.clone(),
+                buy_price: now.buyPrice,
+                buy_change: percent_change(then.buyPrice, now.buyPrice),
+                sell_price: now.sellPrice,
+                sell_change: percent_change(then.sellPrice, now.sellPrice),
+                volume,
+                volume_change: percent_change((then.buyMovingWeek + then.sellMovingWeek) as f64, volume as f64),
+            })
+        })
+        .collect();
+    Ok(Comparison {
+        baseline: read_last_updated(baseline)? as i64,
+        latest: read_last_updated(latest)? as i64,
+        movers,
+    })
+}
+
+fn format_change(value: Option<f64>) -> String {
+    value.map(|value| format!("{:+.2}", value)).unwrap_or_else(|| "-".to_string())
+}
+
+fn print_table(title: &str, movers: &[&Mover], items: &ItemCatalog) {
+    println!("{}", title);
+    println!(
+        "{:<40} {:<32} {:>14} {:>9} {:>14} {:>9} {:>14} {:>9}",
+        "product_id", "name", "buy_price", "buy%", "sell_price", "sell%", "week_volume", "volume%"
+    );
+    for mover in movers {
+        println!(
+            "{:<40} {:<32} {:>14.1} {:>9} {:>14.1} {:>9} {:>14} {:>9}",
+            mover.product_id,
+            items.name(&mover.product_id),
+            mover.buy_price,
+            format_change(mover.buy_change),
+            mover.sell_price,
+            format_change(mover.sell_change),
+            mover.volume,
+            format_change(mover.volume_change)
+        );
+    }
+}
+
+// `top` gainers and losers by `metric`, products without a change in it are left out
+pub fn print_movers(comparison: &Comparison, metric: MoverMetric, top: usize, items: &ItemCatalog) {
+    let mut ranked: Vec<&Mover> = comparison.movers.iter().filter(|mover| mover.change(metric).is_some()).collect();
+    ranked.sort_by(|a, b| {
+        b.change(metric).unwrap_or(0.0).total_cmp(&a.change(metric).unwrap_or(0.0)).then_with(|| a.product_id.cmp(&b.product_id))
+    });
+    println!(
+        "Comparing {} against {} ({} apart)",
+        format_timestamp(comparison.latest), format_timestamp(comparison.baseline), format_duration(comparison.latest - comparison.baseline)
+    );
+    let gainers: Vec<&Mover> = ranked.iter().take(top).filter(|mover| mover.change(metric) > Some(0.0)).copied().collect();
+    let losers: Vec<&Mover> = ranked.iter().rev().take(top).filter(|mover| mover.change(metric) < Some(0.0)).copied().collect();
+    print_table("Gainers", &gainers, items);
+    println!();
+    print_table("Losers", &losers, items);
+}
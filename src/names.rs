@@ -0,0 +1,82 @@
+// Readable names for bazaar product IDs the items API doesn't name: enchantment books, essences and
+// the legacy numeric-damage IDs like INK_SACK:3
+const SPECIAL: &[(&str, &str)] = &[
+    ("INK_SACK", "Ink Sac"),
+    ("INK_SACK:3", "Cocoa Beans"),
+    ("INK_SACK:4", "Lapis Lazuli"),
+    ("LOG", "Oak Log"),
+    ("LOG:1", "Spruce Log"),
+    ("LOG:2", "Birch Log"),
+    ("LOG:3", "Jungle Log"),
+    ("LOG_2", "Acacia Log"),
+    ("LOG_2:1", "Dark Oak Log"),
+    ("RAW_FISH", "Raw Cod"),
+    ("RAW_FISH:1", "Raw Salmon"),
+    ("RAW_FISH:2", "Tropical Fish"),
+    ("RAW_FISH:3", "Pufferfish"),
+    ("CARROT_ITEM", "Carrot"),
+    ("POTATO_ITEM", "Potato"),
+    ("NETHER_STALK", "Nether Wart"),
+    ("SULPHUR", "Gunpowder"),
+    ("ENDER_STONE", "End Stone"),
+    ("SNOW_BALL", "Snowball"),
+    ("WATER_LILY", "Lily Pad"),
+    ("HUGE_MUSHROOM_1", "Brown Mushroom Block"),
+    ("HUGE_MUSHROOM_2", "Red Mushroom Block"),
+];
+
+// 1 to 3999, enchantment levels never get near the top
+fn roman(mut value: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut out: String = String::new();
+    for (amount, numeral) in NUMERALS {
+        while value >= amount {
+            out.push_str(numeral);
+            value -= amount;
+        }
+    }
+    out
+}
+
+// "ENCHANTED_GOLD_BLOCK" -> "Enchanted Gold Block"
+fn title_case(id: &str) -> String {
+    id.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// ENCHANTMENT_ULTIMATE_CHIMERA_1 -> "Chimera I (Enchantment)", INK_SACK:3 -> "Cocoa Beans",
+// ESSENCE_WITHER -> "Wither Essence", anything else title-cased with a damage value kept as ":3"
+pub fn normalize(product_id: &str) -> String {
+    if let Some((_, name)) = SPECIAL.iter().find(|(id, _)| *id == product_id) {
+        return name.to_string();
+    }
+    if let Some(enchantment) = product_id.strip_prefix("ENCHANTMENT_") {
+        let enchantment: &str = enchantment.strip_prefix("ULTIMATE_").unwrap_or(enchantment);
+        if let Some((name, level)) = enchantment.rsplit_once('_')
+            && let Ok(level) = level.parse::<u32>()
+            && (1..4000).contains(&level)
+        {
+            return format!("{} {} (Enchantment)", title_case(name), roman(level));
+        }
+        return format!("{} (Enchantment)", title_case(enchantment));
+    }
+    if let Some(essence) = product_id.strip_prefix("ESSENCE_") {
+        return format!("{} Essence", title_case(essence));
+    }
+    match product_id.split_once(':') {
+        Some((base, damage)) => format!("{}:{}", normalize(base), damage),
+        None => title_case(product_id),
+    }
+}
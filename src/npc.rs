@@ -0,0 +1,84 @@
+// NPC flips: bazaar products that can be bought for less than an NPC pays for them.
+// The items API only carries NPC sell prices, so the reverse direction (buying from an
+// NPC shop to sell on the bazaar) can't be computed from the data we have.
+use crate::items::ItemCatalog;
+use crate::model::{ArithmeticError, BazaarResponse, FixedPoint, QuickStatus};
+use crate::watchlist::Watchlist;
+
+pub struct NpcFlip {
+    pub product_id: String,
+    pub npc_sell_price: FixedPoint,
+    // Insta-buy price and the top buy order, the slower but cheaper way in
+    pub insta_buy_price: FixedPoint,
+    pub order_price: FixedPoint,
+    // Per unit, selling to an NPC isn't taxed
+    pub insta_profit: FixedPoint,
+    pub order_profit: FixedPoint,
+    // Profit for selling `daily_limit` units in a day
+    pub insta_profit_per_limit: FixedPoint,
+    pub order_profit_per_limit: FixedPoint,
+}
+
+// Best daily insta-buy profit first. Products are kept as long as a buy order turns a profit.
+pub fn find_npc_flips(response: &BazaarResponse, watchlist: &Watchlist, items: &ItemCatalog, daily_limit: u64) -> Result<Vec<NpcFlip>, ArithmeticError> {
+    let limit: FixedPoint = FixedPoint::from_float(daily_limit as f64);
+    let mut flips: Vec<NpcFlip> = Vec::new();
+    for (id, product) in response.products.iter().filter(|(id, _)| watchlist.contains(id)) {
+        let Some(npc_sell_price) = items.npc_sell_price(id).map(FixedPoint::from_float) else {
+            continue;
+        };
+        let quick_status: &QuickStatus = &product.quick_status;
+        let insta_buy_price: FixedPoint = FixedPoint::from_float(quick_status.buyPrice);
+        let order_price: FixedPoint = FixedPoint::from_float(quick_status.sellPrice);
+        if order_price.raw() <= 0 {
+            continue;
+        }
+        let order_profit: FixedPoint = npc_sell_price.checked_sub(order_price).ok_or(ArithmeticError("order profit"))?;
+        if order_profit.raw() <= 0 {
+            continue;
+        }
+        // Without sell offers there's nothing to insta-buy
+        let insta_profit: FixedPoint = if insta_buy_price.raw() > 0 {
+            npc_sell_price.checked_sub(insta_buy_price).ok_or(ArithmeticError("insta profit"))?
+        } else {
+            FixedPoint::from_int(0)
+        };
+        flips.push(NpcFlip {
+            product_id: id.clone(),
+            npc_sell_price,
+            insta_buy_price,
+            order_price,
+            insta_profit,
+            order_profit,
+            insta_profit_per_limit: insta_profit.max(FixedPoint::from_int(0)).checked_mul(limit).ok_or(ArithmeticError("insta profit per limit"))?,
+            order_profit_per_limit: order_profit.checked_mul(limit).ok_or(ArithmeticError("order profit per limit"))?,
+        });
+    }
+    flips.sort_by(|a, b| {
+        b.insta_profit_per_limit.cmp(&a.insta_profit_per_limit)
+            .then_with(|| b.order_profit_per_limit.cmp(&a.order_profit_per_limit))
+            .then_with(|| a.product_id.cmp(&b.product_id))
+    });
+    Ok(flips)
+}
+
+pub fn print_npc_flips(flips: &[NpcFlip], items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>12} {:>12} {:>12} {:>12} {:>12} {:>14} {:>14}",
+        "product_id", "name", "npc_price", "insta_buy", "buy_order", "insta/unit", "order/unit", "insta/limit", "order/limit"
+    );
+    for flip in flips {
+        println!(
+            "{:<40} {:<32} {:>12} {:>12} {:>12} {:>12} {:>12} {:>14} {:>14}",
+            flip.product_id,
+            items.name(&flip.product_id),
+            flip.npc_sell_price.to_string(),
+            flip.insta_buy_price.to_string(),
+            flip.order_price.to_string(),
+            flip.insta_profit.to_string(),
+            flip.order_profit.to_string(),
+            flip.insta_profit_per_limit.to_string(),
+            flip.order_profit_per_limit.to_string()
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Paper trading: the daemon steps a strategy through every snapshot it fetches with a virtual
+// portfolio, the backtester's simulator with the live order books deciding where orders queue.
+// The portfolio is saved after every snapshot and filled orders go to a CSV, so a restart carries on.
+use crate::backtest::{self, BacktestOptions, EquityPoint, Simulator, Trade};
+use crate::error::BazaarError;
+use crate::model::BazaarResponse;
+use crate::strategy::Strategy;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+struct Session {
+    strategy: Box<dyn Strategy + Send>,
+    simulator: Simulator,
+}
+
+pub struct PaperTrader {
+    session: Mutex<Session>,
+    state: PathBuf,
+    trades: PathBuf,
+}
+
+impl PaperTrader {
+    // Resumes the portfolio saved in `state`, a new one starts with the options' capital
+    pub fn new(strategy: Box<dyn Strategy + Send>, options: BacktestOptions, state: PathBuf, trades: PathBuf) -> Result<Self, BazaarError> {
+        let simulator: Simulator = Simulator::resume(options, &state)?;
+        info!(
+            strategy = %strategy.describe(), capital = simulator.capital(), cash = simulator.account().cash(),
+            "Paper trading {}", if simulator.previous().is_some() { "resumed" } else { "started" }
+        );
+        Ok(Self { session: Mutex::new(Session { strategy, simulator }), state, trades })
+    }
+
+    // A snapshot no newer than the last one traded on, from overlapping daemon cycles, is skipped
+    pub fn record(&self, response: &BazaarResponse) {
+        let mut session = self.session.lock().unwrap();
+        let Session { strategy, simulator } = &mut *session;
+        if simulator.previous().is_some_and(|previous| response.lastUpdated as i64 <= previous) {
+            return;
+        }
+        simulator.step_live(strategy.as_mut(), response);
+        let (trades, equity): (Vec<Trade>, Vec<EquityPoint>) = simulator.take_log();
+        for trade in &trades {
+            info!(
+                product = %trade.product_id, units = trade.units, price = format!("{:.1}", trade.price),
+                profit = trade.profit.map(|profit| format!("{:+.1}", profit)).unwrap_or_default(),
+                "Paper {} order closed", trade.side.name()
+            );
+        }
+        if !trades.is_empty()
+            && let Err(e) = backtest::append_trades_csv(&trades, &self.trades)
+        {
+            warn!("Couldn't append the paper trades to {}: {}", self.trades.display(), e);
+        }
+        if let Some(point) = equity.last() {
+            let profit: f64 = point.equity - simulator.capital();
+            info!(
+                cash = format!("{:.1}", point.cash), holdings = format!("{:.1}", point.holdings),
+                equity = format!("{:.1}", point.equity),
+                "Paper P&L {:+.1} ({:+.2}%)", profit, profit / simulator.capital() * 100.0
+            );
+        }
+        if let Err(e) = simulator.save(&self.state) {
+            warn!("Couldn't save the paper portfolio to {}: {}", self.state.display(), e);
+        }
+    }
+}
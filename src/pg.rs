@@ -0,0 +1,156 @@
+// PostgreSQL store of the quick_status history for a central collector, with the same tables and
+// API as the SQLite store in db.rs. The schema is versioned: each connection applies the
+// migrations the database hasn't seen yet, so upgrading only needs a newer binary.
+use crate::db::{self, BackfillReport};
+use crate::error::BazaarError;
+use crate::model::QuickStatus;
+use postgres::types::ToSql;
+use postgres::{Client, NoTls, Transaction};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::info;
+
+// Applied in order, never edit one that has shipped, add a new one instead
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE snapshots (
+        last_updated BIGINT PRIMARY KEY,
+        source TEXT NOT NULL
+    );
+    CREATE TABLE quick_status (
+        last_updated BIGINT NOT NULL REFERENCES snapshots (last_updated),
+        product_id TEXT NOT NULL,
+        sell_price DOUBLE PRECISION NOT NULL,
+        sell_volume BIGINT NOT NULL,
+        sell_moving_week BIGINT NOT NULL,
+        sell_orders BIGINT NOT NULL,
+        buy_price DOUBLE PRECISION NOT NULL,
+        buy_volume BIGINT NOT NULL,
+        buy_moving_week BIGINT NOT NULL,
+        buy_orders BIGINT NOT NULL,
+        PRIMARY KEY (last_updated, product_id)
+    );
+    CREATE INDEX quick_status_product ON quick_status (product_id, last_updated);
+    ",
+];
+
+// Serializes migrations when several collectors start at once, the value is arbitrary
+const MIGRATION_LOCK: i64 = 0x6261_7a61_6172;
+
+// Rows per INSERT statement, 10 parameters each stays well below Postgres' limit of 65535
+const BATCH_ROWS: usize = 500;
+
+pub struct PgDatabase {
+    client: Client,
+}
+
+impl PgDatabase {
+    // Connects without TLS, keep the database on localhost or a private network
+    pub fn connect(url: &str) -> Result<Self, BazaarError> {
+        let mut client: Client = Client::connect(url, NoTls)?;
+        migrate(&mut client)?;
+        Ok(Self { client })
+    }
+
+    // Ok(false) when a snapshot with this lastUpdated is already stored
+    pub fn insert_snapshot(&mut self, last_updated: u64, source: &str, products: &HashMap<String, QuickStatus>) -> Result<bool, BazaarError> {
+        let last_updated: i64 = last_updated as i64;
+        let mut transaction: Transaction = self.client.transaction()?;
+        let inserted: u64 = transaction.execute(
+            "INSERT INTO snapshots (last_updated, source) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&last_updated, &source],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        let rows: Vec<(&String, &QuickStatus, [i64; 6])> = products.iter()
+            .map(|(id, quick_status)| (id, quick_status, [
+                quick_status.sellVolume as i64,
+                quick_status.sellMovingWeek as i64,
+                quick_status.sellOrders as i64,
+                quick_status.buyVolume as i64,
+                quick_status.buyMovingWeek as i64,
+                quick_status.buyOrders as i64,
+            ]))
+            .collect();
+        for chunk in rows.chunks(BATCH_ROWS) {
+            let mut values: Vec<String> = Vec::with_capacity(chunk.len());
+            let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(chunk.len() * 10);
+            for (i, (id, quick_status, counts)) in chunk.iter().enumerate() {
+                let placeholders: Vec<String> = (1..=10).map(|n| format!("${}", i * 10 + n)).collect();
+                values.push(format!("({})", placeholders.join(", ")));
+                params.extend_from_slice(&[
+                    &last_updated,
+                    *id,
+                    &quick_status.sellPrice,
+                    &counts[0],
+                    &counts[1],
+                    &counts[2],
+                    &quick_status.buyPrice,
+                    &counts[3],
+                    &counts[4],
+                    &counts[5],
+                ]);
+            }
+            transaction.execute(&format!("INSERT INTO quick_status VALUES {}", values.join(", ")), &params)?;
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    // One product's rows from `since` to `until` (epoch millis, both included), oldest first
+    pub fn product_series(&mut self, product_id: &str, since: i64, until: i64) -> Result<Vec<(i64, QuickStatus)>, BazaarError> {
+        let rows: Vec<postgres::Row> = self.client.query(
+            &format!("SELECT {} FROM quick_status WHERE product_id = $1 AND last_updated BETWEEN $2 AND $3 ORDER BY last_updated", db::SERIES_COLUMNS),
+            &[&product_id, &since, &until],
+        )?;
+        Ok(rows.iter()
+            .map(|row| (row.get::<_, i64>(0), QuickStatus {
+                productId: row.get(1),
+                sellPrice: row.get(2),
+                sellVolume: row.get::<_, i64>(3) as u64,
+                sellMovingWeek: row.get::<_, i64>(4) as u64,
+                sellOrders: row.get::<_, i64>(5) as u32,
+                buyPrice: row.get(6),
+                buyVolume: row.get::<_, i64>(7) as u64,
+                buyMovingWeek: row.get::<_, i64>(8) as u64,
+                buyOrders: row.get::<_, i64>(9) as u32,
+            }))
+            .collect())
+    }
+
+    // Bytes both tables take with their indexes, what `stats` reports
+    pub fn disk_usage(&mut self) -> Result<u64, BazaarError> {
+        let row: postgres::Row = self.client.query_one(
+            "SELECT pg_total_relation_size('snapshots') + pg_total_relation_size('quick_status')",
+            &[],
+        )?;
+        Ok(row.get::<_, i64>(0) as u64)
+    }
+
+    pub fn backfill(&mut self, files: &[PathBuf]) -> Result<BackfillReport, BazaarError> {
+        db::backfill(files, |last_updated, source, products| self.insert_snapshot(last_updated, source, products))
+    }
+}
+
+// Brings the schema up to the newest migration, each one in its own transaction
+fn migrate(client: &mut Client) -> Result<(), BazaarError> {
+    // Without this every run logs a NOTICE that schema_migrations already exists
+    client.batch_execute("SET client_min_messages = warning")?;
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, applied_at TIMESTAMPTZ NOT NULL DEFAULT now())")?;
+    for (version, migration) in (1..).zip(MIGRATIONS) {
+        let mut transaction: Transaction = client.transaction()?;
+        transaction.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])?;
+        let applied: bool = transaction
+            .query_one("SELECT EXISTS (SELECT 1 FROM schema_migrations WHERE version = $1)", &[&version])?
+            .get(0);
+        if applied {
+            continue;
+        }
+        transaction.batch_execute(migration)?;
+        transaction.execute("INSERT INTO schema_migrations (version) VALUES ($1)", &[&version])?;
+        transaction.commit()?;
+        info!(version, "Applied database migration");
+    }
+    Ok(())
+}
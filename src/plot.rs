@@ -0,0 +1,79 @@
+// Buy/sell price history charts rendered with plotters
+use crate::error::BazaarError;
+use crate::history::History;
+use crate::timezone::display_timezone;
+use chrono::{DateTime, Utc};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::Path;
+
+type Series = Vec<(DateTime<Utc>, f64)>;
+
+// Every line is non-empty, `start` and `end` the first and last time in them
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, lines: &[(&str, &Series, RGBColor)], (start, end): (DateTime<Utc>, DateTime<Utc>)) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    let prices = || lines.iter().flat_map(|(_, series, _)| series.iter()).map(|(_, price)| *price);
+    let low: f64 = prices().fold(f64::INFINITY, f64::min);
+    let high: f64 = prices().fold(f64::NEG_INFINITY, f64::max);
+    // Pad the range so flat series and single points still render
+    let padding: f64 = ((high - low) * 0.05).max(high.abs() * 0.001).max(0.1);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 28))
+        .margin(12)
+        .x_label_area_size(40)
+        .y_label_area_size(80)
+        .build_cartesian_2d(start..end.max(start + chrono::Duration::seconds(1)), (low - padding)..(high + padding))?;
+    chart.configure_mesh()
+        .x_labels(8)
+        .x_label_formatter(&|t: &DateTime<Utc>| display_timezone().format_millis(t.timestamp_millis(), "%m-%d %H:%M"))
+        .y_desc("coins")
+        .draw()?;
+    for &(label, series, color) in lines {
+        chart.draw_series(LineSeries::new(series.iter().copied(), &color))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    chart.configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    root.present()?;
+    Ok(())
+}
+
+// The output format follows the extension: .svg gives SVG, anything else PNG
+fn render(title: &str, lines: &[(&str, &Series, RGBColor)], output: &Path, size: (u32, u32)) -> Result<(), BazaarError> {
+    let (Some(&(start, _)), Some(&(end, _))) = (lines[0].1.first(), lines[0].1.last()) else {
+        return Err(BazaarError::Input(format!("No history found for {}", title)));
+    };
+    let drawn: Result<(), String> = if output.extension().is_some_and(|ext| ext == "svg") {
+        draw(SVGBackend::new(output, size).into_drawing_area(), title, lines, (start, end)).map_err(|e| e.to_string())
+    } else {
+        draw(BitMapBackend::new(output, size).into_drawing_area(), title, lines, (start, end)).map_err(|e| e.to_string())
+    };
+    drawn.map_err(BazaarError::Plot)
+}
+
+pub fn plot_history(history: &History, product_id: &str, output: &Path, size: (u32, u32)) -> Result<usize, BazaarError> {
+    let mut buy: Series = Vec::new();
+    let mut sell: Series = Vec::new();
+    for point in &history.points {
+        let (Some(quick_status), Some(time)) = (point.products.get(product_id), DateTime::from_timestamp_millis(point.timestamp)) else {
+            continue;
+        };
+        buy.push((time, quick_status.buyPrice));
+        sell.push((time, quick_status.sellPrice));
+    }
+    render(product_id, &[("buyPrice", &buy, RED), ("sellPrice", &sell, BLUE)], output, size)?;
+    Ok(buy.len())
+}
+
+// A portfolio's value over time along with what it cost, from `portfolio::value_history`
+pub fn plot_portfolio(values: &[(i64, f64)], cost_basis: f64, output: &Path, size: (u32, u32)) -> Result<(), BazaarError> {
+    let value: Series = values.iter()
+        .filter_map(|(timestamp, value)| DateTime::from_timestamp_millis(*timestamp).map(|time| (time, *value)))
+        .collect();
+    let cost: Series = value.iter().map(|(time, _)| (*time, cost_basis)).collect();
+    render("portfolio", &[("value", &value, BLUE), ("cost basis", &cost, BLACK)], output, size)
+}
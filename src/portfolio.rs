@@ -0,0 +1,160 @@
+// Holdings you actually own, kept in a TOML file: what they cost and what they're worth against the
+// newest snapshot, or over the stored history. Units are valued at what an instant sell would bring
+// after tax, the coins you'd get selling them right away.
+use crate::coins::format_coins;
+use crate::error::BazaarError;
+use crate::history::History;
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::timezone::format_timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Holding {
+    pub product_id: String,
+    pub quantity: u64,
+    // Coins paid per unit, the average over every purchase recorded
+    pub cost: f64,
+}
+
+impl Holding {
+    pub fn cost_basis(&self) -> f64 {
+        self.quantity as f64 * self.cost
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Portfolio {
+    pub holdings: Vec<Holding>,
+}
+
+impl Portfolio {
+    // A missing file is an empty portfolio
+    pub fn load(path: &Path) -> Result<Self, BazaarError> {
+        let data: String = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(BazaarError::Config(format!("Failed to read the portfolio {}: {}", path.display(), e))),
+        };
+        toml::from_str(&data).map_err(|e| BazaarError::Config(format!("Invalid portfolio {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BazaarError> {
+        let data: String = toml::to_string_pretty(self).map_err(|e| BazaarError::Config(format!("Can't write the portfolio: {}", e)))?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    // Buying more of a product already held averages the cost over all its units
+    pub fn add(&mut self, product_id: String, quantity: u64, cost: f64) {
+        match self.holdings.iter_mut().find(|holding| holding.product_id == product_id) {
+            Some(holding) => {
+                let total: u64 = holding.quantity + quantity;
+                holding.cost = (holding.cost_basis() + quantity as f64 * cost) / total as f64;
+                holding.quantity = total;
+            }
+            None => self.holdings.push(Holding { product_id, quantity, cost }),
+        }
+        self.holdings.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+    }
+
+    // Takes `quantity` units off the product at their average cost, every unit without one.
+    // Returns the units left, None when the product isn't held.
+    pub fn remove(&mut self, product_id: &str, quantity: Option<u64>) -> Option<u64> {
+        let index: usize = self.holdings.iter().position(|holding| holding.product_id == product_id)?;
+        let holding: &mut Holding = &mut self.holdings[index];
+        holding.quantity -= quantity.unwrap_or(holding.quantity).min(holding.quantity);
+        let left: u64 = holding.quantity;
+        if left == 0 {
+            self.holdings.remove(index);
+        }
+        Some(left)
+    }
+
+    pub fn cost_basis(&self) -> f64 {
+        self.holdings.iter().map(Holding::cost_basis).sum()
+    }
+
+    pub fn products(&self) -> Vec<String> {
+        self.holdings.iter().map(|holding| holding.product_id.clone()).collect()
+    }
+}
+
+// What one holding is worth in a snapshot, None when the snapshot lacks the product
+fn value(holding: &Holding, products: &HashMap<String, QuickStatus>, tax_rate: f64) -> Option<f64> {
+    products.get(&holding.product_id).map(|quick_status| holding.quantity as f64 * quick_status.sellPrice * (1.0 - tax_rate))
+}
+
+pub fn print_portfolio(portfolio: &Portfolio, response: &BazaarResponse, tax_rate: f64, items: &ItemCatalog) {
+    if portfolio.holdings.is_empty() {
+        println!("No holdings recorded, add some with `portfolio add`");
+        return;
+    }
+    let products: HashMap<String, QuickStatus> = response.products.iter()
+        .map(|(id, product)| (id.clone(), product.quick_status.clone()))
+        .collect();
+    println!(
+        "{:<40} {:<32} {:>10} {:>10} {:>12} {:>10} {:>12} {:>12} {:>9}",
+        "product_id", "name", "quantity", "cost", "cost_basis", "price", "value", "unrealized", "pnl%"
+    );
+    let (mut basis, mut worth): (f64, f64) = (0.0, 0.0);
+    for holding in &portfolio.holdings {
+        let Some(value) = value(holding, &products, tax_rate) else {
+            warn!("{} is not in the newest snapshot, left out of the totals", holding.product_id);
+            continue;
+        };
+        basis += holding.cost_basis();
+        worth += value;
+        let profit: f64 = value - holding.cost_basis();
+        let percent: String = if holding.cost_basis() > 0.0 { format!("{:+.2}", profit / holding.cost_basis() * 100.0) } else { "-".to_string() };
+        println!(
+            "{:<40} {:<32} {:>10} {:>10} {:>12} {:>10} {:>12} {:>12} {:>9}",
+            holding.product_id,
+            items.name(&holding.product_id),
+            holding.quantity,
+            format!("{:.1}", holding.cost),
+            format_coins(holding.cost_basis()),
+            format!("{:.1}", products[&holding.product_id].sellPrice),
+            format_coins(value),
+            format_coins(profit),
+            percent,
+        );
+    }
+    let profit: f64 = worth - basis;
+    let percent: String = if basis > 0.0 { format!("{:+.2}%", profit / basis * 100.0) } else { "-".to_string() };
+    println!();
+    println!("Cost basis: {}, value: {}, unrealized profit: {} ({})", format_coins(basis), format_coins(worth), format_coins(profit), percent);
+}
+
+// The portfolio's value in every snapshot, as if today's holdings had been held all along.
+// Snapshots missing one of the products are left out rather than valued short.
+pub fn value_history(portfolio: &Portfolio, history: &History, tax_rate: f64) -> Vec<(i64, f64)> {
+    history.points.iter()
+        .filter_map(|point| {
+            let values: Option<Vec<f64>> = portfolio.holdings.iter().map(|holding| value(holding, &point.products, tax_rate)).collect();
+            values.map(|values| (point.timestamp, values.iter().sum::<f64>()))
+        })
+        .collect()
+}
+
+pub fn write_value_csv(values: &[(i64, f64)], cost_basis: f64, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(["timestamp", "time", "value", "cost_basis", "unrealized"])?;
+    for (timestamp, value) in values {
+        wtr.write_record([
+            timestamp.to_string(),
+            format_timestamp(*timestamp),
+            format!("{:.1}", value),
+            format!("{:.1}", cost_basis),
+            format!("{:.1}", value - cost_basis),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
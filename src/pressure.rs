@@ -0,0 +1,65 @@
+// Buy/sell pressure: how much insta-buy volume flowed in against insta-sell volume over a window.
+// movingWeek counters only grow with new trades and shrink as week-old ones roll off, so the
+// positive steps between successive snapshots are the volume traded in between.
+use crate::history::History;
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+
+pub struct Pressure {
+    pub product_id: String,
+    // Units insta-bought (buyMovingWeek steps), the demand side
+    pub insta_buys: u64,
+    // Units insta-sold (sellMovingWeek steps), the supply side
+    pub insta_sells: u64,
+    // (insta_buys - insta_sells) / (insta_buys + insta_sells) in percent, from -100 to 100
+    pub index: f64,
+}
+
+// Most buying pressure first. Products trading fewer than `min_volume` units in the window are left out.
+pub fn pressure(history: &History, watchlist: &Watchlist, min_volume: u64) -> Vec<Pressure> {
+    let mut flows: HashMap<&str, (u64, u64)> = HashMap::new();
+    for pair in history.points.windows(2) {
+        let (before, after) = (&pair[0], &pair[1]);
+        for (id, now) in after.products.iter().filter(|(id, _)| watchlist.contains(id)) {
+            let Some(then): Option<&QuickStatus> = before.products.get(id) else {
+                continue;
+            };
+            let flow: &mut (u64, u64) = flows.entry(id.as_str()).or_default();
+            flow.0 += now.buyMovingWeek.saturating_sub(then.buyMovingWeek);
+            flow.1 += now.sellMovingWeek.saturating_sub(then.sellMovingWeek);
+        }
+    }
+    let mut rows: Vec<Pressure> = flows.into_iter()
+        .filter(|(_, (buys, sells))| buys + sells > 0 && buys + sells >= min_volume)
+        .map(|(id, (insta_buys, insta_sells))| Pressure {
+            product_id: id.to_string(),
+            insta_buys,
+            insta_sells,
+            index: (insta_buys as f64 - insta_sells as f64) / (insta_buys + insta_sells) as f64 * 100.0,
+        })
+        .collect();
+    rows.sort_by(|a, b| b.index.total_cmp(&a.index).then_with(|| a.product_id.cmp(&b.product_id)));
+    rows
+}
+
+fn print_table(title: &str, rows: &[&Pressure], items: &ItemCatalog) {
+    println!("{}", title);
+    println!("{:<40} {:<32} {:>14} {:>14} {:>10}", "product_id", "name", "insta_buys", "insta_sells", "pressure%");
+    for row in rows {
+        println!(
+            "{:<40} {:<32} {:>14} {:>14} {:>10.1}",
+            row.product_id, items.name(&row.product_id), row.insta_buys, row.insta_sells, row.index
+        );
+    }
+}
+
+// `top` products with the most demand and the most supply relative to each other
+pub fn print_pressure(rows: &[Pressure], top: usize, items: &ItemCatalog) {
+    let buying: Vec<&Pressure> = rows.iter().take(top).filter(|row| row.index > 0.0).collect();
+    let selling: Vec<&Pressure> = rows.iter().rev().take(top).filter(|row| row.index < 0.0).collect();
+    print_table("Buying pressure", &buying, items);
+    println!();
+    print_table("Selling pressure", &selling, items);
+}
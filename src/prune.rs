@@ -0,0 +1,77 @@
+// Retention policies for the raw snapshot directory
+use crate::storage::is_delta;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+
+#[derive(Default)]
+pub struct RetentionPolicy {
+    // Delete everything older than this many days
+    pub keep_days: Option<u64>,
+    // Past this age only the first snapshot of every hour is kept
+    pub hourly_after_days: Option<u64>,
+    // Past this age only the first snapshot of every day is kept
+    pub daily_after_days: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_days.is_none() && self.hourly_after_days.is_none() && self.daily_after_days.is_none()
+    }
+
+    // Bucket width a snapshot of this age is thinned to, None keeps it untouched
+    fn bucket_width(&self, age: i64) -> Option<i64> {
+        let older_than = |days: Option<u64>| days.is_some_and(|days| age > days as i64 * DAY_MILLIS);
+        if older_than(self.daily_after_days) {
+            Some(DAY_MILLIS)
+        } else if older_than(self.hourly_after_days) {
+            Some(HOUR_MILLIS)
+        } else {
+            None
+        }
+    }
+}
+
+// A delta needs its keyframe and every delta between them, so a kept delta keeps its whole
+// chain up to itself, even where the policy would have thinned it out
+fn keep_delta_chains(ordered: &[&(PathBuf, i64)], doomed: &mut Vec<PathBuf>) {
+    let doomed_set: HashSet<PathBuf> = doomed.iter().cloned().collect();
+    let mut needed: HashSet<&PathBuf> = HashSet::new();
+    let mut chain: Vec<&PathBuf> = Vec::new();
+    for (path, _) in ordered {
+        if !is_delta(path) {
+            chain.clear();
+        }
+        chain.push(path);
+        if is_delta(path) && !doomed_set.contains(path) {
+            needed.extend(chain.drain(..));
+        }
+    }
+    doomed.retain(|path| !needed.contains(path));
+}
+
+// Snapshots the policy wants gone, given (path, timestamp millis) pairs in any order
+pub fn plan(snapshots: &[(PathBuf, i64)], policy: &RetentionPolicy, now: i64) -> Vec<PathBuf> {
+    let mut ordered: Vec<&(PathBuf, i64)> = snapshots.iter().collect();
+    ordered.sort_by_key(|(path, timestamp)| (*timestamp, path.file_name().map(|name| name.to_os_string())));
+
+    let mut seen_buckets: HashSet<(i64, i64)> = HashSet::new();
+    let mut doomed: Vec<PathBuf> = Vec::new();
+    for (path, timestamp) in ordered.iter().copied() {
+        let age: i64 = now - timestamp;
+        if policy.keep_days.is_some_and(|days| age > days as i64 * DAY_MILLIS) {
+            doomed.push(path.clone());
+            continue;
+        }
+        if let Some(width) = policy.bucket_width(age) {
+            // The oldest snapshot of each bucket survives
+            if !seen_buckets.insert((width, timestamp.div_euclid(width))) {
+                doomed.push(path.clone());
+            }
+        }
+    }
+    keep_delta_chains(&ordered, &mut doomed);
+    doomed
+}
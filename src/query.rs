@@ -0,0 +1,181 @@
+// Ad-hoc questions over the stored history: one metric of a few products over a time range,
+// optionally resampled into fixed buckets, read from the raw snapshots or a database
+use crate::config::AlertMetric;
+use crate::error::BazaarError;
+use crate::history::{History, Window};
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::storage::{chain_start, snapshot_files, snapshot_timestamp};
+use crate::timezone::format_timestamp;
+use crate::watchlist::Watchlist;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Where the history is read from
+#[derive(Clone, Copy, ValueEnum)]
+pub enum QuerySource {
+    // The snapshots in the raw directory
+    Raw,
+    // database.path, filled by `backfill`
+    Sqlite,
+    // database.url, filled by `backfill`
+    Postgres,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Aggregation {
+    Mean,
+    Median,
+    Min,
+    Max,
+    First,
+    Last,
+    Sum,
+    // Number of samples in the bucket
+    Count,
+}
+
+impl Aggregation {
+    // `values` is never empty, kept in time order
+    fn apply(self, values: &[f64]) -> f64 {
+        match self {
+            Aggregation::Mean => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Median => {
+                let mut sorted: Vec<f64> = values.to_vec();
+                sorted.sort_by(f64::total_cmp);
+                let middle: usize = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) { (sorted[middle - 1] + sorted[middle]) / 2.0 } else { sorted[middle] }
+            }
+            Aggregation::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregation::First => values[0],
+            Aggregation::Last => values[values.len() - 1],
+            Aggregation::Sum => values.iter().sum(),
+            Aggregation::Count => values.len() as f64,
+        }
+    }
+}
+
+// Samples of each product, oldest first
+pub type ProductSeries = BTreeMap<String, Vec<(i64, QuickStatus)>>;
+
+pub struct QueryRow {
+    pub product_id: String,
+    // The sample's time, or the start of its bucket when resampled
+    pub timestamp: i64,
+    pub value: f64,
+    // Samples behind the value, 1 without resampling
+    pub samples: usize,
+}
+
+// File names give a cheap first cut, reaching back to the keyframe the first delta in range
+// builds on. The parsed lastUpdated decides.
+pub fn load_raw(raw_dir: &Path, products: &[String], since: i64, until: i64) -> Result<ProductSeries, BazaarError> {
+    let mut files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    files.retain(|file| snapshot_timestamp(file).is_none_or(|timestamp| timestamp <= until));
+    let first: usize = files.iter()
+        .position(|file| snapshot_timestamp(file).is_none_or(|timestamp| timestamp >= since))
+        .unwrap_or(files.len());
+    let history: History = History::load(&files[chain_start(&files, first)..], &Watchlist::new(products.iter().cloned()))?;
+    Ok(products.iter()
+        .map(|id| {
+            let series: Vec<(i64, QuickStatus)> = history.product_series(id)
+                .into_iter()
+                .filter(|(timestamp, _)| (since..=until).contains(timestamp))
+                .map(|(timestamp, quick_status)| (timestamp, quick_status.clone()))
+                .collect();
+            (id.clone(), series)
+        })
+        .collect())
+}
+
+// Buckets start at multiples of the resample width since the epoch, so 1h buckets start on the
+// (UTC) hour. Samples the metric has no value for (a margin without a sell price) are left out.
+pub fn evaluate(series: &ProductSeries, metric: AlertMetric, resample: Option<&Window>, aggregation: Aggregation) -> Vec<QueryRow> {
+    let mut rows: Vec<QueryRow> = Vec::new();
+    for (id, samples) in series {
+        let values = samples.iter().filter_map(|(timestamp, quick_status)| metric.value(quick_status).map(|value| (*timestamp, value)));
+        let Some(width) = resample.map(|window| window.millis) else {
+            rows.extend(values.map(|(timestamp, value)| QueryRow { product_id: id.clone(), timestamp, value, samples: 1 }));
+            continue;
+        };
+        let mut buckets: BTreeMap<i64, Vec<f64>> = BTreeMap::new();
+        for (timestamp, value) in values {
+            buckets.entry(timestamp.div_euclid(width) * width).or_default().push(value);
+        }
+        rows.extend(buckets.into_iter().map(|(start, values)| QueryRow {
+            product_id: id.clone(),
+            timestamp: start,
+            value: aggregation.apply(&values),
+            samples: values.len(),
+        }));
+    }
+    rows
+}
+
+pub fn print_rows(rows: &[QueryRow], metric: AlertMetric, items: &ItemCatalog) {
+    println!("{:<40} {:<32} {:<25} {:>16} {:>8}", "product_id", "name", "time", metric.name(), "samples");
+    for row in rows {
+        println!(
+            "{:<40} {:<32} {:<25} {:>16.2} {:>8}",
+            row.product_id, items.name(&row.product_id), format_timestamp(row.timestamp), row.value, row.samples
+        );
+    }
+}
+
+// What `query --sql` got back from SQLite
+pub struct SqlTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// Columns as wide as their widest value, numbers right-aligned
+pub fn print_table(table: &SqlTable) {
+    let widths: Vec<usize> = table.columns.iter()
+        .enumerate()
+        .map(|(index, column)| table.rows.iter().map(|row| row[index].chars().count()).fold(column.chars().count(), usize::max))
+        .collect();
+    let numeric: Vec<bool> = (0..table.columns.len())
+        .map(|index| table.rows.iter().all(|row| row[index].is_empty() || row[index].parse::<f64>().is_ok()))
+        .collect();
+    let line = |values: &[String]| -> String {
+        values.iter()
+            .zip(widths.iter().zip(&numeric))
+            .map(|(value, (width, numeric))| if *numeric { format!("{value:>width$}") } else { format!("{value:<width$}") })
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", line(&table.columns));
+    for row in &table.rows {
+        println!("{}", line(row));
+    }
+}
+
+pub fn write_table_csv(table: &SqlTable, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<std::fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(&table.columns)?;
+    for row in &table.rows {
+        wtr.write_record(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_csv(rows: &[QueryRow], metric: AlertMetric, output: &Path) -> Result<(), BazaarError> {
+    let mut wtr: csv::Writer<std::fs::File> = csv::Writer::from_path(output)?;
+    wtr.write_record(["product_id", "timestamp", "time", metric.name(), "samples"])?;
+    for row in rows {
+        wtr.write_record([
+            row.product_id.clone(),
+            row.timestamp.to_string(),
+            format_timestamp(row.timestamp),
+            row.value.to_string(),
+            row.samples.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+// Price ratios between crafted items and their ingredients, like an Enchanted Diamond Block against
+// the 160 Enchanted Diamonds it takes. A ratio is the output's worth over the ingredients', both at
+// the insta-sell price, and is tracked against its own mean over the stored history. A row is
+// flagged once crafting (buying the ingredients, selling the result) or decrafting (the other way
+// round, where the game allows it) clears the minimum margin after tax.
+use crate::history::History;
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::recipes::Recipe;
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Signal {
+    Craft,
+    Decraft,
+}
+
+impl Signal {
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Craft => "craft",
+            Signal::Decraft => "decraft",
+        }
+    }
+}
+
+pub struct Ratio {
+    pub product_id: String,
+    pub ratio: f64,
+    // Mean and standard deviation of the ratio over the history, None with fewer than two samples
+    pub mean: Option<f64>,
+    pub stddev: Option<f64>,
+    pub samples: usize,
+    // Profit relative to what's paid, in percent
+    pub craft_margin: f64,
+    pub decraft_margin: f64,
+    pub signal: Option<Signal>,
+}
+
+impl Ratio {
+    // Standard deviations the ratio sits from its mean
+    pub fn deviation(&self) -> Option<f64> {
+        match (self.mean, self.stddev) {
+            (Some(mean), Some(stddev)) if stddev > 0.0 => Some((self.ratio - mean) / stddev),
+            _ => None,
+        }
+    }
+}
+
+// The products a set of recipes needs prices for
+pub fn products(recipes: &[Recipe]) -> Watchlist {
+    Watchlist::new(recipes.iter().flat_map(|recipe| std::iter::once(recipe.output.clone()).chain(recipe.ingredients.keys().cloned())))
+}
+
+// The output's buy and sell prices per craft, and the ingredients' summed the same way. None when
+// a product is missing or one side of its book is empty.
+fn prices(products: &HashMap<String, QuickStatus>, recipe: &Recipe) -> Option<((f64, f64), (f64, f64))> {
+    let output: &QuickStatus = products.get(&recipe.output)?;
+    let mut ingredients: (f64, f64) = (0.0, 0.0);
+    for (id, units) in &recipe.ingredients {
+        let quick_status: &QuickStatus = products.get(id)?;
+        ingredients.0 += quick_status.buyPrice * *units as f64;
+        ingredients.1 += quick_status.sellPrice * *units as f64;
+    }
+    let output: (f64, f64) = (output.buyPrice * recipe.count as f64, output.sellPrice * recipe.count as f64);
+    (output.0 > 0.0 && output.1 > 0.0 && ingredients.0 > 0.0 && ingredients.1 > 0.0).then_some((output, ingredients))
+}
+
+fn ratio(products: &HashMap<String, QuickStatus>, recipe: &Recipe) -> Option<f64> {
+    prices(products, recipe).map(|(output, ingredients)| output.1 / ingredients.1)
+}
+
+// Instant trades pay the insta-buy price and get the insta-sell price, otherwise orders pay the top
+// buy order and sell offers get the lowest sell offer, like `analyze crafts`
+fn margins(output: (f64, f64), ingredients: (f64, f64), tax_rate: f64, instant: bool) -> (f64, f64) {
+    // (paid, got) from (buyPrice, sellPrice)
+    let sides = |prices: (f64, f64)| if instant { prices } else { (prices.1, prices.0) };
+    let (out_paid, out_got): (f64, f64) = sides(output);
+    let (in_paid, in_got): (f64, f64) = sides(ingredients);
+    let craft: f64 = (out_got * (1.0 - tax_rate) / in_paid - 1.0) * 100.0;
+    let decraft: f64 = (in_got * (1.0 - tax_rate) / out_paid - 1.0) * 100.0;
+    (craft, decraft)
+}
+
+// Margins come from the newest snapshot. Flagged rows first, then the ratios furthest from their mean.
+pub fn ratios(
+    response: &BazaarResponse, history: &History, recipes: &[Recipe], watchlist: &Watchlist,
+    tax_rate: f64, instant: bool, min_margin: f64,
+) -> Vec<Ratio> {
+    let current: HashMap<String, QuickStatus> = response.products.iter()
+        .map(|(id, product)| (id.clone(), product.quick_status.clone()))
+        .collect();
+    let mut rows: Vec<Ratio> = recipes.iter()
+        .filter(|recipe| watchlist.contains(&recipe.output))
+        .filter_map(|recipe| {
+            let (output, ingredients) = prices(&current, recipe)?;
+            let past: Vec<f64> = history.points.iter().filter_map(|point| ratio(&point.products, recipe)).collect();
+            let mean: Option<f64> = (past.len() >= 2).then(|| past.iter().sum::<f64>() / past.len() as f64);
+            let stddev: Option<f64> = mean.map(|mean| (past.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / past.len() as f64).sqrt());
+            let (craft_margin, decraft_margin): (f64, f64) = margins(output, ingredients, tax_rate, instant);
+            let signal: Option<Signal> = if craft_margin >= min_margin {
+                Some(Signal::Craft)
+            } else if decraft_margin >= min_margin {
+                Some(Signal::Decraft)
+            } else {
+                None
+            };
+            Some(Ratio {
+                product_id: recipe.output.clone(),
+                ratio: output.1 / ingredients.1,
+                mean,
+                stddev,
+                samples: past.len(),
+                craft_margin,
+                decraft_margin,
+                signal,
+            })
+        })
+        .collect();
+    let distance = |row: &Ratio| row.deviation().map_or(0.0, f64::abs);
+    rows.sort_by(|a, b| {
+        b.signal.is_some().cmp(&a.signal.is_some())
+            .then_with(|| distance(b).total_cmp(&distance(a)))
+            .then_with(|| a.product_id.cmp(&b.product_id))
+    });
+    rows
+}
+
+pub fn print_ratios(rows: &[Ratio], items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>10} {:>10} {:>8} {:>9} {:>9} {:>8}",
+        "product_id", "name", "ratio", "mean", "dev", "craft%", "decraft%", "signal"
+    );
+    for row in rows {
+        println!(
+            "{:<40} {:<32} {:>10.4} {:>10} {:>8} {:>9.2} {:>9.2} {:>8}",
+            row.product_id,
+            items.name(&row.product_id),
+            row.ratio,
+            row.mean.map(|mean| format!("{:.4}", mean)).unwrap_or_else(|| "-".to_string()),
+            row.deviation().map(|deviation| format!("{:+.2}", deviation)).unwrap_or_else(|| "-".to_string()),
+            row.craft_margin,
+            row.decraft_margin,
+            row.signal.map_or("-", Signal::name),
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// Crafting profits: what a recipe's ingredients cost on the bazaar against what the result sells for.
+// Recipes come from a JSON file, see recipes.example.json.
+use crate::error::BazaarError;
+use crate::items::ItemCatalog;
+use crate::model::{ArithmeticError, BazaarResponse, FixedPoint, QuickStatus};
+use crate::watchlist::Watchlist;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    pub output: String,
+    // Units of `output` one craft produces
+    #[serde(default = "one")]
+    pub count: u64,
+    // Product ID to units used per craft
+    pub ingredients: BTreeMap<String, u64>,
+}
+
+fn one() -> u64 {
+    1
+}
+
+pub fn load_recipes(path: &Path) -> Result<Vec<Recipe>, BazaarError> {
+    let data: String = fs::read_to_string(path)
+        .map_err(|e| BazaarError::Config(format!("Failed to read recipes {}: {}", path.display(), e)))?;
+    let recipes: Vec<Recipe> = serde_json::from_str(&data)
+        .map_err(|e| BazaarError::Config(format!("Invalid recipes {}: {}", path.display(), e)))?;
+    Ok(recipes)
+}
+
+pub struct Craft {
+    pub product_id: String,
+    // Ingredients for one craft
+    pub cost: FixedPoint,
+    // What the craft's output sells for after tax
+    pub revenue: FixedPoint,
+    // Profit if selling weren't taxed
+    pub gross_profit: FixedPoint,
+    pub profit: FixedPoint,
+    // Profit relative to the cost, in percent
+    pub margin: FixedPoint,
+}
+
+// Instant trades buy ingredients at the insta-buy price and insta-sell the result. Otherwise
+// ingredients come from buy orders and the result goes out as a sell offer.
+fn unit_prices(quick_status: &QuickStatus, instant: bool) -> (f64, f64) {
+    if instant {
+        (quick_status.buyPrice, quick_status.sellPrice)
+    } else {
+        (quick_status.sellPrice, quick_status.buyPrice)
+    }
+}
+
+fn craft_profit(response: &BazaarResponse, recipe: &Recipe, tax_rate: f64, instant: bool) -> Result<Option<Craft>, ArithmeticError> {
+    let mut cost: FixedPoint = FixedPoint::from_int(0);
+    for (ingredient, units) in &recipe.ingredients {
+        let Some(product) = response.products.get(ingredient) else {
+            warn!("Skipping {}, ingredient {} isn't in the snapshot", recipe.output, ingredient);
+            return Ok(None);
+        };
+        let (buy, _) = unit_prices(&product.quick_status, instant);
+        let price: FixedPoint = FixedPoint::from_float(buy * *units as f64);
+        cost = cost.checked_add(price).ok_or(ArithmeticError("craft cost"))?;
+    }
+    let Some(product) = response.products.get(&recipe.output) else {
+        warn!("Skipping {}, it isn't in the snapshot", recipe.output);
+        return Ok(None);
+    };
+    let (_, sell) = unit_prices(&product.quick_status, instant);
+    if sell <= 0.0 || cost.raw() <= 0 {
+        return Ok(None);
+    }
+    let gross_revenue: FixedPoint = FixedPoint::from_float(sell * recipe.count as f64);
+    let revenue: FixedPoint = gross_revenue.checked_sub(gross_revenue.tax(tax_rate)).ok_or(ArithmeticError("craft revenue"))?;
+    let profit: FixedPoint = revenue.checked_sub(cost).ok_or(ArithmeticError("craft profit"))?;
+    Ok(Some(Craft {
+        product_id: recipe.output.clone(),
+        cost,
+        revenue,
+        gross_profit: gross_revenue.checked_sub(cost).ok_or(ArithmeticError("craft gross profit"))?,
+        profit,
+        margin: crate::analysis::percent_of(profit, cost).ok_or(ArithmeticError("craft margin"))?,
+    }))
+}
+
+// Most profitable first, including the losing ones so a whole recipe book can be compared
+pub fn craft_profits(response: &BazaarResponse, recipes: &[Recipe], watchlist: &Watchlist, tax_rate: f64, instant: bool) -> Result<Vec<Craft>, ArithmeticError> {
+    let mut crafts: Vec<Craft> = Vec::new();
+    for recipe in recipes.iter().filter(|recipe| watchlist.contains(&recipe.output)) {
+        crafts.extend(craft_profit(response, recipe, tax_rate, instant)?);
+    }
+    crafts.sort_by(|a, b| b.profit.cmp(&a.profit).then_with(|| a.product_id.cmp(&b.product_id)));
+    Ok(crafts)
+}
+
+pub fn print_crafts(crafts: &[Craft], items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>14} {:>14} {:>14} {:>14} {:>9}",
+        "product_id", "name", "cost", "revenue", "gross_profit", "net_profit", "margin%"
+    );
+    for craft in crafts {
+        println!(
+            "{:<40} {:<32} {:>14} {:>14} {:>14} {:>14} {:>9}",
+            craft.product_id,
+            items.name(&craft.product_id),
+            craft.cost.format_coins(),
+            craft.revenue.format_coins(),
+            craft.gross_profit.format_coins(),
+            craft.profit.format_coins(),
+            craft.margin.to_string()
+        );
+    }
+}
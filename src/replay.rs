@@ -0,0 +1,186 @@
+// Stored snapshots played back as a live feed: oldest first, spaced out like they were fetched but
+// `speed` times faster, through the same alert rules and anomaly checks a fetch runs. Cooldowns
+// and windows go by the snapshots' lastUpdated, so a rule fires in a replay where it would have
+// fired live.
+use crate::alerts::{self, Alert, AlertState};
+use crate::anomalies::{self, Anomaly};
+use crate::config::{AlertRule, AlertsConfig, AnomaliesConfig};
+use crate::error::BazaarError;
+use crate::gaps::Gap;
+use crate::history::{History, HistoryPoint, Window};
+use crate::indicators::{self, format_percent, format_price, percent_from};
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::storage::{chain_start, read_into, snapshot_files, snapshot_timestamp};
+use crate::watchlist::Watchlist;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::warn;
+
+// How much faster than real time snapshots come, None plays them as fast as they're read
+#[derive(Clone, Copy)]
+pub struct Speed(Option<f64>);
+
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value: &str = value.trim();
+        if value == "max" {
+            return Ok(Speed(None));
+        }
+        match value.strip_suffix('x').unwrap_or(value).parse::<f64>() {
+            Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(Speed(Some(speed))),
+            _ => Err(format!("Invalid speed {:?}, expected a factor like 10x or 0.5x, or max", value)),
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(speed) => write!(f, "{}x", speed),
+            None => f.write_str("max"),
+        }
+    }
+}
+
+impl Speed {
+    // The wait standing in for `millis` between two snapshots
+    fn delay(self, millis: i64) -> Duration {
+        match self.0 {
+            Some(speed) => Duration::from_secs_f64(millis.max(0) as f64 / 1000.0 / speed),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+// The snapshots stored from `since` to `until`, led by the keyframe and deltas the first one
+// builds on. Files whose name doesn't tell their time are kept, the read decides.
+pub fn replay_files(raw_dir: &Path, since: Option<i64>, until: Option<i64>) -> Result<Vec<PathBuf>, BazaarError> {
+    let mut files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    files.retain(|file| snapshot_timestamp(file).is_none_or(|timestamp| until.is_none_or(|until| timestamp <= until)));
+    let first: usize = files.iter()
+        .position(|file| snapshot_timestamp(file).is_none_or(|timestamp| since.is_none_or(|since| timestamp >= since)))
+        .unwrap_or(files.len());
+    Ok(files.split_off(chain_start(&files, first)))
+}
+
+// Sends every snapshot of `files` from `since` on, waiting between them as `speed` says. A file
+// that doesn't read is skipped with a warning, along with the deltas after it up to the next
+// keyframe. The channel closes after the last one, dropping the receiver stops the replay.
+pub fn play(files: Vec<PathBuf>, since: Option<i64>, speed: Speed) -> mpsc::Receiver<BazaarResponse> {
+    let (sender, receiver) = mpsc::channel::<BazaarResponse>(1);
+    tokio::spawn(async move {
+        let everything: Watchlist = Watchlist::default();
+        let mut state: Option<HashMap<String, Product>> = None;
+        let mut previous: Option<u64> = None;
+        for file in files {
+            let read: Result<u64, BazaarError> = tokio::task::block_in_place(|| read_into(&file, &mut state, &everything));
+            let last_updated: u64 = match read {
+                Ok(last_updated) => last_updated,
+                Err(e) => {
+                    warn!("Skipping {}: {}", file.display(), e);
+                    state = None;
+                    continue;
+                }
+            };
+            let Some(products) = &state else {
+                continue;
+            };
+            if since.is_some_and(|since| (last_updated as i64) < since) {
+                continue;
+            }
+            let response: BazaarResponse = BazaarResponse { success: true, lastUpdated: last_updated, products: products.clone() };
+            if let Some(previous) = previous {
+                time::sleep(speed.delay(last_updated as i64 - previous as i64)).await;
+            }
+            previous = Some(last_updated);
+            if sender.send(response).await.is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+// What a fetch checks every snapshot against, with the state it keeps between them held in
+// memory: the cooldowns saved in alerts.state belong to the live collection and aren't touched
+pub struct Pipeline {
+    rules: Vec<AlertRule>,
+    state: AlertState,
+    previous: Option<HashMap<String, QuickStatus>>,
+    anomalies: AnomaliesConfig,
+    // The replayed points within `keep` of the newest, every product when anomalies are checked
+    // and only the watchlisted ones otherwise
+    history: History,
+    keep: i64,
+    watchlist: Watchlist,
+}
+
+impl Pipeline {
+    // `keep` millis of history are held for the anomaly window and any indicators on top of it
+    pub fn new(alerts: &AlertsConfig, anomalies: &AnomaliesConfig, watchlist: Watchlist, keep: i64) -> Self {
+        Self {
+            rules: alerts.all_rules(),
+            state: AlertState::default(),
+            previous: None,
+            anomalies: anomalies.clone(),
+            history: History { points: Vec::new() },
+            keep: keep.max(anomalies.window.millis),
+            watchlist,
+        }
+    }
+
+    // The alerts `response` raises, the rules' then the anomalies', like a fetch sends them
+    pub fn process(&mut self, response: &BazaarResponse) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = alerts::evaluate(&self.rules, self.previous.as_ref(), response, &mut self.state);
+        self.previous = Some(response.products.iter().map(|(id, product)| (id.clone(), product.quick_status.clone())).collect());
+        let timestamp: i64 = response.lastUpdated as i64;
+        let products: HashMap<String, QuickStatus> = response.products.iter()
+            .filter(|(id, _)| self.anomalies.alert || self.watchlist.contains(id))
+            .map(|(id, product)| (id.clone(), product.quick_status.clone()))
+            .collect();
+        let history: History = std::mem::replace(&mut self.history, History { points: Vec::new() });
+        self.history = anomalies::with_newest(history, HistoryPoint { timestamp, products });
+        self.history.points.retain(|point| point.timestamp >= timestamp - self.keep);
+        if self.anomalies.alert {
+            let anomalies: Vec<Anomaly> = anomalies::latest(&self.history, &Watchlist::default(), &self.anomalies);
+            alerts.extend(anomalies.iter().map(|anomaly| anomaly.to_alert(&self.anomalies)));
+        }
+        alerts
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+}
+
+// Moving averages of watchlisted products over what's been replayed so far, windows reaching
+// back past the start of the replay only average the part that's there
+pub fn print_indicators(history: &History, products: &[&str], windows: &[Window], gaps: &[Gap]) {
+    println!(
+        "{:<40} {:<8} {:>14} {:>12} {:>14} {:>12}",
+        "product_id", "window", "buy_sma", "buy_vs_sma%", "sell_sma", "sell_vs_sma%"
+    );
+    for id in products {
+        let Some(&(_, current)) = history.product_series(id).last() else {
+            continue;
+        };
+        for indicators in indicators::compute(history, id, windows, gaps) {
+            println!(
+                "{:<40} {:<8} {:>14} {:>12} {:>14} {:>12}",
+                id,
+                indicators.window.to_string(),
+                format_price(indicators.buy.sma),
+                format_percent(percent_from(current.buyPrice, indicators.buy.sma)),
+                format_price(indicators.sell.sma),
+                format_percent(percent_from(current.sellPrice, indicators.sell.sma)),
+            );
+        }
+    }
+}
@@ -0,0 +1,275 @@
+// Daily and weekly Markdown (optionally HTML) reports: movers over the period, the best flips in
+// the newest snapshot, volume leaders and a price chart per watched product
+use crate::error::BazaarError;
+use crate::flips::{self, Flip, FlipOptions};
+use crate::history::History;
+use crate::items::ItemCatalog;
+use crate::model::BazaarResponse;
+use crate::movers::{self, Comparison, Mover};
+use crate::pressure::{self, Pressure};
+use crate::storage::{read_newest, snapshot_files};
+use crate::timezone::{display_timezone, format_timestamp};
+use crate::watchlist::Watchlist;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportPeriod {
+    Daily,
+    Weekly,
+}
+
+impl ReportPeriod {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "daily",
+            ReportPeriod::Weekly => "weekly",
+        }
+    }
+
+    pub fn millis(self) -> i64 {
+        match self {
+            ReportPeriod::Daily => DAY_MILLIS,
+            ReportPeriod::Weekly => 7 * DAY_MILLIS,
+        }
+    }
+}
+
+pub struct ReportOptions {
+    pub period: ReportPeriod,
+    // Rows per table, and the products charted when the watchlist names none
+    pub top: usize,
+    pub html: bool,
+    pub tax_rate: f64,
+}
+
+// The report is built once and rendered to each format
+enum Block {
+    Heading(String),
+    Text(String),
+    Table { header: Vec<&'static str>, rows: Vec<Vec<String>> },
+    // Path relative to the report
+    #[cfg(feature = "plot")]
+    Image { title: String, path: String },
+}
+
+fn table(header: Vec<&'static str>, rows: Vec<Vec<String>>) -> Block {
+    if rows.is_empty() {
+        return Block::Text("Nothing to show for this period.".to_string());
+    }
+    Block::Table { header, rows }
+}
+
+fn format_change(value: Option<f64>) -> String {
+    value.map(|value| format!("{:+.2}%", value)).unwrap_or_else(|| "-".to_string())
+}
+
+fn mover_rows(movers: &[&Mover], items: &ItemCatalog) -> Vec<Vec<String>> {
+    movers.iter()
+        .map(|mover| vec![
+            items.name(&mover.product_id).into_owned(),
+            format!("{:.1}", mover.buy_price),
+            format_change(mover.buy_change),
+            format!("{:.1}", mover.sell_price),
+            format_change(mover.sell_change),
+        ])
+        .collect()
+}
+
+fn movers_blocks(comparison: &Comparison, top: usize, items: &ItemCatalog) -> Vec<Block> {
+    let mut ranked: Vec<&Mover> = comparison.movers.iter().filter(|mover| mover.buy_change.is_some()).collect();
+    ranked.sort_by(|a, b| b.buy_change.unwrap_or(0.0).total_cmp(&a.buy_change.unwrap_or(0.0)).then_with(|| a.product_id.cmp(&b.product_id)));
+    let gainers: Vec<&Mover> = ranked.iter().take(top).filter(|mover| mover.buy_change > Some(0.0)).copied().collect();
+    let losers: Vec<&Mover> = ranked.iter().rev().take(top).filter(|mover| mover.buy_change < Some(0.0)).copied().collect();
+    let header: Vec<&'static str> = vec!["Product", "Buy price", "Buy change", "Sell price", "Sell change"];
+    vec![
+        Block::Heading("Biggest movers".to_string()),
+        Block::Text(format!("Buy price change from {} to {}.", format_timestamp(comparison.baseline), format_timestamp(comparison.latest))),
+        Block::Text("Gainers".to_string()),
+        table(header.clone(), mover_rows(&gainers, items)),
+        Block::Text("Losers".to_string()),
+        table(header, mover_rows(&losers, items)),
+    ]
+}
+
+// Profitable, liquid flips ranked by margin, the profit per hour shows which are worth the time
+fn flips_blocks(flips: &mut [Flip], top: usize, items: &ItemCatalog) -> Vec<Block> {
+    flips.sort_by(|a, b| b.spread.net_margin.cmp(&a.spread.net_margin).then_with(|| a.spread.product_id.cmp(&b.spread.product_id)));
+    let rows: Vec<Vec<String>> = flips.iter()
+        .take(top)
+        .map(|flip| vec![
+            items.name(&flip.spread.product_id).into_owned(),
+            flip.spread.sell_price.to_string(),
+            flip.spread.buy_price.to_string(),
+            format!("{}%", flip.spread.net_margin),
+            flip.weekly_volume.to_string(),
+            flip.profit_per_hour.to_string(),
+        ])
+        .collect();
+    vec![
+        Block::Heading("Highest-margin flips".to_string()),
+        table(vec!["Product", "Buy order", "Sell offer", "Net margin", "Week volume", "Profit/h"], rows),
+    ]
+}
+
+fn volume_blocks(leaders: &[Pressure], top: usize, items: &ItemCatalog) -> Vec<Block> {
+    let rows: Vec<Vec<String>> = leaders.iter()
+        .take(top)
+        .map(|row| vec![
+            items.name(&row.product_id).into_owned(),
+            (row.insta_buys + row.insta_sells).to_string(),
+            row.insta_buys.to_string(),
+            row.insta_sells.to_string(),
+        ])
+        .collect();
+    vec![
+        Block::Heading("Volume leaders".to_string()),
+        table(vec!["Product", "Units traded", "Insta-buys", "Insta-sells"], rows),
+    ]
+}
+
+// One SVG per product next to the report, the ones without history in the period are left out
+#[cfg(feature = "plot")]
+fn chart_blocks(history: &History, products: &[String], charts_dir: &Path, report_dir: &Path, items: &ItemCatalog) -> Result<Vec<Block>, BazaarError> {
+    fs::create_dir_all(charts_dir)?;
+    let mut blocks: Vec<Block> = vec![Block::Heading("Charts".to_string())];
+    for product_id in products {
+        // Colons (INK_SACK:3) aren't allowed in Windows file names
+        let path: PathBuf = charts_dir.join(format!("{}.svg", product_id.replace(':', "_")));
+        match crate::plot::plot_history(history, product_id, &path, (960, 480)) {
+            Ok(_) => blocks.push(Block::Image {
+                title: items.name(product_id).into_owned(),
+                path: path.strip_prefix(report_dir).unwrap_or(&path).to_string_lossy().replace('\\', "/"),
+            }),
+            Err(BazaarError::Input(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if blocks.len() == 1 {
+        blocks.push(Block::Text("Nothing to show for this period.".to_string()));
+    }
+    Ok(blocks)
+}
+
+fn escape_markdown(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+fn to_markdown(title: &str, blocks: &[Block]) -> String {
+    let mut out: String = format!("# {}\n", title);
+    for block in blocks {
+        match block {
+            Block::Heading(heading) => out.push_str(&format!("\n## {}\n", heading)),
+            Block::Text(text) => out.push_str(&format!("\n{}\n", text)),
+            Block::Table { header, rows } => {
+                out.push_str(&format!("\n| {} |\n", header.join(" | ")));
+                out.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+                for row in rows {
+                    let cells: Vec<String> = row.iter().map(|cell| escape_markdown(cell)).collect();
+                    out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                }
+            }
+            #[cfg(feature = "plot")]
+            Block::Image { title, path } => out.push_str(&format!("\n### {}\n\n![{}]({})\n", title, escape_markdown(title), path)),
+        }
+    }
+    out
+}
+
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn to_html(title: &str, blocks: &[Block]) -> String {
+    let mut out: String = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n\
+         body {{ font-family: sans-serif; margin: 2em auto; max-width: 1000px; }}\n\
+         table {{ border-collapse: collapse; margin: 0.5em 0; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 0.25em 0.6em; }}\n\
+         td:not(:first-child) {{ text-align: right; }}\n\
+         img {{ max-width: 100%; }}\n</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        escape_html(title)
+    );
+    for block in blocks {
+        match block {
+            Block::Heading(heading) => out.push_str(&format!("<h2>{}</h2>\n", escape_html(heading))),
+            Block::Text(text) => out.push_str(&format!("<p>{}</p>\n", escape_html(text))),
+            Block::Table { header, rows } => {
+                out.push_str("<table>\n<tr>");
+                for cell in header {
+                    out.push_str(&format!("<th>{}</th>", escape_html(cell)));
+                }
+                out.push_str("</tr>\n");
+                for row in rows {
+                    out.push_str("<tr>");
+                    for cell in row {
+                        out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            }
+            #[cfg(feature = "plot")]
+            Block::Image { title, path } => out.push_str(&format!(
+                "<h3>{0}</h3>\n<img src=\"{1}\" alt=\"{0}\">\n", escape_html(title), escape_html(path)
+            )),
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+// The period ends at the newest snapshot. Writes <period>_<date>.md (and .html) into `output_dir`,
+// the charts into a folder of the same name, and returns the reports written.
+pub fn generate(raw_dir: &Path, output_dir: &Path, watchlist: &Watchlist, items: &ItemCatalog, options: &ReportOptions) -> Result<Vec<PathBuf>, BazaarError> {
+    let files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    let Some(latest) = files.last() else {
+        return Err(BazaarError::Input(format!("No snapshots in {}", raw_dir.display())));
+    };
+    let response: BazaarResponse = read_newest(raw_dir, watchlist)?;
+    let history: History = History::load_window(&files, options.period.millis(), watchlist)?;
+
+    let name: String = format!("{}_{}", options.period.label(), display_timezone().format_millis(response.lastUpdated as i64, "%Y-%m-%d"));
+    let title: String = format!("Bazaar {} report, {}", options.period.label(), display_timezone().format_millis(response.lastUpdated as i64, "%Y-%m-%d"));
+    let mut blocks: Vec<Block> = Vec::new();
+    blocks.push(Block::Text(format!(
+        "{} snapshots from {} to {}.",
+        history.points.len(),
+        history.points.first().map_or_else(|| "-".to_string(), |point| format_timestamp(point.timestamp)),
+        format_timestamp(response.lastUpdated as i64),
+    )));
+
+    match movers::baseline_file(&files, options.period.millis()) {
+        Some(baseline) => blocks.extend(movers_blocks(&movers::compare(baseline, latest, watchlist)?, options.top, items)),
+        None => blocks.extend([Block::Heading("Biggest movers".to_string()), Block::Text("Movers need at least two snapshots.".to_string())]),
+    }
+    let mut flips: Vec<Flip> = flips::find_flips(&response, watchlist, &FlipOptions::defaults(options.tax_rate))?;
+    blocks.extend(flips_blocks(&mut flips, options.top, items));
+
+    let mut leaders: Vec<Pressure> = pressure::pressure(&history, watchlist, 0);
+    leaders.sort_by(|a, b| (b.insta_buys + b.insta_sells).cmp(&(a.insta_buys + a.insta_sells)).then_with(|| a.product_id.cmp(&b.product_id)));
+    blocks.extend(volume_blocks(&leaders, options.top, items));
+
+    // The watchlist's products, or the volume leaders when it names none
+    #[cfg(feature = "plot")]
+    {
+        let charted: Vec<String> = match watchlist.products() {
+            products if products.is_empty() => leaders.iter().take(options.top).map(|row| row.product_id.clone()).collect(),
+            products => products.into_iter().map(str::to_string).collect(),
+        };
+        blocks.extend(chart_blocks(&history, &charted, &output_dir.join(&name), output_dir, items)?);
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let mut written: Vec<PathBuf> = vec![output_dir.join(format!("{}.md", name))];
+    fs::write(&written[0], to_markdown(&title, &blocks))?;
+    if options.html {
+        let path: PathBuf = output_dir.join(format!("{}.html", name));
+        fs::write(&path, to_html(&title, &blocks))?;
+        written.push(path);
+    }
+    Ok(written)
+}
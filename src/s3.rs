@@ -0,0 +1,214 @@
+// Archival uploads to S3-compatible object storage, either every stored snapshot file or one
+// bundle per completed UTC hour. Requests are signed with AWS Signature Version 4 and sent
+// through the same reqwest client as everything else.
+use crate::config::{S3Bundle, S3Config};
+use crate::error::BazaarError;
+use crate::model::Product;
+use crate::storage::{ZSTD_LEVEL, is_delta, jsonl_lines, read_sequence, snapshot_files, snapshot_timestamp};
+use crate::watchlist::Watchlist;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, instrument, warn};
+
+const HOUR_MILLIS: i64 = 3_600_000;
+
+// Start of the newest hour this process bundled, None until the first fetch
+static LAST_BUNDLED_HOUR: Mutex<Option<i64>> = Mutex::new(None);
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac: Hmac<Sha256> = Hmac::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Everything but unreserved characters is percent-encoded, the slashes between segments stay
+fn encode_path(path: &str) -> String {
+    let mut out: String = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+// The object URL, and the Host header and path it's signed with
+fn object_url(config: &S3Config, endpoint: &str, key: &str) -> Result<(String, String, String), BazaarError> {
+    let (scheme, host) = endpoint.trim_end_matches('/').split_once("://")
+        .ok_or_else(|| BazaarError::Config(format!("s3.endpoint {} needs a scheme like https://", endpoint)))?;
+    let (host, path): (String, String) = if config.path_style {
+        (host.to_string(), format!("/{}/{}", config.bucket, key))
+    } else {
+        (format!("{}.{}", config.bucket, host), format!("/{}", key))
+    };
+    let path: String = encode_path(&path);
+    Ok((format!("{}://{}{}", scheme, host, path), host, path))
+}
+
+pub async fn put_object(client: &reqwest::Client, config: &S3Config, key: &str, body: Vec<u8>, content_type: &str) -> Result<(), BazaarError> {
+    let (Some(endpoint), Some(access_key), Some(secret_key)) = (&config.endpoint, &config.access_key_id, &config.secret_access_key) else {
+        return Err(BazaarError::Config("s3 needs an endpoint, an access_key_id and a secret_access_key".to_string()));
+    };
+    let (url, host, path): (String, String, String) = object_url(config, endpoint, key)?;
+    let now: DateTime<Utc> = Utc::now();
+    let amz_date: String = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date: String = now.format("%Y%m%d").to_string();
+    let payload_hash: String = hex(&Sha256::digest(&body));
+    let canonical_request: String = format!(
+        "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+        path, host, payload_hash, amz_date, payload_hash
+    );
+    let scope: String = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign: String = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+    let mut signing_key: Vec<u8> = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), &date);
+    for part in [config.region.as_str(), "s3", "aws4_request"] {
+        signing_key = hmac_sha256(&signing_key, part);
+    }
+    let authorization: String = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+        access_key, scope, hex(&hmac_sha256(&signing_key, &string_to_sign))
+    );
+    let response: reqwest::Response = client.put(&url)
+        .header("x-amz-date", amz_date)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("Authorization", authorization)
+        .header("Content-Type", content_type)
+        .body(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(BazaarError::Http { status: response.status(), url });
+    }
+    Ok(())
+}
+
+// prefix + the file's path below raw_dir, so the bucket mirrors the raw directory
+pub fn snapshot_key(config: &S3Config, raw_dir: &Path, file: &Path) -> String {
+    let relative: &Path = file.strip_prefix(raw_dir).unwrap_or(file);
+    let relative: String = relative.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}{}", config.prefix, relative)
+}
+
+// prefix + hourly/YYYYMMDD/HH.jsonl.zst
+pub fn hour_key(config: &S3Config, hour: i64) -> String {
+    let time: DateTime<Utc> = DateTime::from_timestamp_millis(hour).unwrap_or_default();
+    format!("{}hourly/{}.jsonl.zst", config.prefix, time.format("%Y%m%d/%H"))
+}
+
+pub async fn upload_file(client: &reqwest::Client, config: &S3Config, raw_dir: &Path, file: &Path) -> Result<(), BazaarError> {
+    let content_type: &str = if file.extension().is_some_and(|ext| ext == "zst") { "application/zstd" } else { "application/json" };
+    put_object(client, config, &snapshot_key(config, raw_dir, file), tokio::fs::read(file).await?, content_type).await
+}
+
+// The snapshots of the hour starting at `hour` as zstd-compressed JSON Lines. Deltas are rebuilt
+// from the keyframe before them, None when the hour has no snapshots.
+fn hour_bundle(files: &[PathBuf], hour: i64) -> Result<Option<Vec<u8>>, BazaarError> {
+    let in_hour = |file: &Path| snapshot_timestamp(file).is_some_and(|timestamp| timestamp >= hour && timestamp < hour + HOUR_MILLIS);
+    let Some(first) = files.iter().position(|file| in_hour(file)) else {
+        return Ok(None);
+    };
+    let end: usize = first + files[first..].iter().take_while(|file| in_hour(file)).count();
+    let start: usize = files[..=first].iter().rposition(|file| !is_delta(file)).unwrap_or(0);
+    let mut lines: String = String::new();
+    read_sequence(&files[start..end], &Watchlist::default(), |file, last_updated, products: &HashMap<String, Product>| {
+        if in_hour(file) {
+            lines.push_str(&jsonl_lines(last_updated, products)?);
+        }
+        Ok(())
+    })?;
+    Ok(Some(zstd::encode_all(lines.as_bytes(), ZSTD_LEVEL)?))
+}
+
+pub async fn upload_hour(client: &reqwest::Client, config: &S3Config, files: &[PathBuf], hour: i64) -> Result<bool, BazaarError> {
+    // Rebuilding deltas and compressing blocks, so it leaves the async workers
+    let Some(bundle) = tokio::task::block_in_place(|| hour_bundle(files, hour))? else {
+        return Ok(false);
+    };
+    put_object(client, config, &hour_key(config, hour), bundle, "application/zstd").await?;
+    Ok(true)
+}
+
+// Called after every stored fetch. With hourly bundles every hour that completed since the last
+// bundle is sent, starting with the one before the first fetch. Failures are logged and never
+// fail the fetch, an hour that failed is retried on the next one and `upload` sends everything.
+pub async fn record_fetch(client: &reqwest::Client, config: &S3Config, raw_dir: &Path, file: &Path, last_updated: u64) {
+    if config.endpoint.is_none() {
+        return;
+    }
+    // The daily JSON Lines log has no per-fetch files to bundle, it's uploaded as it grows
+    if config.bundle == S3Bundle::Snapshot || file.extension().is_some_and(|ext| ext == "jsonl") {
+        if let Err(e) = upload_file(client, config, raw_dir, file).await {
+            warn!("Couldn't upload {} to S3: {}", file.display(), e);
+        }
+        return;
+    }
+    let current: i64 = last_updated as i64 / HOUR_MILLIS * HOUR_MILLIS;
+    let last: i64 = LAST_BUNDLED_HOUR.lock().unwrap().unwrap_or(current - 2 * HOUR_MILLIS);
+    if last + HOUR_MILLIS >= current {
+        return;
+    }
+    let files: Vec<PathBuf> = match snapshot_files(raw_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("Couldn't list snapshots for the hourly S3 bundle: {}", e);
+            return;
+        }
+    };
+    let mut hour: i64 = last + HOUR_MILLIS;
+    while hour < current {
+        if let Err(e) = upload_hour(client, config, &files, hour).await {
+            warn!("Couldn't upload the bundle for {} to S3: {}", hour_key(config, hour), e);
+            break;
+        }
+        *LAST_BUNDLED_HOUR.lock().unwrap() = Some(hour);
+        hour += HOUR_MILLIS;
+    }
+}
+
+// Uploads the whole raw directory, or every completed hour with hourly bundles, from `since` on.
+// Keys are derived from the files, so running it again overwrites instead of duplicating.
+// Returns the number of objects uploaded.
+#[instrument(name = "upload", skip_all)]
+pub async fn upload_all(client: &reqwest::Client, config: &S3Config, raw_dir: &Path, bundle: S3Bundle, since: Option<i64>) -> Result<usize, BazaarError> {
+    let files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    let mut uploaded: usize = 0;
+    match bundle {
+        S3Bundle::Snapshot => {
+            for file in &files {
+                if since.is_some_and(|since| snapshot_timestamp(file).is_some_and(|timestamp| timestamp < since)) {
+                    continue;
+                }
+                upload_file(client, config, raw_dir, file).await?;
+                uploaded += 1;
+                info!(path = %file.display(), "Uploaded");
+            }
+        }
+        S3Bundle::Hourly => {
+            let (Some(first), Some(newest)) = (files.first().and_then(|file| snapshot_timestamp(file)), files.last().and_then(|file| snapshot_timestamp(file))) else {
+                return Ok(0);
+            };
+            let mut hour: i64 = since.unwrap_or(first).max(first) / HOUR_MILLIS * HOUR_MILLIS;
+            // The newest hour is still filling up
+            while hour + HOUR_MILLIS <= newest / HOUR_MILLIS * HOUR_MILLIS {
+                if upload_hour(client, config, &files, hour).await? {
+                    uploaded += 1;
+                    info!(key = %hour_key(config, hour), "Uploaded");
+                }
+                hour += HOUR_MILLIS;
+            }
+        }
+    }
+    Ok(uploaded)
+}
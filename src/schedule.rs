@@ -0,0 +1,107 @@
+// When the daemon fetches: a fixed interval, or whenever any of a set of cron expressions matches.
+// Several expressions cover schedules like "every 20s at night, every 2m otherwise".
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::debug;
+
+// "sec min hour day-of-month month day-of-week [year]", evaluated in UTC. Five-field expressions
+// without seconds are accepted too and fire at second 0.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CronExpression {
+    schedule: cron::Schedule,
+    label: String,
+}
+
+impl CronExpression {
+    pub fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(after).next()
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value: &str = value.trim();
+        let expression: String = match value.split_whitespace().count() {
+            5 => format!("0 {}", value),
+            _ => value.to_string(),
+        };
+        let schedule: cron::Schedule = expression.parse()
+            .map_err(|e| format!("Invalid cron expression {:?}: {}", value, e))?;
+        Ok(Self { schedule, label: value.to_string() })
+    }
+}
+
+impl TryFrom<String> for CronExpression {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+// The earliest time after `after` any of the expressions matches
+pub fn next_fire(expressions: &[CronExpression], after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    expressions.iter().filter_map(|expression| expression.next_after(after)).min()
+}
+
+pub enum Ticker {
+    // Fires right away, then every period, never bursting to catch up after a slow cycle
+    Interval(time::Interval),
+    Cron(Vec<CronExpression>),
+}
+
+impl Ticker {
+    pub fn interval(seconds: u64) -> Self {
+        let mut ticker: time::Interval = time::interval(Duration::from_secs(seconds));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ticker::Interval(ticker)
+    }
+
+    // An empty list falls back to the interval
+    pub fn new(expressions: Vec<CronExpression>, interval: u64) -> Self {
+        if expressions.is_empty() { Ticker::interval(interval) } else { Ticker::Cron(expressions) }
+    }
+
+    // Time between two fetches, for a schedule the one between its next two matches
+    pub fn period(&self) -> Option<Duration> {
+        match self {
+            Ticker::Interval(ticker) => Some(ticker.period()),
+            Ticker::Cron(expressions) => {
+                let next: DateTime<Utc> = next_fire(expressions, &Utc::now())?;
+                (next_fire(expressions, &next)? - next).to_std().ok()
+            }
+        }
+    }
+
+    // Waits for the next fetch, false once no expression can match again (like one for a past year)
+    pub async fn tick(&mut self) -> bool {
+        match self {
+            Ticker::Interval(ticker) => {
+                ticker.tick().await;
+                true
+            }
+            Ticker::Cron(expressions) => {
+                let now: DateTime<Utc> = Utc::now();
+                let Some(next) = next_fire(expressions, &now) else {
+                    return false;
+                };
+                debug!(next = %next.to_rfc3339(), "Waiting for the next scheduled fetch");
+                time::sleep((next - now).to_std().unwrap_or_default()).await;
+                true
+            }
+        }
+    }
+}
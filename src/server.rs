@@ -0,0 +1,247 @@
+// Small read-only HTTP API over the stored snapshots, plus /ws and /events live updates when a daemon feeds it
+use crate::error::BazaarError;
+use crate::feed::{Feed, Update};
+use crate::history::History;
+use crate::watchlist::Watchlist;
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::storage::{chain_start, newest_file, read_snapshot, snapshot_files, snapshot_timestamp};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{debug, info};
+
+type ApiError = (StatusCode, String);
+// Path of the newest snapshot and its parsed contents
+type CachedSnapshot = Option<(PathBuf, Arc<BazaarResponse>)>;
+
+#[derive(Clone)]
+struct AppState {
+    raw_dir: Arc<PathBuf>,
+    // Parsed newest snapshot, reloaded whenever a newer file shows up
+    latest: Arc<Mutex<CachedSnapshot>>,
+    feed: Option<Arc<Feed>>,
+}
+
+#[derive(Serialize)]
+struct ProductsResponse<'a> {
+    last_updated: u64,
+    products: Vec<&'a QuickStatus>,
+}
+
+#[derive(Serialize)]
+struct ProductResponse<'a> {
+    last_updated: u64,
+    #[serde(flatten)]
+    product: &'a Product,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    timestamp: i64,
+    quick_status: QuickStatus,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    #[serde(default = "default_history_hours")]
+    hours: u64,
+}
+
+fn default_history_hours() -> u64 {
+    24
+}
+
+fn internal(e: impl ToString) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn latest(state: &AppState) -> Result<Arc<BazaarResponse>, ApiError> {
+    let newest: PathBuf = newest_file(&state.raw_dir)
+        .ok_or((StatusCode::NOT_FOUND, "No raw files found".to_string()))?;
+    if let Some((path, response)) = &*state.latest.lock().map_err(internal)?
+        && *path == newest
+    {
+        return Ok(response.clone());
+    }
+    let path: PathBuf = newest.clone();
+    let response: BazaarResponse = tokio::task::spawn_blocking(move || read_snapshot(&path, &Watchlist::default()).map_err(|e| e.to_string()))
+        .await
+        .map_err(internal)?
+        .map_err(internal)?;
+    let response: Arc<BazaarResponse> = Arc::new(response);
+    *state.latest.lock().map_err(internal)? = Some((newest, response.clone()));
+    Ok(response)
+}
+
+// Bodies borrow from the cached snapshot, so they're serialized before returning
+async fn products(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let response: Arc<BazaarResponse> = latest(&state).await?;
+    let mut products: Vec<&QuickStatus> = response.products.values().map(|product| &product.quick_status).collect();
+    products.sort_by(|a, b| a.productId.cmp(&b.productId));
+    let body: ProductsResponse = ProductsResponse { last_updated: response.lastUpdated, products };
+    Ok(Json(body).into_response())
+}
+
+async fn product(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, ApiError> {
+    let response: Arc<BazaarResponse> = latest(&state).await?;
+    let product: &Product = response.products.get(&id.to_uppercase())
+        .ok_or((StatusCode::NOT_FOUND, format!("Unknown product {}", id)))?;
+    let body: ProductResponse = ProductResponse { last_updated: response.lastUpdated, product };
+    Ok(Json(body).into_response())
+}
+
+async fn product_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let id: String = id.to_uppercase();
+    let cutoff: i64 = chrono::Utc::now().timestamp_millis() - query.hours as i64 * 60 * 60 * 1000;
+    let raw_dir: Arc<PathBuf> = state.raw_dir.clone();
+    let watchlist: Watchlist = Watchlist::new([id.clone()]);
+    let history: History = tokio::task::spawn_blocking(move || -> Result<History, String> {
+        // File names give a cheap first cut before anything is parsed, reaching back to the
+        // keyframe the first delta after the cutoff builds on
+        let files: Vec<PathBuf> = snapshot_files(&raw_dir).map_err(|e| e.to_string())?;
+        let first: usize = files.iter()
+            .position(|file| snapshot_timestamp(file).is_none_or(|timestamp| timestamp >= cutoff))
+            .unwrap_or(files.len());
+        History::load(&files[chain_start(&files, first)..], &watchlist).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(internal)?
+    .map_err(internal)?;
+    let entries: Vec<HistoryEntry> = history.points.into_iter()
+        .filter(|point| point.timestamp >= cutoff)
+        .filter_map(|mut point| {
+            let quick_status: QuickStatus = point.products.remove(&id)?;
+            Some(HistoryEntry { timestamp: point.timestamp, quick_status })
+        })
+        .collect();
+    Ok(Json(entries))
+}
+
+// Sent by a client to only get some products, an empty list subscribes to all of them again
+#[derive(Deserialize)]
+struct Subscription {
+    products: Vec<String>,
+}
+
+// IDs as given by a client, matched like the watchlist does
+fn product_filter<'a>(ids: impl Iterator<Item = &'a str>) -> Vec<String> {
+    ids.map(|id| id.trim().to_uppercase()).filter(|id| !id.is_empty()).collect()
+}
+
+fn live_feed(state: &AppState) -> Result<&Arc<Feed>, ApiError> {
+    state.feed.as_ref().ok_or((StatusCode::NOT_FOUND, "Live updates are only streamed by `daemon --serve`".to_string()))
+}
+
+async fn send_json<T: Serialize>(socket: &mut WebSocket, value: &T) -> bool {
+    match serde_json::to_string(value) {
+        Ok(text) => socket.send(Message::Text(text.into())).await.is_ok(),
+        Err(_) => true,
+    }
+}
+
+// Every update goes out as {"last_updated": ..., "products": [quick_status, ...]}, filtered to the
+// client's subscription. Updates a slow client missed are skipped, the next one catches it up.
+async fn stream_updates(mut socket: WebSocket, mut updates: broadcast::Receiver<Arc<Update>>) {
+    let mut products: Vec<String> = Vec::new();
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) => {
+                    if let Some(update) = update.filtered(&products)
+                        && !send_json(&mut socket, &update).await
+                    {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => debug!(missed, "A WebSocket client fell behind"),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Subscription>(&text) {
+                    Ok(subscription) => {
+                        products = product_filter(subscription.products.iter().map(String::as_str));
+                        if !send_json(&mut socket, &serde_json::json!({ "subscribed": products })).await {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let error: String = format!("Expected {{\"products\": [...]}}: {}", e);
+                        if !send_json(&mut socket, &serde_json::json!({ "error": error })).await {
+                            break;
+                        }
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn websocket(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> Result<Response, ApiError> {
+    let updates: broadcast::Receiver<Arc<Update>> = live_feed(&state)?.subscribe();
+    Ok(upgrade.on_upgrade(move |socket| stream_updates(socket, updates)))
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    // Comma separated, all products when missing
+    #[serde(default)]
+    products: String,
+}
+
+// The same updates as /ws as "update" events, their id is the fetch's lastUpdated. The filter is
+// fixed by the query, a browser's EventSource can't send one later.
+async fn events(State(state): State<AppState>, Query(query): Query<EventsQuery>) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let updates: broadcast::Receiver<Arc<Update>> = live_feed(&state)?.subscribe();
+    let products: Vec<String> = product_filter(query.products.split(','));
+    let stream = futures_util::stream::unfold((updates, products), |(mut updates, products)| async move {
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    if let Some(update) = update.filtered(&products) {
+                        let event: Result<Event, axum::Error> = Event::default().event("update").id(update.last_updated.to_string()).json_data(&update);
+                        return Some((event, (updates, products)));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => debug!(missed, "An event stream client fell behind"),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// `feed` carries the daemon's fetches to /ws and /events, without one only the stored snapshots are served
+pub async fn serve(raw_dir: PathBuf, bind: SocketAddr, feed: Option<Arc<Feed>>) -> Result<(), BazaarError> {
+    let state: AppState = AppState {
+        raw_dir: Arc::new(raw_dir),
+        latest: Arc::new(Mutex::new(None)),
+        feed,
+    };
+    let app: Router = Router::new()
+        .route("/products", get(products))
+        .route("/products/{id}", get(product))
+        .route("/products/{id}/history", get(product_history))
+        .route("/ws", get(websocket))
+        .route("/events", get(events))
+        .with_state(state);
+    let listener: tokio::net::TcpListener = tokio::net::TcpListener::bind(bind).await?;
+    info!("Serving on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
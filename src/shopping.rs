@@ -0,0 +1,204 @@
+// Shopping lists on a budget: each item is either insta-bought through the listed sell offers or
+// ordered at the top buy order, whichever the budget allows with the shortest wait. Orders all run
+// at once, so the wait is the slowest order's and the cheapest plan that shortens it wins.
+use crate::coins::format_coins;
+use crate::depth::{self, Fill};
+use crate::error::BazaarError;
+use crate::flips::{self, HOURS_PER_WEEK};
+use crate::gaps::format_duration;
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::recipes::Recipe;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// "ENCHANTED_DIAMOND=64", the quantity needed of one product
+#[derive(Clone)]
+pub struct ListItem {
+    pub product_id: String,
+    pub quantity: u64,
+}
+
+impl FromStr for ListItem {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid item {:?}, expected PRODUCT=QUANTITY like ENCHANTED_DIAMOND=64", value);
+        let (product_id, quantity) = value.rsplit_once('=').ok_or_else(invalid)?;
+        let quantity: u64 = quantity.trim().parse().map_err(|_| invalid())?;
+        if product_id.trim().is_empty() || quantity == 0 {
+            return Err(invalid());
+        }
+        Ok(ListItem { product_id: product_id.trim().to_uppercase(), quantity })
+    }
+}
+
+// The items plus the ingredients of `crafts` crafts of the recipe, a product listed twice adds up
+pub fn shopping_list(items: &[ListItem], recipe: Option<(&Recipe, u64)>) -> Vec<ListItem> {
+    let mut quantities: BTreeMap<String, u64> = BTreeMap::new();
+    for item in items {
+        *quantities.entry(item.product_id.clone()).or_default() += item.quantity;
+    }
+    if let Some((recipe, crafts)) = recipe {
+        for (id, quantity) in &recipe.ingredients {
+            *quantities.entry(id.clone()).or_default() += quantity * crafts;
+        }
+    }
+    quantities.into_iter().map(|(product_id, quantity)| ListItem { product_id, quantity }).collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Method {
+    Instabuy,
+    Order,
+}
+
+impl Method {
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Instabuy => "instabuy",
+            Method::Order => "buy order",
+        }
+    }
+}
+
+pub struct Purchase {
+    pub product_id: String,
+    pub quantity: u64,
+    // Everything through the listed sell offers, None when they hold fewer units than needed
+    pub instabuy: Option<f64>,
+    // A buy order at the top buy order's price
+    pub order_price: f64,
+    pub order_cost: f64,
+    // Hours until the order is expected to fill, infinite when no one sells the product
+    pub order_hours: f64,
+    pub method: Method,
+}
+
+impl Purchase {
+    pub fn cost(&self) -> f64 {
+        match (self.method, self.instabuy) {
+            (Method::Instabuy, Some(cost)) => cost,
+            _ => self.order_cost,
+        }
+    }
+
+    pub fn hours(&self) -> f64 {
+        if self.method == Method::Instabuy { 0.0 } else { self.order_hours }
+    }
+}
+
+pub struct ShoppingPlan {
+    pub budget: f64,
+    pub purchases: Vec<Purchase>,
+    // On the list but not in the snapshot
+    pub missing: Vec<String>,
+}
+
+impl ShoppingPlan {
+    pub fn cost(&self) -> f64 {
+        self.purchases.iter().map(Purchase::cost).sum()
+    }
+
+    // The slowest order's, everything is bought at once
+    pub fn hours(&self) -> f64 {
+        self.purchases.iter().map(Purchase::hours).fold(0.0, f64::max)
+    }
+}
+
+// A buy order joins the queue at the top buy order, insta-sells fill the units listed there before
+// it and it gets at most `max_share` of the rest, like paper trading. Without a listed book the
+// quick status's order count splits the volume instead.
+fn order_hours(product: &Product, quantity: u64, max_share: f64) -> f64 {
+    let quick_status: &QuickStatus = &product.quick_status;
+    let hourly: f64 = quick_status.sellMovingWeek as f64 / HOURS_PER_WEEK;
+    let Some(top) = product.sell_summary.first() else {
+        return quantity as f64 / flips::fill_rate(quick_status.sellMovingWeek, quick_status.sellOrders, max_share);
+    };
+    let queue: f64 = top.amount as f64;
+    ((queue + quantity as f64) / hourly).max(quantity as f64 / (hourly * max_share))
+}
+
+// Every item starts out bought the cheaper way, by order unless instabuying costs no more. Then,
+// slowest first, orders become instabuys while the budget covers the difference, until the
+// slowest one can't be switched.
+pub fn plan(response: &BazaarResponse, list: &[ListItem], budget: f64, max_share: f64) -> Result<ShoppingPlan, BazaarError> {
+    let mut purchases: Vec<Purchase> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    for item in list {
+        let Some(product) = response.products.get(&item.product_id) else {
+            missing.push(item.product_id.clone());
+            continue;
+        };
+        let fill: Fill = depth::walk(&product.buy_summary, item.quantity)?;
+        let instabuy: Option<f64> = (fill.filled == item.quantity).then(|| fill.total.to_float());
+        let order_price: f64 = product.quick_status.sellPrice;
+        let order_cost: f64 = order_price * item.quantity as f64;
+        let method: Method = if instabuy.is_some_and(|instabuy| instabuy <= order_cost) { Method::Instabuy } else { Method::Order };
+        purchases.push(Purchase {
+            product_id: item.product_id.clone(),
+            quantity: item.quantity,
+            instabuy,
+            order_price,
+            order_cost,
+            order_hours: order_hours(product, item.quantity, max_share),
+            method,
+        });
+    }
+    let mut plan: ShoppingPlan = ShoppingPlan { budget, purchases, missing };
+    let mut orders: Vec<usize> = (0..plan.purchases.len()).filter(|index| plan.purchases[*index].method == Method::Order).collect();
+    orders.sort_by(|a, b| plan.purchases[*b].order_hours.total_cmp(&plan.purchases[*a].order_hours));
+    for index in orders {
+        let purchase: &Purchase = &plan.purchases[index];
+        let Some(instabuy) = purchase.instabuy else {
+            break;
+        };
+        if plan.cost() - purchase.order_cost + instabuy > budget {
+            break;
+        }
+        plan.purchases[index].method = Method::Instabuy;
+    }
+    Ok(plan)
+}
+
+fn format_hours(hours: f64) -> String {
+    if hours == 0.0 {
+        "now".to_string()
+    } else if hours.is_finite() {
+        format_duration((hours * 3_600_000.0) as i64)
+    } else {
+        "never".to_string()
+    }
+}
+
+pub fn print_plan(plan: &ShoppingPlan, items: &ItemCatalog) {
+    println!(
+        "{:<40} {:<32} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12}",
+        "product_id", "name", "quantity", "method", "cost", "instabuy", "order", "order_wait"
+    );
+    for purchase in &plan.purchases {
+        println!(
+            "{:<40} {:<32} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12}",
+            purchase.product_id,
+            items.name(&purchase.product_id),
+            purchase.quantity,
+            purchase.method.name(),
+            format_coins(purchase.cost()),
+            purchase.instabuy.map(format_coins).unwrap_or_else(|| "-".to_string()),
+            format_coins(purchase.order_cost),
+            format_hours(purchase.order_hours),
+        );
+    }
+    println!();
+    let cost: f64 = plan.cost();
+    println!("Total: {} of a {} budget, everything in {}", format_coins(cost), format_coins(plan.budget), format_hours(plan.hours()));
+    if cost > plan.budget {
+        println!("Even the cheapest way costs {} more than the budget", format_coins(cost - plan.budget));
+    }
+    for purchase in plan.purchases.iter().filter(|purchase| purchase.instabuy.is_none()) {
+        println!("The listed sell offers hold fewer than {} {}, it can only be ordered", purchase.quantity, purchase.product_id);
+    }
+    for id in &plan.missing {
+        println!("{} is not in the newest snapshot, left out", id);
+    }
+}
@@ -0,0 +1,52 @@
+// Top products of the newest snapshot as a table meant for reading, not for further processing
+use crate::coins::format_coins;
+use crate::export::CsvOrder;
+use crate::items::ItemCatalog;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::watchlist::Watchlist;
+
+// 1234567 -> "1,234,567"
+pub fn format_thousands(value: u64) -> String {
+    let digits: String = value.to_string();
+    let mut out: String = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+fn format_margin(quick_status: &QuickStatus) -> String {
+    if quick_status.sellPrice <= 0.0 {
+        return "-".to_string();
+    }
+    format!("{:.1}", (quick_status.buyPrice - quick_status.sellPrice) / quick_status.sellPrice * 100.0)
+}
+
+pub fn print_top(response: &BazaarResponse, watchlist: &Watchlist, items: &ItemCatalog, order: CsvOrder, top: usize) {
+    let mut rows: Vec<(&str, &QuickStatus)> = response.products.iter()
+        .filter(|(id, _)| watchlist.contains(id))
+        .map(|(id, product)| (id.as_str(), &product.quick_status))
+        .collect();
+    order.sort(&mut rows, items);
+    println!(
+        "{:<40} {:<32} {:>10} {:>10} {:>8} {:>13} {:>13} {:>13} {:>13}",
+        "product_id", "name", "buy_price", "sell_price", "margin%", "buy_volume", "sell_volume", "buy_week", "sell_week"
+    );
+    for (id, quick_status) in rows.into_iter().take(top) {
+        println!(
+            "{:<40} {:<32} {:>10} {:>10} {:>8} {:>13} {:>13} {:>13} {:>13}",
+            id,
+            items.name(id),
+            format_coins(quick_status.buyPrice),
+            format_coins(quick_status.sellPrice),
+            format_margin(quick_status),
+            format_thousands(quick_status.buyVolume),
+            format_thousands(quick_status.sellVolume),
+            format_thousands(quick_status.buyMovingWeek),
+            format_thousands(quick_status.sellMovingWeek)
+        );
+    }
+}
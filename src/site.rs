@@ -0,0 +1,205 @@
+// Static dashboard: the stored history rendered into one self-contained index.html, with the
+// data and the chart code inline so it works from GitHub Pages or straight off the disk
+use crate::categories;
+use crate::error::BazaarError;
+use crate::history::History;
+use crate::items::ItemCatalog;
+use crate::model::QuickStatus;
+use crate::watchlist::Watchlist;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Searchable product list next to an SVG chart of the selected one. The page reads its data from
+// the JSON block, the #PRODUCT_ID anchor selects a product so charts can be linked.
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Bazaar dashboard</title>
+<style>
+body { font-family: sans-serif; margin: 0; display: flex; height: 100vh; color: #222; }
+#sidebar { width: 420px; display: flex; flex-direction: column; border-right: 1px solid #ccc; }
+#search { margin: 0.6em; padding: 0.4em; font-size: 1em; }
+#list { overflow-y: auto; flex: 1; }
+table { border-collapse: collapse; width: 100%; font-size: 0.9em; }
+th, td { padding: 0.25em 0.5em; text-align: right; white-space: nowrap; }
+th { position: sticky; top: 0; background: #f4f4f4; cursor: pointer; }
+td:first-child, th:first-child { text-align: left; }
+tr.product { cursor: pointer; }
+tr.product:hover { background: #eef; }
+tr.selected { background: #dde; }
+#main { flex: 1; padding: 1em 2em; overflow-y: auto; }
+#chart { width: 100%; height: 420px; }
+.muted { color: #777; }
+</style>
+</head>
+<body>
+<div id="sidebar">
+<input id="search" type="search" placeholder="Search products, IDs or categories" autofocus>
+<div id="list"><table><thead><tr><th data-key="name">Product</th><th data-key="buy">Buy</th><th data-key="sell">Sell</th><th data-key="volume">Week volume</th></tr></thead><tbody id="rows"></tbody></table></div>
+</div>
+<div id="main">
+<h1 id="title">Bazaar dashboard</h1>
+<p id="details" class="muted"></p>
+<svg id="chart"></svg>
+<p class="muted"><span style="color:#d33">&#9632;</span> buyPrice <span style="color:#36c">&#9632;</span> sellPrice</p>
+</div>
+<script id="data" type="application/json">{{DATA}}</script>
+<script>
+const data = JSON.parse(document.getElementById("data").textContent);
+const rows = document.getElementById("rows");
+const search = document.getElementById("search");
+const svg = document.getElementById("chart");
+const NS = "http://www.w3.org/2000/svg";
+let sortKey = "volume", descending = true, selected = null;
+
+const coins = value => value.toLocaleString(undefined, { maximumFractionDigits: 1 });
+const time = millis => new Date(millis).toLocaleString();
+
+function element(name, attributes, text) {
+  const node = document.createElementNS(NS, name);
+  for (const [key, value] of Object.entries(attributes)) node.setAttribute(key, value);
+  if (text !== undefined) node.textContent = text;
+  svg.appendChild(node);
+  return node;
+}
+
+function draw(product) {
+  svg.innerHTML = "";
+  const width = svg.clientWidth, height = svg.clientHeight, left = 80, right = 20, top = 10, bottom = 30;
+  if (product.t.length === 0) return;
+  const prices = product.b.concat(product.s).filter(price => price > 0);
+  let low = Math.min(...prices), high = Math.max(...prices);
+  const padding = Math.max((high - low) * 0.05, high * 0.001, 0.1);
+  low -= padding; high += padding;
+  const start = product.t[0], end = Math.max(product.t[product.t.length - 1], start + 1000);
+  const x = t => left + (t - start) / (end - start) * (width - left - right);
+  const y = price => top + (high - price) / (high - low) * (height - top - bottom);
+  for (let i = 0; i <= 4; i++) {
+    const price = low + (high - low) * i / 4;
+    element("line", { x1: left, x2: width - right, y1: y(price), y2: y(price), stroke: "#eee" });
+    element("text", { x: left - 6, y: y(price) + 4, "text-anchor": "end", "font-size": 12 }, coins(price));
+  }
+  for (let i = 0; i <= 4; i++) {
+    const t = start + (end - start) * i / 4;
+    element("text", { x: x(t), y: height - 8, "text-anchor": "middle", "font-size": 12 }, new Date(t).toLocaleString(undefined, { month: "2-digit", day: "2-digit", hour: "2-digit", minute: "2-digit" }));
+  }
+  for (const [values, color] of [[product.b, "#d33"], [product.s, "#36c"]]) {
+    const points = product.t.map((t, i) => values[i] > 0 ? x(t) + "," + y(values[i]) : null).filter(point => point);
+    element("polyline", { points: points.join(" "), fill: "none", stroke: color, "stroke-width": 1.5 });
+  }
+}
+
+function select(product) {
+  selected = product;
+  document.getElementById("title").textContent = product.name;
+  document.getElementById("details").textContent = [
+    product.id, product.category, "buy " + coins(product.buy), "sell " + coins(product.sell),
+    product.t.length + " points from " + time(product.t[0]) + " to " + time(product.t[product.t.length - 1]),
+  ].filter(part => part).join(" · ");
+  history.replaceState(null, "", "#" + encodeURIComponent(product.id));
+  draw(product);
+  render();
+}
+
+function render() {
+  const query = search.value.trim().toLowerCase();
+  const shown = data.products
+    .filter(product => !query || [product.id, product.name, product.category || ""].some(field => field.toLowerCase().includes(query)))
+    .sort((a, b) => {
+      const order = typeof a[sortKey] === "string" ? a[sortKey].localeCompare(b[sortKey]) : a[sortKey] - b[sortKey];
+      return descending ? -order : order;
+    });
+  rows.innerHTML = "";
+  for (const product of shown) {
+    const row = rows.insertRow();
+    row.className = "product" + (product === selected ? " selected" : "");
+    row.title = product.id;
+    for (const cell of [product.name, coins(product.buy), coins(product.sell), product.volume.toLocaleString()]) {
+      row.insertCell().textContent = cell;
+    }
+    row.onclick = () => select(product);
+  }
+}
+
+for (const header of document.querySelectorAll("th")) {
+  header.onclick = () => {
+    descending = header.dataset.key === sortKey ? !descending : header.dataset.key !== "name";
+    sortKey = header.dataset.key;
+    render();
+  };
+}
+search.oninput = render;
+window.onresize = () => selected && draw(selected);
+document.getElementById("details").textContent = data.products.length + " products, generated " + time(data.generated);
+const linked = data.products.find(product => "#" + encodeURIComponent(product.id) === location.hash);
+if (linked) select(linked); else render();
+</script>
+</body>
+</html>
+"##;
+
+#[derive(Serialize)]
+struct SiteProduct<'a> {
+    id: &'a str,
+    name: String,
+    category: Option<&'static str>,
+    buy: f64,
+    sell: f64,
+    // buyMovingWeek + sellMovingWeek
+    volume: u64,
+    // Epoch millis with the buy and sell price at each
+    t: Vec<i64>,
+    b: Vec<f64>,
+    s: Vec<f64>,
+}
+
+#[derive(Serialize)]
+struct SiteData<'a> {
+    generated: i64,
+    products: Vec<SiteProduct<'a>>,
+}
+
+// At most `max_points` evenly picked samples, always keeping the newest
+fn downsample<T: Copy>(series: &[T], max_points: usize) -> Vec<T> {
+    if series.len() <= max_points {
+        return series.to_vec();
+    }
+    let step: f64 = (series.len() - 1) as f64 / (max_points - 1) as f64;
+    (0..max_points).map(|i| series[((i as f64 * step).round() as usize).min(series.len() - 1)]).collect()
+}
+
+// Writes <output_dir>/index.html and returns its path along with the number of products on it
+pub fn generate(history: &History, watchlist: &Watchlist, items: &ItemCatalog, output_dir: &Path, max_points: usize, generated: i64) -> Result<(PathBuf, usize), BazaarError> {
+    let ids: BTreeSet<&String> = history.points.iter()
+        .flat_map(|point| point.products.keys())
+        .filter(|id| watchlist.contains(id))
+        .collect();
+    let products: Vec<SiteProduct> = ids.into_iter()
+        .filter_map(|id| {
+            let series: Vec<(i64, &QuickStatus)> = downsample(&history.product_series(id), max_points);
+            let (_, latest) = *series.last()?;
+            Some(SiteProduct {
+                id,
+                name: items.name(id).into_owned(),
+                category: categories::category(id),
+                buy: latest.buyPrice,
+                sell: latest.sellPrice,
+                volume: latest.buyMovingWeek + latest.sellMovingWeek,
+                t: series.iter().map(|(timestamp, _)| *timestamp).collect(),
+                b: series.iter().map(|(_, quick_status)| quick_status.buyPrice).collect(),
+                s: series.iter().map(|(_, quick_status)| quick_status.sellPrice).collect(),
+            })
+        })
+        .collect();
+    let count: usize = products.len();
+    // "</" would end the script block early, JSON allows the escaped slash
+    let data: String = serde_json::to_string(&SiteData { generated, products })?.replace("</", "<\\/");
+    fs::create_dir_all(output_dir)?;
+    let path: PathBuf = output_dir.join("index.html");
+    fs::write(&path, TEMPLATE.replace("{{DATA}}", &data))?;
+    Ok((path, count))
+}
@@ -0,0 +1,150 @@
+// Health of the collection at a glance: how many snapshots cover which time, where the holes are
+// and how much disk each store takes
+use crate::compact::COMPACTED_DIR;
+use crate::error::BazaarError;
+use crate::gaps::{self, Gap};
+use crate::storage::quarantine::QUARANTINE_DIR;
+use crate::storage::{is_compressed, is_delta, snapshot_files, snapshot_timestamp};
+use crate::timezone::{display_timezone, format_timestamp};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Files and bytes one store takes
+pub struct Usage {
+    pub label: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+pub struct DatasetStats {
+    pub snapshots: usize,
+    pub deltas: usize,
+    pub compressed: usize,
+    // Oldest and newest snapshot, epoch millis
+    pub range: Option<(i64, i64)>,
+    pub gaps: Vec<Gap>,
+    // Gaps the daemon or `check gaps --record` wrote down
+    pub markers: usize,
+    // Snapshots per day in the display timezone, YYYY-MM-DD
+    pub per_day: BTreeMap<String, usize>,
+    pub usage: Vec<Usage>,
+}
+
+// Every file below `dir`, a missing directory takes nothing
+fn dir_usage(label: &str, dir: &Path) -> Usage {
+    fn walk(dir: &Path, usage: &mut Usage) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path: PathBuf = entry.path();
+            if path.is_dir() {
+                walk(&path, usage);
+            } else if let Ok(metadata) = entry.metadata() {
+                usage.files += 1;
+                usage.bytes += metadata.len();
+            }
+        }
+    }
+    let mut usage: Usage = Usage { label: label.to_string(), files: 0, bytes: 0 };
+    walk(dir, &mut usage);
+    usage
+}
+
+fn files_usage(label: &str, files: &[PathBuf]) -> Usage {
+    Usage {
+        label: label.to_string(),
+        files: files.len(),
+        bytes: files.iter().filter_map(|file| fs::metadata(file).ok()).map(|metadata| metadata.len()).sum(),
+    }
+}
+
+// A SQLite file along with its write-ahead log, None when it doesn't exist
+pub fn sqlite_usage(path: &Path) -> Option<Usage> {
+    let files: Vec<PathBuf> = ["", "-wal", "-shm"].iter()
+        .map(|suffix| PathBuf::from(format!("{}{}", path.display(), suffix)))
+        .filter(|file| file.exists())
+        .collect();
+    (!files.is_empty()).then(|| files_usage(&format!("SQLite {}", path.display()), &files))
+}
+
+// Only the names are read, no snapshot is parsed. Consecutive snapshots further apart than
+// `min_gap` millis count as a gap, like `check gaps`.
+pub fn collect(raw_dir: &Path, min_gap: i64) -> Result<DatasetStats, BazaarError> {
+    let files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    let timestamps: Vec<i64> = files.iter().filter_map(|file| snapshot_timestamp(file)).collect();
+    let mut per_day: BTreeMap<String, usize> = BTreeMap::new();
+    for timestamp in &timestamps {
+        *per_day.entry(display_timezone().format_millis(*timestamp, "%Y-%m-%d")).or_default() += 1;
+    }
+    let (keyframes, deltas): (Vec<PathBuf>, Vec<PathBuf>) = files.iter().cloned().partition(|file| !is_delta(file));
+    let jsonl: Vec<PathBuf> = fs::read_dir(raw_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+        .collect();
+    let usage: Vec<Usage> = vec![
+        files_usage("keyframes", &keyframes),
+        files_usage("deltas", &deltas),
+        files_usage("daily JSON Lines logs", &jsonl),
+        dir_usage("quarantine", &raw_dir.join(QUARANTINE_DIR)),
+        dir_usage("compacted candles", &raw_dir.join(COMPACTED_DIR)),
+        dir_usage(&format!("raw directory {}", raw_dir.display()), raw_dir),
+    ];
+    Ok(DatasetStats {
+        snapshots: files.len(),
+        deltas: deltas.len(),
+        compressed: files.iter().filter(|file| is_compressed(file)).count(),
+        range: timestamps.iter().min().zip(timestamps.iter().max()).map(|(first, last)| (*first, *last)),
+        gaps: gaps::find_gaps(&timestamps, min_gap),
+        markers: gaps::load_markers(raw_dir).len(),
+        per_day,
+        usage,
+    })
+}
+
+// 1.5 KiB, 20.3 MiB
+pub fn format_bytes(bytes: u64) -> String {
+    let mut value: f64 = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if value < 1024.0 {
+            return if unit == "B" { format!("{} B", bytes) } else { format!("{:.1} {}", value, unit) };
+        }
+        value /= 1024.0;
+    }
+    format!("{:.1} TiB", value)
+}
+
+// Only the `days` newest days are listed
+pub fn print_stats(stats: &DatasetStats, days: usize) {
+    println!(
+        "Snapshots: {} ({} keyframes, {} deltas, {} compressed)",
+        stats.snapshots, stats.snapshots - stats.deltas, stats.deltas, stats.compressed
+    );
+    let Some((first, last)) = stats.range else {
+        println!("No snapshots collected yet");
+        return;
+    };
+    println!("Time range: {} -> {} ({})", format_timestamp(first), format_timestamp(last), gaps::format_duration(last - first));
+    let missing: i64 = stats.gaps.iter().map(Gap::millis).sum();
+    let coverage: f64 = if last > first { 100.0 * (last - first - missing) as f64 / (last - first) as f64 } else { 100.0 };
+    println!(
+        "Gaps: {}, {} in total ({:.1}% covered), {} recorded markers",
+        stats.gaps.len(), gaps::format_duration(missing), coverage, stats.markers
+    );
+    if let Some(longest) = stats.gaps.iter().max_by_key(|gap| gap.millis()) {
+        println!("Longest gap: {} -> {} ({})", format_timestamp(longest.start), format_timestamp(longest.end), gaps::format_duration(longest.millis()));
+    }
+    println!();
+    println!("{:<48} {:>10} {:>12}", "store", "files", "size");
+    for usage in &stats.usage {
+        println!("{:<48} {:>10} {:>12}", usage.label, usage.files, format_bytes(usage.bytes));
+    }
+    println!();
+    println!("{:<12} {:>10}", "day", "snapshots");
+    let skip: usize = stats.per_day.len().saturating_sub(days);
+    for (day, count) in stats.per_day.iter().skip(skip) {
+        println!("{:<12} {:>10}", day, count);
+    }
+}
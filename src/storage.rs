@@ -0,0 +1,622 @@
+// Raw snapshot directory: listing, naming and reading (optionally zstd-compressed) snapshots
+pub mod archive;
+pub mod lock;
+pub mod merge;
+pub mod naming;
+pub mod quarantine;
+
+use naming::NameTemplate;
+use crate::error::BazaarError;
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::watchlist::Watchlist;
+use serde::{Deserialize, Deserializer, Serialize};
+use rayon::prelude::*;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::{DateTime, Utc};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, instrument, warn};
+
+const TEMPORARY_SUFFIX: &str = ".tmp";
+
+// zstd's default level, a good size/speed tradeoff for pretty-printed JSON
+pub const ZSTD_LEVEL: i32 = 3;
+
+// By the time encoded in the name, not the file name string, so legacy and newer names mix
+pub fn newest_file(raw_dir: &Path) -> Option<PathBuf> {
+    snapshot_files(raw_dir).ok()?.pop()
+}
+
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+// Plain and zstd-compressed snapshots can live side by side in the same directory
+pub fn is_snapshot(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".json") || name.ends_with(".json.zst"))
+}
+
+// Deltas are named <name>.delta.json[.zst] and sort among the full snapshots
+pub fn is_delta(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.contains(".delta."))
+}
+
+// Set once at startup from storage.name_template, every later call sees the same naming
+static NAME_TEMPLATE: OnceLock<NameTemplate> = OnceLock::new();
+
+// Has no effect after the first call, or once a snapshot path has been built
+pub fn set_name_template(template: NameTemplate) {
+    let _ = NAME_TEMPLATE.set(template);
+}
+
+fn name_template() -> &'static NameTemplate {
+    NAME_TEMPLATE.get_or_init(NameTemplate::default)
+}
+
+fn snapshot_stem(last_updated: u64) -> String {
+    let time: DateTime<Utc> = DateTime::from_timestamp_millis(last_updated as i64).unwrap_or_else(Utc::now);
+    name_template().format(&time)
+}
+
+// The name template filled in with the snapshot's lastUpdated, <epoch millis>.json by default
+pub fn new_snapshot_path(dir: &Path, last_updated: u64, compress: bool) -> PathBuf {
+    let extension: &str = if compress { "json.zst" } else { "json" };
+    dir.join(format!("{}.{}", snapshot_stem(last_updated), extension))
+}
+
+pub fn new_delta_path(dir: &Path, last_updated: u64, compress: bool) -> PathBuf {
+    let extension: &str = if compress { "delta.json.zst" } else { "delta.json" };
+    dir.join(format!("{}.{}", snapshot_stem(last_updated), extension))
+}
+
+// An already stored snapshot of this lastUpdated in any of the formats, since names come from it
+pub fn stored_snapshot(dir: &Path, last_updated: u64) -> Option<PathBuf> {
+    let stem: String = snapshot_stem(last_updated);
+    ["json", "json.zst", "delta.json", "delta.json.zst"].iter()
+        .map(|extension| dir.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.exists())
+}
+
+// Pretty-printed JSON, zstd-compressed when asked to. Creates the directory if it doesn't exist.
+#[instrument(name = "write", skip_all, fields(path = %path.display()))]
+pub async fn write_snapshot<T: Serialize>(path: &Path, value: &T, compress: bool) -> Result<(), BazaarError> {
+    let json: String = serde_json::to_string_pretty(value)?;
+    write_raw_snapshot(path, json.as_bytes(), compress).await
+}
+
+// Already serialized JSON written as is, like a response body that was never parsed into structs
+#[instrument(name = "write", skip_all, fields(path = %path.display()))]
+pub async fn write_raw_snapshot(path: &Path, json: &[u8], compress: bool) -> Result<(), BazaarError> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    if compress {
+        write_atomic(path, &zstd::encode_all(json, ZSTD_LEVEL)?).await
+    } else {
+        write_atomic(path, json).await
+    }
+}
+
+// <path>.tmp, where a file is written before it's renamed into place
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let mut name: OsString = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(TEMPORARY_SUFFIX);
+    path.with_file_name(name)
+}
+
+pub fn is_temporary(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(TEMPORARY_SUFFIX))
+}
+
+// Written to <path>.tmp, synced and renamed over `path`, so a crash or a full disk leaves either
+// the whole file or none of it, never a truncated snapshot
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), BazaarError> {
+    let temporary: PathBuf = temporary_path(path);
+    let result: std::io::Result<()> = async {
+        let mut file: tokio::fs::File = tokio::fs::File::create(&temporary).await?;
+        file.write_all(data).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temporary, path).await
+    }.await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temporary).await;
+    }
+    Ok(result?)
+}
+
+// Daily JSON Lines log, YYYYMMDD.jsonl for the UTC day of lastUpdated
+pub fn daily_jsonl_path(dir: &Path, last_updated: u64) -> PathBuf {
+    let time: DateTime<Utc> = DateTime::from_timestamp_millis(last_updated as i64).unwrap_or_else(Utc::now);
+    dir.join(format!("{}.jsonl", time.format("%Y%m%d")))
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    lastUpdated: u64,
+    #[serde(flatten)]
+    product: &'a Product,
+}
+
+// Written with a single append so concurrent writers don't interleave
+pub async fn append_lines(path: &Path, lines: &str) -> Result<(), BazaarError> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file: tokio::fs::File = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(lines.as_bytes()).await?;
+    Ok(())
+}
+
+// One compact line per product, sorted by product ID
+pub fn jsonl_lines(last_updated: u64, products: &HashMap<String, Product>) -> Result<String, BazaarError> {
+    let mut ids: Vec<&String> = products.keys().collect();
+    ids.sort();
+    let mut lines: String = String::new();
+    for id in ids {
+        let record: JsonlRecord = JsonlRecord { lastUpdated: last_updated, product: &products[id] };
+        lines.push_str(&serde_json::to_string(&record)?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+#[instrument(name = "write", skip_all, fields(path = %path.display()))]
+pub async fn append_jsonl(path: &Path, response: &BazaarResponse) -> Result<(), BazaarError> {
+    append_lines(path, &jsonl_lines(response.lastUpdated, &response.products)?).await
+}
+
+// lastUpdated of the last line in the newest daily log
+pub fn newest_jsonl_last_updated(raw_dir: &Path) -> Option<u64> {
+    let mut logs: Vec<PathBuf> = fs::read_dir(raw_dir).ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    logs.sort();
+    let data: String = fs::read_to_string(logs.pop()?).ok()?;
+    let header: SnapshotHeader = serde_json::from_str(data.lines().last()?).ok()?;
+    Some(header.lastUpdated)
+}
+
+// The last `depth + 1` components of `path`, '/'-separated and without the extension
+fn relative_stem(path: &Path, depth: usize) -> Option<String> {
+    let components: Vec<&str> = path.iter().map(|component| component.to_str()).collect::<Option<_>>()?;
+    let relative: String = components.get(components.len().checked_sub(depth + 1)?..)?.join("/");
+    Some(relative.split('.').next()?.to_string())
+}
+
+// Legacy names are only trusted to span 2000 up to now, a day of slack for the local offset
+const LEGACY_SINCE: i64 = 946_684_800_000;
+const LEGACY_SLACK: i64 = 86_400_000;
+
+// Time encoded in a snapshot's path, epoch millis. Legacy YYYYMMDDsssss names at the top level
+// are checked first, as they're as long as epoch millis and would read as one far in the future.
+// They only count when the date lands between 2000 and now though: an epoch like 1650031506152
+// also reads as a date, one in 1650. Then the current template, then default names left at the
+// top level by a flat layout.
+pub fn snapshot_timestamp(path: &Path) -> Option<i64> {
+    let template: &NameTemplate = name_template();
+    let file_stem: String = relative_stem(path, 0)?;
+    let until: i64 = Utc::now().timestamp_millis() + LEGACY_SLACK;
+    NameTemplate::legacy().parse(&file_stem)
+        .filter(|timestamp| (LEGACY_SINCE..=until).contains(timestamp))
+        .or_else(|| template.parse(&relative_stem(path, template.depth())?))
+        .or_else(|| NameTemplate::default().parse(&file_stem))
+}
+
+// The raw directory `path` was listed from
+fn raw_root(path: &Path) -> Option<&Path> {
+    let template: &NameTemplate = name_template();
+    let nested: bool = relative_stem(path, template.depth()).is_some_and(|stem| template.parse(&stem).is_some());
+    path.ancestors().nth(if nested { template.depth() + 1 } else { 1 })
+}
+
+// A .tmp file is a write that's still running or one cut short by a crash, it's never read
+fn report_temporary(path: &Path) {
+    if is_temporary(path) {
+        warn!("Skipping {}, an unfinished write (delete it if no fetch is running)", path.display());
+    }
+}
+
+// Snapshots `depth` folders below `dir`, only the folders and files the template produces are entered
+fn collect_nested(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) -> Result<(), BazaarError> {
+    for entry in fs::read_dir(dir)? {
+        let path: PathBuf = entry?.path();
+        if depth == 0 {
+            report_temporary(&path);
+            if is_snapshot(&path) && snapshot_timestamp(&path).is_some() {
+                files.push(path);
+            }
+        } else if path.is_dir() {
+            collect_nested(&path, depth - 1, files)?;
+        }
+    }
+    Ok(())
+}
+
+// Every snapshot in the raw directory, oldest first. With a nested template only files at the
+// template's depth that match it are listed, plus any snapshot directly in the directory.
+pub fn snapshot_files(raw_dir: &Path) -> Result<Vec<PathBuf>, BazaarError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(raw_dir)? {
+        let path: PathBuf = entry?.path();
+        report_temporary(&path);
+        if is_snapshot(&path) {
+            files.push(path);
+        }
+    }
+    let depth: usize = name_template().depth();
+    if depth > 0 {
+        for entry in fs::read_dir(raw_dir)? {
+            let path: PathBuf = entry?.path();
+            if path.is_dir() && !path.ends_with(quarantine::QUARANTINE_DIR) {
+                collect_nested(&path, depth - 1, &mut files)?;
+            }
+        }
+    }
+    files.sort_by_cached_key(|file| (snapshot_timestamp(file), file.file_name().map(|name| name.to_os_string())));
+    Ok(files)
+}
+
+// Where reading has to start for the snapshots from `files[first]` on: a delta only rebuilds on
+// top of the keyframe and deltas before it, so that's the last keyframe before it
+pub fn chain_start(files: &[PathBuf], first: usize) -> usize {
+    match files.get(first) {
+        Some(file) if is_delta(file) => files[..first].iter().rposition(|file| !is_delta(file)).unwrap_or(first),
+        _ => first,
+    }
+}
+
+// Decompresses when needed, `T` can be a partial view of the snapshot
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, BazaarError> {
+    Ok(serde_json::from_slice(&read_bytes(path)?)?)
+}
+
+// File contents, decompressed when needed
+fn read_bytes(path: &Path) -> Result<Vec<u8>, BazaarError> {
+    let data: Vec<u8> = fs::read(path)?;
+    if is_compressed(path) {
+        // Decoding from memory can't fail for I/O reasons, zstd reports a broken frame as Other
+        zstd::decode_all(data.as_slice()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+    } else {
+        Ok(data)
+    }
+}
+
+// Delta snapshots hold only the products whose quick_status changed since the snapshot before
+// them, plus the IDs of products that disappeared. They're only meaningful on top of the
+// previous snapshot, going back to the last full one (the keyframe).
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize)]
+pub struct Delta<P> {
+    pub success: bool,
+    pub lastUpdated: u64,
+    pub products: HashMap<String, P>,
+    #[serde(default)]
+    pub removed: Vec<String>,
+}
+
+// What keyframes and deltas have in common, `P` can be a partial view of a product
+struct StoredProducts<P> {
+    success: bool,
+    last_updated: u64,
+    products: HashMap<String, P>,
+    removed: Vec<String>,
+}
+
+// Deserializes a stored snapshot, only building the products `watchlist` contains.
+// The others are skipped as they're parsed, so they never take up memory.
+struct StoredSeed<'a, P> {
+    watchlist: &'a Watchlist,
+    product: PhantomData<P>,
+}
+
+impl<'de, P: Deserialize<'de>> DeserializeSeed<'de> for StoredSeed<'_, P> {
+    type Value = StoredProducts<P>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, P: Deserialize<'de>> Visitor<'de> for StoredSeed<'_, P> {
+    type Value = StoredProducts<P>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a bazaar snapshot")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut stored: StoredProducts<P> = StoredProducts { success: true, last_updated: 0, products: HashMap::new(), removed: Vec::new() };
+        let mut has_last_updated: bool = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "success" => stored.success = map.next_value()?,
+                "lastUpdated" => {
+                    stored.last_updated = map.next_value()?;
+                    has_last_updated = true;
+                }
+                "products" => stored.products = map.next_value_seed(FilteredProducts { watchlist: self.watchlist, product: PhantomData })?,
+                "removed" => stored.removed = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if !has_last_updated {
+            return Err(de::Error::missing_field("lastUpdated"));
+        }
+        Ok(stored)
+    }
+}
+
+struct FilteredProducts<'a, P> {
+    watchlist: &'a Watchlist,
+    product: PhantomData<P>,
+}
+
+impl<'de, P: Deserialize<'de>> DeserializeSeed<'de> for FilteredProducts<'_, P> {
+    type Value = HashMap<String, P>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, P: Deserialize<'de>> Visitor<'de> for FilteredProducts<'_, P> {
+    type Value = HashMap<String, P>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of products")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut products: HashMap<String, P> = HashMap::new();
+        while let Some(id) = map.next_key::<String>()? {
+            if self.watchlist.contains(&id) {
+                let product: P = map.next_value()?;
+                products.insert(id, product);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(products)
+    }
+}
+
+fn snapshot_error(path: &Path, source: BazaarError) -> BazaarError {
+    BazaarError::Snapshot { path: path.to_path_buf(), source: Box::new(source) }
+}
+
+// Errors name the file, so a caller going over many can skip it
+fn read_stored<P: DeserializeOwned>(path: &Path, watchlist: &Watchlist) -> Result<StoredProducts<P>, BazaarError> {
+    let parse = || -> Result<StoredProducts<P>, BazaarError> {
+        let data: Vec<u8> = read_bytes(path)?;
+        let mut deserializer: serde_json::Deserializer<serde_json::de::SliceRead> = serde_json::Deserializer::from_slice(&data);
+        let stored: StoredProducts<P> = StoredSeed { watchlist, product: PhantomData }.deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(stored)
+    };
+    parse().map_err(|e| snapshot_error(path, e))
+}
+
+// Products whose quick_status differs from `previous`, and the ones that are gone
+pub fn delta_from<'a>(previous: &HashMap<String, QuickStatus>, current: &'a BazaarResponse) -> Delta<&'a Product> {
+    Delta {
+        success: current.success,
+        lastUpdated: current.lastUpdated,
+        products: current.products.iter()
+            .filter(|(id, product)| previous.get(*id) != Some(&product.quick_status))
+            .map(|(id, product)| (id.clone(), product))
+            .collect(),
+        removed: previous.keys().filter(|id| !current.products.contains_key(*id)).cloned().collect(),
+    }
+}
+
+// lastUpdated and products of one snapshot after its delta chain is applied
+pub type Rebuilt<P> = (u64, HashMap<String, P>);
+
+// Reads one file of a sequence into `state`: keyframes replace it, deltas patch it. Only
+// watchlisted products are kept. Returns the file's lastUpdated. A delta without a state to
+// build on is an error.
+pub fn read_into<P: DeserializeOwned>(file: &Path, state: &mut Option<HashMap<String, P>>, watchlist: &Watchlist) -> Result<u64, BazaarError> {
+    let stored: StoredProducts<P> = read_stored(file, watchlist)?;
+    if is_delta(file) {
+        let products: &mut HashMap<String, P> = state.as_mut()
+            .ok_or_else(|| snapshot_error(file, BazaarError::Storage("the delta has no keyframe before it".to_string())))?;
+        for id in &stored.removed {
+            products.remove(id);
+        }
+        products.extend(stored.products);
+    } else {
+        *state = Some(stored.products);
+    }
+    Ok(stored.last_updated)
+}
+
+// Reads `files` oldest first, rebuilding deltas on top of the state before them, and hands
+// every snapshot's (lastUpdated, products) to `visit`. Returns the products of the last snapshot.
+pub fn read_sequence<P: DeserializeOwned>(files: &[PathBuf], watchlist: &Watchlist, mut visit: impl FnMut(&Path, u64, &HashMap<String, P>) -> Result<(), BazaarError>) -> Result<Option<Rebuilt<P>>, BazaarError> {
+    let mut state: Option<HashMap<String, P>> = None;
+    let mut last_updated: u64 = 0;
+    for file in files {
+        last_updated = read_into(file, &mut state, watchlist)?;
+        if let Some(products) = &state {
+            visit(file, last_updated, products)?;
+        }
+    }
+    Ok(state.map(|products| (last_updated, products)))
+}
+
+// Files per parallel batch, bounds how many decoded snapshots are held at once
+const PARALLEL_BATCH_FILES: usize = 512;
+
+// Splits `files` into runs that decode independently: a keyframe and the deltas after it
+fn independent_chains(files: &[PathBuf]) -> Vec<&[PathBuf]> {
+    let mut chains: Vec<&[PathBuf]> = Vec::new();
+    let mut start: usize = 0;
+    for (index, file) in files.iter().enumerate().skip(1) {
+        if !is_delta(file) {
+            chains.push(&files[start..index]);
+            start = index;
+        }
+    }
+    if start < files.len() {
+        chains.push(&files[start..]);
+    }
+    chains
+}
+
+// Like read_sequence, but `map` runs on every snapshot in parallel and `consume` gets the
+// results in file order, a failed file as its error. A failure also fails the deltas
+// after it up to the next keyframe. Files that don't parse are quarantined, along with the
+// deltas that failed because of them. Independent chains are spread over the rayon pool in
+// batches of about PARALLEL_BATCH_FILES files.
+pub fn read_parallel<P: DeserializeOwned, T: Send>(
+    files: &[PathBuf],
+    watchlist: &Watchlist,
+    map: impl Fn(&Path, u64, &HashMap<String, P>) -> Result<T, BazaarError> + Sync,
+    mut consume: impl FnMut(&Path, Result<T, BazaarError>) -> Result<(), BazaarError>,
+) -> Result<(), BazaarError> {
+    let chains: Vec<&[PathBuf]> = independent_chains(files);
+    let mut remaining: &[&[PathBuf]] = &chains;
+    while !remaining.is_empty() {
+        let mut size: usize = 0;
+        let count: usize = remaining.iter().take_while(|chain| {
+            let take: bool = size < PARALLEL_BATCH_FILES;
+            size += chain.len();
+            take
+        }).count();
+        let (batch, rest) = remaining.split_at(count);
+        remaining = rest;
+        let results: Vec<Vec<Result<T, BazaarError>>> = batch.par_iter()
+            .map(|chain| {
+                let mut state: Option<HashMap<String, P>> = None;
+                chain.iter()
+                    .map(|file| {
+                        let result: Result<T, BazaarError> = read_into(file, &mut state, watchlist)
+                            .and_then(|last_updated| map(file, last_updated, state.as_ref().expect("read_into leaves a state on success")));
+                        if result.is_err() {
+                            // Patching the deltas after it onto an older state would rebuild wrong prices
+                            state = None;
+                        }
+                        result
+                    })
+                    .collect()
+            })
+            .collect();
+        for (chain, results) in batch.iter().zip(results) {
+            // Set at the first file that doesn't parse, every failure after it in the chain goes too
+            let mut broken: bool = false;
+            for (file, result) in chain.iter().zip(results) {
+                if let Err(e) = &result {
+                    broken |= e.is_corrupt();
+                    if broken {
+                        quarantine::quarantine_or_warn(file);
+                    }
+                }
+                consume(file, result)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The files needed to rebuild `path`: itself, or the last keyframe before it and every delta since
+pub fn delta_chain(path: &Path) -> Result<Vec<PathBuf>, BazaarError> {
+    if !is_delta(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir: &Path = raw_root(path).ok_or_else(|| BazaarError::Storage("Snapshot has no parent directory".to_string()))?;
+    let files: Vec<PathBuf> = snapshot_files(dir)?;
+    let end: usize = files.iter().position(|file| file == path)
+        .ok_or_else(|| BazaarError::Storage(format!("{} isn't a snapshot in {}", path.display(), dir.display())))?;
+    let start: usize = files[..=end].iter().rposition(|file| !is_delta(file))
+        .ok_or_else(|| snapshot_error(path, BazaarError::Storage("the delta has no keyframe before it".to_string())))?;
+    Ok(files[start..=end].to_vec())
+}
+
+// BazaarResponse borrowing its products, for writing a state that's still needed afterwards
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+struct Keyframe<'a> {
+    success: bool,
+    lastUpdated: u64,
+    products: &'a HashMap<String, Product>,
+}
+
+// A delta only makes sense after the snapshot it was taken against. When that one goes away or
+// another snapshot is put in between, the delta is rebuilt from its chain and stored in full
+// next to it, in the same compression.
+pub async fn rewrite_as_keyframe(delta: &Path) -> Result<PathBuf, BazaarError> {
+    let dir: &Path = raw_root(delta).ok_or_else(|| BazaarError::Storage(format!("{} has no raw directory", delta.display())))?;
+    let (last_updated, products): Rebuilt<Product> = read_products(delta, &Watchlist::default())?;
+    let path: PathBuf = new_snapshot_path(dir, last_updated, is_compressed(delta));
+    write_snapshot(&path, &Keyframe { success: true, lastUpdated: last_updated, products: &products }, is_compressed(delta)).await?;
+    fs::remove_file(delta)?;
+    info!(delta = %delta.display(), path = %path.display(), "Rewrote delta as a keyframe");
+    Ok(path)
+}
+
+// Watchlisted products of one snapshot with any delta chain resolved, `P` can be a partial view of a product
+pub fn read_products<P: DeserializeOwned>(path: &Path, watchlist: &Watchlist) -> Result<Rebuilt<P>, BazaarError> {
+    let last: Option<Rebuilt<P>> = read_sequence(&delta_chain(path)?, watchlist, |_, _, _| Ok(()))?;
+    last.ok_or_else(|| BazaarError::Storage("No snapshot to read".to_string()))
+}
+
+// Only the watchlisted products are materialized, an empty watchlist reads everything
+pub fn read_snapshot(path: &Path, watchlist: &Watchlist) -> Result<BazaarResponse, BazaarError> {
+    if !is_delta(path) {
+        let stored: StoredProducts<Product> = read_stored(path, watchlist)?;
+        return Ok(BazaarResponse { success: stored.success, lastUpdated: stored.last_updated, products: stored.products });
+    }
+    let (last_updated, products): (u64, HashMap<String, Product>) = read_products(path, watchlist)?;
+    Ok(BazaarResponse { success: true, lastUpdated: last_updated, products })
+}
+
+// Only the header of a snapshot, the products are skipped while parsing
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    lastUpdated: u64,
+}
+
+pub fn read_last_updated(path: &Path) -> Result<u64, BazaarError> {
+    let header: SnapshotHeader = read_json(path).map_err(|e| snapshot_error(path, e))?;
+    Ok(header.lastUpdated)
+}
+
+pub fn read_newest(raw_dir: &Path, watchlist: &Watchlist) -> Result<BazaarResponse, BazaarError> {
+    let newest_path: PathBuf = newest_file(raw_dir).ok_or_else(|| BazaarError::Storage("No raw files found".to_string()))?;
+    read_snapshot(&newest_path, watchlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch_stem_isnt_read_as_a_legacy_date() {
+        // 16500315 is a valid date and 06152 a valid second of the day
+        assert_eq!(snapshot_timestamp(Path::new("raw/1650031506152.json")), Some(1650031506152));
+        assert_eq!(snapshot_timestamp(Path::new("raw/1650031506152.json.zst")), Some(1650031506152));
+    }
+
+    #[test]
+    fn legacy_stem_is_read_as_a_date() {
+        let expected: i64 = NameTemplate::legacy().parse("2022041543200").unwrap();
+        assert_eq!(snapshot_timestamp(Path::new("raw/2022041543200.json")), Some(expected));
+    }
+}
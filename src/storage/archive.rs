@@ -0,0 +1,207 @@
+// Monthly archives: a month of snapshots bundled into one zstd-compressed tarball outside the raw
+// directory, read back and compared before the originals are deleted. Files go in as stored,
+// under their path below the raw directory, so unpacking the tarball into it restores them.
+use super::{ZSTD_LEVEL, is_delta, rewrite_as_keyframe, snapshot_files, snapshot_timestamp, temporary_path};
+use crate::error::BazaarError;
+use chrono::{Datelike, Months, NaiveDate};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{info, instrument};
+
+const BLOCK: usize = 512;
+
+// A calendar month in UTC, written YYYY-MM
+#[derive(Clone, Copy)]
+pub struct Month(NaiveDate);
+
+impl FromStr for Month {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d")
+            .map(Month)
+            .map_err(|_| format!("Expected a month like 2024-05, got {}", value))
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.0.year(), self.0.month())
+    }
+}
+
+impl Month {
+    // Epoch millis of its first instant and of the next month's
+    fn range(&self) -> (i64, i64) {
+        let millis = |date: NaiveDate| date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc().timestamp_millis();
+        (millis(self.0), millis(self.0 + Months::new(1)))
+    }
+}
+
+pub struct ArchiveReport {
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    // Deltas at either edge of the month rewritten as keyframes, so both sides still rebuild
+    pub rekeyed: usize,
+}
+
+// `value` as a NUL-terminated octal field of `field.len()` bytes
+fn octal(field: &mut [u8], value: u64) {
+    let digits: String = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+// A ustar header. Names longer than 100 bytes are split into the prefix at a '/'.
+fn header(name: &str, size: u64, mtime: u64) -> Result<[u8; BLOCK], BazaarError> {
+    let mut header: [u8; BLOCK] = [0; BLOCK];
+    let (prefix, name): (&str, &str) = match name.len() {
+        0..=100 => ("", name),
+        _ => name.char_indices()
+            .filter(|(index, c)| *c == '/' && *index <= 155 && name.len() - index - 1 <= 100)
+            .map(|(index, _)| (&name[..index], &name[index + 1..]))
+            .next()
+            .ok_or_else(|| BazaarError::Storage(format!("{} is too long a name for a tarball", name)))?,
+    };
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    // The checksum is taken with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+    octal(&mut header[148..155], checksum);
+    Ok(header)
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let digits: &str = std::str::from_utf8(field).ok()?.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).ok()
+}
+
+fn field(bytes: &[u8]) -> Result<&str, BazaarError> {
+    let end: usize = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).map_err(|_| BazaarError::Storage("tarball entry name isn't UTF-8".to_string()))
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - size as usize % BLOCK) % BLOCK
+}
+
+// '/'-separated path of `file` below `raw_dir`, the name it's stored under
+fn entry_name(raw_dir: &Path, file: &Path) -> Result<String, BazaarError> {
+    let relative: &Path = file.strip_prefix(raw_dir).map_err(|_| BazaarError::Storage(format!("{} isn't in {}", file.display(), raw_dir.display())))?;
+    relative.iter()
+        .map(|component| component.to_str())
+        .collect::<Option<Vec<&str>>>()
+        .map(|components| components.join("/"))
+        .ok_or_else(|| BazaarError::Storage(format!("{} isn't a UTF-8 path", file.display())))
+}
+
+fn write_tarball(path: &Path, raw_dir: &Path, files: &[PathBuf]) -> Result<(), BazaarError> {
+    let mut encoder: zstd::Encoder<'static, BufWriter<File>> = zstd::Encoder::new(BufWriter::new(File::create(path)?), ZSTD_LEVEL)?;
+    for file in files {
+        let data: Vec<u8> = fs::read(file)?;
+        let mtime: u64 = snapshot_timestamp(file).map_or(0, |timestamp| (timestamp / 1000).max(0) as u64);
+        encoder.write_all(&header(&entry_name(raw_dir, file)?, data.len() as u64, mtime)?)?;
+        encoder.write_all(&data)?;
+        encoder.write_all(&[0; BLOCK][..padding(data.len() as u64)])?;
+    }
+    encoder.write_all(&[0; 2 * BLOCK])?;
+    encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+// Reads the tarball back and checks it holds exactly `files`, byte for byte
+fn verify_tarball(path: &Path, raw_dir: &Path, files: &[PathBuf]) -> Result<(), BazaarError> {
+    let corrupt = |reason: String| BazaarError::Storage(format!("{} doesn't match the snapshots: {}", path.display(), reason));
+    let mut reader: zstd::Decoder<'static, BufReader<File>> = zstd::Decoder::new(File::open(path)?)?;
+    let mut expected = files.iter();
+    let mut block: [u8; BLOCK] = [0; BLOCK];
+    loop {
+        reader.read_exact(&mut block)?;
+        if block.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        let prefix: &str = field(&block[345..500])?;
+        let name: String = match prefix {
+            "" => field(&block[..100])?.to_string(),
+            prefix => format!("{}/{}", prefix, field(&block[..100])?),
+        };
+        let size: u64 = parse_octal(&block[124..136]).ok_or_else(|| corrupt(format!("{} has no size", name)))?;
+        let file: &PathBuf = expected.next().ok_or_else(|| corrupt(format!("{} wasn't archived", name)))?;
+        if name != entry_name(raw_dir, file)? {
+            return Err(corrupt(format!("found {} where {} should be", name, file.display())));
+        }
+        let mut data: Vec<u8> = vec![0; size as usize + padding(size)];
+        reader.read_exact(&mut data)?;
+        if data[..size as usize] != fs::read(file)? {
+            return Err(corrupt(format!("{} differs", name)));
+        }
+    }
+    match expected.next() {
+        Some(file) => Err(corrupt(format!("{} is missing", file.display()))),
+        None => Ok(()),
+    }
+}
+
+// Writes <output_dir>/<month>.tar.zst from the month's snapshots, then deletes them unless
+// `keep`. A delta opening the month or the one right after it is made a keyframe first, so
+// neither the tarball nor what's left of the raw directory needs the other to rebuild.
+#[instrument(name = "archive", skip_all, fields(month = %month))]
+pub async fn archive(raw_dir: &Path, output_dir: &Path, month: Month, keep: bool) -> Result<ArchiveReport, BazaarError> {
+    let path: PathBuf = output_dir.join(format!("{}.tar.zst", month));
+    if path.exists() {
+        return Err(BazaarError::Input(format!("{} already exists, move it away to archive {} again", path.display(), month)));
+    }
+    let (start, end): (i64, i64) = month.range();
+    let in_month = |file: &PathBuf| snapshot_timestamp(file).is_some_and(|timestamp| (start..end).contains(&timestamp));
+    let mut files: Vec<PathBuf> = snapshot_files(raw_dir)?;
+    if !files.iter().any(in_month) {
+        return Err(BazaarError::Input(format!("No snapshots from {} in {}", month, raw_dir.display())));
+    }
+    let first: Option<&PathBuf> = files.iter().find(|file| in_month(file));
+    let after: Option<&PathBuf> = files.iter().find(|file| snapshot_timestamp(file).is_some_and(|timestamp| timestamp >= end));
+    let edges: Vec<PathBuf> = [first, after].into_iter().flatten().filter(|file| is_delta(file)).cloned().collect();
+    for delta in &edges {
+        rewrite_as_keyframe(delta).await?;
+    }
+    if !edges.is_empty() {
+        files = snapshot_files(raw_dir)?;
+    }
+    files.retain(in_month);
+    fs::create_dir_all(output_dir)?;
+    let temporary: PathBuf = temporary_path(&path);
+    let written: Result<(), BazaarError> = tokio::task::block_in_place(|| {
+        write_tarball(&temporary, raw_dir, &files)?;
+        verify_tarball(&temporary, raw_dir, &files)
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    fs::rename(&temporary, &path)?;
+    let bytes: u64 = fs::metadata(&path)?.len();
+    info!(path = %path.display(), files = files.len(), bytes, "Archive written and verified");
+    if !keep {
+        for file in &files {
+            fs::remove_file(file)?;
+            // Date folders of a nested name template go once their last snapshot does
+            for dir in file.ancestors().skip(1).take_while(|dir| *dir != raw_dir) {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(ArchiveReport { path, files: files.len(), bytes, rekeyed: edges.len() })
+}
@@ -0,0 +1,61 @@
+// Single-instance lock on the raw directory, taken by every command that writes to it so a cron
+// run and a daemon never interleave their snapshot writes. It's an advisory OS lock held through
+// the open file, so it goes away with the process and a crash can't leave a stale lock behind.
+use crate::error::BazaarError;
+use std::fs::{self, File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+pub const LOCK_FILE: &str = ".bazaar_update.lock";
+
+// Released when dropped
+pub struct DirectoryLock {
+    _file: File,
+}
+
+// Fails right away instead of waiting when another process holds the lock. The file holds the
+// holder's pid, only to tell the user who it is.
+pub fn lock(raw_dir: &Path) -> Result<DirectoryLock, BazaarError> {
+    fs::create_dir_all(raw_dir)?;
+    let path: PathBuf = raw_dir.join(LOCK_FILE);
+    let mut file: File = File::options().read(true).write(true).create(true).truncate(false).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut holder: String = String::new();
+            let _ = file.read_to_string(&mut holder);
+            let holder: String = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {})", pid),
+            };
+            return Err(BazaarError::Locked(format!(
+                "Another bazaar_update{} is already writing to {}, try again once it's done (lock held on {})",
+                holder, raw_dir.display(), path.display()
+            )));
+        }
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    hold(file, &path)
+}
+
+// Like `lock`, but None when the lock is taken, for readers that only write if nobody else is.
+// That includes this process holding it through another DirectoryLock.
+pub fn try_lock(raw_dir: &Path) -> Result<Option<DirectoryLock>, BazaarError> {
+    let path: PathBuf = raw_dir.join(LOCK_FILE);
+    let file: File = File::options().read(true).write(true).create(true).truncate(false).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => hold(file, &path).map(Some),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+// Writes the pid of the process now holding the lock
+fn hold(mut file: File, path: &Path) -> Result<DirectoryLock, BazaarError> {
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    debug!(path = %path.display(), "Locked the raw directory");
+    Ok(DirectoryLock { _file: file })
+}
@@ -0,0 +1,107 @@
+// Combines the raw directories of several collectors into one history. Snapshots are the same when
+// their lastUpdated is, two collectors polling the same API mostly store the same ones. The ones
+// only a source has are written into the target as keyframes, the target's own files are kept.
+use super::{Keyframe, is_delta, new_snapshot_path, read_into, read_sequence, rewrite_as_keyframe, snapshot_files, write_snapshot};
+use crate::error::BazaarError;
+use crate::history::SummaryProduct;
+use crate::model::{Product, QuickStatus};
+use crate::watchlist::Watchlist;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use tracing::{debug, instrument};
+
+// Same lastUpdated, different quick_status of some product
+pub struct Conflict {
+    pub last_updated: u64,
+    pub kept: PathBuf,
+    pub skipped: PathBuf,
+}
+
+#[derive(Default)]
+pub struct MergeReport {
+    pub merged: usize,
+    // Already in the target with the same contents
+    pub duplicates: usize,
+    pub conflicts: Vec<Conflict>,
+    // Deltas of the target a merged snapshot landed right in front of, rewritten as keyframes
+    pub rekeyed: usize,
+    // Unreadable source files, every error names its file
+    pub failed: Vec<BazaarError>,
+}
+
+// Where a lastUpdated is stored and a hash of its quick_status, so duplicates are told from
+// conflicts without keeping every snapshot in memory
+struct Stored {
+    path: PathBuf,
+    fingerprint: u64,
+}
+
+fn fingerprint<'a>(quick_statuses: impl Iterator<Item = &'a QuickStatus>) -> u64 {
+    let mut sorted: Vec<&QuickStatus> = quick_statuses.collect();
+    sorted.sort_by(|a, b| a.productId.cmp(&b.productId));
+    let mut hasher: DefaultHasher = DefaultHasher::new();
+    hasher.write(&serde_json::to_vec(&sorted).unwrap_or_default());
+    hasher.finish()
+}
+
+// Sources are taken in order, the target's copy of a lastUpdated wins over theirs and an earlier
+// source's over a later one's. A source file that doesn't read fails the deltas after it up to
+// the next keyframe, the rest of the source is still merged.
+#[instrument(name = "merge", skip_all, fields(target = %target.display()))]
+pub async fn merge(target: &Path, sources: &[PathBuf], compress: bool, dry_run: bool) -> Result<MergeReport, BazaarError> {
+    let everything: Watchlist = Watchlist::default();
+    let mut stored: BTreeMap<u64, Stored> = BTreeMap::new();
+    read_sequence::<SummaryProduct>(&snapshot_files(target)?, &everything, |file, last_updated, products| {
+        let fingerprint: u64 = fingerprint(products.values().map(|product| &product.quick_status));
+        stored.insert(last_updated, Stored { path: file.to_path_buf(), fingerprint });
+        Ok(())
+    })?;
+    let mut report: MergeReport = MergeReport::default();
+    for source in sources {
+        let mut state: Option<HashMap<String, Product>> = None;
+        for file in snapshot_files(source)? {
+            let last_updated: u64 = match read_into(&file, &mut state, &everything) {
+                Ok(last_updated) => last_updated,
+                Err(e) => {
+                    state = None;
+                    report.failed.push(e);
+                    continue;
+                }
+            };
+            let Some(products) = &state else {
+                continue;
+            };
+            let fingerprint: u64 = fingerprint(products.values().map(|product| &product.quick_status));
+            if let Some(existing) = stored.get(&last_updated) {
+                if existing.fingerprint == fingerprint {
+                    report.duplicates += 1;
+                } else {
+                    report.conflicts.push(Conflict { last_updated, kept: existing.path.clone(), skipped: file });
+                }
+                continue;
+            }
+            // The delta was taken against the snapshot before it, the new keyframe would come in between
+            if let Some((_, next)) = stored.range(last_updated..).next()
+                && is_delta(&next.path)
+            {
+                report.rekeyed += 1;
+                if !dry_run {
+                    let path: PathBuf = rewrite_as_keyframe(&next.path).await?;
+                    if let Some((_, next)) = stored.range_mut(last_updated..).next() {
+                        next.path = path;
+                    }
+                }
+            }
+            let path: PathBuf = new_snapshot_path(target, last_updated, compress);
+            if !dry_run {
+                write_snapshot(&path, &Keyframe { success: true, lastUpdated: last_updated, products }, compress).await?;
+            }
+            debug!(source = %file.display(), path = %path.display(), "Merged snapshot");
+            stored.insert(last_updated, Stored { path, fingerprint });
+            report.merged += 1;
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,242 @@
+// Snapshot file names from a template like "{epoch_ms}" (the default) or "{date}/{epoch}", filled
+// in with the snapshot's lastUpdated in UTC. Templates have to be reversible so a snapshot's time
+// can be read back from its path.
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// lastUpdated in epoch millis, sorts correctly across DST changes and machines
+pub const DEFAULT_NAME_TEMPLATE: &str = "{epoch_ms}";
+// YYYYMMDD<seconds from midnight> of the fetch in local time, how snapshots used to be named
+pub const LEGACY_NAME_TEMPLATE: &str = "{date}{seconds}";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    // YYYYMMDD
+    Date,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    // Seconds from midnight, 5 digits
+    Seconds,
+    // Unix seconds, any number of digits
+    Epoch,
+    // Unix millis, any number of digits
+    EpochMs,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "date" => Field::Date,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "hour" => Field::Hour,
+            "minute" => Field::Minute,
+            "second" => Field::Second,
+            "seconds" => Field::Seconds,
+            "epoch" => Field::Epoch,
+            "epoch_ms" => Field::EpochMs,
+            _ => return None,
+        })
+    }
+
+    // Digits the field always takes up, None for variable width
+    fn width(self) -> Option<usize> {
+        match self {
+            Field::Date => Some(8),
+            Field::Year => Some(4),
+            Field::Month | Field::Day | Field::Hour | Field::Minute | Field::Second => Some(2),
+            Field::Seconds => Some(5),
+            Field::Epoch | Field::EpochMs => None,
+        }
+    }
+
+    fn format(self, time: &DateTime<Utc>) -> String {
+        match self {
+            Field::Date => time.format("%Y%m%d").to_string(),
+            Field::Year => format!("{:04}", time.year()),
+            Field::Month => format!("{:02}", time.month()),
+            Field::Day => format!("{:02}", time.day()),
+            Field::Hour => format!("{:02}", time.hour()),
+            Field::Minute => format!("{:02}", time.minute()),
+            Field::Second => format!("{:02}", time.second()),
+            Field::Seconds => format!("{:05}", time.num_seconds_from_midnight()),
+            Field::Epoch => time.timestamp().to_string(),
+            Field::EpochMs => time.timestamp_millis().to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Piece {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NameTemplate {
+    pieces: Vec<Piece>,
+    source: String,
+    // Dates and times in the name are local rather than UTC, only for legacy names
+    local: bool,
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    // A trailing ".json" is accepted and dropped, the extension is added per snapshot kind
+    fn from_str(value: &str) -> Result<Self, String> {
+        let template: &str = value.strip_suffix(".json").unwrap_or(value);
+        if template.contains('.') || template.contains('\\') || template.starts_with('/') || template.contains("//") {
+            return Err(format!("Invalid name template {:?}, use '/' between folders and no dots or leading '/'", value));
+        }
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut rest: &str = template;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end: usize = rest.find('}').ok_or_else(|| format!("Unclosed {{ in name template {:?}", value))?;
+                    let field: Field = Field::from_name(&rest[1..end])
+                        .ok_or_else(|| format!("Unknown placeholder {} in name template {:?}", &rest[..=end], value))?;
+                    // Epochs have no fixed width, so they can't tell where a placeholder right after them starts
+                    if matches!(pieces.last(), Some(Piece::Field(Field::Epoch | Field::EpochMs))) {
+                        return Err(format!("Nothing separates the epoch from the placeholder after it in {:?}", value));
+                    }
+                    pieces.push(Piece::Field(field));
+                    rest = &rest[end + 1..];
+                }
+                Some(start) => {
+                    pieces.push(Piece::Literal(rest[..start].to_string()));
+                    rest = &rest[start..];
+                }
+                None => {
+                    pieces.push(Piece::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+        let has = |field: Field| pieces.iter().any(|piece| matches!(piece, Piece::Field(f) if *f == field));
+        let has_date: bool = has(Field::Date) || (has(Field::Year) && has(Field::Month) && has(Field::Day));
+        let has_time: bool = has(Field::Seconds) || (has(Field::Hour) && has(Field::Minute) && has(Field::Second));
+        if !(has(Field::Epoch) || has(Field::EpochMs) || (has_date && has_time)) {
+            return Err(format!(
+                "Name template {:?} doesn't pin down the second, it needs {{epoch_ms}}, {{epoch}} or a date and time like {{date}}{{seconds}}",
+                value
+            ));
+        }
+        if template.ends_with('/') {
+            return Err(format!("Name template {:?} ends in a folder, the last part is the file name", value));
+        }
+        Ok(Self { pieces, source: value.to_string(), local: false })
+    }
+}
+
+impl TryFrom<String> for NameTemplate {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl NameTemplate {
+    pub fn legacy() -> Self {
+        let template: Self = LEGACY_NAME_TEMPLATE.parse().expect("valid legacy template");
+        Self { local: true, ..template }
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_NAME_TEMPLATE.parse().expect("valid default template")
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl NameTemplate {
+    // Folders between the raw directory and the snapshot files
+    pub fn depth(&self) -> usize {
+        self.pieces.iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => text.matches('/').count(),
+                Piece::Field(_) => 0,
+            })
+            .sum()
+    }
+
+    // Path relative to the raw directory, '/'-separated and without an extension
+    pub fn format(&self, time: &DateTime<Utc>) -> String {
+        self.pieces.iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => text.clone(),
+                Piece::Field(field) => field.format(time),
+            })
+            .collect()
+    }
+
+    // Epoch millis encoded in a relative, extensionless path `format` produced
+    pub fn parse(&self, stem: &str) -> Option<i64> {
+        let mut rest: &str = stem;
+        let (mut date, mut year, mut month, mut day) = (None, None, None, None);
+        let (mut hour, mut minute, mut second, mut seconds, mut epoch) = (0, 0, 0, None, None);
+        let mut epoch_ms: Option<i64> = None;
+        for piece in &self.pieces {
+            let field: Field = match piece {
+                Piece::Literal(text) => {
+                    rest = rest.strip_prefix(text.as_str())?;
+                    continue;
+                }
+                Piece::Field(field) => *field,
+            };
+            let width: usize = field.width().unwrap_or_else(|| rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len()));
+            let digits: &str = rest.get(..width).filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))?;
+            rest = &rest[width..];
+            match field {
+                Field::Date => date = Some(NaiveDate::parse_from_str(digits, "%Y%m%d").ok()?),
+                Field::Year => year = Some(digits.parse().ok()?),
+                Field::Month => month = Some(digits.parse().ok()?),
+                Field::Day => day = Some(digits.parse().ok()?),
+                Field::Hour => hour = digits.parse().ok()?,
+                Field::Minute => minute = digits.parse().ok()?,
+                Field::Second => second = digits.parse().ok()?,
+                Field::Seconds => seconds = Some(digits.parse().ok()?),
+                Field::Epoch => epoch = Some(digits.parse::<i64>().ok()?),
+                Field::EpochMs => epoch_ms = Some(digits.parse().ok()?),
+            }
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+        if let Some(epoch_ms) = epoch_ms {
+            return Some(epoch_ms);
+        }
+        if let Some(epoch) = epoch {
+            return Some(epoch * 1000);
+        }
+        let date: NaiveDate = match date {
+            Some(date) => date,
+            None => NaiveDate::from_ymd_opt(year?, month?, day?)?,
+        };
+        let time: NaiveTime = match seconds {
+            Some(seconds) => NaiveTime::from_num_seconds_from_midnight_opt(seconds, 0)?,
+            None => NaiveTime::from_hms_opt(hour, minute, second)?,
+        };
+        let datetime: NaiveDateTime = date.and_time(time);
+        if self.local {
+            return Some(Local.from_local_datetime(&datetime).earliest()?.timestamp_millis());
+        }
+        Some(datetime.and_utc().timestamp_millis())
+    }
+}
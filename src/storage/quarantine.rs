@@ -0,0 +1,228 @@
+// Snapshots that don't parse are moved to <raw_dir>/_quarantine/ under the same relative path,
+// so one bad file stops failing every read of the history. Deltas behind a quarantined file go
+// with it, patched onto an older keyframe they'd rebuild wrong prices. Readers only move files
+// while the raw directory's lock is free. `repair` salvages what it can and moves snapshots back.
+use super::lock::{self, DirectoryLock};
+use super::{is_compressed, is_delta, is_snapshot, raw_root, read_stored, snapshot_timestamp, write_snapshot};
+use crate::error::BazaarError;
+use crate::model::{BazaarResponse, Product};
+use crate::watchlist::Watchlist;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::{info, instrument, warn};
+
+pub const QUARANTINE_DIR: &str = "_quarantine";
+
+// Moves `file` into the quarantine of the raw directory it was listed from
+pub fn quarantine(file: &Path) -> Result<PathBuf, BazaarError> {
+    let root: &Path = raw_root(file).ok_or_else(|| BazaarError::Storage(format!("{} has no raw directory", file.display())))?;
+    let destination: PathBuf = root.join(QUARANTINE_DIR).join(file.strip_prefix(root).unwrap_or(file));
+    if let Some(dir) = destination.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::rename(file, &destination)?;
+    Ok(destination)
+}
+
+// For readers going over the whole history, which carry on either way. They don't hold the
+// lock, so the file is moved only when it can be taken, otherwise it's left for a later run.
+pub fn quarantine_or_warn(file: &Path) {
+    let locked: Result<Option<DirectoryLock>, BazaarError> = match raw_root(file) {
+        Some(root) => lock::try_lock(root),
+        None => Err(BazaarError::Storage(format!("{} has no raw directory", file.display()))),
+    };
+    match locked {
+        Ok(Some(_lock)) => match quarantine(file) {
+            Ok(destination) => warn!("Quarantined {} to {}", file.display(), destination.display()),
+            Err(e) => warn!("Couldn't quarantine {}: {}", file.display(), e),
+        },
+        Ok(None) => warn!("Not quarantining {} while the raw directory is locked, it's left in place", file.display()),
+        Err(e) => warn!("Couldn't quarantine {}: {}", file.display(), e),
+    }
+}
+
+// Quarantined snapshots of `raw_dir`, oldest first
+pub fn quarantined_files(raw_dir: &Path) -> Result<Vec<PathBuf>, BazaarError> {
+    fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), BazaarError> {
+        for entry in fs::read_dir(dir)? {
+            let path: PathBuf = entry?.path();
+            if path.is_dir() {
+                collect(&path, files)?;
+            } else if is_snapshot(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    let dir: PathBuf = raw_dir.join(QUARANTINE_DIR);
+    let mut files: Vec<PathBuf> = Vec::new();
+    if dir.is_dir() {
+        collect(&dir, &mut files)?;
+    }
+    files.sort_by_cached_key(|file| (snapshot_timestamp(file), file.file_name().map(|name| name.to_os_string())));
+    Ok(files)
+}
+
+// Whatever decompresses: a truncated zstd frame still gives the blocks before the cut
+fn salvage_bytes(path: &Path) -> Result<Vec<u8>, BazaarError> {
+    let data: Vec<u8> = fs::read(path)?;
+    if !is_compressed(path) {
+        return Ok(data);
+    }
+    let mut decoder: zstd::Decoder<'_, std::io::BufReader<&[u8]>> = zstd::Decoder::new(data.as_slice())?;
+    let mut out: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 8192] = [0; 8192];
+    while let Ok(read @ 1..) = decoder.read(&mut buffer) {
+        out.extend_from_slice(&buffer[..read]);
+    }
+    Ok(out)
+}
+
+// A cursor over possibly cut off JSON, every step gives up at the first thing that doesn't parse
+struct Salvager<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Salvager<'_> {
+    fn skip(&mut self, byte: u8) -> bool {
+        while self.data.get(self.position).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        if self.data.get(self.position) == Some(&byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value<T: DeserializeOwned>(&mut self) -> Option<T> {
+        let mut stream = serde_json::Deserializer::from_slice(&self.data[self.position..]).into_iter::<T>();
+        let value: T = stream.next()?.ok()?;
+        self.position += stream.byte_offset();
+        Some(value)
+    }
+
+    // The next "key": of the object being walked, None at its end or where it breaks off
+    fn key(&mut self, first: bool) -> Option<String> {
+        if !first && !self.skip(b',') {
+            return None;
+        }
+        let key: String = self.value()?;
+        self.skip(b':').then_some(key)
+    }
+}
+
+// What could be read from a broken snapshot
+pub struct Salvage {
+    pub last_updated: Option<u64>,
+    pub products: HashMap<String, Product>,
+}
+
+// Every product before the point where the file breaks, and lastUpdated if it comes before that
+// (the API and this tool both write it ahead of the products)
+pub fn salvage(path: &Path) -> Result<Salvage, BazaarError> {
+    let data: Vec<u8> = salvage_bytes(path)?;
+    let mut salvager: Salvager = Salvager { data: &data, position: 0 };
+    let mut salvage: Salvage = Salvage { last_updated: None, products: HashMap::new() };
+    if !salvager.skip(b'{') {
+        return Ok(salvage);
+    }
+    let mut first: bool = true;
+    while let Some(key) = salvager.key(first) {
+        first = false;
+        match key.as_str() {
+            "lastUpdated" => salvage.last_updated = salvager.value(),
+            "products" => {
+                if !salvager.skip(b'{') {
+                    break;
+                }
+                let mut first_product: bool = true;
+                while let Some(id) = salvager.key(first_product) {
+                    first_product = false;
+                    let Some(product) = salvager.value::<Product>() else {
+                        break;
+                    };
+                    salvage.products.insert(id, product);
+                }
+                if !salvager.skip(b'}') {
+                    break;
+                }
+            }
+            _ => {
+                if salvager.value::<serde::de::IgnoredAny>().is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(salvage)
+}
+
+#[derive(Default)]
+pub struct RepairReport {
+    // Keyframes written back with the products that could be salvaged
+    pub recovered: usize,
+    pub products: usize,
+    // Intact deltas moved back behind a recovered keyframe
+    pub restored: usize,
+    // Left in quarantine
+    pub kept: usize,
+}
+
+// Walks the quarantine oldest first. A broken keyframe comes back with the products read before
+// the break, the deltas quarantined along with it follow as long as they're intact. A broken
+// delta can't be fixed: the products it lost would keep stale prices, so it and the deltas
+// after it stay.
+#[instrument(name = "repair", skip_all)]
+pub async fn repair(raw_dir: &Path, dry_run: bool) -> Result<RepairReport, BazaarError> {
+    let quarantine: PathBuf = raw_dir.join(QUARANTINE_DIR);
+    let mut report: RepairReport = RepairReport::default();
+    let mut chain_recovered: bool = false;
+    for file in quarantined_files(raw_dir)? {
+        let destination: PathBuf = raw_dir.join(file.strip_prefix(&quarantine).unwrap_or(&file));
+        if destination.exists() {
+            warn!("Keeping {}, {} exists again", file.display(), destination.display());
+            report.kept += 1;
+            continue;
+        }
+        if is_delta(&file) {
+            let intact: bool = read_stored::<serde::de::IgnoredAny>(&file, &Watchlist::default()).is_ok();
+            if chain_recovered && intact {
+                if !dry_run {
+                    fs::rename(&file, &destination)?;
+                }
+                info!(path = %destination.display(), "Restored delta");
+                report.restored += 1;
+            } else {
+                chain_recovered = false;
+                warn!("Keeping {}, a delta can't be rebuilt without {}", file.display(), if intact { "its keyframe" } else { "all of its products" });
+                report.kept += 1;
+            }
+            continue;
+        }
+        let salvage: Salvage = salvage(&file)?;
+        let last_updated: Option<u64> = salvage.last_updated.or_else(|| snapshot_timestamp(&file).map(|timestamp| timestamp as u64));
+        let (Some(last_updated), false) = (last_updated, salvage.products.is_empty()) else {
+            chain_recovered = false;
+            warn!("Keeping {}, no product could be salvaged", file.display());
+            report.kept += 1;
+            continue;
+        };
+        let products: usize = salvage.products.len();
+        if !dry_run {
+            let response: BazaarResponse = BazaarResponse { success: true, lastUpdated: last_updated, products: salvage.products };
+            write_snapshot(&destination, &response, is_compressed(&destination)).await?;
+            fs::remove_file(&file)?;
+        }
+        info!(path = %destination.display(), products, "Recovered keyframe");
+        chain_recovered = true;
+        report.recovered += 1;
+        report.products += products;
+    }
+    Ok(report)
+}
@@ -0,0 +1,205 @@
+// Trading strategies for the backtester and paper trading: the `Strategy` trait both run, and the
+// rule-based one `backtest` builds from its --buy and --sell conditions. A type of your own that
+// implements the trait runs through either from the library, without changes to the crate.
+use crate::backtest::Account;
+use crate::config::{AlertMetric, AlertOp};
+use crate::history::Window;
+use crate::indicators::sma;
+use crate::model::QuickStatus;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// What a strategy sees of one snapshot
+pub struct Market<'a> {
+    // lastUpdated, epoch millis
+    pub timestamp: i64,
+    // Quick status of the strategy's products, the ones the snapshot has
+    pub products: &'a HashMap<String, QuickStatus>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+// What a strategy asks for. A product has at most one open order, placing another takes the
+// old one down first, keeping whatever of it filled.
+pub enum Instruction {
+    // A buy order or sell offer of `units` at `price`. Buys are cut to what the cash pays for and
+    // sells to the units held, u64::MAX asks for as many as that allows.
+    Place { product_id: String, side: Side, price: f64, units: u64 },
+    // Take down the product's open order
+    Cancel { product_id: String },
+}
+
+pub trait Strategy {
+    // Products the strategy trades or looks at, only these are read from the snapshots
+    fn products(&self) -> Vec<String>;
+
+    // Called with every snapshot, oldest first, once the open orders got the fills since the
+    // previous one
+    fn on_snapshot(&mut self, market: &Market, account: &Account) -> Vec<Instruction>;
+
+    // Shown along with the results
+    fn describe(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+// What a condition compares its metric with
+#[derive(Clone)]
+pub enum Target {
+    Value(f64),
+    // The metric's simple moving average over the window, moved by `offset` percent
+    Sma { window: Window, offset: f64 },
+}
+
+// Like "sellPrice < sma:1h-2%" or "margin > 5", the three parts separated by spaces
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    pub metric: AlertMetric,
+    pub op: AlertOp,
+    pub target: Target,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid condition {:?}, expected one like \"sellPrice < sma:1h-2%\" or \"margin > 5\"", value);
+        let [metric, op, target]: [&str; 3] = value.split_whitespace().collect::<Vec<&str>>().try_into().map_err(|_| invalid())?;
+        let metric: AlertMetric = AlertMetric::value_variants().iter()
+            .find(|candidate| candidate.name() == metric)
+            .copied()
+            .ok_or_else(|| format!("Unknown metric {} in {:?}", metric, value))?;
+        let op: AlertOp = match op {
+            ">" => AlertOp::Above,
+            ">=" => AlertOp::AtLeast,
+            "<" => AlertOp::Below,
+            "<=" => AlertOp::AtMost,
+            _ => return Err(invalid()),
+        };
+        let target: Target = match target.strip_prefix("sma:") {
+            Some(average) => {
+                let split: usize = average.find(['+', '-']).unwrap_or(average.len());
+                let (window, offset) = average.split_at(split);
+                let offset: f64 = match offset {
+                    "" => 0.0,
+                    offset => offset.strip_suffix('%').and_then(|offset| offset.parse().ok()).ok_or_else(invalid)?,
+                };
+                Target::Sma { window: window.parse()?, offset }
+            }
+            None => Target::Value(target.parse().map_err(|_| invalid())?),
+        };
+        Ok(Condition { metric, op, target })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Target::Value(value) => write!(f, "{} {} {}", self.metric.name(), self.op, value),
+            Target::Sma { window, offset } if *offset == 0.0 => write!(f, "{} {} sma:{}", self.metric.name(), self.op, window),
+            Target::Sma { window, offset } => write!(f, "{} {} sma:{}{:+}%", self.metric.name(), self.op, window, offset),
+        }
+    }
+}
+
+// Flips one product on conditions. Orders go in at the top of the book: buys at the sell price,
+// sells at the buy price. A buy order waiting to fill is moved up when it's outbid and the buy
+// conditions still hold, and replaced by an offer of what it bought once the sell conditions do.
+// A sell offer is moved down when undercut while the sell conditions hold.
+pub struct RuleStrategy {
+    product_id: String,
+    // Every buy condition has to hold to place a buy order, every sell condition to sell
+    buy: Vec<Condition>,
+    sell: Vec<Condition>,
+    // Samples of every metric the conditions use, keyed by its name
+    series: HashMap<&'static str, Vec<(i64, f64)>>,
+}
+
+impl RuleStrategy {
+    pub fn new(product_id: String, buy: Vec<Condition>, sell: Vec<Condition>) -> Self {
+        let series: HashMap<&'static str, Vec<(i64, f64)>> = buy.iter()
+            .chain(&sell)
+            .map(|condition| (condition.metric.name(), Vec::new()))
+            .collect();
+        Self { product_id, buy, sell, series }
+    }
+
+    // Metrics the snapshot has no value for fail the condition, and so does an average without samples
+    fn holds(&self, conditions: &[Condition], quick_status: &QuickStatus) -> bool {
+        conditions.iter().all(|condition| {
+            let Some(value) = condition.metric.value(quick_status) else {
+                return false;
+            };
+            let limit: Option<f64> = match &condition.target {
+                Target::Value(limit) => Some(*limit),
+                Target::Sma { window, offset } => self.series.get(condition.metric.name())
+                    .and_then(|series| sma(series, window.millis))
+                    .map(|average| average * (1.0 + offset / 100.0)),
+            };
+            limit.is_some_and(|limit| condition.op.holds(value, limit))
+        })
+    }
+}
+
+impl Strategy for RuleStrategy {
+    fn products(&self) -> Vec<String> {
+        vec![self.product_id.clone()]
+    }
+
+    fn on_snapshot(&mut self, market: &Market, account: &Account) -> Vec<Instruction> {
+        let Some(quick_status) = market.products.get(&self.product_id) else {
+            return Vec::new();
+        };
+        for condition in self.buy.iter().chain(&self.sell) {
+            let series: &mut Vec<(i64, f64)> = self.series.entry(condition.metric.name()).or_default();
+            if let Some(value) = condition.metric.value(quick_status)
+                && series.last().is_none_or(|(timestamp, _)| *timestamp < market.timestamp)
+            {
+                series.push((market.timestamp, value));
+            }
+        }
+        let buy: bool = self.holds(&self.buy, quick_status);
+        let sell: bool = self.holds(&self.sell, quick_status);
+        let place = |side: Side, price: f64| vec![Instruction::Place { product_id: self.product_id.clone(), side, price, units: u64::MAX }];
+        match account.open_order(&self.product_id).map(|order| (order.side, order.price)) {
+            Some((Side::Buy, _)) if sell => place(Side::Sell, quick_status.buyPrice),
+            Some((Side::Buy, price)) if buy && quick_status.sellPrice > price => place(Side::Buy, quick_status.sellPrice),
+            Some((Side::Sell, price)) if sell && quick_status.buyPrice < price => place(Side::Sell, quick_status.buyPrice),
+            Some(_) => Vec::new(),
+            None if account.units(&self.product_id) > 0 && sell => place(Side::Sell, quick_status.buyPrice),
+            None if account.units(&self.product_id) == 0 && buy => place(Side::Buy, quick_status.sellPrice),
+            None => Vec::new(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let conditions = |conditions: &[Condition]| conditions.iter().map(Condition::to_string).collect::<Vec<String>>().join(" and ");
+        format!("{}, buy when {}, sell when {}", self.product_id, conditions(&self.buy), conditions(&self.sell))
+    }
+}
@@ -0,0 +1,88 @@
+// Timezone timestamps are shown in. Everything is stored in UTC, this only changes how reports print it.
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DisplayTimezone {
+    #[default]
+    Utc,
+    // Whatever the machine is set to, DST included
+    Local,
+    Fixed(FixedOffset),
+}
+
+// "UTC", "local" or an offset like "+02:00"
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "utc" | "z" => Ok(DisplayTimezone::Utc),
+            "local" => Ok(DisplayTimezone::Local),
+            _ => value.parse::<FixedOffset>()
+                .map(DisplayTimezone::Fixed)
+                .map_err(|_| format!("Invalid timezone {:?}, use UTC, local or an offset like +02:00", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for DisplayTimezone {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for DisplayTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayTimezone::Utc => f.write_str("UTC"),
+            DisplayTimezone::Local => f.write_str("local"),
+            DisplayTimezone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl DisplayTimezone {
+    // `millis` in this timezone with a strftime `format`, the raw number if it's out of range
+    pub fn format_millis(self, millis: i64, format: &str) -> String {
+        let Some(time) = DateTime::<Utc>::from_timestamp_millis(millis) else {
+            return millis.to_string();
+        };
+        match self {
+            DisplayTimezone::Utc => time.format(format).to_string(),
+            DisplayTimezone::Local => time.with_timezone(&Local).format(format).to_string(),
+            DisplayTimezone::Fixed(offset) => time.with_timezone(&offset).format(format).to_string(),
+        }
+    }
+
+    // Full date and time with the zone appended, so printed times are never ambiguous
+    pub fn format_timestamp(self, millis: i64) -> String {
+        match self {
+            DisplayTimezone::Utc => format!("{} UTC", self.format_millis(millis, DEFAULT_TIMESTAMP_FORMAT)),
+            _ => self.format_millis(millis, &format!("{} %:z", DEFAULT_TIMESTAMP_FORMAT)),
+        }
+    }
+}
+
+// Set once from the config and command line before anything is printed
+static DISPLAY_TIMEZONE: OnceLock<DisplayTimezone> = OnceLock::new();
+
+pub fn set_display_timezone(timezone: DisplayTimezone) {
+    let _ = DISPLAY_TIMEZONE.set(timezone);
+}
+
+pub fn display_timezone() -> DisplayTimezone {
+    DISPLAY_TIMEZONE.get().copied().unwrap_or_default()
+}
+
+pub fn format_timestamp(millis: i64) -> String {
+    display_timezone().format_timestamp(millis)
+}
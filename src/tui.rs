@@ -0,0 +1,363 @@
+// Live terminal viewer for `watch` and `replay`: sortable watchlist table with price sparklines
+use crate::analysis::{BAZAAR_TAX_RATE, Spread};
+use crate::config::ApiConfig;
+use crate::error::BazaarError;
+use crate::history::History;
+use crate::watchlist::Watchlist;
+use crate::fetch::fetch_bazaar;
+use crate::model::{BazaarResponse, QuickStatus};
+use crate::names;
+use crate::storage::snapshot_files;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Sparkline, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+
+// Samples kept per product for the sparklines
+const HISTORY_LEN: usize = 60;
+// Samples squeezed into the inline trend column
+const TREND_LEN: usize = 20;
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Product,
+    BuyPrice,
+    SellPrice,
+    Spread,
+    Margin,
+    BuyVolume,
+    SellVolume,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 7] = [
+        SortColumn::Product,
+        SortColumn::BuyPrice,
+        SortColumn::SellPrice,
+        SortColumn::Spread,
+        SortColumn::Margin,
+        SortColumn::BuyVolume,
+        SortColumn::SellVolume,
+    ];
+
+    fn next(self) -> Self {
+        let index: usize = Self::ALL.iter().position(|column| *column == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            SortColumn::Product => "product",
+            SortColumn::BuyPrice => "buy",
+            SortColumn::SellPrice => "sell",
+            SortColumn::Spread => "spread",
+            SortColumn::Margin => "margin%",
+            SortColumn::BuyVolume => "buy vol",
+            SortColumn::SellVolume => "sell vol",
+        }
+    }
+}
+
+// What the viewer is sent
+pub enum Update {
+    // A fetched or replayed snapshot, or why there's none
+    Bazaar(Result<BazaarResponse, String>),
+    // The alerts the newest snapshot raised, as messages
+    Alerts(Vec<String>),
+    // Replaces the status in the title, until the next snapshot
+    Status(String),
+}
+
+struct Line {
+    // Readable name, what the product column shows and sorts by
+    name: String,
+    quick_status: QuickStatus,
+    spread: Option<Spread>,
+}
+
+impl Line {
+    // Comparable key for numeric columns, products are sorted by name separately
+    fn key(&self, column: SortColumn) -> f64 {
+        match column {
+            SortColumn::Product => 0.0,
+            SortColumn::BuyPrice => self.quick_status.buyPrice,
+            SortColumn::SellPrice => self.quick_status.sellPrice,
+            SortColumn::Spread => self.spread.as_ref().map_or(f64::MIN, |s| s.spread.to_float()),
+            SortColumn::Margin => self.spread.as_ref().map_or(f64::MIN, |s| s.margin.to_float()),
+            SortColumn::BuyVolume => self.quick_status.buyVolume as f64,
+            SortColumn::SellVolume => self.quick_status.sellVolume as f64,
+        }
+    }
+}
+
+struct App {
+    watchlist: Watchlist,
+    lines: Vec<Line>,
+    last_updated: Option<u64>,
+    // Recent buy prices per product, oldest first
+    history: HashMap<String, VecDeque<f64>>,
+    sort: SortColumn,
+    descending: bool,
+    table_state: TableState,
+    status: String,
+    // The newest alerts, kept until others come
+    alerts: Option<String>,
+}
+
+impl App {
+    fn new(watchlist: Watchlist, seed: Option<History>) -> Self {
+        let mut app: App = App {
+            watchlist,
+            lines: Vec::new(),
+            last_updated: None,
+            history: HashMap::new(),
+            sort: SortColumn::Product,
+            descending: false,
+            table_state: TableState::default(),
+            status: "Waiting for the first fetch...".to_string(),
+            alerts: None,
+        };
+        for point in seed.map(|history| history.points).unwrap_or_default() {
+            for (id, quick_status) in point.products {
+                app.record(&id, quick_status.buyPrice);
+            }
+        }
+        app
+    }
+
+    fn record(&mut self, product_id: &str, price: f64) {
+        let samples: &mut VecDeque<f64> = self.history.entry(product_id.to_string()).or_default();
+        samples.push_back(price);
+        while samples.len() > HISTORY_LEN {
+            samples.pop_front();
+        }
+    }
+
+    fn update(&mut self, update: Update) {
+        match update {
+            Update::Bazaar(update) => self.apply(update),
+            Update::Alerts(messages) => {
+                self.alerts = messages.first().map(|first| match messages.len() {
+                    1 => format!("alert: {}", first),
+                    count => format!("{} alerts: {}, ...", count, first),
+                });
+            }
+            Update::Status(status) => self.status = status,
+        }
+    }
+
+    fn apply(&mut self, update: Result<BazaarResponse, String>) {
+        let mut response: BazaarResponse = match update {
+            Ok(response) => response,
+            Err(e) => {
+                self.status = format!("Fetch failed: {}", e);
+                return;
+            }
+        };
+        self.watchlist.retain(&mut response);
+        // The API refreshes slower than we may poll, repeated data adds no samples
+        if self.last_updated != Some(response.lastUpdated) {
+            for (id, product) in &response.products {
+                self.record(id, product.quick_status.buyPrice);
+            }
+        }
+        self.last_updated = Some(response.lastUpdated);
+        self.lines = response.products.into_iter()
+            .map(|(id, product)| Line {
+                name: names::normalize(&id),
+                // Overflowing products just show no spread in the viewer
+                spread: Spread::from_quick_status(&id, &product.quick_status, BAZAAR_TAX_RATE).ok().flatten(),
+                quick_status: product.quick_status,
+            })
+            .collect();
+        self.sort_lines();
+        self.status = format!("Last updated: {}", response.lastUpdated);
+        if self.table_state.selected().is_none() && !self.lines.is_empty() {
+            self.table_state.select(Some(0));
+        }
+    }
+
+    fn sort_lines(&mut self) {
+        let sort: SortColumn = self.sort;
+        self.lines.sort_by(|a, b| {
+            let order: std::cmp::Ordering = match sort {
+                SortColumn::Product => a.name.cmp(&b.name),
+                _ => a.key(sort).total_cmp(&b.key(sort)),
+            };
+            if self.descending { order.reverse() } else { order }
+        });
+    }
+
+    fn selected_id(&self) -> Option<&str> {
+        let index: usize = self.table_state.selected()?;
+        self.lines.get(index).map(|line| line.quick_status.productId.as_str())
+    }
+
+    fn trend(&self, product_id: &str) -> String {
+        let Some(samples) = self.history.get(product_id) else {
+            return String::new();
+        };
+        let recent: Vec<f64> = samples.iter().rev().take(TREND_LEN).rev().copied().collect();
+        let min: f64 = recent.iter().copied().fold(f64::INFINITY, f64::min);
+        let max: f64 = recent.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        recent.iter()
+            .map(|price| {
+                let level: f64 = if max > min { (price - min) / (max - min) } else { 0.5 };
+                SPARK_CHARS[((level * (SPARK_CHARS.len() - 1) as f64).round() as usize).min(SPARK_CHARS.len() - 1)]
+            })
+            .collect()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, chart_area, help_area] = Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(7),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let header: Row = Row::new(SortColumn::ALL.iter().map(|column| {
+            if *column == self.sort {
+                format!("{}{}", column.title(), if self.descending { " ▼" } else { " ▲" })
+            } else {
+                column.title().to_string()
+            }
+        }).chain(["trend".to_string()]))
+        .style(Style::default().add_modifier(Modifier::BOLD));
+
+        let rows: Vec<Row> = self.lines.iter().map(|line| {
+            let quick_status: &QuickStatus = &line.quick_status;
+            Row::new([
+                line.name.clone(),
+                format!("{:.1}", quick_status.buyPrice),
+                format!("{:.1}", quick_status.sellPrice),
+                line.spread.as_ref().map_or("-".to_string(), |s| s.spread.to_string()),
+                line.spread.as_ref().map_or("-".to_string(), |s| s.margin.to_string()),
+                quick_status.buyVolume.to_string(),
+                quick_status.sellVolume.to_string(),
+                self.trend(&quick_status.productId),
+            ])
+        }).collect();
+
+        let widths: [Constraint; 8] = [
+            Constraint::Min(24),
+            Constraint::Length(13),
+            Constraint::Length(13),
+            Constraint::Length(11),
+            Constraint::Length(9),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(TREND_LEN as u16),
+        ];
+        let title: String = match &self.alerts {
+            Some(alerts) => format!(" bazaar watch | {} | {} ", self.status, alerts),
+            None => format!(" bazaar watch | {} ", self.status),
+        };
+        let table: Table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.table_state);
+
+        self.draw_chart(frame, chart_area);
+        frame.render_widget(Paragraph::new("q quit  ↑/↓ select  s sort column  r reverse"), help_area);
+    }
+
+    // Buy price sparkline of the selected product, offset so small moves stay visible
+    fn draw_chart(&self, frame: &mut Frame, area: Rect) {
+        let Some(id) = self.selected_id() else {
+            frame.render_widget(Block::default().borders(Borders::ALL), area);
+            return;
+        };
+        let samples: Vec<f64> = self.history.get(id).map(|s| s.iter().copied().collect()).unwrap_or_default();
+        let min: f64 = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let data: Vec<u64> = samples.iter().map(|price| ((price - min) * 100.0) as u64 + 1).collect();
+        let sparkline: Sparkline = Sparkline::default()
+            .block(Block::default().borders(Borders::ALL).title(format!(" {} buy price, last {} samples ", id, samples.len())))
+            .data(&data);
+        frame.render_widget(sparkline, area);
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        if self.lines.is_empty() {
+            return;
+        }
+        let current: isize = self.table_state.selected().unwrap_or(0) as isize;
+        let next: isize = (current + offset).clamp(0, self.lines.len() as isize - 1);
+        self.table_state.select(Some(next as usize));
+    }
+}
+
+// Seeds the sparklines from the newest stored snapshots so they aren't empty at startup
+fn seed_history(raw_dir: &Path, watchlist: &Watchlist) -> Option<History> {
+    let files: Vec<PathBuf> = snapshot_files(raw_dir).ok()?;
+    let recent: &[PathBuf] = &files[files.len().saturating_sub(HISTORY_LEN)..];
+    History::load(recent, watchlist).ok()
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App, updates: &mut mpsc::UnboundedReceiver<Update>) -> Result<(), BazaarError> {
+    loop {
+        while let Ok(update) = updates.try_recv() {
+            app.update(update);
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+                KeyCode::PageDown => app.move_selection(10),
+                KeyCode::PageUp => app.move_selection(-10),
+                KeyCode::Char('s') => {
+                    app.sort = app.sort.next();
+                    app.sort_lines();
+                }
+                KeyCode::Char('r') => {
+                    app.descending = !app.descending;
+                    app.sort_lines();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Shows whatever comes through `updates` until the user quits, the sparklines start from `seed`.
+// The loop leaves the async workers, so whatever sends the updates keeps running on one core.
+pub fn view(watchlist: Watchlist, seed: Option<History>, mut updates: mpsc::UnboundedReceiver<Update>) -> Result<(), BazaarError> {
+    let mut app: App = App::new(watchlist, seed);
+    let mut terminal: DefaultTerminal = ratatui::init();
+    let result: Result<(), BazaarError> = tokio::task::block_in_place(|| event_loop(&mut terminal, &mut app, &mut updates));
+    ratatui::restore();
+    result
+}
+
+pub async fn run(client: reqwest::Client, api: ApiConfig, raw_dir: &Path, interval: u64, watchlist: Watchlist) -> Result<(), BazaarError> {
+    let seed: Option<History> = seed_history(raw_dir, &watchlist);
+    let (sender, updates) = mpsc::unbounded_channel::<Update>();
+    let fetcher: tokio::task::JoinHandle<()> = tokio::spawn(async move {
+        let mut ticker: time::Interval = time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            let update: Result<BazaarResponse, String> = fetch_bazaar(&client, &api).await.map_err(|e| e.to_string());
+            if sender.send(Update::Bazaar(update)).is_err() {
+                break;
+            }
+        }
+    });
+    let result: Result<(), BazaarError> = view(watchlist, seed, updates);
+    fetcher.abort();
+    result
+}
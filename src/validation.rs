@@ -0,0 +1,205 @@
+// Lenient parsing of bazaar responses. Hypixel adds fields and sometimes sends odd values, one bad
+// product shouldn't cost the whole snapshot, so problems are repaired or skipped and reported.
+use crate::error::BazaarError;
+use crate::model::{BazaarResponse, Product, QuickStatus};
+use crate::storage::{append_lines, daily_jsonl_path};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// Reports go to <raw-dir>/validation/YYYYMMDD.jsonl, one line per stored snapshot
+pub const VALIDATION_DIR: &str = "validation";
+
+// Prices and volumes above these are kept but warned about, far past anything the bazaar has seen
+const ABSURD_PRICE: f64 = 1e10;
+const ABSURD_VOLUME: u64 = 100_000_000_000;
+
+const RESPONSE_FIELDS: &[&str] = &["success", "lastUpdated", "products", "cause"];
+const PRODUCT_FIELDS: &[&str] = &["product_id", "sell_summary", "buy_summary", "quick_status"];
+const ORDER_FIELDS: &[&str] = &["amount", "pricePerUnit", "orders"];
+const QUICK_STATUS_FIELDS: &[&str] = &[
+    "productId", "sellPrice", "sellVolume", "sellMovingWeek", "sellOrders", "buyPrice", "buyVolume", "buyMovingWeek", "buyOrders",
+];
+// Unsigned in the model, a negative value would fail the whole product
+const QUICK_STATUS_COUNTS: &[&str] = &["sellVolume", "sellMovingWeek", "sellOrders", "buyVolume", "buyMovingWeek", "buyOrders"];
+const ORDER_COUNTS: &[&str] = &["amount", "orders"];
+
+#[derive(Serialize)]
+pub struct Issue {
+    pub product_id: String,
+    pub message: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+pub struct ValidationReport {
+    pub lastUpdated: u64,
+    // Products kept in the snapshot
+    pub products: usize,
+    // Left out because they couldn't be parsed, e.g. without quick_status
+    pub skipped: Vec<Issue>,
+    // Kept after clamping values the model can't hold, like negative volumes
+    pub repaired: Vec<Issue>,
+    // Kept as they are, but the values look wrong
+    pub warnings: Vec<Issue>,
+    // Fields the model doesn't know, by path, with how many products had them
+    pub unknown_fields: BTreeMap<String, usize>,
+}
+
+impl ValidationReport {
+    // The parsed response differs from the body, so the body can't be stored verbatim
+    pub fn has_changes(&self) -> bool {
+        !self.skipped.is_empty() || !self.repaired.is_empty()
+    }
+
+    pub fn print_warnings(&self) {
+        for (label, issues) in [("skipped", &self.skipped), ("repaired", &self.repaired), ("suspicious", &self.warnings)] {
+            for issue in issues {
+                warn!("{} {}: {}", label, issue.product_id, issue.message);
+            }
+        }
+        if !self.unknown_fields.is_empty() {
+            let fields: Vec<String> = self.unknown_fields.iter().map(|(field, count)| format!("{} ({})", field, count)).collect();
+            warn!("Unknown fields in the response: {}", fields.join(", "));
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct RawResponse {
+    success: bool,
+    lastUpdated: u64,
+    products: HashMap<String, Value>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+fn note_unknown(object: &Map<String, Value>, known: &[&str], path: &str, unknown: &mut BTreeMap<String, usize>) {
+    for key in object.keys().filter(|key| !known.contains(&key.as_str())) {
+        *unknown.entry(format!("{}.{}", path, key)).or_default() += 1;
+    }
+}
+
+// Negative counts become 0, returns the fields that were clamped
+fn clamp_counts(object: &mut Map<String, Value>, fields: &[&str]) -> Vec<String> {
+    let mut clamped: Vec<String> = Vec::new();
+    for field in fields {
+        if let Some(value) = object.get_mut(*field) && value.as_f64().is_some_and(|number| number < 0.0) {
+            clamped.push(format!("{} was {}", field, value));
+            *value = Value::from(0);
+        }
+    }
+    clamped
+}
+
+// Unknown fields are counted and clamped values noted, the checks that need the model come later
+fn inspect_product(object: &mut Map<String, Value>, unknown: &mut BTreeMap<String, usize>) -> Vec<String> {
+    note_unknown(object, PRODUCT_FIELDS, "product", unknown);
+    let mut repairs: Vec<String> = Vec::new();
+    if let Some(Value::Object(quick_status)) = object.get_mut("quick_status") {
+        note_unknown(quick_status, QUICK_STATUS_FIELDS, "quick_status", unknown);
+        repairs.extend(clamp_counts(quick_status, QUICK_STATUS_COUNTS).into_iter().map(|repair| format!("quick_status.{}", repair)));
+    }
+    for side in ["sell_summary", "buy_summary"] {
+        let Some(Value::Array(orders)) = object.get_mut(side) else {
+            continue;
+        };
+        for order in orders.iter_mut().filter_map(Value::as_object_mut) {
+            note_unknown(order, ORDER_FIELDS, side, unknown);
+            repairs.extend(clamp_counts(order, ORDER_COUNTS).into_iter().map(|repair| format!("{}.{}", side, repair)));
+        }
+    }
+    repairs
+}
+
+// Values that parsed fine but can't be right
+fn range_warnings(product: &Product) -> Vec<String> {
+    let quick_status: &QuickStatus = &product.quick_status;
+    let mut warnings: Vec<String> = Vec::new();
+    for (field, price) in [("buyPrice", quick_status.buyPrice), ("sellPrice", quick_status.sellPrice)] {
+        if !price.is_finite() || price < 0.0 {
+            warnings.push(format!("{} is {}", field, price));
+        } else if price > ABSURD_PRICE {
+            warnings.push(format!("{} is {:.1}, above {:.0}", field, price, ABSURD_PRICE));
+        }
+    }
+    for (field, volume) in [
+        ("buyVolume", quick_status.buyVolume), ("sellVolume", quick_status.sellVolume),
+        ("buyMovingWeek", quick_status.buyMovingWeek), ("sellMovingWeek", quick_status.sellMovingWeek),
+    ] {
+        if volume > ABSURD_VOLUME {
+            warnings.push(format!("{} is {}, above {}", field, volume, ABSURD_VOLUME));
+        }
+    }
+    if product.product_id != quick_status.productId {
+        warnings.push(format!("quick_status.productId is {:?}", quick_status.productId));
+    }
+    warnings
+}
+
+// Only a body that isn't a bazaar response at all is an error, product-level problems end up in the report
+pub fn parse_response(body: &[u8]) -> Result<(BazaarResponse, ValidationReport), serde_json::Error> {
+    let raw: RawResponse = serde_json::from_slice(body)?;
+    let mut report: ValidationReport = ValidationReport {
+        lastUpdated: raw.lastUpdated,
+        products: 0,
+        skipped: Vec::new(),
+        repaired: Vec::new(),
+        warnings: Vec::new(),
+        unknown_fields: BTreeMap::new(),
+    };
+    note_unknown(&raw.extra, RESPONSE_FIELDS, "response", &mut report.unknown_fields);
+    let mut products: HashMap<String, Product> = HashMap::with_capacity(raw.products.len());
+    for (id, mut value) in raw.products {
+        let repairs: Vec<String> = match value.as_object_mut() {
+            Some(object) if object.contains_key("quick_status") => inspect_product(object, &mut report.unknown_fields),
+            Some(_) => {
+                report.skipped.push(Issue { product_id: id, message: "no quick_status".to_string() });
+                continue;
+            }
+            None => {
+                report.skipped.push(Issue { product_id: id, message: format!("expected an object, got {}", value) });
+                continue;
+            }
+        };
+        let product: Product = match serde_json::from_value(value) {
+            Ok(product) => product,
+            Err(e) => {
+                report.skipped.push(Issue { product_id: id, message: e.to_string() });
+                continue;
+            }
+        };
+        if !repairs.is_empty() {
+            report.repaired.push(Issue { product_id: id.clone(), message: repairs.join(", ") });
+        }
+        let warnings: Vec<String> = range_warnings(&product);
+        if !warnings.is_empty() {
+            report.warnings.push(Issue { product_id: id.clone(), message: warnings.join(", ") });
+        }
+        products.insert(id, product);
+    }
+    report.products = products.len();
+    for issues in [&mut report.skipped, &mut report.repaired, &mut report.warnings] {
+        issues.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+    }
+    let response: BazaarResponse = BazaarResponse { success: raw.success, lastUpdated: raw.lastUpdated, products };
+    Ok((response, report))
+}
+
+#[derive(Serialize)]
+struct ReportRecord<'a> {
+    snapshot: &'a Path,
+    #[serde(flatten)]
+    report: &'a ValidationReport,
+}
+
+// Appends the report of the snapshot just stored at `snapshot`
+pub async fn record(raw_dir: &Path, snapshot: &Path, report: &ValidationReport) -> Result<(), BazaarError> {
+    let path: PathBuf = daily_jsonl_path(&raw_dir.join(VALIDATION_DIR), report.lastUpdated);
+    let mut line: String = serde_json::to_string(&ReportRecord { snapshot, report })?;
+    line.push('\n');
+    append_lines(&path, &line).await
+}
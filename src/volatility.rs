@@ -0,0 +1,98 @@
+// How much buy and sell prices moved within rolling windows of the snapshot history
+use crate::history::{History, Window};
+use crate::indicators::{Series, price_series, recent};
+use crate::items::ItemCatalog;
+use std::collections::BTreeSet;
+
+// Spread of one price within one window, relative to its mean so products compare
+#[derive(Clone, Copy)]
+pub struct Dispersion {
+    pub stddev: f64,
+    // Standard deviation as a percentage of the mean
+    pub stddev_percent: f64,
+    // (max - min) as a percentage of min
+    pub range_percent: f64,
+}
+
+// None without samples or with a price that dropped to zero (an empty side of the book)
+pub fn dispersion(values: &[f64]) -> Option<Dispersion> {
+    if values.is_empty() {
+        return None;
+    }
+    let mean: f64 = values.iter().sum::<f64>() / values.len() as f64;
+    let min: f64 = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max: f64 = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if min <= 0.0 {
+        return None;
+    }
+    let variance: f64 = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    Some(Dispersion {
+        stddev: variance.sqrt(),
+        stddev_percent: variance.sqrt() / mean * 100.0,
+        range_percent: (max - min) / min * 100.0,
+    })
+}
+
+pub struct Volatility {
+    pub product_id: String,
+    // (buy, sell) dispersion per window, in the order the windows were given
+    pub windows: Vec<(Option<Dispersion>, Option<Dispersion>)>,
+}
+
+impl Volatility {
+    // The larger relative standard deviation of both prices in the last window
+    pub fn score(&self) -> f64 {
+        let Some((buy, sell)) = self.windows.last() else {
+            return 0.0;
+        };
+        [buy, sell].iter()
+            .filter_map(|dispersion| dispersion.map(|dispersion| dispersion.stddev_percent))
+            .fold(0.0, f64::max)
+    }
+}
+
+// Most volatile first, ranked by the last (usually the longest) window
+pub fn volatility(history: &History, windows: &[Window]) -> Vec<Volatility> {
+    let ids: BTreeSet<&String> = history.points.iter().flat_map(|point| point.products.keys()).collect();
+    let mut rows: Vec<Volatility> = ids.into_iter()
+        .map(|id| {
+            let (buy, sell): (Series, Series) = price_series(history, id);
+            Volatility {
+                product_id: id.clone(),
+                windows: windows.iter()
+                    .map(|window| (dispersion(&recent(&buy, window.millis)), dispersion(&recent(&sell, window.millis))))
+                    .collect(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| b.score().total_cmp(&a.score()).then_with(|| a.product_id.cmp(&b.product_id)));
+    rows
+}
+
+fn format_percent(value: Option<f64>) -> String {
+    value.map(|value| format!("{:.2}", value)).unwrap_or_else(|| "-".to_string())
+}
+
+pub fn print_volatility(rows: &[Volatility], windows: &[Window], items: &ItemCatalog) {
+    let mut header: String = format!("{:<40} {:<32}", "product_id", "name");
+    for window in windows {
+        for column in ["buy_sd%", "buy_rng%", "sell_sd%", "sell_rng%"] {
+            header.push_str(&format!(" {:>14}", format!("{}_{}", column, window)));
+        }
+    }
+    println!("{}", header);
+    for row in rows {
+        let mut line: String = format!("{:<40} {:<32}", row.product_id, items.name(&row.product_id));
+        for (buy, sell) in &row.windows {
+            for value in [
+                buy.map(|buy| buy.stddev_percent),
+                buy.map(|buy| buy.range_percent),
+                sell.map(|sell| sell.stddev_percent),
+                sell.map(|sell| sell.range_percent),
+            ] {
+                line.push_str(&format!(" {:>14}", format_percent(value)));
+            }
+        }
+        println!("{}", line);
+    }
+}
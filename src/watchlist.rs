@@ -0,0 +1,73 @@
+// Set of product IDs and categories commands are restricted to, an empty watchlist keeps every product
+use crate::categories;
+use crate::model::BazaarResponse;
+use std::collections::HashSet;
+use tracing::warn;
+
+#[derive(Clone, Default)]
+pub struct Watchlist {
+    products: HashSet<String>,
+    // Products must also be in one of these, if any
+    categories: HashSet<String>,
+}
+
+impl Watchlist {
+    // IDs are matched case-insensitively, so `booster_cookie` works on the command line
+    pub fn new<I: IntoIterator<Item = String>>(products: I) -> Self {
+        Self {
+            products: products
+                .into_iter()
+                .map(|id| id.trim().to_uppercase())
+                .filter(|id| !id.is_empty())
+                .collect(),
+            categories: HashSet::new(),
+        }
+    }
+
+    // Categories are lowercase, like "farming"
+    pub fn with_categories<I: IntoIterator<Item = String>>(mut self, categories: I) -> Self {
+        self.categories = categories.into_iter()
+            .map(|category| category.trim().to_lowercase())
+            .filter(|category| !category.is_empty())
+            .collect();
+        for category in &self.categories {
+            if !categories::is_known(category) {
+                warn!("No product is in category {}, known ones are {}", category, categories::CATEGORIES.join(", "));
+            }
+        }
+        self
+    }
+
+    pub fn contains(&self, product_id: &str) -> bool {
+        (self.products.is_empty() || self.products.contains(product_id))
+            && (self.categories.is_empty() || categories::category(product_id).is_some_and(|category| self.categories.contains(category)))
+    }
+
+    // The products named, sorted, empty when every product is watched
+    pub fn products(&self) -> Vec<&str> {
+        let mut products: Vec<&str> = self.products.iter().map(|id| id.as_str()).collect();
+        products.sort();
+        products
+    }
+
+    // Drop every product that isn't watched
+    pub fn retain(&self, response: &mut BazaarResponse) {
+        response.products.retain(|id, _| self.contains(id));
+    }
+
+    // Watched IDs the snapshot doesn't know about, usually typos
+    pub fn missing(&self, response: &BazaarResponse) -> Vec<&str> {
+        let mut missing: Vec<&str> = self.products.iter()
+            .filter(|id| !response.products.contains_key(*id))
+            .map(|id| id.as_str())
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    pub fn warn_missing(&self, response: &BazaarResponse) {
+        for id in self.missing(response) {
+            warn!("Watchlisted product {} is not in the snapshot", id);
+        }
+    }
+}